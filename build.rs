@@ -0,0 +1,17 @@
+use std::fs;
+
+// Makes the resolved octocrab version available as OCTOCRAB_VERSION so it can
+// be recorded in the written files.
+fn main() {
+    println!("cargo:rerun-if-changed=Cargo.lock");
+    let version = fs::read_to_string("Cargo.lock")
+        .ok()
+        .and_then(|lock| {
+            let mut lines = lock.lines();
+            lines.find(|line| *line == "name = \"octocrab\"")?;
+            let version = lines.next()?.strip_prefix("version = \"")?;
+            Some(version.trim_end_matches('"').to_string())
+        })
+        .unwrap_or_else(|| "unknown".to_string());
+    println!("cargo:rustc-env=OCTOCRAB_VERSION={}", version);
+}
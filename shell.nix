@@ -5,5 +5,10 @@ pkgs.mkShell {
       pkgs.cargo
       pkgs.rustfmt
       pkgs.rustc
+      pkgs.pkg-config
+    ];
+    # git2 links against OpenSSL to clone the wiki over https.
+    buildInputs = [
+      pkgs.openssl
     ];
 }
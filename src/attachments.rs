@@ -0,0 +1,285 @@
+use log::{debug, info, warn};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::io::prelude::*;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::Duration;
+use tokio::time::sleep;
+
+use crate::permissions;
+use crate::types::WriteError;
+
+pub const ATTACHMENTS_DIR: &str = "attachments";
+/// Maps the downloaded URLs to their file in the attachments directory.
+const URLS_FILE: &str = "urls.json";
+/// How long to wait for an attachment to download.
+const DOWNLOAD_TIMEOUT: Duration = Duration::from_secs(60);
+/// Backoff when a download is rate-limited without a `Retry-After`.
+const RATE_LIMIT_BACKOFF: Duration = Duration::from_secs(60);
+
+/// URL prefixes of images and files uploaded to issues and pull-requests.
+const ATTACHMENT_PREFIXES: [&str; 3] = [
+    "https://user-images.githubusercontent.com/",
+    "https://private-user-images.githubusercontent.com/",
+    "https://github.com/user-attachments/",
+];
+
+#[derive(Debug)]
+pub enum DownloadError {
+    Http(reqwest::Error),
+    Io(io::Error),
+}
+
+impl fmt::Display for DownloadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DownloadError::Http(e) => write!(f, "HTTP error: {}", e),
+            DownloadError::Io(e) => write!(f, "IO error: {}", e),
+        }
+    }
+}
+
+impl From<reqwest::Error> for DownloadError {
+    fn from(err: reqwest::Error) -> Self {
+        DownloadError::Http(err)
+    }
+}
+
+impl From<io::Error> for DownloadError {
+    fn from(err: io::Error) -> Self {
+        DownloadError::Io(err)
+    }
+}
+
+/// Whether the `url` is an attachment: an upload to `user-images`, a
+/// `user-attachments` asset, or a file like
+/// `https://github.com/owner/repo/files/123/log.txt`.
+fn is_attachment(url: &str) -> bool {
+    if ATTACHMENT_PREFIXES
+        .iter()
+        .any(|prefix| url.starts_with(prefix))
+    {
+        return true;
+    }
+    match url.strip_prefix("https://github.com/") {
+        Some(path) => path.split('/').nth(2) == Some("files"),
+        None => false,
+    }
+}
+
+/// Returns all `https://` URLs in a markdown `text`, both in links and
+/// images, and in HTML tags.
+pub fn find_all_urls(text: &str) -> Vec<String> {
+    let mut urls = vec![];
+    let mut rest = text;
+    while let Some(start) = rest.find("https://") {
+        rest = &rest[start..];
+        let end = rest
+            .find(|c: char| c.is_whitespace() || matches!(c, ')' | ']' | '"' | '\'' | '<' | '>'))
+            .unwrap_or(rest.len());
+        urls.push(rest[..end].to_string());
+        rest = &rest[end..];
+    }
+    urls
+}
+
+/// Returns the attachment URLs in a markdown `text`, both in links and
+/// images, and in HTML tags.
+pub fn find_urls(text: &str) -> Vec<String> {
+    find_all_urls(text)
+        .into_iter()
+        .filter(|url| is_attachment(url))
+        .collect()
+}
+
+/// Returns the URLs `find` returns for all `body` fields of a serialized
+/// entry, i.e. for the body of the issue or pull-request and of its comments
+/// and reviews.
+pub fn body_urls(value: &Value, find: fn(&str) -> Vec<String>) -> Vec<String> {
+    let mut urls = vec![];
+    match value {
+        Value::Object(map) => {
+            for (key, field) in map {
+                match (key.as_str(), field) {
+                    ("body", Value::String(body)) => urls.append(&mut find(body)),
+                    _ => urls.append(&mut body_urls(field, find)),
+                }
+            }
+        }
+        Value::Array(items) => items
+            .iter()
+            .for_each(|item| urls.append(&mut body_urls(item, find))),
+        _ => (),
+    }
+    urls
+}
+
+/// Returns the attachment URLs in all `body` fields of a serialized entry.
+pub fn entry_urls(value: &Value) -> Vec<String> {
+    body_urls(value, find_urls)
+}
+
+/// Replaces the downloaded attachment URLs in all `body` fields of a
+/// serialized entry with the path of their file below `prefix`.
+pub fn rewrite_urls(value: &mut Value, files: &BTreeMap<String, String>, prefix: &str) {
+    match value {
+        Value::Object(map) => {
+            for (key, field) in map.iter_mut() {
+                match (key.as_str(), field) {
+                    ("body", Value::String(body)) => {
+                        for url in find_urls(body) {
+                            if let Some(file) = files.get(&url) {
+                                *body = body.replace(&url, &format!("{}/{}", prefix, file));
+                            }
+                        }
+                    }
+                    (_, field) => rewrite_urls(field, files, prefix),
+                }
+            }
+        }
+        Value::Array(items) => items
+            .iter_mut()
+            .for_each(|item| rewrite_urls(item, files, prefix)),
+        _ => (),
+    }
+}
+
+/// The file extension of the last path segment of the `url`, if it looks
+/// like one.
+fn extension(url: &str) -> Option<&str> {
+    let path = url.split(['?', '#']).next()?;
+    let (_, extension) = path.rsplit('/').next()?.rsplit_once('.')?;
+    let plausible = !extension.is_empty()
+        && extension.len() <= 8
+        && extension.chars().all(|c| c.is_ascii_alphanumeric());
+    plausible.then_some(extension)
+}
+
+/// Downloads attachments into a directory, naming each file by the SHA-256
+/// hash of its content, so the same file attached twice is only stored once.
+pub struct AttachmentStore {
+    dir: PathBuf,
+    client: reqwest::Client,
+    token: String,
+    max_retries: u8,
+    /// The file of each downloaded URL.
+    files: Mutex<BTreeMap<String, String>>,
+}
+
+impl AttachmentStore {
+    /// Opens the attachments directory in `destination`, with the URLs
+    /// downloaded by previous backups. The `token` is sent to github.com, which
+    /// requires it for the attachments of private repositories.
+    pub fn open(destination: &Path, token: &str, max_retries: u8) -> Result<Self, WriteError> {
+        let dir = destination.join(ATTACHMENTS_DIR);
+        permissions::create_dir_all(&dir)?;
+        let files = match fs::read_to_string(dir.join(URLS_FILE)) {
+            Ok(contents) => serde_json::from_str(&contents)?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => BTreeMap::new(),
+            Err(e) => return Err(e.into()),
+        };
+        let client = reqwest::Client::builder()
+            .timeout(DOWNLOAD_TIMEOUT)
+            .user_agent("github-metadata-backup")
+            .build()
+            .map_err(|e| io::Error::other(e.to_string()))?;
+        Ok(Self {
+            dir,
+            client,
+            token: token.to_string(),
+            max_retries,
+            files: Mutex::new(files),
+        })
+    }
+
+    fn files(&self) -> std::sync::MutexGuard<'_, BTreeMap<String, String>> {
+        self.files
+            .lock()
+            .expect("the attachment files are never poisoned")
+    }
+
+    /// Downloads the attachments of a serialized entry that weren't
+    /// downloaded before, and returns the files of all its attachments. A
+    /// failed download is logged and retried with the next backup of the
+    /// entry.
+    pub async fn download_entry(&self, value: &Value) -> BTreeMap<String, String> {
+        let mut files = BTreeMap::new();
+        for url in entry_urls(value) {
+            if let Some(file) = self.files().get(&url) {
+                files.insert(url, file.clone());
+                continue;
+            }
+            match self.download(&url).await {
+                Ok(file) => {
+                    debug!("Downloaded {} to {}", url, file);
+                    self.files().insert(url.clone(), file.clone());
+                    files.insert(url, file);
+                }
+                Err(e) => warn!("Could not download the attachment {}: {}", url, e),
+            }
+        }
+        files
+    }
+
+    /// Downloads the `url` and returns the name of its file. Rate-limited
+    /// downloads are retried after the `Retry-After` time.
+    async fn download(&self, url: &str) -> Result<String, DownloadError> {
+        let mut attempt = 0;
+        let response = loop {
+            let mut request = self.client.get(url);
+            if url.starts_with("https://github.com/") {
+                request = request.bearer_auth(&self.token);
+            }
+            let response = request.send().await?;
+            let status = response.status();
+            let rate_limited = status == reqwest::StatusCode::TOO_MANY_REQUESTS
+                || (status == reqwest::StatusCode::FORBIDDEN
+                    && response.headers().contains_key("retry-after"));
+            if !rate_limited || attempt >= self.max_retries {
+                break response.error_for_status()?;
+            }
+            let wait = response
+                .headers()
+                .get("retry-after")
+                .and_then(|value| value.to_str().ok())
+                .and_then(|value| value.parse().ok())
+                .map(Duration::from_secs)
+                .unwrap_or(RATE_LIMIT_BACKOFF);
+            info!(
+                "Downloading attachments is rate-limited. Waiting {} seconds.",
+                wait.as_secs()
+            );
+            sleep(wait).await;
+            attempt += 1;
+        };
+        let content = response.bytes().await?;
+
+        let hash: String = Sha256::digest(&content)
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect();
+        let file = match extension(url) {
+            Some(extension) => format!("{}.{}", hash, extension),
+            None => hash,
+        };
+        let path = self.dir.join(&file);
+        if !path.exists() {
+            let partial = self.dir.join(format!("{}.partial", file));
+            permissions::create_file(&partial)?.write_all(&content)?;
+            fs::rename(&partial, &path)?;
+        }
+        Ok(file)
+    }
+
+    /// Writes which file each URL was downloaded to.
+    pub fn write_urls(&self) -> Result<(), WriteError> {
+        let json = serde_json::to_string_pretty(&*self.files())?;
+        permissions::create_file(self.dir.join(URLS_FILE))?.write_all(json.as_bytes())?;
+        Ok(())
+    }
+}
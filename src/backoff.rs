@@ -0,0 +1,153 @@
+use log::warn;
+use std::time::Duration;
+
+use crate::types::BackoffStrategy;
+
+/// How long to wait before retrying a failed request, growing with each
+/// retry according to the `--backoff-strategy`.
+#[derive(Debug, Clone, Copy)]
+pub struct Backoff {
+    pub strategy: BackoffStrategy,
+    /// The delay before the first retry.
+    pub base: Duration,
+    /// The longest delay.
+    pub cap: Duration,
+}
+
+/// An attempt to send a request. Passed on to the retry of a failed attempt,
+/// so the next delay can depend on the previous one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Attempt {
+    /// Number of failed attempts before this one.
+    pub retries: u8,
+    /// The backoff delay waited before this attempt. None for the first
+    /// attempt, and for retries that waited on a rate-limit instead.
+    pub previous_delay: Option<Duration>,
+}
+
+impl Attempt {
+    pub const FIRST: Attempt = Attempt {
+        retries: 0,
+        previous_delay: None,
+    };
+
+    /// The retry of this attempt, sent after waiting the backoff `delay`, if
+    /// any.
+    pub fn retry(self, delay: Option<Duration>) -> Attempt {
+        Attempt {
+            retries: self.retries.saturating_add(1),
+            previous_delay: delay,
+        }
+    }
+}
+
+impl Backoff {
+    /// Returns the delay before the retry of the failed `attempt`. `random`
+    /// is a number in `[0, 1)` spreading the decorrelated jitter delays.
+    pub fn delay(&self, attempt: Attempt, random: f64) -> Duration {
+        let delay = match self.strategy {
+            BackoffStrategy::Fixed => self.base,
+            BackoffStrategy::Exponential => self
+                .base
+                .checked_mul(2u32.checked_pow(attempt.retries.into()).unwrap_or(u32::MAX))
+                .unwrap_or(Duration::MAX),
+            // Decorrelated jitter picks a random delay between the base and
+            // three times the previous delay, starting from the base.
+            BackoffStrategy::DecorrelatedJitter => {
+                let previous = attempt.previous_delay.unwrap_or(self.base);
+                let upper = previous.saturating_mul(3).min(self.cap);
+                self.base
+                    + upper
+                        .saturating_sub(self.base)
+                        .mul_f64(random.clamp(0.0, 1.0))
+            }
+        };
+        delay.min(self.cap)
+    }
+}
+
+/// Returns a random number in `[0, 1)`. Falls back to 0.5 if no random bytes
+/// are available.
+pub fn random() -> f64 {
+    let mut bytes = [0u8; 8];
+    if let Err(e) = getrandom::getrandom(&mut bytes) {
+        warn!("Could not get random bytes for the backoff jitter: {}", e);
+        return 0.5;
+    }
+    (u64::from_le_bytes(bytes) >> 11) as f64 / (1u64 << 53) as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn backoff(strategy: BackoffStrategy) -> Backoff {
+        Backoff {
+            strategy,
+            base: Duration::from_secs(1),
+            cap: Duration::from_secs(60),
+        }
+    }
+
+    /// The delays of the first `n` retries, with the same `random` number
+    /// for each.
+    fn delays(backoff: Backoff, n: usize, random: f64) -> Vec<u64> {
+        let mut attempt = Attempt::FIRST;
+        let mut delays = vec![];
+        for _ in 0..n {
+            let delay = backoff.delay(attempt, random);
+            delays.push(delay.as_secs());
+            attempt = attempt.retry(Some(delay));
+        }
+        delays
+    }
+
+    #[test]
+    fn fixed() {
+        assert_eq!(
+            delays(backoff(BackoffStrategy::Fixed), 4, 0.5),
+            [1, 1, 1, 1]
+        );
+    }
+
+    #[test]
+    fn exponential() {
+        assert_eq!(
+            delays(backoff(BackoffStrategy::Exponential), 8, 0.5),
+            [1, 2, 4, 8, 16, 32, 60, 60]
+        );
+        let attempt = Attempt {
+            retries: u8::MAX,
+            previous_delay: None,
+        };
+        assert_eq!(
+            backoff(BackoffStrategy::Exponential).delay(attempt, 0.5),
+            Duration::from_secs(60)
+        );
+    }
+
+    #[test]
+    fn decorrelated_jitter() {
+        let jitter = backoff(BackoffStrategy::DecorrelatedJitter);
+        // The lowest random number always waits the base delay, the highest
+        // three times the previous delay, up to the cap.
+        assert_eq!(delays(jitter, 4, 0.0), [1, 1, 1, 1]);
+        assert_eq!(delays(jitter, 6, 1.0), [3, 9, 27, 60, 60, 60]);
+        // Each delay is between the base and three times the previous one.
+        let mut attempt = Attempt::FIRST;
+        for random in [0.3, 0.9, 0.1, 0.7, 0.5, 0.99] {
+            let previous = attempt.previous_delay.unwrap_or(jitter.base);
+            let delay = jitter.delay(attempt, random);
+            assert!(delay >= jitter.base);
+            assert!(delay <= (previous * 3).min(jitter.cap));
+            attempt = attempt.retry(Some(delay));
+        }
+    }
+
+    #[test]
+    fn random_is_in_range() {
+        for _ in 0..100 {
+            assert!((0.0..1.0).contains(&random()));
+        }
+    }
+}
@@ -0,0 +1,200 @@
+use chrono::{DateTime, Utc};
+use log::info;
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::path::Path;
+
+use crate::index::Index;
+use crate::permissions;
+use crate::types::{ChangelogGroupBy, EntryWithMetadata, WriteError};
+
+pub const CHANGELOG_FILE: &str = "changelog.md";
+/// The heading of the entries without a label with `--changelog-group-by
+/// label`.
+const UNLABELED: &str = "Unlabeled";
+
+/// How an issue or pull-request changed since the previous backup, in the
+/// order of the changelog sections.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum ChangeKind {
+    Merged,
+    Closed,
+    Reopened,
+    Opened,
+}
+
+impl ChangeKind {
+    fn verb(&self) -> &'static str {
+        match self {
+            ChangeKind::Merged => "Merged",
+            ChangeKind::Closed => "Closed",
+            ChangeKind::Reopened => "Reopened",
+            ChangeKind::Opened => "Opened",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Change {
+    kind: ChangeKind,
+    is_pull: bool,
+    number: u64,
+    title: String,
+    labels: Vec<String>,
+}
+
+/// The changes of the issues and pull-requests written by a backup, compared
+/// with their entries in the index of the previous backup.
+#[derive(Debug, Default)]
+pub struct Changelog {
+    changes: Vec<Change>,
+}
+
+impl Changelog {
+    /// Records how the written entry `x` changed since the previous backup,
+    /// compared with its entry in the `previous` index. Entries that were
+    /// neither opened, closed, reopened nor merged since aren't recorded.
+    pub fn record(&mut self, previous: &Index, x: &EntryWithMetadata) {
+        let (is_pull, title, labels, previous) = match x {
+            EntryWithMetadata::Issue(i) => (
+                false,
+                i.issue.title.clone(),
+                i.issue.labels.iter().map(|l| l.name.clone()).collect(),
+                previous.issues.get(&i.issue.number),
+            ),
+            EntryWithMetadata::Pull(p) => (
+                true,
+                p.pull.title.clone().unwrap_or_default(),
+                p.pull
+                    .labels
+                    .iter()
+                    .flatten()
+                    .map(|l| l.name.clone())
+                    .collect(),
+                previous.pulls.get(&p.pull.number),
+            ),
+        };
+        let (is_open, is_merged) = match x {
+            EntryWithMetadata::Issue(i) => {
+                (i.issue.state == octocrab::models::IssueState::Open, false)
+            }
+            EntryWithMetadata::Pull(p) => (
+                p.pull.state == Some(octocrab::models::IssueState::Open),
+                p.pull.merged_at.is_some(),
+            ),
+        };
+        let was_open = previous.map(|entry| entry.state == "open");
+        let was_merged = previous.and_then(|entry| entry.merged) == Some(true);
+        let kind = match (was_open, is_open) {
+            (_, false) if is_merged && !was_merged => ChangeKind::Merged,
+            (None | Some(true), false) if !is_merged => ChangeKind::Closed,
+            (Some(false), true) => ChangeKind::Reopened,
+            (None, true) => ChangeKind::Opened,
+            _ => return,
+        };
+        self.changes.push(Change {
+            kind,
+            is_pull,
+            number: x.number(),
+            title,
+            labels,
+        });
+    }
+
+    /// Renders the changes of `repository` since the previous backup at
+    /// `since` as markdown, with a section per label or per kind of change.
+    fn render(
+        &self,
+        repository: &str,
+        since: Option<DateTime<Utc>>,
+        group_by: ChangelogGroupBy,
+    ) -> String {
+        let mut changes = self.changes.clone();
+        changes.sort_by_key(|change| (change.kind, change.is_pull, change.number));
+        let noun = |change: &Change| {
+            if change.is_pull {
+                "pull-request"
+            } else {
+                "issue"
+            }
+        };
+        let mut sections: Vec<(String, Vec<String>)> = vec![];
+        match group_by {
+            ChangelogGroupBy::Label => {
+                let mut by_label: BTreeMap<&str, Vec<String>> = BTreeMap::new();
+                let mut unlabeled = vec![];
+                for change in changes.iter() {
+                    let line = format!(
+                        "- {} {} #{}: {}",
+                        change.kind.verb(),
+                        noun(change),
+                        change.number,
+                        change.title
+                    );
+                    for label in change.labels.iter() {
+                        by_label.entry(label).or_default().push(line.clone());
+                    }
+                    if change.labels.is_empty() {
+                        unlabeled.push(line);
+                    }
+                }
+                sections.extend(
+                    by_label
+                        .into_iter()
+                        .map(|(label, lines)| (label.to_string(), lines)),
+                );
+                if !unlabeled.is_empty() {
+                    sections.push((UNLABELED.to_string(), unlabeled));
+                }
+            }
+            ChangelogGroupBy::Kind => {
+                for change in changes.iter() {
+                    let heading = format!("{} {}s", change.kind.verb(), noun(change));
+                    let mut line = format!("- #{}: {}", change.number, change.title);
+                    if !change.labels.is_empty() {
+                        let _ = write!(line, " ({})", change.labels.join(", "));
+                    }
+                    match sections.last_mut() {
+                        Some((last, lines)) if *last == heading => lines.push(line),
+                        _ => sections.push((heading, vec![line])),
+                    }
+                }
+            }
+        }
+
+        let mut markdown = format!("# Changes in {}\n\n", repository);
+        if let Some(since) = since {
+            let _ = writeln!(markdown, "Since the backup of {}.\n", since.to_rfc3339());
+        }
+        if sections.is_empty() {
+            markdown.push_str("No issues or pull-requests were opened, closed or merged.\n\n");
+        }
+        for (heading, lines) in sections {
+            let _ = writeln!(markdown, "## {}\n\n{}\n", heading, lines.join("\n"));
+        }
+        markdown.truncate(markdown.trim_end().len());
+        markdown.push('\n');
+        markdown
+    }
+
+    /// Writes the changelog of `repository` since the previous backup at
+    /// `since` to `changelog.md` in `destination`, replacing the one of the
+    /// previous backup.
+    pub fn write(
+        &self,
+        repository: &str,
+        since: Option<DateTime<Utc>>,
+        group_by: ChangelogGroupBy,
+        destination: &Path,
+    ) -> Result<(), WriteError> {
+        let path = destination.join(CHANGELOG_FILE);
+        let markdown = self.render(repository, since, group_by);
+        permissions::write_atomically(&path, markdown.as_bytes())?;
+        info!(
+            "Written the changelog of {} changes to {}",
+            self.changes.len(),
+            path.display()
+        );
+        Ok(())
+    }
+}
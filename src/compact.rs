@@ -0,0 +1,131 @@
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use log::{debug, info};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::io::prelude::*;
+use std::path::{Path, PathBuf};
+
+use crate::index::Index;
+use crate::permissions;
+use crate::types::WriteError;
+
+/// What the `compact` subcommand did.
+#[derive(Debug, Default)]
+pub struct CompactReport {
+    /// Number of issue and pull-request files merged into shards.
+    pub compacted: usize,
+    /// Number of shards written.
+    pub shards: usize,
+}
+
+/// Name of the shard holding the entry with the `number`, e.g.
+/// `0000-0999.ndjson.gz` with a `shard_size` of 1000.
+fn shard_name(number: u64, shard_size: u64) -> String {
+    let start = number / shard_size * shard_size;
+    format!("{:04}-{:04}.ndjson.gz", start, start + shard_size - 1)
+}
+
+/// Merges the issue and pull-request files in `directory` into gzip-compressed
+/// NDJSON shards of `shard_size` numbers each, and removes the merged files.
+/// Entries already in a shard are replaced by a newer file. The shard of each
+/// entry is recorded in `index.json`.
+pub fn compact(
+    directory: &Path,
+    issues_dir: &str,
+    pulls_dir: &str,
+    shard_size: u64,
+) -> Result<CompactReport, WriteError> {
+    let mut index = Index::load(directory, issues_dir, pulls_dir);
+    let mut report = CompactReport::default();
+    for (dir, key, entries) in [
+        (issues_dir, "issue", &mut index.issues),
+        (pulls_dir, "pull", &mut index.pulls),
+    ] {
+        let path = directory.join(dir);
+        let mut shards: BTreeMap<String, Vec<(u64, PathBuf)>> = BTreeMap::new();
+        let files = match fs::read_dir(&path) {
+            Ok(files) => files,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(e.into()),
+        };
+        for file in files {
+            let file = file?.path();
+            let number = file
+                .file_name()
+                .and_then(|name| name.to_str())
+                .and_then(|name| name.strip_suffix(".json"))
+                .and_then(|number| number.parse::<u64>().ok());
+            if let Some(number) = number {
+                shards
+                    .entry(shard_name(number, shard_size))
+                    .or_default()
+                    .push((number, file));
+            }
+        }
+
+        for (name, files) in shards {
+            let shard = path.join(&name);
+            let mut lines = read_shard(&shard, key)?;
+            for (number, file) in files.iter() {
+                let value: Value = serde_json::from_str(&fs::read_to_string(file)?)?;
+                lines.insert(*number, serde_json::to_string(&value)?);
+            }
+            write_shard(&shard, &lines)?;
+
+            let relative = format!("{}/{}", dir, name);
+            for (number, file) in files.iter() {
+                fs::remove_file(file)?;
+                if let Some(entry) = entries.get_mut(number) {
+                    entry.shard = Some(relative.clone());
+                }
+            }
+            report.compacted += files.len();
+            report.shards += 1;
+        }
+    }
+    index.write(directory)?;
+    info!(
+        "Compacted {} files into {} shards",
+        report.compacted, report.shards
+    );
+    Ok(report)
+}
+
+/// Writes the `lines` to the `shard`, replacing it atomically.
+pub fn write_shard(shard: &Path, lines: &BTreeMap<u64, String>) -> Result<(), WriteError> {
+    let mut partial = shard.as_os_str().to_owned();
+    partial.push(".partial");
+    let partial = PathBuf::from(partial);
+    let mut encoder = GzEncoder::new(permissions::create_file(&partial)?, Compression::default());
+    for line in lines.values() {
+        writeln!(encoder, "{}", line)?;
+    }
+    encoder.finish()?;
+    fs::rename(&partial, shard)?;
+    debug!("Written {} entries to {}", lines.len(), shard.display());
+    Ok(())
+}
+
+/// Reads the lines of an existing shard, keyed by the number of their `key`
+/// object ("issue" or "pull").
+pub fn read_shard(shard: &Path, key: &str) -> Result<BTreeMap<u64, String>, WriteError> {
+    let mut contents = String::new();
+    match File::open(shard) {
+        Ok(file) => GzDecoder::new(file).read_to_string(&mut contents)?,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(BTreeMap::new()),
+        Err(e) => return Err(e.into()),
+    };
+    let mut lines = BTreeMap::new();
+    for line in contents.lines() {
+        let value: Value = serde_json::from_str(line)?;
+        if let Some(number) = value[key]["number"].as_u64() {
+            lines.insert(number, line.to_string());
+        }
+    }
+    Ok(lines)
+}
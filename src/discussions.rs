@@ -0,0 +1,195 @@
+use serde_json::{Map, Value};
+
+pub const DISCUSSIONS_DIR: &str = "discussions";
+/// Discussions per page of the [discussions_query]. Each comes with up to 50
+/// comments with up to 50 replies each, so pages are small. The page size is
+/// halved if a page still exceeds the GraphQL limits.
+pub const PAGE_SIZE: u8 = 10;
+
+/// The fields of a discussion comment or reply. GitHub only nests replies one
+/// level deep: a reply to a reply is listed among the replies of the comment,
+/// with the reply it answers in `replyTo`.
+const COMMENT_FIELDS: &str = r#"
+fragment CommentFields on DiscussionComment {
+  id
+  databaseId
+  url
+  author { login }
+  authorAssociation
+  body
+  createdAt
+  updatedAt
+  lastEditedAt
+  isAnswer
+  isMinimized
+  minimizedReason
+  upvoteCount
+  replyTo { id }
+  reactionGroups { content reactors { totalCount } }
+}
+"#;
+
+/// GraphQL query for a page of the discussions of a repository, the most
+/// recently updated first, with the first 50 comments of each and the first
+/// 50 replies of each comment. `$first` starts at the [PAGE_SIZE].
+pub fn discussions_query() -> String {
+    format!(
+        r#"
+query($owner: String!, $repo: String!, $first: Int!, $after: String) {{
+  repository(owner: $owner, name: $repo) {{
+    discussions(first: $first, after: $after, orderBy: {{field: UPDATED_AT, direction: DESC}}) {{
+      pageInfo {{ hasNextPage endCursor }}
+      nodes {{
+        id
+        number
+        title
+        url
+        author {{ login }}
+        authorAssociation
+        body
+        createdAt
+        updatedAt
+        lastEditedAt
+        closed
+        closedAt
+        stateReason
+        locked
+        isAnswered
+        answer {{ id }}
+        upvoteCount
+        category {{ name slug }}
+        labels(first: 100) {{ nodes {{ name }} }}
+        reactionGroups {{ content reactors {{ totalCount }} }}
+        comments(first: 50) {{
+          pageInfo {{ hasNextPage endCursor }}
+          nodes {{
+            ...CommentFields
+            replies(first: 50) {{
+              pageInfo {{ hasNextPage endCursor }}
+              nodes {{ ...CommentFields }}
+            }}
+          }}
+        }}
+      }}
+    }}
+  }}
+}}
+{}"#,
+        COMMENT_FIELDS
+    )
+}
+
+/// GraphQL query for the comments of the discussion `$number` after the
+/// cursor `$after`, with the first 50 replies of each.
+pub fn comments_query() -> String {
+    format!(
+        r#"
+query($owner: String!, $repo: String!, $number: Int!, $after: String) {{
+  repository(owner: $owner, name: $repo) {{
+    discussion(number: $number) {{
+      comments(first: 50, after: $after) {{
+        pageInfo {{ hasNextPage endCursor }}
+        nodes {{
+          ...CommentFields
+          replies(first: 50) {{
+            pageInfo {{ hasNextPage endCursor }}
+            nodes {{ ...CommentFields }}
+          }}
+        }}
+      }}
+    }}
+  }}
+}}
+{}"#,
+        COMMENT_FIELDS
+    )
+}
+
+/// GraphQL query for the replies to the discussion comment `$id` after the
+/// cursor `$after`.
+pub fn replies_query() -> String {
+    format!(
+        r#"
+query($id: ID!, $after: String) {{
+  node(id: $id) {{
+    ... on DiscussionComment {{
+      replies(first: 100, after: $after) {{
+        pageInfo {{ hasNextPage endCursor }}
+        nodes {{ ...CommentFields }}
+      }}
+    }}
+  }}
+}}
+{}"#,
+        COMMENT_FIELDS
+    )
+}
+
+/// The nodes of a page of a GraphQL connection, and the cursor of the next
+/// page, if any.
+#[derive(Debug, Default)]
+pub struct Connection {
+    pub nodes: Vec<Value>,
+    pub next: Option<String>,
+}
+
+/// Takes the nodes and the next cursor out of the GraphQL `connection`.
+pub fn take_connection(connection: &mut Value) -> Connection {
+    let next = match connection["pageInfo"]["hasNextPage"].as_bool() {
+        Some(true) => connection["pageInfo"]["endCursor"]
+            .as_str()
+            .map(str::to_string),
+        _ => None,
+    };
+    let nodes = match connection["nodes"].take() {
+        Value::Array(nodes) => nodes,
+        _ => vec![],
+    };
+    Connection { nodes, next }
+}
+
+/// Converts the GraphQL `reactionGroups` of a discussion, comment or reply
+/// into `reactions` with the same keys as the reactions of the REST API, so
+/// discussions are read like issues.
+fn reactions(groups: &Value) -> Value {
+    let mut reactions = Map::new();
+    let mut total = 0;
+    for group in groups.as_array().into_iter().flatten() {
+        let key = match group["content"].as_str() {
+            Some("THUMBS_UP") => "+1",
+            Some("THUMBS_DOWN") => "-1",
+            Some("LAUGH") => "laugh",
+            Some("HOORAY") => "hooray",
+            Some("CONFUSED") => "confused",
+            Some("HEART") => "heart",
+            Some("ROCKET") => "rocket",
+            Some("EYES") => "eyes",
+            _ => continue,
+        };
+        let count = group["reactors"]["totalCount"].as_u64().unwrap_or_default();
+        total += count;
+        reactions.insert(key.to_string(), count.into());
+    }
+    reactions.insert("total_count".to_string(), total.into());
+    Value::Object(reactions)
+}
+
+/// Replaces the `reactionGroups` of a discussion, comment or reply `node`
+/// with its `reactions`, and the `labels` connection of a discussion with
+/// the label names.
+pub fn normalize(node: &mut Value) {
+    let Some(object) = node.as_object_mut() else {
+        return;
+    };
+    if let Some(groups) = object.remove("reactionGroups") {
+        object.insert("reactions".to_string(), reactions(&groups));
+    }
+    if let Some(mut labels) = object.remove("labels") {
+        let names: Vec<Value> = take_connection(&mut labels)
+            .nodes
+            .iter()
+            .filter_map(|label| label.get("name").cloned())
+            .collect();
+        object.insert("labels".to_string(), names.into());
+    }
+}
@@ -0,0 +1,153 @@
+use std::borrow::Cow;
+
+use crate::types::OutputEncoding;
+
+/// Normalizes a JSON response body, so a single malformed comment doesn't
+/// fail the whole response. Invalid UTF-8 byte sequences and `\u` escapes of
+/// lone UTF-16 surrogates, which serde_json rejects, are replaced with U+FFFD
+/// or escaped as text, depending on the `policy`. Returns None if the body
+/// didn't need to be changed.
+pub fn normalize(body: &[u8], policy: OutputEncoding) -> Option<Vec<u8>> {
+    if policy == OutputEncoding::Strict {
+        return None;
+    }
+    let text = match std::str::from_utf8(body) {
+        Ok(text) => Cow::Borrowed(text),
+        Err(_) => Cow::Owned(fix_utf8(body, policy)),
+    };
+    match fix_surrogates(&text, policy) {
+        Some(fixed) => Some(fixed.into_bytes()),
+        None => match text {
+            Cow::Owned(text) => Some(text.into_bytes()),
+            Cow::Borrowed(_) => None,
+        },
+    }
+}
+
+/// Replaces invalid UTF-8 byte sequences with U+FFFD, or with `\\xNN` escapes
+/// that show up as `\xNN` in the deserialized string.
+fn fix_utf8(body: &[u8], policy: OutputEncoding) -> String {
+    let mut text = String::with_capacity(body.len());
+    for chunk in body.utf8_chunks() {
+        text.push_str(chunk.valid());
+        if chunk.invalid().is_empty() {
+            continue;
+        }
+        match policy {
+            OutputEncoding::Escape => {
+                for byte in chunk.invalid() {
+                    text.push_str(&format!("\\\\x{:02x}", byte));
+                }
+            }
+            _ => text.push('\u{fffd}'),
+        }
+    }
+    text
+}
+
+/// Parses the four hex digits of a `\u` escape starting at `i`.
+fn hex_escape(bytes: &[u8], i: usize) -> Option<u16> {
+    let digits = bytes.get(i..i + 4)?;
+    u16::from_str_radix(std::str::from_utf8(digits).ok()?, 16).ok()
+}
+
+/// Replaces the `\u` escapes of lone surrogates with `\ufffd`, or escapes
+/// their backslash so they are kept as text. Returns None if there are none.
+fn fix_surrogates(text: &str, policy: OutputEncoding) -> Option<String> {
+    let bytes = text.as_bytes();
+    let mut fixed: Option<String> = None;
+    // Start of the text not yet copied to `fixed`.
+    let mut copied = 0;
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] != b'\\' {
+            i += 1;
+            continue;
+        }
+        if bytes.get(i + 1) != Some(&b'u') {
+            // Skip the escaped character, which might be a backslash.
+            i += 2;
+            continue;
+        }
+        let unit = match hex_escape(bytes, i + 2) {
+            Some(unit) => unit,
+            None => {
+                i += 2;
+                continue;
+            }
+        };
+        let is_high = (0xD800..0xDC00).contains(&unit);
+        let is_low = (0xDC00..0xE000).contains(&unit);
+        let has_low = bytes.get(i + 6..i + 8) == Some(b"\\u")
+            && hex_escape(bytes, i + 8).is_some_and(|next| (0xDC00..0xE000).contains(&next));
+        if is_high && has_low {
+            i += 12;
+            continue;
+        }
+        if !is_high && !is_low {
+            i += 6;
+            continue;
+        }
+        let fixed = fixed.get_or_insert_with(|| String::with_capacity(text.len()));
+        fixed.push_str(&text[copied..i]);
+        match policy {
+            OutputEncoding::Escape => fixed.push_str(&format!("\\\\u{:04x}", unit)),
+            _ => fixed.push_str("\\ufffd"),
+        }
+        i += 6;
+        copied = i;
+    }
+    fixed.map(|mut fixed| {
+        fixed.push_str(&text[copied..]);
+        fixed
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::Value;
+
+    /// The `body` of the normalized comment.
+    fn body(comment: &[u8], policy: OutputEncoding) -> Option<String> {
+        let normalized = normalize(comment, policy)?;
+        let comment: Value = serde_json::from_slice(&normalized).unwrap();
+        Some(comment["body"].as_str().unwrap().to_string())
+    }
+
+    #[test]
+    fn invalid_utf8() {
+        let comment = b"{\"body\": \"caf\xe9 \xff\xfe ok\"}";
+        assert!(serde_json::from_slice::<Value>(comment).is_err());
+        assert_eq!(
+            body(comment, OutputEncoding::Replace).unwrap(),
+            "caf\u{fffd} \u{fffd}\u{fffd} ok"
+        );
+        assert_eq!(
+            body(comment, OutputEncoding::Escape).unwrap(),
+            "caf\\xe9 \\xff\\xfe ok"
+        );
+        assert_eq!(normalize(comment, OutputEncoding::Strict), None);
+    }
+
+    #[test]
+    fn lone_surrogates() {
+        let comment = br#"{"body": "a\ud800b \udc00 \ud83d\ude00 \\ud800"}"#;
+        assert!(serde_json::from_slice::<Value>(comment).is_err());
+        assert_eq!(
+            body(comment, OutputEncoding::Replace).unwrap(),
+            "a\u{fffd}b \u{fffd} \u{1f600} \\ud800"
+        );
+        assert_eq!(
+            body(comment, OutputEncoding::Escape).unwrap(),
+            "a\\ud800b \\udc00 \u{1f600} \\ud800"
+        );
+    }
+
+    #[test]
+    fn valid_bodies_are_unchanged() {
+        let comment = r#"{"body": "café ☕ 😀"}"#.as_bytes();
+        assert_eq!(normalize(comment, OutputEncoding::Replace), None);
+        assert_eq!(normalize(comment, OutputEncoding::Escape), None);
+    }
+}
@@ -0,0 +1,76 @@
+use chrono::{DateTime, Utc};
+use log::warn;
+use serde::Serialize;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::io::prelude::*;
+use std::path::Path;
+use std::sync::{Mutex, OnceLock};
+
+/// Where the failures are streamed to with `--emit-jsonl-errors`. Not set
+/// without it.
+static STREAM: OnceLock<Mutex<Box<dyn Write + Send>>> = OnceLock::new();
+
+/// A failure as written to the `--emit-jsonl-errors` stream, one per line.
+#[derive(Serialize, Debug)]
+struct ErrorEvent<'a> {
+    /// The repository as `owner/repo`.
+    repository: &'a str,
+    /// The number of the issue or pull-request. Not set for a failed page.
+    number: Option<u64>,
+    /// Either "issue", "pull" or "page".
+    kind: &'a str,
+    /// Either "listing", "fetch", "serialize" or "write".
+    phase: &'a str,
+    error: String,
+    timestamp: DateTime<Utc>,
+}
+
+/// Opens the stream of failures. The file at `path` is appended to, and `-`
+/// streams to stderr.
+pub fn open(path: &Path) -> io::Result<()> {
+    let out: Box<dyn Write + Send> = match path.to_str() {
+        Some("-") => Box::new(io::stderr()),
+        _ => Box::new(
+            fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)?,
+        ),
+    };
+    STREAM.get_or_init(|| Mutex::new(out));
+    Ok(())
+}
+
+/// Writes a failure to the stream as it happens. Does nothing without
+/// `--emit-jsonl-errors`.
+pub fn emit(
+    repository: &str,
+    number: Option<u64>,
+    kind: &str,
+    phase: &str,
+    error: &dyn fmt::Display,
+) {
+    let Some(stream) = STREAM.get() else {
+        return;
+    };
+    let event = ErrorEvent {
+        repository,
+        number,
+        kind,
+        phase,
+        error: error.to_string(),
+        timestamp: Utc::now(),
+    };
+    let result = serde_json::to_string(&event)
+        .map_err(io::Error::from)
+        .and_then(|line| {
+            let mut out = stream.lock().expect("the error stream is never poisoned");
+            writeln!(out, "{}", line)?;
+            out.flush()
+        });
+    if let Err(e) = result {
+        warn!("Could not write to the --emit-jsonl-errors stream: {}", e);
+    }
+}
@@ -0,0 +1,84 @@
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+
+/// The fields octocrab dropped with `--detect-schema-changes`, by model and
+/// field path, with how often each was dropped.
+static DROPPED: Mutex<BTreeMap<String, BTreeMap<String, u64>>> = Mutex::new(BTreeMap::new());
+
+/// Lists the fields of a raw API response that are missing from the
+/// re-serialized octocrab model, i.e. the fields octocrab drops. Fields are
+/// named by their path, e.g. `user.user_view_type` or `labels[0].description`.
+/// Fields that are `null` in the response aren't considered dropped.
+pub fn dropped_fields(raw: &Value, typed: &Value) -> Vec<String> {
+    let mut dropped = Vec::new();
+    collect(raw, typed, "", &mut dropped);
+    dropped
+}
+
+fn collect(raw: &Value, typed: &Value, path: &str, dropped: &mut Vec<String>) {
+    match (raw, typed) {
+        (Value::Object(raw), Value::Object(typed)) => {
+            for (key, raw_value) in raw.iter().filter(|(_, v)| !v.is_null()) {
+                let field = if path.is_empty() {
+                    key.clone()
+                } else {
+                    format!("{}.{}", path, key)
+                };
+                match typed.get(key) {
+                    Some(typed_value) => collect(raw_value, typed_value, &field, dropped),
+                    None => dropped.push(field),
+                }
+            }
+        }
+        (Value::Array(raw), Value::Array(typed)) => {
+            for (i, (raw_item, typed_item)) in raw.iter().zip(typed.iter()).enumerate() {
+                collect(raw_item, typed_item, &format!("{}[{}]", path, i), dropped);
+            }
+        }
+        _ => (),
+    }
+}
+
+/// Records the `dropped` fields of a response parsed into the `model` for the
+/// `--detect-schema-changes` report. Array indices are left out of the field
+/// paths, so `labels[0].description` and `labels[1].description` are counted
+/// as `labels[].description`.
+pub fn record(model: &str, dropped: &[String]) {
+    let mut all = DROPPED
+        .lock()
+        .expect("the dropped fields are never poisoned");
+    let fields = all.entry(model.to_string()).or_default();
+    for field in dropped {
+        *fields.entry(without_indices(field)).or_default() += 1;
+    }
+}
+
+/// Returns the fields recorded since the last call, by model.
+pub fn take_recorded() -> BTreeMap<String, BTreeMap<String, u64>> {
+    std::mem::take(
+        &mut *DROPPED
+            .lock()
+            .expect("the dropped fields are never poisoned"),
+    )
+}
+
+fn without_indices(field: &str) -> String {
+    let mut path = String::with_capacity(field.len());
+    let mut in_index = false;
+    for c in field.chars() {
+        match c {
+            '[' => {
+                in_index = true;
+                path.push(c);
+            }
+            ']' => {
+                in_index = false;
+                path.push(c);
+            }
+            _ if in_index => (),
+            _ => path.push(c),
+        }
+    }
+    path
+}
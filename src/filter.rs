@@ -0,0 +1,351 @@
+use chrono::{DateTime, NaiveDate, Utc};
+use std::time::Duration;
+
+use crate::types::{parse_duration, EntryWithMetadata};
+
+/// The attributes of an entry a [EntryFilter] can compare, with their type.
+const FIELDS: [(&str, Kind); 11] = [
+    ("type", Kind::Text),
+    ("number", Kind::Number),
+    ("state", Kind::Text),
+    ("merged", Kind::Bool),
+    ("comments", Kind::Number),
+    ("author", Kind::Text),
+    ("title", Kind::Text),
+    ("labels", Kind::List),
+    ("created", Kind::Time),
+    ("updated", Kind::Time),
+    ("closed", Kind::Time),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    Text,
+    Number,
+    Bool,
+    Time,
+    List,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Op {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Contains,
+}
+
+#[derive(Debug, Clone)]
+enum Literal {
+    Text(String),
+    Number(u64),
+    Bool(bool),
+    Time(DateTime<Utc>),
+    /// A time relative to when the entry is checked, e.g. `30d ago`.
+    Ago(Duration),
+}
+
+#[derive(Debug, Clone)]
+enum Expr {
+    Or(Box<Expr>, Box<Expr>),
+    And(Box<Expr>, Box<Expr>),
+    Not(Box<Expr>),
+    /// A bool field on its own, e.g. `merged`.
+    Field(&'static str),
+    Compare(&'static str, Op, Literal),
+}
+
+/// A value of an entry attribute. Attributes an entry doesn't have (e.g.
+/// `closed` of an open issue) are `None`, and comparisons with them are false.
+enum Value {
+    Text(Option<String>),
+    Number(Option<u64>),
+    Bool(bool),
+    Time(Option<DateTime<Utc>>),
+    List(Vec<String>),
+}
+
+/// An `--entry-filter-expr` deciding which entries are written, e.g.
+/// `state == "closed" and comments > 10` or `merged and updated > 30d ago`.
+/// Comparisons are `field op value`, with `==`, `!=`, `<`, `<=`, `>` and
+/// `>=`, and `contains` for substrings of text and items of `labels`. They are
+/// combined with `not`, `and` and `or` (binding in that order) and
+/// parentheses. Values are numbers, quoted text, `true` and `false`, dates
+/// like `2024-01-31` or RFC 3339 timestamps, and durations like `30d ago`.
+#[derive(Debug, Clone)]
+pub struct EntryFilter {
+    expr: Expr,
+}
+
+impl EntryFilter {
+    pub fn parse(s: &str) -> Result<Self, String> {
+        let tokens = tokenize(s)?;
+        let mut parser = Parser { tokens, pos: 0 };
+        let expr = parser.or()?;
+        match parser.peek() {
+            None => Ok(Self { expr }),
+            Some(token) => Err(format!("unexpected '{}'", token)),
+        }
+    }
+
+    /// Returns true if the entry should be written.
+    pub fn matches(&self, entry: &EntryWithMetadata) -> bool {
+        eval(&self.expr, entry, Utc::now())
+    }
+}
+
+/// Splits an expression into words, operators, parentheses and quoted text.
+/// Quoted text keeps its quotes, so it's told apart from words.
+fn tokenize(s: &str) -> Result<Vec<String>, String> {
+    let mut tokens = vec![];
+    let mut chars = s.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+        } else if c == '(' || c == ')' {
+            tokens.push(c.to_string());
+            chars.next();
+        } else if c == '"' || c == '\'' {
+            chars.next();
+            let mut text = String::from('"');
+            loop {
+                match chars.next() {
+                    Some(q) if q == c => break,
+                    Some(other) => text.push(other),
+                    None => return Err(format!("unterminated text starting with {}", c)),
+                }
+            }
+            tokens.push(text);
+        } else if "=!<>".contains(c) {
+            let mut op = String::new();
+            while let Some(&o) = chars.peek().filter(|o| "=!<>".contains(**o)) {
+                op.push(o);
+                chars.next();
+            }
+            tokens.push(op);
+        } else {
+            let mut word = String::new();
+            while let Some(&w) = chars
+                .peek()
+                .filter(|w| !w.is_whitespace() && !"()\"'=!<>".contains(**w))
+            {
+                word.push(w);
+                chars.next();
+            }
+            tokens.push(word);
+        }
+    }
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<String>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&str> {
+        self.tokens.get(self.pos).map(String::as_str)
+    }
+
+    fn next(&mut self) -> Result<String, String> {
+        let token = self
+            .tokens
+            .get(self.pos)
+            .cloned()
+            .ok_or("unexpected end of the expression")?;
+        self.pos += 1;
+        Ok(token)
+    }
+
+    fn or(&mut self) -> Result<Expr, String> {
+        let mut expr = self.and()?;
+        while self.peek() == Some("or") {
+            self.pos += 1;
+            expr = Expr::Or(Box::new(expr), Box::new(self.and()?));
+        }
+        Ok(expr)
+    }
+
+    fn and(&mut self) -> Result<Expr, String> {
+        let mut expr = self.not()?;
+        while self.peek() == Some("and") {
+            self.pos += 1;
+            expr = Expr::And(Box::new(expr), Box::new(self.not()?));
+        }
+        Ok(expr)
+    }
+
+    fn not(&mut self) -> Result<Expr, String> {
+        if self.peek() == Some("not") {
+            self.pos += 1;
+            return Ok(Expr::Not(Box::new(self.not()?)));
+        }
+        self.atom()
+    }
+
+    fn atom(&mut self) -> Result<Expr, String> {
+        let token = self.next()?;
+        if token == "(" {
+            let expr = self.or()?;
+            return match self.next()?.as_str() {
+                ")" => Ok(expr),
+                other => Err(format!("expected ')', found '{}'", other)),
+            };
+        }
+        let (field, kind) = FIELDS
+            .iter()
+            .find(|(name, _)| *name == token)
+            .copied()
+            .ok_or_else(|| {
+                format!(
+                    "unknown field '{}': use one of {}",
+                    token,
+                    FIELDS.map(|(name, _)| name).join(", ")
+                )
+            })?;
+        let op_token = self.peek().unwrap_or_default().to_string();
+        let op = match op_token.as_str() {
+            "==" => Op::Eq,
+            "!=" => Op::Ne,
+            "<" => Op::Lt,
+            "<=" => Op::Le,
+            ">" => Op::Gt,
+            ">=" => Op::Ge,
+            "contains" => Op::Contains,
+            _ if kind == Kind::Bool => return Ok(Expr::Field(field)),
+            _ => return Err(format!("expected a comparison after '{}'", field)),
+        };
+        let supported = match kind {
+            Kind::Text => matches!(op, Op::Eq | Op::Ne | Op::Contains),
+            Kind::List => op == Op::Contains,
+            Kind::Bool => matches!(op, Op::Eq | Op::Ne),
+            Kind::Number | Kind::Time => op != Op::Contains,
+        };
+        if !supported {
+            return Err(format!("'{}' can't be compared with '{}'", field, op_token));
+        }
+        self.pos += 1;
+        Ok(Expr::Compare(field, op, self.literal(kind)?))
+    }
+
+    /// Parses the value compared with a field of the `kind`.
+    fn literal(&mut self, kind: Kind) -> Result<Literal, String> {
+        let token = self.next()?;
+        let literal = match kind {
+            Kind::Text | Kind::List => token.strip_prefix('"').map(|t| Literal::Text(t.into())),
+            Kind::Number => token.parse().ok().map(Literal::Number),
+            Kind::Bool => token.parse().ok().map(Literal::Bool),
+            Kind::Time if self.peek() == Some("ago") => {
+                self.pos += 1;
+                Some(Literal::Ago(parse_duration(&token)?))
+            }
+            Kind::Time => token
+                .parse::<DateTime<Utc>>()
+                .ok()
+                .or_else(|| {
+                    let date = token.parse::<NaiveDate>().ok()?;
+                    Some(date.and_hms_opt(0, 0, 0)?.and_utc())
+                })
+                .map(Literal::Time),
+        };
+        literal.ok_or_else(|| {
+            let expected = match kind {
+                Kind::Text | Kind::List => "quoted text",
+                Kind::Number => "a number",
+                Kind::Bool => "true or false",
+                Kind::Time => "a date like 2024-01-31 or a duration like 30d ago",
+            };
+            format!("expected {}, found '{}'", expected, token)
+        })
+    }
+}
+
+fn field(name: &str, entry: &EntryWithMetadata) -> Value {
+    let login = |user: &octocrab::models::Author| Some(user.login.clone());
+    let names =
+        |labels: &[octocrab::models::Label]| labels.iter().map(|l| l.name.clone()).collect();
+    match (name, entry) {
+        ("type", _) => Value::Text(Some(entry.type_name().to_string())),
+        ("number", _) => Value::Number(Some(entry.number())),
+        ("state", EntryWithMetadata::Issue(i)) => Value::Text(
+            serde_json::to_value(&i.issue.state)
+                .ok()
+                .and_then(|s| s.as_str().map(str::to_string)),
+        ),
+        ("state", EntryWithMetadata::Pull(p)) => Value::Text(
+            serde_json::to_value(&p.pull.state)
+                .ok()
+                .and_then(|s| s.as_str().map(str::to_string)),
+        ),
+        ("merged", EntryWithMetadata::Issue(_)) => Value::Bool(false),
+        ("merged", EntryWithMetadata::Pull(p)) => Value::Bool(p.pull.merged_at.is_some()),
+        ("comments", EntryWithMetadata::Issue(i)) => Value::Number(Some(i.issue.comments as u64)),
+        ("comments", EntryWithMetadata::Pull(p)) => {
+            Value::Number(match (p.pull.comments, p.pull.review_comments) {
+                (None, None) => None,
+                (comments, review_comments) => {
+                    Some(comments.unwrap_or_default() + review_comments.unwrap_or_default())
+                }
+            })
+        }
+        ("author", EntryWithMetadata::Issue(i)) => Value::Text(login(&i.issue.user)),
+        ("author", EntryWithMetadata::Pull(p)) => {
+            Value::Text(p.pull.user.as_deref().and_then(login))
+        }
+        ("title", EntryWithMetadata::Issue(i)) => Value::Text(Some(i.issue.title.clone())),
+        ("title", EntryWithMetadata::Pull(p)) => Value::Text(p.pull.title.clone()),
+        ("labels", EntryWithMetadata::Issue(i)) => Value::List(names(&i.issue.labels)),
+        ("labels", EntryWithMetadata::Pull(p)) => {
+            Value::List(p.pull.labels.as_deref().map(names).unwrap_or_default())
+        }
+        ("created", EntryWithMetadata::Issue(i)) => Value::Time(Some(i.issue.created_at)),
+        ("created", EntryWithMetadata::Pull(p)) => Value::Time(p.pull.created_at),
+        ("updated", EntryWithMetadata::Issue(i)) => Value::Time(Some(i.issue.updated_at)),
+        ("updated", EntryWithMetadata::Pull(p)) => Value::Time(p.pull.updated_at),
+        ("closed", EntryWithMetadata::Issue(i)) => Value::Time(i.issue.closed_at),
+        ("closed", EntryWithMetadata::Pull(p)) => Value::Time(p.pull.closed_at),
+        _ => unreachable!("fields are checked when parsing"),
+    }
+}
+
+fn compare<T: PartialOrd>(a: &T, op: Op, b: &T) -> bool {
+    match op {
+        Op::Eq => a == b,
+        Op::Ne => a != b,
+        Op::Lt => a < b,
+        Op::Le => a <= b,
+        Op::Gt => a > b,
+        Op::Ge => a >= b,
+        Op::Contains => false,
+    }
+}
+
+fn eval(expr: &Expr, entry: &EntryWithMetadata, now: DateTime<Utc>) -> bool {
+    match expr {
+        Expr::Or(a, b) => eval(a, entry, now) || eval(b, entry, now),
+        Expr::And(a, b) => eval(a, entry, now) && eval(b, entry, now),
+        Expr::Not(a) => !eval(a, entry, now),
+        Expr::Field(name) => matches!(field(name, entry), Value::Bool(true)),
+        Expr::Compare(name, op, literal) => match (field(name, entry), literal) {
+            (Value::Text(Some(text)), Literal::Text(s)) if *op == Op::Contains => {
+                text.contains(s.as_str())
+            }
+            (Value::Text(Some(text)), Literal::Text(s)) => {
+                compare(&text.as_str(), *op, &s.as_str())
+            }
+            (Value::Number(Some(n)), Literal::Number(m)) => compare(&n, *op, m),
+            (Value::Bool(b), Literal::Bool(c)) => compare(&b, *op, c),
+            (Value::Time(Some(t)), Literal::Time(u)) => compare(&t, *op, u),
+            (Value::Time(Some(t)), Literal::Ago(ago)) => match chrono::Duration::from_std(*ago) {
+                Ok(ago) => compare(&t, *op, &(now - ago)),
+                Err(_) => false,
+            },
+            (Value::List(items), Literal::Text(s)) => items.iter().any(|item| item == s),
+            _ => false,
+        },
+    }
+}
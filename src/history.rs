@@ -0,0 +1,1204 @@
+use chrono::{DateTime, Utc};
+use serde_json::Value;
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::types::{
+    AssignmentEvent, CommitReference, CrossReference, DeploymentEvent, DraftEvent, LabelEvent,
+    LinkedEntry, LockEvent, MilestoneEvent, MinimizedComment, ModerationEvent, PinEvent,
+    ProjectEvent, RefEvent, ReferencingEntry, RepositoryRef, Review, ShaChange, TransferEvent,
+};
+
+/// Timeline event types of the classic project boards.
+const PROJECT_EVENTS: [&str; 4] = [
+    "added_to_project",
+    "moved_columns_in_project",
+    "removed_from_project",
+    "converted_note_to_issue",
+];
+
+/// Timeline event types of moderators acting on an issue or pull-request.
+const MODERATION_EVENTS: [&str; 4] = [
+    "user_blocked",
+    "comment_deleted",
+    "marked_as_duplicate",
+    "unmarked_as_duplicate",
+];
+
+/// GraphQL query for the issues and pull-requests linked to an issue or
+/// pull-request. Links are made either manually (`ConnectedEvent` and
+/// `DisconnectedEvent` in the timeline) or with closing keywords (the closing
+/// references). The link events are paginated with `$after`, while the
+/// closing references come with each page. `$first` is the
+/// `--graphql-page-size`.
+pub const LINKS_QUERY: &str = r#"
+query($owner: String!, $repo: String!, $number: Int!, $first: Int!, $after: String) {
+  repository(owner: $owner, name: $repo) {
+    issueOrPullRequest(number: $number) {
+      ... on Issue {
+        closedByPullRequestsReferences(first: 100, includeClosedPrs: true) {
+          nodes { ...LinkedPullRequest }
+        }
+        timelineItems(first: $first, after: $after, itemTypes: [CONNECTED_EVENT, DISCONNECTED_EVENT]) {
+          pageInfo { hasNextPage endCursor }
+          nodes {
+            __typename
+            ... on ConnectedEvent { subject { ...LinkedIssue ...LinkedPullRequest } }
+            ... on DisconnectedEvent { subject { ...LinkedIssue ...LinkedPullRequest } }
+          }
+        }
+      }
+      ... on PullRequest {
+        closingIssuesReferences(first: 100) {
+          nodes { ...LinkedIssue }
+        }
+        timelineItems(first: $first, after: $after, itemTypes: [CONNECTED_EVENT, DISCONNECTED_EVENT]) {
+          pageInfo { hasNextPage endCursor }
+          nodes {
+            __typename
+            ... on ConnectedEvent { subject { ...LinkedIssue ...LinkedPullRequest } }
+            ... on DisconnectedEvent { subject { ...LinkedIssue ...LinkedPullRequest } }
+          }
+        }
+      }
+    }
+  }
+}
+
+fragment LinkedIssue on Issue {
+  __typename
+  number
+  repository { nameWithOwner }
+}
+
+fragment LinkedPullRequest on PullRequest {
+  __typename
+  number
+  repository { nameWithOwner }
+}
+"#;
+
+/// GraphQL query for a page of the `CrossReferencedEvent`s of an issue or
+/// pull-request, with the referencing entries. Unlike the REST timeline,
+/// the referencing entries are resolved even if they are in another
+/// repository. `$first` is the `--graphql-page-size`.
+pub const CROSS_REFS_QUERY: &str = r#"
+query($owner: String!, $repo: String!, $number: Int!, $first: Int!, $after: String) {
+  repository(owner: $owner, name: $repo) {
+    issueOrPullRequest(number: $number) {
+      ... on Issue {
+        timelineItems(first: $first, after: $after, itemTypes: [CROSS_REFERENCED_EVENT]) {
+          pageInfo { hasNextPage endCursor }
+          nodes { ...CrossReference }
+        }
+      }
+      ... on PullRequest {
+        timelineItems(first: $first, after: $after, itemTypes: [CROSS_REFERENCED_EVENT]) {
+          pageInfo { hasNextPage endCursor }
+          nodes { ...CrossReference }
+        }
+      }
+    }
+  }
+}
+
+fragment CrossReference on CrossReferencedEvent {
+  referencedAt
+  isCrossRepository
+  willCloseTarget
+  actor { login }
+  source {
+    __typename
+    ... on Issue {
+      number
+      title
+      state
+      url
+      createdAt
+      author { login }
+      repository { nameWithOwner }
+    }
+    ... on PullRequest {
+      number
+      title
+      state
+      url
+      createdAt
+      author { login }
+      repository { nameWithOwner }
+    }
+  }
+}
+"#;
+
+/// Returns the cross-references in a response to the [CROSS_REFS_QUERY] and
+/// the cursor of the next page, if any. References from entries the token
+/// can't see are left out.
+pub fn cross_references(data: &Value) -> (Vec<CrossReference>, Option<String>) {
+    let timeline = &data["repository"]["issueOrPullRequest"]["timelineItems"];
+    let string = |value: &Value| value.as_str().map(str::to_string);
+    let references = timeline["nodes"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|event| {
+            let source = &event["source"];
+            let r#type = match source["__typename"].as_str()? {
+                "PullRequest" => "pull",
+                _ => "issue",
+            };
+            Some(CrossReference {
+                referenced_at: string(&event["referencedAt"]).and_then(|t| t.parse().ok()),
+                actor: string(&event["actor"]["login"]),
+                cross_repository: event["isCrossRepository"].as_bool() == Some(true),
+                will_close_target: event["willCloseTarget"].as_bool() == Some(true),
+                source: ReferencingEntry {
+                    r#type: r#type.to_string(),
+                    repository: string(&source["repository"]["nameWithOwner"])?,
+                    number: source["number"].as_u64()?,
+                    title: string(&source["title"]),
+                    state: string(&source["state"]),
+                    url: string(&source["url"]),
+                    author: string(&source["author"]["login"]),
+                    created_at: string(&source["createdAt"]).and_then(|t| t.parse().ok()),
+                },
+            })
+        })
+        .collect();
+    let next = match timeline["pageInfo"]["hasNextPage"].as_bool() {
+        Some(true) => string(&timeline["pageInfo"]["endCursor"]),
+        _ => None,
+    };
+    (references, next)
+}
+
+/// GraphQL query for a page of the comments on the conversation of an issue
+/// or pull-request with their minimized state. `$first` is the
+/// `--graphql-page-size`.
+pub const COMMENT_MODERATION_QUERY: &str = r#"
+query($owner: String!, $repo: String!, $number: Int!, $first: Int!, $after: String) {
+  repository(owner: $owner, name: $repo) {
+    issueOrPullRequest(number: $number) {
+      ... on Issue {
+        comments(first: $first, after: $after) {
+          pageInfo { hasNextPage endCursor }
+          nodes { databaseId isMinimized minimizedReason }
+        }
+      }
+      ... on PullRequest {
+        comments(first: $first, after: $after) {
+          pageInfo { hasNextPage endCursor }
+          nodes { databaseId isMinimized minimizedReason }
+        }
+      }
+    }
+  }
+}
+"#;
+
+/// GraphQL query for a page of the review threads of a pull-request with the
+/// minimized state of their comments. `$first` is the `--graphql-page-size`.
+/// Only the first 100 comments of each thread are included.
+pub const REVIEW_COMMENT_MODERATION_QUERY: &str = r#"
+query($owner: String!, $repo: String!, $number: Int!, $first: Int!, $after: String) {
+  repository(owner: $owner, name: $repo) {
+    issueOrPullRequest(number: $number) {
+      ... on PullRequest {
+        reviewThreads(first: $first, after: $after) {
+          pageInfo { hasNextPage endCursor }
+          nodes {
+            comments(first: 100) {
+              pageInfo { hasNextPage }
+              nodes { databaseId isMinimized minimizedReason }
+            }
+          }
+        }
+      }
+    }
+  }
+}
+"#;
+
+/// The minimized comments in a response to the [COMMENT_MODERATION_QUERY] or
+/// the [REVIEW_COMMENT_MODERATION_QUERY].
+#[derive(Debug, Default)]
+pub struct ModerationPage {
+    pub minimized: Vec<MinimizedComment>,
+    /// Number of comments without an `isMinimized`, e.g. because the token
+    /// can't see it.
+    pub unknown: usize,
+    /// Set if a review thread has more comments than were fetched.
+    pub truncated: bool,
+    /// The cursor of the next page, if any.
+    pub next: Option<String>,
+}
+
+/// Returns the minimized comments in a response to the
+/// [COMMENT_MODERATION_QUERY] (`review` false) or the
+/// [REVIEW_COMMENT_MODERATION_QUERY] (`review` true).
+pub fn minimized_comments(data: &Value, review: bool) -> ModerationPage {
+    let entry = &data["repository"]["issueOrPullRequest"];
+    let connection = match review {
+        true => &entry["reviewThreads"],
+        false => &entry["comments"],
+    };
+    let mut page = ModerationPage::default();
+    let nodes = connection["nodes"].as_array().into_iter().flatten();
+    let comments: Vec<&Value> = match review {
+        true => nodes
+            .flat_map(|thread| {
+                if thread["comments"]["pageInfo"]["hasNextPage"].as_bool() == Some(true) {
+                    page.truncated = true;
+                }
+                thread["comments"]["nodes"].as_array().into_iter().flatten()
+            })
+            .collect(),
+        false => nodes.collect(),
+    };
+    for comment in comments {
+        match comment["isMinimized"].as_bool() {
+            Some(true) => page.minimized.push(MinimizedComment {
+                id: comment["databaseId"].as_u64(),
+                kind: match review {
+                    true => "review_comment",
+                    false => "comment",
+                }
+                .to_string(),
+                minimized_reason: comment["minimizedReason"].as_str().map(str::to_lowercase),
+            }),
+            Some(false) => (),
+            None => page.unknown += 1,
+        }
+    }
+    page.next = match connection["pageInfo"]["hasNextPage"].as_bool() {
+        Some(true) => connection["pageInfo"]["endCursor"]
+            .as_str()
+            .map(str::to_string),
+        _ => None,
+    };
+    page
+}
+
+/// The issues and pull-requests linked to an entry.
+#[derive(Debug, Default)]
+pub struct Links {
+    pub issues: Vec<LinkedEntry>,
+    pub pulls: Vec<LinkedEntry>,
+}
+
+fn linked_entry(node: &Value) -> Option<(bool, LinkedEntry)> {
+    let is_pull = node["__typename"].as_str()? == "PullRequest";
+    let entry = LinkedEntry {
+        repository: node["repository"]["nameWithOwner"].as_str()?.to_string(),
+        number: node["number"].as_u64()?,
+    };
+    Some((is_pull, entry))
+}
+
+/// The cursor of the page of link events after the response `data` to the
+/// [LINKS_QUERY], if there is one.
+pub fn links_next_page(data: &Value) -> Option<String> {
+    let page_info = &data["repository"]["issueOrPullRequest"]["timelineItems"]["pageInfo"];
+    match page_info["hasNextPage"].as_bool() {
+        Some(true) => page_info["endCursor"].as_str().map(str::to_string),
+        _ => None,
+    }
+}
+
+/// Derives the currently linked issues and pull-requests from the responses
+/// to the [LINKS_QUERY], one per page of link events. The connect and
+/// disconnect events are replayed in order, and the entries referenced with
+/// closing keywords are added.
+pub fn links(pages: &[Value]) -> Links {
+    fn entry(data: &Value) -> &Value {
+        &data["repository"]["issueOrPullRequest"]
+    }
+
+    let mut linked = BTreeSet::new();
+    let events = pages
+        .iter()
+        .flat_map(|data| entry(data)["timelineItems"]["nodes"].as_array())
+        .flatten();
+    for event in events {
+        if let Some(subject) = linked_entry(&event["subject"]) {
+            match event["__typename"].as_str() {
+                Some("ConnectedEvent") => {
+                    linked.insert(subject);
+                }
+                Some("DisconnectedEvent") => {
+                    linked.remove(&subject);
+                }
+                _ => (),
+            }
+        }
+    }
+
+    if let Some(first) = pages.first() {
+        let closing = entry(first)["closingIssuesReferences"]["nodes"]
+            .as_array()
+            .or(entry(first)["closedByPullRequestsReferences"]["nodes"].as_array());
+        linked.extend(closing.into_iter().flatten().filter_map(linked_entry));
+    }
+
+    let mut links = Links::default();
+    for (is_pull, entry) in linked {
+        if is_pull {
+            links.pulls.push(entry);
+        } else {
+            links.issues.push(entry);
+        }
+    }
+    links
+}
+
+/// Derives the classic project board history from the raw timeline `events`.
+/// The raw events are used, so the `project_card` is kept as returned by the
+/// API and events filtered out by `--event-types` are included.
+pub fn project_history(events: &[Value]) -> Vec<ProjectEvent> {
+    events
+        .iter()
+        .filter(|event| {
+            event["event"]
+                .as_str()
+                .is_some_and(|name| PROJECT_EVENTS.contains(&name))
+        })
+        .map(|event| {
+            let card = &event["project_card"];
+            let string = |value: &Value| value.as_str().map(str::to_string);
+            ProjectEvent {
+                event: event["event"].as_str().unwrap_or_default().to_string(),
+                created_at: string(&event["created_at"]).and_then(|t| t.parse().ok()),
+                actor: string(&event["actor"]["login"]),
+                project_id: card["project_id"].as_u64(),
+                project_url: string(&card["project_url"]),
+                column_name: string(&card["column_name"]),
+                previous_column_name: string(&card["previous_column_name"]),
+                project_card: Some(card.clone()).filter(|card| !card.is_null()),
+            }
+        })
+        .collect()
+}
+
+/// Derives who assigned or unassigned whom from the raw timeline `events`.
+/// The `actor`, `assignee` and `assigner` are taken from the raw events, so
+/// they are kept apart even if an event can't be fully deserialized.
+pub fn assignment_history(events: &[Value]) -> Vec<AssignmentEvent> {
+    let login = |user: &Value| user["login"].as_str().map(str::to_string);
+    events
+        .iter()
+        .filter(|event| matches!(event["event"].as_str(), Some("assigned" | "unassigned")))
+        .map(|event| {
+            let actor = login(&event["actor"]);
+            let assignee = login(&event["assignee"]);
+            AssignmentEvent {
+                event: event["event"].as_str().unwrap_or_default().to_string(),
+                created_at: event["created_at"].as_str().and_then(|t| t.parse().ok()),
+                self_assigned: actor.is_some() && actor == assignee,
+                actor,
+                assignee,
+                assigner: login(&event["assigner"]),
+            }
+        })
+        .collect()
+}
+
+/// Derives the milestone changes from the raw timeline `events`. The raw
+/// events are used, so events filtered out by `--event-types` are included.
+pub fn milestone_history(events: &[Value]) -> Vec<MilestoneEvent> {
+    events
+        .iter()
+        .filter(|event| matches!(event["event"].as_str(), Some("milestoned" | "demilestoned")))
+        .map(|event| MilestoneEvent {
+            event: event["event"].as_str().unwrap_or_default().to_string(),
+            created_at: event["created_at"].as_str().and_then(|t| t.parse().ok()),
+            actor: event["actor"]["login"].as_str().map(str::to_string),
+            title: event["milestone"]["title"].as_str().map(str::to_string),
+            number: None,
+        })
+        .collect()
+}
+
+/// Collects the `performed_via_github_app` of the raw timeline `events` and
+/// review `comments` that were performed via a GitHub App, keyed by their
+/// `node_id`. octocrab's models drop the app, and events filtered out by
+/// `--event-types` are included.
+pub fn github_apps(events: &[Value], comments: &[Value]) -> BTreeMap<String, Value> {
+    events
+        .iter()
+        .chain(comments)
+        .filter_map(|item| {
+            let app = &item["performed_via_github_app"];
+            match (item["node_id"].as_str(), app.is_object()) {
+                (Some(node_id), true) => Some((node_id.to_string(), app.clone())),
+                _ => None,
+            }
+        })
+        .collect()
+}
+
+/// Returns the `actor` of the latest `closed` timeline event in the raw
+/// `events`, i.e. who closed the entry last. Events filtered out by
+/// `--event-types` are included.
+pub fn closed_by(events: &[Value]) -> Option<Value> {
+    events
+        .iter()
+        .rev()
+        .find(|event| event["event"] == "closed")
+        .map(|event| event["actor"].clone())
+        .filter(Value::is_object)
+}
+
+/// Derives the label history from the raw `labeled` and `unlabeled` timeline
+/// `events`. The label of an event has the name and color it had at the
+/// time, unlike the current `labels` of the entry. The raw events are kept
+/// with `keep_raw`, and events filtered out by `--event-types` are included.
+pub fn label_history(events: &[Value], keep_raw: bool) -> Vec<LabelEvent> {
+    let string = |value: &Value| value.as_str().map(str::to_string);
+    events
+        .iter()
+        .filter(|event| matches!(event["event"].as_str(), Some("labeled" | "unlabeled")))
+        .map(|event| LabelEvent {
+            event: event["event"].as_str().unwrap_or_default().to_string(),
+            created_at: string(&event["created_at"]).and_then(|t| t.parse().ok()),
+            actor: string(&event["actor"]["login"]),
+            name: string(&event["label"]["name"]),
+            color: string(&event["label"]["color"]),
+            raw: keep_raw.then(|| event.clone()),
+        })
+        .collect()
+}
+
+/// Derives the moderation history from the raw timeline `events`. The raw
+/// events are kept with `keep_raw`, so fields octocrab's model doesn't know
+/// are preserved, and events filtered out by `--event-types` are included.
+pub fn moderation_history(events: &[Value], keep_raw: bool) -> Vec<ModerationEvent> {
+    events
+        .iter()
+        .filter(|event| {
+            event["event"]
+                .as_str()
+                .is_some_and(|name| MODERATION_EVENTS.contains(&name))
+        })
+        .map(|event| ModerationEvent {
+            event: event["event"].as_str().unwrap_or_default().to_string(),
+            created_at: event["created_at"].as_str().and_then(|t| t.parse().ok()),
+            actor: event["actor"]["login"].as_str().map(str::to_string),
+            raw: keep_raw.then(|| event.clone()),
+        })
+        .collect()
+}
+
+/// GraphQL query for the `TransferredEvent`s of an issue with the repository
+/// it was transferred from, which the REST timeline doesn't include. The
+/// `fromRepository` is null if the token can't see it.
+pub const TRANSFERS_QUERY: &str = r#"
+query($owner: String!, $repo: String!, $number: Int!) {
+  repository(owner: $owner, name: $repo) {
+    issue(number: $number) {
+      timelineItems(first: 100, itemTypes: [TRANSFERRED_EVENT]) {
+        nodes {
+          ... on TransferredEvent {
+            createdAt
+            fromRepository { nameWithOwner }
+          }
+        }
+      }
+    }
+  }
+}
+"#;
+
+/// Derives the transfers of an issue from the raw `transferred` timeline
+/// `events`. The repository it was transferred from is taken from the
+/// response to the [TRANSFERS_QUERY] (`data`) by the time of the transfer,
+/// if any. The repository it was transferred to is the one it was
+/// transferred from next, and for the last transfer the backed up
+/// `repository`.
+pub fn transfer_history(
+    events: &[Value],
+    data: Option<&Value>,
+    repository: &str,
+    keep_raw: bool,
+) -> Vec<TransferEvent> {
+    let resolved: Vec<&Value> = data
+        .map(|data| &data["repository"]["issue"]["timelineItems"]["nodes"])
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+        .collect();
+    let time = |value: &Value| value.as_str().and_then(|t| t.parse::<DateTime<Utc>>().ok());
+    let mut transfers: Vec<TransferEvent> = events
+        .iter()
+        .filter(|event| event["event"].as_str() == Some("transferred"))
+        .map(|event| {
+            let created_at = time(&event["created_at"]);
+            let from_repository = resolved
+                .iter()
+                .find(|node| time(&node["createdAt"]) == created_at)
+                .and_then(|node| node["fromRepository"]["nameWithOwner"].as_str())
+                .map(str::to_string);
+            TransferEvent {
+                created_at,
+                actor: event["actor"]["login"].as_str().map(str::to_string),
+                from_repository,
+                to_repository: None,
+                raw: keep_raw.then(|| event.clone()),
+            }
+        })
+        .collect();
+    let mut to_repository = Some(repository.to_string());
+    for transfer in transfers.iter_mut().rev() {
+        transfer.to_repository = to_repository;
+        to_repository = transfer.from_repository.clone();
+    }
+    transfers
+}
+
+/// Derives the commits that mentioned an issue or pull-request from the raw
+/// `referenced` timeline `events`. The repository of the commit is taken from
+/// the `commit_url`, e.g.
+/// `https://api.github.com/repos/<owner>/<repo>/commits/<sha>`. The messages
+/// are only fetched with `--resolve-referencing-commits`.
+pub fn commit_references(events: &[Value], keep_raw: bool) -> Vec<CommitReference> {
+    events
+        .iter()
+        .filter(|event| event["event"].as_str() == Some("referenced"))
+        .map(|event| {
+            let repository = event["commit_url"].as_str().and_then(|url| {
+                let (_, path) = url.split_once("/repos/")?;
+                let mut path = path.split('/');
+                Some(RepositoryRef {
+                    owner: path.next()?.to_string(),
+                    name: path.next()?.to_string(),
+                })
+            });
+            CommitReference {
+                created_at: event["created_at"].as_str().and_then(|t| t.parse().ok()),
+                actor: event["actor"]["login"].as_str().map(str::to_string),
+                commit_id: event["commit_id"].as_str().map(str::to_string),
+                repository,
+                message: None,
+                raw: keep_raw.then(|| event.clone()),
+            }
+        })
+        .collect()
+}
+
+/// Derives the deployments of a pull-request from the raw `deployed` and
+/// `deployment_environment_changed` timeline `events`, as octocrab's model
+/// drops their deployment and environment. The deployment id is taken from
+/// the `deployment_url` if the event has no `deployment_id`.
+pub fn deployment_history(events: &[Value], keep_raw: bool) -> Vec<DeploymentEvent> {
+    let string = |value: &Value| value.as_str().map(str::to_string);
+    events
+        .iter()
+        .filter(|event| {
+            matches!(
+                event["event"].as_str(),
+                Some("deployed" | "deployment_environment_changed")
+            )
+        })
+        .map(|event| {
+            let deployment = &event["deployment"];
+            DeploymentEvent {
+                event: event["event"].as_str().unwrap_or_default().to_string(),
+                created_at: string(&event["created_at"]).and_then(|t| t.parse().ok()),
+                actor: string(&event["actor"]["login"]),
+                commit_id: string(&event["commit_id"]).or_else(|| string(&deployment["sha"])),
+                deployment_id: event["deployment_id"]
+                    .as_u64()
+                    .or_else(|| deployment["id"].as_u64())
+                    .or_else(|| {
+                        event["deployment_url"]
+                            .as_str()?
+                            .rsplit('/')
+                            .next()?
+                            .parse()
+                            .ok()
+                    }),
+                environment: string(&event["environment"])
+                    .or_else(|| string(&deployment["environment"])),
+                raw: keep_raw.then(|| event.clone()),
+            }
+        })
+        .collect()
+}
+
+/// Derives the branch history of a pull-request from the raw
+/// `head_ref_deleted`, `head_ref_restored` and `base_ref_changed` timeline
+/// `events`. The REST API doesn't report the branch names of these events, so
+/// the head events get the `head_ref` of the pull-request and the last base
+/// change its current `base_ref`. Names reported by an event take precedence.
+pub fn ref_history(
+    events: &[Value],
+    head_ref: &str,
+    base_ref: &str,
+    keep_raw: bool,
+) -> Vec<RefEvent> {
+    let string = |value: &Value| value.as_str().map(str::to_string);
+    let last_base_change = events
+        .iter()
+        .rposition(|event| event["event"].as_str() == Some("base_ref_changed"));
+    events
+        .iter()
+        .enumerate()
+        .filter_map(|(i, event)| {
+            let name = match event["event"].as_str()? {
+                "head_ref_deleted" | "head_ref_restored" => Some(head_ref),
+                "base_ref_changed" => (Some(i) == last_base_change).then_some(base_ref),
+                _ => return None,
+            };
+            Some(RefEvent {
+                event: event["event"].as_str()?.to_string(),
+                created_at: string(&event["created_at"]).and_then(|t| t.parse().ok()),
+                actor: string(&event["actor"]["login"]),
+                ref_name: string(&event["current_ref_name"])
+                    .or_else(|| string(&event["ref"]))
+                    .or_else(|| name.map(str::to_string)),
+                previous_ref_name: string(&event["previous_ref_name"]),
+                raw: keep_raw.then(|| event.clone()),
+            })
+        })
+        .collect()
+}
+
+/// Derives the draft history of a pull-request from the raw `convert_to_draft`
+/// and `ready_for_review` timeline `events`, including those filtered out by
+/// `--event-types`. A pull-request opened as a draft starts with a
+/// `ready_for_review` event, as opening it has no event of its own.
+pub fn draft_history(events: &[Value], keep_raw: bool) -> Vec<DraftEvent> {
+    events
+        .iter()
+        .filter_map(|event| {
+            let draft = match event["event"].as_str()? {
+                "convert_to_draft" => true,
+                "ready_for_review" => false,
+                _ => return None,
+            };
+            Some(DraftEvent {
+                event: event["event"].as_str()?.to_string(),
+                created_at: event["created_at"].as_str().and_then(|t| t.parse().ok()),
+                actor: event["actor"]["login"].as_str().map(str::to_string),
+                draft,
+                raw: keep_raw.then(|| event.clone()),
+            })
+        })
+        .collect()
+}
+
+/// Derives the lock history from the raw `locked` and `unlocked` timeline
+/// `events`, including those filtered out by `--event-types`.
+pub fn lock_history(events: &[Value], keep_raw: bool) -> Vec<LockEvent> {
+    events
+        .iter()
+        .filter_map(|event| {
+            let locked = match event["event"].as_str()? {
+                "locked" => true,
+                "unlocked" => false,
+                _ => return None,
+            };
+            Some(LockEvent {
+                event: event["event"].as_str()?.to_string(),
+                created_at: event["created_at"].as_str().and_then(|t| t.parse().ok()),
+                actor: event["actor"]["login"].as_str().map(str::to_string),
+                locked,
+                lock_reason: event["lock_reason"].as_str().map(str::to_string),
+                raw: keep_raw.then(|| event.clone()),
+            })
+        })
+        .collect()
+}
+
+/// Derives the pin history of an issue from the raw `pinned` and `unpinned`
+/// timeline `events`, including those filtered out by `--event-types`.
+pub fn pin_history(events: &[Value], keep_raw: bool) -> Vec<PinEvent> {
+    events
+        .iter()
+        .filter_map(|event| {
+            let pinned = match event["event"].as_str()? {
+                "pinned" => true,
+                "unpinned" => false,
+                _ => return None,
+            };
+            Some(PinEvent {
+                event: event["event"].as_str()?.to_string(),
+                created_at: event["created_at"].as_str().and_then(|t| t.parse().ok()),
+                actor: event["actor"]["login"].as_str().map(str::to_string),
+                pinned,
+                raw: keep_raw.then(|| event.clone()),
+            })
+        })
+        .collect()
+}
+
+/// Derives the commits of a pull-request from its current `base_sha` and
+/// `head_sha` and the raw `head_ref_force_pushed` timeline `events`. The raw
+/// events are used, as their `commit_id` isn't kept by every deserialized
+/// event, and events filtered out by `--event-types` are included.
+pub fn sha_history(events: &[Value], base_sha: &str, head_sha: &str) -> Vec<ShaChange> {
+    let current = |event: &str, sha: &str| ShaChange {
+        event: event.to_string(),
+        sha: Some(sha.to_string()),
+        created_at: None,
+        actor: None,
+    };
+    let mut history = vec![current("base", base_sha)];
+    history.extend(
+        events
+            .iter()
+            .filter(|event| event["event"].as_str() == Some("head_ref_force_pushed"))
+            .map(|event| ShaChange {
+                event: "head_ref_force_pushed".to_string(),
+                sha: event["commit_id"].as_str().map(str::to_string),
+                created_at: event["created_at"].as_str().and_then(|t| t.parse().ok()),
+                actor: event["actor"]["login"].as_str().map(str::to_string),
+            }),
+    );
+    history.push(current("head", head_sha));
+    history
+}
+
+/// Converts a raw review of the reviews API into a review without its
+/// comments. The `state` is kept in lower case, like in the timeline.
+fn review(raw: &Value) -> Review {
+    let string = |value: &Value| value.as_str().map(str::to_string);
+    Review {
+        id: raw["id"].as_u64(),
+        node_id: string(&raw["node_id"]),
+        state: raw["state"].as_str().map(str::to_lowercase),
+        body: string(&raw["body"]),
+        user: string(&raw["user"]["login"]),
+        submitted_at: raw["submitted_at"].as_str().and_then(|t| t.parse().ok()),
+        commit_id: string(&raw["commit_id"]),
+        html_url: string(&raw["html_url"]),
+        comment_ids: vec![],
+    }
+}
+
+/// Derives the reviews of a pull-request from the raw reviews of the reviews
+/// API, linking each to its raw review `comments`. The raw reviews are used,
+/// so the review body and state are kept even if a review can't be fully
+/// deserialized. Returns the ids of the reviews the comments refer to that
+/// aren't `listed`.
+pub fn reviews(listed: &[Value], comments: &[Value]) -> (Vec<Review>, Vec<u64>) {
+    link_review_comments(listed.iter().map(review).collect(), comments)
+}
+
+/// Adds the ids of the raw review `comments` to the `reviews` they were made
+/// in. Returns the ids of the reviews the comments refer to that aren't in
+/// `reviews`.
+fn link_review_comments(mut reviews: Vec<Review>, comments: &[Value]) -> (Vec<Review>, Vec<u64>) {
+    let mut unmatched = BTreeSet::new();
+    for comment in comments {
+        let (Some(id), Some(review_id)) = (
+            comment["id"].as_u64(),
+            comment["pull_request_review_id"].as_u64(),
+        ) else {
+            continue;
+        };
+        match reviews
+            .iter_mut()
+            .find(|review| review.id == Some(review_id))
+        {
+            Some(review) => review.comment_ids.push(id),
+            None => {
+                unmatched.insert(review_id);
+            }
+        }
+    }
+    (reviews, unmatched.into_iter().collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(repository: &str, number: u64) -> LinkedEntry {
+        LinkedEntry {
+            repository: repository.to_string(),
+            number,
+        }
+    }
+
+    #[test]
+    fn links_across_pages() {
+        let pages: Vec<Value> =
+            serde_json::from_str(include_str!("../tests/fixtures/links_pages.json")).unwrap();
+        assert_eq!(
+            links_next_page(&pages[0]).as_deref(),
+            Some("Y3Vyc29yOjEwMA==")
+        );
+        assert_eq!(links_next_page(&pages[1]), None);
+
+        // The issue connected on the first page is disconnected on the second.
+        let links = links(&pages);
+        assert_eq!(links.issues, vec![entry("octo-org/octo-repo", 4)]);
+        assert_eq!(
+            links.pulls,
+            vec![
+                entry("octo-org/octo-repo", 7),
+                entry("octo-org/other-repo", 5)
+            ]
+        );
+    }
+
+    #[test]
+    fn reviews_with_their_comments() {
+        let listed: Vec<Value> =
+            serde_json::from_str(include_str!("../tests/fixtures/reviews.json")).unwrap();
+        let pages: Vec<Vec<Value>> = serde_json::from_str(include_str!(
+            "../tests/fixtures/review_comments_duplicated.json"
+        ))
+        .unwrap();
+        let mut comments: Vec<Value> = pages.into_iter().flatten().collect();
+        // A comment of a review that isn't listed.
+        comments.last_mut().unwrap()["pull_request_review_id"] = 43.into();
+
+        let (reviews, unmatched) = reviews(&listed, &comments);
+        assert_eq!(reviews.len(), 2);
+        assert_eq!(reviews[0].state.as_deref(), Some("commented"));
+        assert_eq!(reviews[0].body.as_deref(), Some("Some nits."));
+        assert_eq!(reviews[0].user.as_deref(), Some("octocat"));
+        assert_eq!(reviews[0].comment_ids, vec![10, 11, 11]);
+        assert_eq!(reviews[1].state.as_deref(), Some("approved"));
+        assert!(reviews[1].comment_ids.is_empty());
+        assert_eq!(unmatched, vec![43]);
+    }
+
+    /// The raw timeline events of the `fixture`, checked to deserialize as
+    /// timeline events as well.
+    fn timeline(fixture: &str) -> Vec<Value> {
+        let events: Vec<Value> = serde_json::from_str(fixture).unwrap();
+        for event in &events {
+            serde_json::from_value::<octocrab::models::timelines::TimelineEvent>(event.clone())
+                .unwrap();
+        }
+        events
+    }
+
+    #[test]
+    fn assignments() {
+        let events = timeline(include_str!("../tests/fixtures/timeline_assignments.json"));
+        let history: Vec<_> = assignment_history(&events)
+            .into_iter()
+            .map(|event| {
+                (
+                    event.event,
+                    event.actor.unwrap(),
+                    event.assignee.unwrap(),
+                    event.self_assigned,
+                )
+            })
+            .collect();
+        let expected = [
+            ("assigned", "octocat", "octocat", true),
+            ("assigned", "hubot", "monalisa", false),
+            ("unassigned", "monalisa", "monalisa", true),
+            ("unassigned", "hubot", "octocat", false),
+        ];
+        assert_eq!(
+            history,
+            expected.map(|(event, actor, assignee, self_assigned)| (
+                event.to_string(),
+                actor.to_string(),
+                assignee.to_string(),
+                self_assigned
+            ))
+        );
+    }
+
+    #[test]
+    fn raw_events_are_opt_in() {
+        let events = timeline(include_str!("../tests/fixtures/timeline_assignments.json"));
+        let history = label_history(&events, false);
+        assert!(history[0].raw.is_none());
+        assert!(serde_json::to_value(&history[0])
+            .unwrap()
+            .get("raw")
+            .is_none());
+        let history = label_history(&events, true);
+        assert_eq!(history[0].raw.as_ref(), Some(&events[2]));
+    }
+
+    #[test]
+    fn labels_in_order_with_their_color_at_the_time() {
+        let events = timeline(include_str!("../tests/fixtures/timeline_labels.json"));
+        let history: Vec<_> = label_history(&events, false)
+            .into_iter()
+            .map(|event| {
+                (
+                    event.event,
+                    event.actor.unwrap(),
+                    event.name.unwrap(),
+                    event.color.unwrap(),
+                )
+            })
+            .collect();
+        let expected = [
+            ("labeled", "hubot", "bug", "d73a4a"),
+            ("labeled", "octocat", "enhancement", "a2eeef"),
+            ("unlabeled", "octocat", "bug", "d73a4a"),
+            ("labeled", "hubot", "bug", "ee0701"),
+        ];
+        assert_eq!(
+            history,
+            expected.map(|(event, actor, name, color)| (
+                event.to_string(),
+                actor.to_string(),
+                name.to_string(),
+                color.to_string()
+            ))
+        );
+    }
+
+    #[test]
+    fn app_performed_events() {
+        let events = timeline(include_str!("../tests/fixtures/timeline_app.json"));
+        let mut comments: Vec<Value> = serde_json::from_str::<Vec<Vec<Value>>>(include_str!(
+            "../tests/fixtures/review_comments_duplicated.json"
+        ))
+        .unwrap()
+        .concat();
+        comments[0]["performed_via_github_app"] = events[1]["performed_via_github_app"].clone();
+
+        let apps = github_apps(&events, &comments);
+        // The event of a person isn't listed.
+        assert_eq!(
+            apps.keys().collect::<Vec<_>>(),
+            ["EV_lADOA502", "IC_kwDOA503", "PRRC_kwDOA10"]
+        );
+        for app in apps.values() {
+            assert_eq!(app["slug"], "github-actions");
+            assert_eq!(app["permissions"]["issues"], "write");
+        }
+    }
+
+    #[test]
+    fn moderation_events() {
+        let events = timeline(include_str!("../tests/fixtures/timeline_moderation.json"));
+        let history: Vec<_> = moderation_history(&events, false)
+            .into_iter()
+            .map(|event| (event.event, event.actor.unwrap()))
+            .collect();
+        let expected = [
+            ("user_blocked", "octocat"),
+            ("comment_deleted", "octocat"),
+            ("marked_as_duplicate", "hubot"),
+            ("unmarked_as_duplicate", "octocat"),
+        ];
+        assert_eq!(
+            history,
+            expected.map(|(event, actor)| (event.to_string(), actor.to_string()))
+        );
+    }
+
+    #[test]
+    fn transfers_between_repositories() {
+        let events = timeline(include_str!("../tests/fixtures/timeline_transferred.json"));
+        let mut data: Value =
+            serde_json::from_str(include_str!("../tests/fixtures/transfers_query.json")).unwrap();
+        let repositories = |data: Option<&Value>| -> Vec<_> {
+            transfer_history(&events, data, "octo-org/octo-repo", false)
+                .into_iter()
+                .map(|transfer| {
+                    (
+                        transfer.actor.unwrap(),
+                        transfer.from_repository,
+                        transfer.to_repository,
+                    )
+                })
+                .collect()
+        };
+        let some = |repository: &str| Some(repository.to_string());
+
+        assert_eq!(
+            repositories(Some(&data)),
+            [
+                (
+                    "octocat".to_string(),
+                    some("octo-org/old-repo"),
+                    some("octo-org/staging-repo")
+                ),
+                (
+                    "hubot".to_string(),
+                    some("octo-org/staging-repo"),
+                    some("octo-org/octo-repo")
+                ),
+            ]
+        );
+
+        // The repository in between is private to the token.
+        data["repository"]["issue"]["timelineItems"]["nodes"][1]["fromRepository"] = Value::Null;
+        assert_eq!(
+            repositories(Some(&data)),
+            [
+                ("octocat".to_string(), some("octo-org/old-repo"), None),
+                ("hubot".to_string(), None, some("octo-org/octo-repo")),
+            ]
+        );
+
+        // The query failed.
+        assert_eq!(
+            repositories(None),
+            [
+                ("octocat".to_string(), None, None),
+                ("hubot".to_string(), None, some("octo-org/octo-repo")),
+            ]
+        );
+    }
+
+    #[test]
+    fn referencing_commits() {
+        let events = timeline(include_str!("../tests/fixtures/timeline_referenced.json"));
+        let references = commit_references(&events, false);
+        let commits: Vec<_> = references
+            .iter()
+            .map(|reference| {
+                let repository = reference.repository.as_ref().unwrap();
+                (
+                    reference.actor.as_deref().unwrap(),
+                    reference.commit_id.as_deref().unwrap(),
+                    format!("{}/{}", repository.owner, repository.name),
+                )
+            })
+            .collect();
+        assert_eq!(
+            commits,
+            [
+                (
+                    "octocat",
+                    "6dcb09b5b57875f334f61aebed695e2e4193db5e",
+                    "octo-org/octo-repo".to_string()
+                ),
+                (
+                    "hubot",
+                    "7638417db6d59f3c431d3e1f261cc637155684cd",
+                    "hubot/octo-repo".to_string()
+                ),
+            ]
+        );
+        // The messages are only resolved with --resolve-referencing-commits.
+        assert!(references
+            .iter()
+            .all(|reference| reference.message.is_none()));
+    }
+
+    #[test]
+    fn deployments() {
+        let events = timeline(include_str!("../tests/fixtures/timeline_deployments.json"));
+        let history: Vec<_> = deployment_history(&events, false)
+            .into_iter()
+            .map(|deployment| {
+                (
+                    deployment.event,
+                    deployment.deployment_id,
+                    deployment.commit_id,
+                    deployment.environment,
+                )
+            })
+            .collect();
+        let sha = Some("6dcb09b5b57875f334f61aebed695e2e4193db5e".to_string());
+        assert_eq!(
+            history,
+            [
+                ("deployed".to_string(), Some(1201), None, None),
+                (
+                    "deployed".to_string(),
+                    Some(1202),
+                    sha.clone(),
+                    Some("production".to_string())
+                ),
+                (
+                    "deployment_environment_changed".to_string(),
+                    Some(1202),
+                    sha,
+                    Some("staging".to_string())
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn ref_changes() {
+        let events = timeline(include_str!("../tests/fixtures/timeline_refs.json"));
+        let names = |events: &[Value]| -> Vec<_> {
+            ref_history(events, "feature", "master", false)
+                .into_iter()
+                .map(|change| (change.event, change.ref_name, change.previous_ref_name))
+                .collect()
+        };
+        let some = |name: &str| Some(name.to_string());
+
+        assert_eq!(
+            names(&events),
+            [
+                ("base_ref_changed".to_string(), None, None),
+                ("head_ref_deleted".to_string(), some("feature"), None),
+                ("head_ref_restored".to_string(), some("feature"), None),
+                (
+                    "base_ref_changed".to_string(),
+                    some("main"),
+                    some("develop")
+                ),
+            ]
+        );
+        // Without names reported by the event, the last base change gets the
+        // current base branch.
+        assert_eq!(
+            names(&events[..3]),
+            [
+                ("base_ref_changed".to_string(), some("master"), None),
+                ("head_ref_deleted".to_string(), some("feature"), None),
+                ("head_ref_restored".to_string(), some("feature"), None),
+            ]
+        );
+    }
+
+    #[test]
+    fn draft_changes() {
+        let events = timeline(include_str!("../tests/fixtures/timeline_draft.json"));
+        let history = draft_history(&events, false);
+        let changes: Vec<_> = history
+            .iter()
+            .map(|change| (change.event.as_str(), change.actor.as_deref(), change.draft))
+            .collect();
+        assert_eq!(
+            changes,
+            [
+                ("ready_for_review", Some("octocat"), false),
+                ("convert_to_draft", Some("hubot"), true),
+                ("ready_for_review", Some("octocat"), false),
+            ]
+        );
+        // The time the pull-request sat in draft after it was converted.
+        let in_draft = history[2].created_at.unwrap() - history[1].created_at.unwrap();
+        assert_eq!(in_draft.num_minutes(), 2 * 24 * 60 + 6 * 60 + 30);
+    }
+
+    #[test]
+    fn lock_and_pin_changes() {
+        let events = timeline(include_str!("../tests/fixtures/timeline_lock_pin.json"));
+        let locks: Vec<_> = lock_history(&events, false)
+            .into_iter()
+            .map(|lock| (lock.event, lock.locked, lock.lock_reason))
+            .collect();
+        assert_eq!(
+            locks,
+            [
+                ("locked".to_string(), true, Some("too heated".to_string())),
+                ("unlocked".to_string(), false, None),
+                ("locked".to_string(), true, None),
+            ]
+        );
+        let pins: Vec<_> = pin_history(&events, false)
+            .into_iter()
+            .map(|pin| (pin.event, pin.actor.unwrap(), pin.pinned))
+            .collect();
+        assert_eq!(
+            pins,
+            [
+                ("pinned".to_string(), "octocat".to_string(), true),
+                ("unpinned".to_string(), "hubot".to_string(), false),
+            ]
+        );
+    }
+}
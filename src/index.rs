@@ -0,0 +1,236 @@
+use chrono::{DateTime, Utc};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::permissions;
+use crate::sink;
+use crate::types::{EntryWithMetadata, WriteError};
+
+pub const INDEX_FILE: &str = "index.json";
+const INDEX_VERSION: u32 = 2;
+/// Version 1 entries don't have the `comment_count` and `total_reactions`.
+const INDEX_VERSION_WITHOUT_COUNTS: u32 = 1;
+
+/// Summary of an issue or pull-request in `index.json`.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct IndexEntry {
+    pub title: String,
+    /// Either "open" or "closed".
+    pub state: String,
+    /// Whether a closed pull-request was merged. Not present for issues.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub merged: Option<bool>,
+    pub updated_at: Option<DateTime<Utc>>,
+    /// Number of comments. For pull-requests, this includes the review
+    /// comments.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub comment_count: Option<u64>,
+    /// Number of reactions to the issue or pull-request. Not present if the
+    /// API didn't return the reaction totals.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub total_reactions: Option<u64>,
+    /// The shard the entry was merged into by the `compact` subcommand,
+    /// relative to the backup directory. Not present for entries in their own
+    /// file.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub shard: Option<String>,
+    /// The file of the entry relative to the backup directory, e.g.
+    /// `issues/2024/01/123.json`. Only present with `--layout by-date`, where
+    /// it can't be derived from the number.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
+    /// The SHA-256 of the written entry without its `updated_at`. Only
+    /// present with `--diff-only-state`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub content_hash: Option<String>,
+}
+
+/// Overview of all issues and pull-requests in the backup, keyed by number.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Index {
+    /// Version of the Index
+    pub version: u32,
+    pub issues: BTreeMap<u64, IndexEntry>,
+    pub pulls: BTreeMap<u64, IndexEntry>,
+}
+
+impl Index {
+    /// Loads the index of a previous backup from `destination`, so that an
+    /// incremental backup only needs to update the changed entries. Starts
+    /// with an empty index if there is none or it can't be read. The issue
+    /// and pull-request files are in the `issues_dir` and `pulls_dir`.
+    pub fn load(destination: &Path, issues_dir: &str, pulls_dir: &str) -> Self {
+        let path = destination.join(INDEX_FILE);
+        let empty = Index {
+            version: INDEX_VERSION,
+            issues: BTreeMap::new(),
+            pulls: BTreeMap::new(),
+        };
+        let contents = match fs::read_to_string(&path) {
+            Ok(contents) => contents,
+            Err(_) => return empty,
+        };
+        match serde_json::from_str::<Index>(&contents) {
+            Ok(index) if index.version == INDEX_VERSION => index,
+            Ok(mut index) if index.version == INDEX_VERSION_WITHOUT_COUNTS => {
+                info!(
+                    "Adding the comment and reaction counts to {} from the written files.",
+                    path.display()
+                );
+                index.add_counts(destination, issues_dir, pulls_dir);
+                index
+            }
+            Ok(index) => {
+                warn!(
+                    "Index version {} is unknown. Rebuilding {}.",
+                    index.version,
+                    path.display()
+                );
+                empty
+            }
+            Err(e) => {
+                warn!(
+                    "Index file {} could not be deserialized: {}. Rebuilding it.",
+                    path.display(),
+                    e
+                );
+                empty
+            }
+        }
+    }
+
+    /// Fills in the comment and reaction counts of the entries from their
+    /// files in `destination`, which were written before the index had them.
+    fn add_counts(&mut self, destination: &Path, issues_dir: &str, pulls_dir: &str) {
+        for (dir, key, entries) in [
+            (issues_dir, "issue", &mut self.issues),
+            (pulls_dir, "pull", &mut self.pulls),
+        ] {
+            for (number, entry) in entries.iter_mut() {
+                // With --layout split-by-state or sharded, the file is in the
+                // subdirectory of its state or shard, and with by-date at its
+                // recorded path.
+                let name = format!("{}.json", number);
+                let path = [
+                    entry.path.as_ref().map_or_else(
+                        || destination.join(dir).join(&name),
+                        |path| destination.join(path),
+                    ),
+                    destination.join(dir).join(&name),
+                    destination.join(dir).join(&entry.state).join(&name),
+                    destination
+                        .join(dir)
+                        .join(sink::shard_dir(*number))
+                        .join(&name),
+                ]
+                .into_iter()
+                .find(|path| path.exists());
+                let file = match path
+                    .and_then(|path| fs::read_to_string(path).ok())
+                    .and_then(|contents| serde_json::from_str::<Value>(&contents).ok())
+                {
+                    Some(file) => file,
+                    None => continue,
+                };
+                let model = &file[key];
+                entry.comment_count = match (
+                    model["comments"].as_u64(),
+                    model["review_comments"].as_u64(),
+                ) {
+                    (None, None) => None,
+                    (comments, review_comments) => {
+                        Some(comments.unwrap_or_default() + review_comments.unwrap_or_default())
+                    }
+                };
+                entry.total_reactions = file["reactions"]["total_count"].as_u64();
+            }
+        }
+        self.version = INDEX_VERSION;
+    }
+
+    /// Adds the entry to the index, replacing an older version of it. The
+    /// `path` of the file is only recorded where it can't be derived from the
+    /// number.
+    pub fn insert(&mut self, entry: &EntryWithMetadata, path: Option<String>) {
+        match entry {
+            EntryWithMetadata::Issue(i) => {
+                self.issues.insert(
+                    i.issue.number,
+                    IndexEntry {
+                        title: i.issue.title.clone(),
+                        state: state_name(&i.issue.state),
+                        merged: None,
+                        updated_at: Some(i.issue.updated_at),
+                        comment_count: Some(i.issue.comments as u64),
+                        total_reactions: total_reactions(&i.reactions),
+                        shard: None,
+                        path,
+                        content_hash: None,
+                    },
+                );
+            }
+            EntryWithMetadata::Pull(p) => {
+                self.pulls.insert(
+                    p.pull.number,
+                    IndexEntry {
+                        title: p.pull.title.clone().unwrap_or_default(),
+                        state: p.pull.state.as_ref().map(state_name).unwrap_or_default(),
+                        // The state of a merged pull-request is "closed" too.
+                        merged: Some(p.pull.merged_at.is_some()),
+                        updated_at: p.pull.updated_at,
+                        comment_count: match (p.pull.comments, p.pull.review_comments) {
+                            (None, None) => None,
+                            (comments, review_comments) => Some(
+                                comments.unwrap_or_default() + review_comments.unwrap_or_default(),
+                            ),
+                        },
+                        total_reactions: total_reactions(&p.reactions),
+                        shard: None,
+                        path,
+                        content_hash: None,
+                    },
+                );
+            }
+        }
+    }
+
+    /// Records the `hash` of the content of an entry inserted before.
+    pub fn set_content_hash(&mut self, entry: &EntryWithMetadata, hash: String) {
+        let entries = match entry {
+            EntryWithMetadata::Issue(_) => &mut self.issues,
+            EntryWithMetadata::Pull(_) => &mut self.pulls,
+        };
+        if let Some(indexed) = entries.get_mut(&entry.number()) {
+            indexed.content_hash = Some(hash);
+        }
+    }
+
+    /// Removes the issue or pull-request from the index.
+    pub fn remove(&mut self, number: u64) {
+        self.issues.remove(&number);
+        self.pulls.remove(&number);
+    }
+
+    pub fn write(&self, destination: &Path) -> Result<(), WriteError> {
+        let path: PathBuf = destination.join(INDEX_FILE);
+        let json = serde_json::to_string_pretty(self)?;
+        permissions::write_atomically(&path, json.as_bytes())?;
+        info!("Written index to {}", path.display());
+        Ok(())
+    }
+}
+
+fn total_reactions(reactions: &Option<Value>) -> Option<u64> {
+    reactions.as_ref()?["total_count"].as_u64()
+}
+
+fn state_name(state: &octocrab::models::IssueState) -> String {
+    match serde_json::to_value(state) {
+        Ok(Value::String(s)) => s,
+        _ => format!("{:?}", state).to_lowercase(),
+    }
+}
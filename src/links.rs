@@ -0,0 +1,199 @@
+use log::{debug, info, warn};
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tokio::time::sleep;
+
+use crate::attachments;
+
+pub const DEAD_LINKS_FILE: &str = "dead-links.json";
+/// How long to wait for the response to a HEAD request.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+/// How often a rate-limited HEAD request is retried.
+const MAX_RATE_LIMIT_RETRIES: u8 = 3;
+/// Backoff when a request is rate-limited without a `Retry-After`.
+const RATE_LIMIT_BACKOFF: Duration = Duration::from_secs(60);
+
+/// A URL that answered with 404 or 410, and the files linking to it.
+#[derive(Debug, Serialize)]
+pub struct DeadLink {
+    pub url: String,
+    pub status: u16,
+    pub files: Vec<PathBuf>,
+}
+
+/// Result of checking the links in the files of a backup.
+#[derive(Debug, Default)]
+pub struct Report {
+    /// Number of issue and pull-request files scanned.
+    pub files: usize,
+    /// Number of distinct URLs checked.
+    pub checked: usize,
+    /// Number of distinct URLs on other hosts than GitHub, which were not
+    /// checked.
+    pub skipped: usize,
+    /// Number of URLs that couldn't be checked, e.g. because of a timeout.
+    pub failed: usize,
+    /// The URLs that are already dead.
+    pub dead: Vec<DeadLink>,
+}
+
+/// Returns the URLs in a markdown `text`, without the punctuation that
+/// usually follows a URL in a sentence.
+fn find_links(text: &str) -> Vec<String> {
+    attachments::find_all_urls(text)
+        .into_iter()
+        .map(|url| {
+            url.trim_end_matches(['.', ',', ';', ':', '!', '?'])
+                .to_string()
+        })
+        .collect()
+}
+
+/// Whether the `url` is hosted by GitHub, e.g. on `github.com` or
+/// `user-images.githubusercontent.com`.
+fn is_github_host(url: &str) -> bool {
+    let host = url
+        .trim_start_matches("https://")
+        .split(['/', '?', '#', ':'])
+        .next()
+        .unwrap_or_default();
+    host == "github.com"
+        || host.ends_with(".github.com")
+        || host == "githubusercontent.com"
+        || host.ends_with(".githubusercontent.com")
+}
+
+/// Returns the URLs in the bodies of every issue and pull-request file in the
+/// `issues_dir` and `pulls_dir` directories below `directory`, with the files
+/// linking to each, and the number of files scanned.
+fn collect_urls(
+    directory: &Path,
+    issues_dir: &str,
+    pulls_dir: &str,
+) -> io::Result<(usize, BTreeMap<String, Vec<PathBuf>>)> {
+    let mut files = 0;
+    let mut urls: BTreeMap<String, Vec<PathBuf>> = BTreeMap::new();
+    let mut dirs = vec![directory.to_path_buf()];
+    while let Some(dir) = dirs.pop() {
+        let is_entry_dir = dir
+            .file_name()
+            .is_some_and(|name| name == issues_dir || name == pulls_dir);
+        let mut entries: Vec<PathBuf> = fs::read_dir(&dir)?
+            .map(|entry| entry.map(|e| e.path()))
+            .collect::<io::Result<_>>()?;
+        entries.sort();
+        for path in entries {
+            if path.is_dir() {
+                dirs.push(path);
+            } else if is_entry_dir && path.extension().is_some_and(|ext| ext == "json") {
+                let value: Value = match serde_json::from_str(&fs::read_to_string(&path)?) {
+                    Ok(value) => value,
+                    Err(e) => {
+                        warn!("Skipping {}: {}", path.display(), e);
+                        continue;
+                    }
+                };
+                files += 1;
+                for url in attachments::body_urls(&value, find_links) {
+                    let linking = urls.entry(url).or_default();
+                    if linking.last() != Some(&path) {
+                        linking.push(path.clone());
+                    }
+                }
+            }
+        }
+    }
+    Ok((files, urls))
+}
+
+/// Sends a HEAD request to each URL linked from the bodies of the issues,
+/// pull-requests and comments below `directory`, waiting `delay` between the
+/// requests, and reports the ones answering with 404 or 410. Only URLs hosted
+/// by GitHub are checked, unless `external` is set. The `token` is only sent
+/// to `github.com`, which requires it for the attachments of private
+/// repositories.
+pub async fn validate_links(
+    directory: &Path,
+    issues_dir: &str,
+    pulls_dir: &str,
+    external: bool,
+    delay: Duration,
+    token: Option<&str>,
+) -> io::Result<Report> {
+    let (files, urls) = collect_urls(directory, issues_dir, pulls_dir)?;
+    let client = reqwest::Client::builder()
+        .timeout(REQUEST_TIMEOUT)
+        .user_agent("github-metadata-backup")
+        .build()
+        .map_err(|e| io::Error::other(e.to_string()))?;
+    let mut report = Report {
+        files,
+        ..Default::default()
+    };
+    for (url, linking) in urls {
+        if !external && !is_github_host(&url) {
+            report.skipped += 1;
+            continue;
+        }
+        if report.checked > 0 {
+            sleep(delay).await;
+        }
+        report.checked += 1;
+        let token = token.filter(|_| url.starts_with("https://github.com/"));
+        match head(&client, &url, token).await {
+            Ok(status) if status == 404 || status == 410 => {
+                info!("{} is dead: {}", url, status);
+                report.dead.push(DeadLink {
+                    url,
+                    status,
+                    files: linking,
+                });
+            }
+            Ok(status) => debug!("{}: {}", url, status),
+            Err(e) => {
+                warn!("Could not check {}: {}", url, e);
+                report.failed += 1;
+            }
+        }
+    }
+    Ok(report)
+}
+
+/// Returns the status of a HEAD request to the `url`. Rate-limited requests
+/// are retried after the `Retry-After` time.
+async fn head(
+    client: &reqwest::Client,
+    url: &str,
+    token: Option<&str>,
+) -> Result<u16, reqwest::Error> {
+    let mut attempt = 0;
+    loop {
+        let mut request = client.head(url);
+        if let Some(token) = token {
+            request = request.bearer_auth(token);
+        }
+        let response = request.send().await?;
+        let status = response.status();
+        if status != reqwest::StatusCode::TOO_MANY_REQUESTS || attempt >= MAX_RATE_LIMIT_RETRIES {
+            return Ok(status.as_u16());
+        }
+        let wait = response
+            .headers()
+            .get("retry-after")
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.parse().ok())
+            .map(Duration::from_secs)
+            .unwrap_or(RATE_LIMIT_BACKOFF);
+        info!(
+            "Checking links is rate-limited. Waiting {} seconds.",
+            wait.as_secs()
+        );
+        sleep(wait).await;
+        attempt += 1;
+    }
+}
@@ -0,0 +1,178 @@
+use chrono::{DateTime, Utc};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::permissions;
+use crate::types::LockError;
+
+pub const LOCK_FILE: &str = ".backup-lock";
+
+/// Contents of the lock file.
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct LockInfo {
+    /// Process id of the backup holding the lock.
+    pub pid: u32,
+    /// UTC timestamp when the lock was acquired.
+    pub acquired_at: DateTime<Utc>,
+}
+
+/// Prevents two backups from writing to the same destination at the same
+/// time. The lock is released when this is dropped, unless another backup
+/// took it over in the meantime.
+pub struct BackupLock {
+    path: PathBuf,
+    /// What was written to the lock file when it was acquired.
+    info: LockInfo,
+}
+
+impl BackupLock {
+    /// Acquires the lock on `destination`. A lock held by a process that
+    /// isn't running anymore is reclaimed. With `force`, a lock held by a
+    /// running process is taken over too.
+    pub fn acquire(destination: &Path, force: bool) -> Result<Self, LockError> {
+        permissions::create_dir_all(destination)?;
+        let path = destination.join(LOCK_FILE);
+
+        match create(&path) {
+            Err(LockError::IoError(e)) if e.kind() == io::ErrorKind::AlreadyExists => (),
+            result => return result.map(|info| BackupLock { path, info }),
+        }
+
+        match fs::read_to_string(&path)
+            .ok()
+            .and_then(|contents| serde_json::from_str::<LockInfo>(&contents).ok())
+        {
+            Some(lock) if is_running(lock.pid) && !force => {
+                return Err(LockError::Held(lock));
+            }
+            Some(lock) if is_running(lock.pid) => warn!(
+                "Taking over the lock {} of the running process {} (--force).",
+                path.display(),
+                lock.pid
+            ),
+            Some(lock) => info!(
+                "Reclaiming the stale lock {} of process {} that isn't running anymore.",
+                path.display(),
+                lock.pid
+            ),
+            None => warn!("Reclaiming the unreadable lock {}.", path.display()),
+        }
+        fs::remove_file(&path)?;
+        let info = create(&path)?;
+        Ok(BackupLock { path, info })
+    }
+}
+
+impl Drop for BackupLock {
+    fn drop(&mut self) {
+        let current = fs::read_to_string(&self.path)
+            .ok()
+            .and_then(|contents| serde_json::from_str::<LockInfo>(&contents).ok());
+        if current.as_ref() != Some(&self.info) {
+            warn!(
+                "Not removing the lock {} as it was taken over by another backup.",
+                self.path.display()
+            );
+            return;
+        }
+        if let Err(e) = fs::remove_file(&self.path) {
+            warn!("Could not remove the lock {}: {}", self.path.display(), e);
+        }
+    }
+}
+
+/// Creates the lock file, failing if it already exists. The lock is written
+/// to a file of this process first and hard-linked into place, so another
+/// backup never sees a lock file without its contents and reclaims it.
+fn create(path: &Path) -> Result<LockInfo, LockError> {
+    let lock = LockInfo {
+        pid: std::process::id(),
+        acquired_at: Utc::now(),
+    };
+    let mut temp = path.as_os_str().to_owned();
+    temp.push(format!(".{}", lock.pid));
+    let temp = PathBuf::from(temp);
+    fs::write(&temp, serde_json::to_string_pretty(&lock)?)?;
+    let linked = fs::hard_link(&temp, path);
+    if let Err(e) = fs::remove_file(&temp) {
+        warn!("Could not remove {}: {}", temp.display(), e);
+    }
+    linked?;
+    Ok(lock)
+}
+
+/// Returns true if a process with the `pid` is running.
+#[cfg(unix)]
+fn is_running(pid: u32) -> bool {
+    // Signal 0 only checks if the process exists and could be signaled.
+    if unsafe { libc::kill(pid as libc::pid_t, 0) } == 0 {
+        return true;
+    }
+    io::Error::last_os_error().raw_os_error() == Some(libc::EPERM)
+}
+
+/// Without a portable way to check, a lock is assumed to be held by a
+/// running process.
+#[cfg(not(unix))]
+fn is_running(_pid: u32) -> bool {
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "github-metadata-backup-lock-{}-{}",
+            name,
+            std::process::id()
+        ));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    #[test]
+    fn released_on_drop() {
+        let dir = temp_dir("released");
+        let lock = BackupLock::acquire(&dir, false).unwrap();
+        assert!(matches!(
+            BackupLock::acquire(&dir, false),
+            Err(LockError::Held(_))
+        ));
+        drop(lock);
+        assert!(!dir.join(LOCK_FILE).exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn taken_over_lock_is_kept() {
+        let dir = temp_dir("taken-over");
+        let lock = BackupLock::acquire(&dir, false).unwrap();
+        let taken_over = BackupLock::acquire(&dir, true).unwrap();
+        // The first lock was replaced, so dropping it must leave the new one.
+        drop(lock);
+        assert!(dir.join(LOCK_FILE).exists());
+        drop(taken_over);
+        assert!(!dir.join(LOCK_FILE).exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn lock_file_is_complete() {
+        let dir = temp_dir("complete");
+        let lock = BackupLock::acquire(&dir, false).unwrap();
+        let contents = fs::read_to_string(dir.join(LOCK_FILE)).unwrap();
+        assert_eq!(
+            serde_json::from_str::<LockInfo>(&contents).unwrap(),
+            lock.info
+        );
+        // Only the lock itself is left in the destination.
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+        drop(lock);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1,59 +1,502 @@
 use async_recursion::async_recursion;
+use bytes::Bytes;
 use chrono::prelude::*;
 use clap::Parser;
 use env_logger::Env;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use http_body_util::combinators::BoxBody;
+use http_body_util::{BodyExt, Full};
 use log::{debug, error, info, warn};
+use octocrab::models;
 use octocrab::models::{issues, pulls};
+use octocrab::FromResponse;
 use octocrab::Page;
-use octocrab::{models, params};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
 use std::fs;
-use std::fs::File;
+use std::io;
 use std::io::prelude::*;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::process::ExitCode;
-use std::time::SystemTime;
-use tokio::sync::mpsc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Instant, SystemTime};
+use tokio::sync::{mpsc, Notify, Semaphore};
 use tokio::task;
 use tokio::time::{sleep, Duration};
 
+use backoff::Attempt;
+use sink::Sink;
 use types::*;
 
 const STATE_FILE: &str = "state.json";
+const STATE_FILE_GZ: &str = "state.json.gz";
+/// The first two bytes of a gzip stream.
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+const REPOSITORY_FILE: &str = "repository.json";
+const OWNER_FILE: &str = "owner.json";
+const MILESTONES_FILE: &str = "milestones.json";
+const LABELS_FILE: &str = "labels.json";
+const STARRED_FILE: &str = "starred.json";
+/// The ETags of the entries' responses, kept by `--use-etags`.
+const ETAGS_FILE: &str = "etags.json";
+const ETAGS_VERSION: u32 = 1;
+const PARTIAL_DIR: &str = "partial";
+/// Copies of the entries linking to the downloaded attachments.
+const REWRITTEN_DIR: &str = "rewritten";
+/// Where `--prune-mode move` keeps the files of the pruned entries, inside
+/// the issues and pulls directories.
+const PRUNED_DIR: &str = "deleted";
+/// The API URLs in the entries start with this without a `--base-url`.
+const GITHUB_API_URL: &str = "https://api.github.com";
+/// The `--rewrite-api-urls` base that makes the API URLs relative.
+const RELATIVE_API_URLS: &str = "relative";
 
 const MAX_PER_PAGE: u8 = 100;
 const START_PAGE: u32 = 1; // GitHub starts indexing at page 1
-const STATE_VERSION: u32 = 1;
+const STATE_VERSION: u32 = 2;
+type StateMigration = fn(serde_json::Value) -> serde_json::Value;
+/// Upgrades a state file from the paired version to the next one. When
+/// bumping the STATE_VERSION, add a step from the previous version here, so
+/// state files of older versions keep their cursor and failed entries.
+const STATE_MIGRATIONS: &[(u32, StateMigration)] = &[(1, split_cursor)];
+// GitHub doesn't always tell us how long to wait after hitting a secondary
+// rate-limit. This is the backoff used in that case.
+const SECONDARY_RATE_LIMIT_BACKOFF: Duration = Duration::from_secs(60);
+// 5xx errors, like 502, 503 and 504 gateway errors, and dropped connections
+// are usually transient. Retry them after a short backoff.
+const GATEWAY_ERROR_BACKOFF: Duration = Duration::from_secs(1);
 
 const EXIT_CREATING_DIRS: u8 = 1;
 const EXIT_CREATING_OCTOCRAB_INSTANCE: u8 = 2;
 const EXIT_API_ERROR: u8 = 3;
-const EXIT_WRITING: u8 = 3;
+const EXIT_WRITING: u8 = 12;
 const EXIT_NO_PAT: u8 = 4;
+const EXIT_LOW_DISK_SPACE: u8 = 5;
+const EXIT_ANONYMIZE_SALT: u8 = 6;
+const EXIT_LOCKED: u8 = 7;
+const EXIT_DRIFT: u8 = 8;
+const EXIT_STATE_VERSION: u8 = 9;
+const EXIT_REQUEST_BUDGET: u8 = 10;
+const EXIT_FAILURE_THRESHOLD: u8 = 11;
 
+/// How long to wait for the `--notify-url` to respond.
+const NOTIFY_TIMEOUT: Duration = Duration::from_secs(10);
+
+mod attachments;
+mod backoff;
+mod changelog;
+mod compact;
+mod discussions;
+mod encoding;
+mod error_stream;
+mod fidelity;
+mod filter;
+mod history;
+mod index;
+mod links;
+mod lock;
+mod metrics;
+mod migration;
+mod permissions;
+mod plan;
+mod profile;
+mod progress;
+mod releases;
+mod resume_token;
+mod run_log;
+mod self_test;
+mod single_file;
+mod sink;
+mod sitemap;
+mod throttle;
+mod token_pool;
+mod transform;
 mod types;
+mod upgrade;
+mod users;
+mod validate;
+mod wiki;
 
-async fn wait_on_ratelimit() {
-    let gh = octocrab::instance();
-    let now = SystemTime::now();
-    let unix_time = now
+static ARGS: OnceLock<Args> = OnceLock::new();
+/// Destination of the backup. Resolved once on startup, as the `{date}` of a
+/// `--destination-template` would change over time.
+static DESTINATION: OnceLock<PathBuf> = OnceLock::new();
+/// Salt for `--anonymize-users`. Only set if users are anonymized.
+static ANONYMIZE_SALT: OnceLock<Vec<u8>> = OnceLock::new();
+/// The JSON Schema entries are checked against with `--validate-output`.
+static OUTPUT_SCHEMA: OnceLock<serde_json::Value> = OnceLock::new();
+/// Client for the GraphQL API of a GitHub Enterprise Server, which isn't below
+/// the `--base-url` of its REST API. Only set if they differ.
+static GRAPHQL_INSTANCE: OnceLock<octocrab::Octocrab> = OnceLock::new();
+/// Held while waiting for the primary rate-limit to reset.
+static RATE_LIMIT_WAIT: tokio::sync::Mutex<()> = tokio::sync::Mutex::const_new(());
+/// Number of requests sent to the GitHub API by the current backup, counted
+/// against the `--request-budget`.
+static REQUESTS: AtomicU64 = AtomicU64::new(0);
+/// The changed responses of the conditional requests of `--use-etags`, by
+/// route. `api_get_tagged()` takes them instead of sending the request again.
+static PREFETCHED: Mutex<BTreeMap<String, http::Response<Bytes>>> = Mutex::new(BTreeMap::new());
+
+/// Returns the command line arguments parsed on startup.
+fn args() -> &'static Args {
+    ARGS.get().expect("arguments are parsed on startup")
+}
+
+/// Inspects the headers and status of a response from the GitHub API before
+/// it's deserialized.
+fn inspect_response(
+    route: &str,
+    response: &http::Response<impl http_body::Body>,
+) -> Result<(), FetchError> {
+    if args().verbose_rate_limit {
+        match response
+            .headers()
+            .get("x-ratelimit-remaining")
+            .and_then(|v| v.to_str().ok())
+        {
+            Some(remaining) => debug!("{}: x-ratelimit-remaining={}", route, remaining),
+            None => debug!("{}: no x-ratelimit-remaining header", route),
+        }
+    }
+
+    let status = response.status();
+    if status == http::StatusCode::BAD_GATEWAY
+        || status == http::StatusCode::SERVICE_UNAVAILABLE
+        || status == http::StatusCode::GATEWAY_TIMEOUT
+    {
+        return Err(FetchError::Gateway(status));
+    }
+    if status == http::StatusCode::FORBIDDEN || status == http::StatusCode::TOO_MANY_REQUESTS {
+        if let Some(wait) = rate_limit_wait(response.headers()) {
+            if args().throttle_on_abuse_detection && response.headers().contains_key("retry-after")
+            {
+                throttle::on_secondary_rate_limit();
+            }
+            return Err(FetchError::RateLimited(wait));
+        }
+    }
+    Ok(())
+}
+
+/// Returns how long a rate-limited response asks to wait: its `Retry-After`,
+/// which GitHub sends with secondary rate-limits, or the time until the reset
+/// of an exhausted rate-limit other than the `core` one, e.g. of the search
+/// API. The `core` rate-limit is left to `wait_on_ratelimit()`.
+fn rate_limit_wait(headers: &http::HeaderMap) -> Option<Duration> {
+    let header = |name: &str| headers.get(name).and_then(|v| v.to_str().ok());
+    if let Some(seconds) = header("retry-after").and_then(|v| v.parse().ok()) {
+        return Some(Duration::from_secs(seconds));
+    }
+    let exhausted = header("x-ratelimit-remaining") == Some("0")
+        && header("x-ratelimit-resource").is_some_and(|resource| resource != "core");
+    if !exhausted {
+        return None;
+    }
+    let reset: u64 = header("x-ratelimit-reset")?.parse().ok()?;
+    let now = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .expect("SystemTime before UNIX EPOCH!")
+        .as_secs();
+    Some(Duration::from_secs(reset.saturating_sub(now) + 1))
+}
+
+/// Returns how long to pause after the response if fewer than
+/// `--rate-limit-floor` requests remain, according to its rate-limit headers.
+/// The pause lasts until the rate-limit resets. With a `--token-pool-file`,
+/// the floor applies to the pool as a whole instead.
+fn rate_limit_floor_wait(
+    route: &str,
+    response: &http::Response<impl http_body::Body>,
+) -> Option<Duration> {
+    let floor = args().rate_limit_floor?;
+    if token_pool::is_active() {
+        return None;
+    }
+    let header = |name: &str| {
+        response
+            .headers()
+            .get(name)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.parse::<u64>().ok())
+    };
+    let remaining = header("x-ratelimit-remaining")?;
+    if remaining >= floor {
+        return None;
+    }
+    let reset = header("x-ratelimit-reset")?;
+    let unix_time = SystemTime::now()
         .duration_since(SystemTime::UNIX_EPOCH)
         .expect("SystemTime before UNIX EPOCH!")
         .as_secs();
+    let reset_in = reset.saturating_sub(unix_time) + 2;
+    info!(
+        "{}: only {} requests left, below the --rate-limit-floor of {}: pausing for {} seconds until the rate-limit resets (at {}).",
+        route, remaining, floor, reset_in, reset
+    );
+    Some(Duration::from_secs(reset_in))
+}
+
+/// Picks the client for the next request, waiting if all tokens of a
+/// `--token-pool-file` are below the `--rate-limit-floor`. The request is
+/// counted, and refused once the `--request-budget` is used up. With
+/// `--throttle-on-abuse-detection`, it's paced to the adapted request rate.
+async fn api_client() -> Result<token_pool::Client, FetchError> {
+    token_pool::wait_for_quota(args().rate_limit_floor.unwrap_or(0)).await;
+    if args().throttle_on_abuse_detection {
+        throttle::pace().await;
+    }
+    let budget = args().request_budget;
+    REQUESTS
+        .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |made| {
+            budget
+                .is_none_or(|budget| made < budget)
+                .then_some(made + 1)
+        })
+        .map_err(FetchError::RequestBudget)?;
+    Ok(token_pool::pick())
+}
+
+/// The exit code of a backup aborted by the error `e`.
+fn fetch_exit_code(e: &FetchError) -> ExitCode {
+    match e {
+        FetchError::RequestBudget(_) => ExitCode::from(EXIT_REQUEST_BUDGET),
+        _ => ExitCode::from(EXIT_API_ERROR),
+    }
+}
+
+/// Sends a GET request for `route` to the GitHub API and deserializes the
+/// response. Unlike octocrab's typed request builders, this has access to the
+/// response headers before they are discarded. The body is normalized with
+/// the `--output-encoding` policy first.
+async fn api_get<R: FromResponse>(route: String) -> Result<R, FetchError> {
+    api_get_with_headers(route, None).await
+}
+
+/// Like `api_get`, but sends the extra `headers`, e.g. to select another
+/// media type with `Accept`.
+async fn api_get_with_headers<R: FromResponse>(
+    route: String,
+    headers: Option<http::HeaderMap>,
+) -> Result<R, FetchError> {
+    Ok(api_get_tagged(route, headers).await?.0)
+}
+
+/// Like `api_get_with_headers`, but also returns the `ETag` of the response,
+/// which `--use-etags` sends with the conditional requests of later backups.
+async fn api_get_tagged<R: FromResponse>(
+    route: String,
+    headers: Option<http::HeaderMap>,
+) -> Result<(R, Option<String>), FetchError> {
+    let prefetched = PREFETCHED.lock().unwrap().remove(&route);
+    if let Some(response) = prefetched {
+        debug!("{}: using the response of the conditional request", route);
+        let response = response.map(|body| Full::new(body).map_err(|never| match never {}).boxed());
+        return tagged_response(&route, response).await;
+    }
+    let client = api_client().await?;
+    let started = Instant::now();
+    let result = async {
+        let response = client
+            .instance
+            ._get_with_headers(route.as_str(), headers)
+            .await?;
+        client.record(&response);
+        inspect_response(&route, &response)?;
+        let floor_wait = rate_limit_floor_wait(&route, &response);
+        Ok::<_, FetchError>((tagged_response(&route, response).await?, floor_wait))
+    }
+    .await;
+    profile::record(profile::Phase::of_route(&route), started.elapsed());
+    let (result, floor_wait) = result?;
+    if let Some(wait) = floor_wait {
+        sleep(wait).await;
+    }
+    Ok(result)
+}
+
+/// Converts a `response` of the GitHub API into `R`, with the `ETag` of the
+/// response. The body is normalized with the `--output-encoding` policy first.
+async fn tagged_response<R: FromResponse>(
+    route: &str,
+    response: http::Response<BoxBody<Bytes, octocrab::Error>>,
+) -> Result<(R, Option<String>), FetchError> {
+    let etag = response
+        .headers()
+        .get(http::header::ETAG)
+        .and_then(|v| v.to_str().ok())
+        .map(str::to_string);
+    let response = octocrab::map_github_error(response).await?;
+    if args().output_encoding == OutputEncoding::Strict {
+        return Ok((R::from_response(response).await?, etag));
+    }
+    let (parts, body) = response.into_parts();
+    let mut body = body.collect().await?.to_bytes();
+    if let Some(normalized) = encoding::normalize(&body, args().output_encoding) {
+        warn!(
+            "{}: normalized invalid UTF-8 or lone surrogates in the response",
+            route
+        );
+        body = Bytes::from(normalized);
+    }
+    let body = Full::new(body).map_err(|never| match never {});
+    let result = R::from_response(http::Response::from_parts(parts, body)).await?;
+    Ok((result, etag))
+}
+
+/// Sends a conditional request for `route` with the `etag` of an earlier
+/// response. Returns true if GitHub answers with 304 Not Modified, which
+/// doesn't count against the rate-limit. A changed response is kept in
+/// [PREFETCHED], so fetching the route afterwards doesn't request it again.
+#[async_recursion]
+async fn is_not_modified(
+    route: String,
+    etag: String,
+    attempt: Attempt,
+) -> Result<bool, FetchError> {
+    let Ok(value) = http::HeaderValue::from_str(&etag) else {
+        return Ok(false);
+    };
+    let mut headers = http::HeaderMap::new();
+    headers.insert(http::header::IF_NONE_MATCH, value);
+    let client = api_client().await?;
+    let started = Instant::now();
+    let result = async {
+        let response = client
+            .instance
+            ._get_with_headers(route.as_str(), Some(headers))
+            .await?;
+        client.record(&response);
+        inspect_response(&route, &response)?;
+        if response.status() == http::StatusCode::NOT_MODIFIED {
+            return Ok(true);
+        }
+        let (parts, body) = octocrab::map_github_error(response).await?.into_parts();
+        let body = body.collect().await?.to_bytes();
+        PREFETCHED
+            .lock()
+            .unwrap()
+            .insert(route.clone(), http::Response::from_parts(parts, body));
+        Ok::<_, FetchError>(false)
+    }
+    .await;
+    profile::record(profile::Phase::of_route(&route), started.elapsed());
+    match result {
+        Ok(not_modified) => Ok(not_modified),
+        Err(e) => {
+            let Some(attempt) = retry_after_error(&e, attempt).await else {
+                return Err(e);
+            };
+            is_not_modified(route, etag, attempt).await
+        }
+    }
+}
+
+/// Sends a query to the GitHub GraphQL API and returns the `data` of the
+/// response.
+async fn api_graphql(
+    query: &str,
+    variables: serde_json::Value,
+) -> Result<serde_json::Value, FetchError> {
+    let body = serde_json::json!({ "query": query, "variables": variables });
+    let client = api_client().await?;
+    let started = Instant::now();
+    let result = async {
+        let instance = GRAPHQL_INSTANCE.get().unwrap_or(&client.instance);
+        let response = instance._post("/graphql", Some(&body)).await?;
+        client.record(&response);
+        inspect_response("/graphql", &response)?;
+        let floor_wait = rate_limit_floor_wait("/graphql", &response);
+        let response = octocrab::map_github_error(response).await?;
+        let response = serde_json::Value::from_response(response).await?;
+        Ok::<_, FetchError>((response, floor_wait))
+    }
+    .await;
+    profile::record(profile::Phase::Other, started.elapsed());
+    let (mut response, floor_wait) = result?;
+    if let Some(wait) = floor_wait {
+        sleep(wait).await;
+    }
+    match response.get("errors") {
+        Some(errors) if response["data"].is_null() => Err(FetchError::GraphQL(errors.to_string())),
+        Some(errors) => {
+            warn!("GitHub GraphQL API returned partial data: {}", errors);
+            Ok(response["data"].take())
+        }
+        None => Ok(response["data"].take()),
+    }
+}
+
+/// Renders GitHub flavored markdown to HTML with GitHub's markdown API.
+/// References like `#123` are resolved in the context of the repository.
+async fn api_render_markdown(text: &str, context: &str) -> Result<String, FetchError> {
+    let body = serde_json::json!({ "text": text, "mode": "gfm", "context": context });
+    let client = api_client().await?;
+    let started = Instant::now();
+    let result = async {
+        let response = client.instance._post("/markdown", Some(&body)).await?;
+        client.record(&response);
+        inspect_response("/markdown", &response)?;
+        let floor_wait = rate_limit_floor_wait("/markdown", &response);
+        let response = octocrab::map_github_error(response).await?;
+        let html = client.instance.body_to_string(response).await?;
+        Ok::<_, FetchError>((html, floor_wait))
+    }
+    .await;
+    profile::record(profile::Phase::Other, started.elapsed());
+    let (html, floor_wait) = result?;
+    if let Some(wait) = floor_wait {
+        sleep(wait).await;
+    }
+    Ok(html)
+}
+
+/// Waits until the core rate-limit resets if it's exhausted. Returns false if
+/// it wasn't exhausted, so waiting didn't help. With a `--token-pool-file`,
+/// waits until a token has quota left instead.
+async fn wait_on_ratelimit() -> bool {
+    if token_pool::is_active() {
+        token_pool::wait_for_quota(1).await;
+        return true;
+    }
+    // With --concurrency, several fetches can hit the rate-limit at once.
+    // Only one of them polls the rate-limit, the others wait for it and then
+    // find it reset.
+    let _waiting = RATE_LIMIT_WAIT.lock().await;
+    let gh = octocrab::instance();
+    let mut waited = false;
 
     loop {
-        let ratelimit = gh
-            .ratelimit()
-            .get()
-            .await
-            .expect("could not get ratelimit info");
-        let remaining = ratelimit.resources.core.remaining;
+        let unix_time = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .expect("SystemTime before UNIX EPOCH!")
+            .as_secs();
+        // A GitHub Enterprise Server can have the rate-limit disabled, and
+        // then answers with a 404 or without the core rate-limit.
+        let core = match gh.ratelimit().get().await {
+            Ok(ratelimit) => ratelimit.resources.core,
+            Err(e) => {
+                warn!("Could not get the rate-limit, not waiting for it: {}", e);
+                return false;
+            }
+        };
+        let remaining = core.remaining;
 
         if remaining > 0 {
+            if !waited {
+                return false;
+            }
             break;
         }
+        waited = true;
 
-        let reset = ratelimit.resources.core.reset;
-        let reset_in = (reset - unix_time) + 2;
+        let reset = core.reset;
+        let reset_in = reset.saturating_sub(unix_time) + 2;
 
         info!(
             "GitHub rate-limit hit (remaining={}): should reset in {} seconds (at {}).",
@@ -63,6 +506,211 @@ async fn wait_on_ratelimit() {
         sleep(Duration::from_secs(reset_in as u64)).await;
     }
     info!("Github rate-limiting has reset.");
+    true
+}
+
+/// Returns true if the error is a 403 or 429 caused by GitHub's secondary
+/// rate-limit. These don't necessarily come with a `Retry-After` header, so we
+/// detect them by their message.
+fn is_secondary_rate_limit(e: &FetchError) -> bool {
+    match e {
+        FetchError::Octocrab(e) => match e.as_ref() {
+            octocrab::Error::GitHub { source, .. } => {
+                (source.status_code == http::StatusCode::FORBIDDEN
+                    || source.status_code == http::StatusCode::TOO_MANY_REQUESTS)
+                    && source
+                        .message
+                        .to_lowercase()
+                        .contains("secondary rate limit")
+            }
+            _ => false,
+        },
+        _ => false,
+    }
+}
+
+/// Returns true if the error is a 403 or 429 caused by GitHub's primary
+/// rate-limit, e.g. "API rate limit exceeded for user ID 1.", or another 429.
+/// Other 403s, like for a missing permission, would fail again.
+fn is_rate_limit(e: &FetchError) -> bool {
+    match e {
+        FetchError::Octocrab(e) => match e.as_ref() {
+            octocrab::Error::GitHub { source, .. } => {
+                source.status_code == http::StatusCode::TOO_MANY_REQUESTS
+                    || (source.status_code == http::StatusCode::FORBIDDEN
+                        && source
+                            .message
+                            .to_lowercase()
+                            .contains("rate limit exceeded"))
+            }
+            _ => false,
+        },
+        _ => false,
+    }
+}
+
+/// Whether a request failed for a transient reason worth retrying: a 5xx
+/// response, or a dropped connection or timeout. Other errors, like a 404 or
+/// 422, would fail again.
+fn is_transient(e: &FetchError) -> bool {
+    match e {
+        FetchError::Gateway(_) => true,
+        FetchError::Octocrab(e) => match e.as_ref() {
+            octocrab::Error::GitHub { source, .. } => source.status_code.is_server_error(),
+            octocrab::Error::Hyper { .. } | octocrab::Error::Service { .. } => true,
+            _ => false,
+        },
+        _ => false,
+    }
+}
+
+/// Returns the delay before the retry of the failed `attempt`, with the
+/// `--backoff-strategy` starting from `base` unless `--backoff-base` is set.
+fn backoff_delay(base: Duration, attempt: Attempt, random: f64) -> Duration {
+    backoff::Backoff {
+        strategy: args().backoff_strategy,
+        base: args().backoff_base.unwrap_or(base),
+        cap: args().backoff_cap,
+    }
+    .delay(attempt, random)
+}
+
+/// How to wait before retrying a failed request.
+#[derive(Debug, PartialEq)]
+enum RetryWait {
+    /// Wait the backoff delay of a transient error.
+    Transient(Duration),
+    /// Wait as long as the rate-limited response asked.
+    RateLimited(Duration),
+    /// Wait the backoff delay of a secondary rate-limit.
+    SecondaryRateLimit(Duration),
+    /// Wait until the rate-limit resets, or the backoff delay if it isn't
+    /// exhausted.
+    RateLimitReset(Duration),
+}
+
+/// Decides if the request that failed with `e` on its `attempt` should be
+/// retried, and how to wait before. Transient errors and rate-limits are
+/// retried up to `--max-retries` times, waiting as the `--backoff-strategy`
+/// decides, unless the response said how long to wait. Secondary rate-limits
+/// are independent of the core rate-limit. Other rate-limits are retried once
+/// after waiting on the rate-limit, while other 403s aren't retried. `random`
+/// spreads the decorrelated jitter delays.
+fn retry_wait(e: &FetchError, attempt: Attempt, random: f64) -> Option<RetryWait> {
+    let retries_left = attempt.retries < args().max_retries;
+    match e {
+        _ if is_transient(e) => retries_left
+            .then(|| RetryWait::Transient(backoff_delay(GATEWAY_ERROR_BACKOFF, attempt, random))),
+        FetchError::RateLimited(wait) => retries_left.then_some(RetryWait::RateLimited(*wait)),
+        FetchError::Octocrab(_) if is_secondary_rate_limit(e) => retries_left.then(|| {
+            RetryWait::SecondaryRateLimit(backoff_delay(
+                SECONDARY_RATE_LIMIT_BACKOFF,
+                attempt,
+                random,
+            ))
+        }),
+        FetchError::Octocrab(_) if is_rate_limit(e) => {
+            // retry once incase we hit the rate-limiting
+            (attempt.retries == 0).then(|| {
+                RetryWait::RateLimitReset(backoff_delay(
+                    SECONDARY_RATE_LIMIT_BACKOFF,
+                    attempt,
+                    random,
+                ))
+            })
+        }
+        _ => None,
+    }
+}
+
+/// Decides if a failed request should be retried, see [retry_wait], and if
+/// so, waits before the retry. Returns the attempt of the retry.
+async fn retry_after_error(e: &FetchError, attempt: Attempt) -> Option<Attempt> {
+    if is_secondary_rate_limit(e) && args().throttle_on_abuse_detection {
+        throttle::on_secondary_rate_limit();
+    }
+    let retry = attempt.retries + 1;
+    let max_retries = args().max_retries;
+    match retry_wait(e, attempt, backoff::random())? {
+        RetryWait::Transient(delay) => {
+            warn!(
+                "Request failed with {}: retrying in {:.1} seconds (retry {} of {}).",
+                e,
+                delay.as_secs_f64(),
+                retry,
+                max_retries
+            );
+            sleep(delay).await;
+            Some(attempt.retry(Some(delay)))
+        }
+        RetryWait::RateLimited(wait) => {
+            warn!(
+                "GitHub rate-limit hit: waiting {} seconds as asked before retrying (retry {} of {}).",
+                wait.as_secs(),
+                retry,
+                max_retries
+            );
+            sleep(wait).await;
+            Some(attempt.retry(None))
+        }
+        RetryWait::SecondaryRateLimit(delay) => {
+            warn!(
+                "GitHub secondary rate-limit hit: waiting {:.1} seconds before retrying (retry {} of {}).",
+                delay.as_secs_f64(),
+                retry,
+                max_retries
+            );
+            sleep(delay).await;
+            Some(attempt.retry(Some(delay)))
+        }
+        RetryWait::RateLimitReset(delay) => {
+            if wait_on_ratelimit().await {
+                return Some(attempt.retry(None));
+            }
+            warn!(
+                "Request failed with {}, but the rate-limit isn't exhausted: retrying in {:.1} seconds.",
+                e,
+                delay.as_secs_f64()
+            );
+            sleep(delay).await;
+            Some(attempt.retry(Some(delay)))
+        }
+    }
+}
+
+fn pull_route(owner: &str, repo: &str, number: u64) -> String {
+    format!("/repos/{}/{}/pulls/{}", owner, repo, number)
+}
+
+fn pull_comments_route(owner: &str, repo: &str, number: u64, page: u32) -> String {
+    format!(
+        "/repos/{}/{}/pulls/{}/comments?per_page={}&page={}",
+        owner, repo, number, MAX_PER_PAGE, page
+    )
+}
+
+fn timeline_route(owner: &str, repo: &str, number: u64, page: u32) -> String {
+    format!(
+        "/repos/{}/{}/issues/{}/timeline?per_page={}&page={}",
+        owner, repo, number, MAX_PER_PAGE, page
+    )
+}
+
+/// Records the `etag` of a fetched page of `items` in `progress` for
+/// `--use-etags`. A page without an ETag, or a full last page, after which
+/// new items would go onto a new page without changing this one, leaves the
+/// ETags incomplete.
+fn record_page_etag(progress: &mut PageProgress, etag: Option<String>, items: usize, last: bool) {
+    if !args().use_etags {
+        return;
+    }
+    match etag {
+        Some(etag) => progress.etags.push(etag),
+        None => progress.etags.clear(),
+    }
+    if last && items == MAX_PER_PAGE as usize {
+        progress.etags.clear();
+    }
 }
 
 #[async_recursion]
@@ -70,26 +718,15 @@ async fn get_pull_body(
     number: u64,
     owner: String,
     repo: String,
-    attempt: u8,
-) -> octocrab::Result<pulls::PullRequest> {
-    match octocrab::instance()
-        .pulls(owner.clone(), repo.clone())
-        .get(number)
-        .await
-    {
+    attempt: Attempt,
+) -> Result<(serde_json::Value, Option<String>), FetchError> {
+    match api_get_tagged(pull_route(&owner, &repo, number), None).await {
         Ok(p) => Ok(p),
         Err(e) => {
-            match e {
-                octocrab::Error::GitHub { .. } => {
-                    if attempt > 0 {
-                        return Err(e);
-                    }
-                    // retry once incase we hit the rate-limiting
-                    wait_on_ratelimit().await;
-                    get_pull_body(number, owner, repo, attempt + 1).await
-                }
-                _ => Err(e),
-            }
+            let Some(attempt) = retry_after_error(&e, attempt).await else {
+                return Err(e);
+            };
+            get_pull_body(number, owner, repo, attempt).await
         }
     }
 }
@@ -100,509 +737,7084 @@ async fn get_pull_comments_page(
     page: u32,
     owner: String,
     repo: String,
-    attempt: u8,
-) -> octocrab::Result<Page<pulls::Comment>> {
-    match octocrab::instance()
-        .pulls(owner.clone(), repo.clone())
-        .list_comments(Some(number))
-        .per_page(MAX_PER_PAGE)
-        .page(page)
-        .send()
-        .await
-    {
+    attempt: Attempt,
+) -> Result<(Page<serde_json::Value>, Option<String>), FetchError> {
+    match api_get_tagged(pull_comments_route(&owner, &repo, number, page), None).await {
         Ok(p) => Ok(p),
         Err(e) => {
-            match e {
-                octocrab::Error::GitHub { .. } => {
-                    if attempt > 0 {
-                        return Err(e);
-                    }
-                    // retry once incase we hit the rate-limiting
-                    wait_on_ratelimit().await;
-                    get_pull_comments_page(number, page, owner, repo, attempt + 1).await
-                }
-                _ => Err(e),
-            }
+            let Some(attempt) = retry_after_error(&e, attempt).await else {
+                return Err(e);
+            };
+            get_pull_comments_page(number, page, owner, repo, attempt).await
         }
     }
 }
 
-async fn get_pull_comments(
+#[async_recursion]
+async fn get_pull_reviews_page(
     number: u64,
+    page: u32,
     owner: String,
     repo: String,
-) -> Result<Vec<models::pulls::Comment>, octocrab::Error> {
-    let mut comments = Vec::<models::pulls::Comment>::new();
-
-    for page in 1..u32::MAX {
-        match get_pull_comments_page(number, page, owner.clone(), repo.clone(), 0).await {
-            Ok(mut comments_page) => {
-                comments.append(&mut comments_page.take_items());
-
-                debug!(
-                    "Loaded {} comments for pull {} in {}:{}",
-                    comments.len(),
-                    number,
-                    owner,
-                    repo
-                );
-
-                if comments_page.next.is_none() {
-                    return Ok(comments);
-                }
-            }
-            Err(e) => return Err(e),
+    attempt: Attempt,
+) -> Result<Page<serde_json::Value>, FetchError> {
+    match api_get(format!(
+        "/repos/{}/{}/pulls/{}/reviews?per_page={}&page={}",
+        owner, repo, number, MAX_PER_PAGE, page
+    ))
+    .await
+    {
+        Ok(p) => Ok(p),
+        Err(e) => {
+            let Some(attempt) = retry_after_error(&e, attempt).await else {
+                return Err(e);
+            };
+            get_pull_reviews_page(number, page, owner, repo, attempt).await
         }
     }
-
-    Ok(comments)
 }
 
-#[async_recursion]
-async fn get_timeline_page(
+/// Fetches the raw reviews of a pull-request with the reviews API.
+async fn get_pull_reviews(
     number: u64,
-    page: u32,
     owner: String,
     repo: String,
-    attempt: u8,
-) -> octocrab::Result<Page<octocrab::models::timelines::TimelineEvent>> {
-    match octocrab::instance()
-        .issues(owner.clone(), repo.clone())
-        .list_timeline_events(number)
-        .per_page(MAX_PER_PAGE)
-        .page(page)
-        .send()
-        .await
-    {
-        Ok(p) => Ok(p),
-        Err(e) => {
-            match e {
-                octocrab::Error::GitHub { .. } => {
-                    if attempt > 0 {
-                        return Err(e);
-                    }
-                    // retry once incase we hit the rate-limiting
-                    wait_on_ratelimit().await;
-                    get_timeline_page(number, page, owner, repo, attempt + 1).await
-                }
-                _ => Err(e),
-            }
+) -> Result<Vec<serde_json::Value>, FetchError> {
+    let mut reviews = vec![];
+    for page in START_PAGE..u32::MAX {
+        let mut reviews_page =
+            get_pull_reviews_page(number, page, owner.clone(), repo.clone(), Attempt::FIRST)
+                .await?;
+        reviews.append(&mut reviews_page.take_items());
+        if reviews_page.next.is_none() {
+            break;
         }
     }
+    Ok(reviews)
 }
 
-async fn get_timeline(
+/// Fetches the review comments of a pull-request into `progress`, starting
+/// after the pages fetched before.
+async fn get_pull_comments_raw(
     number: u64,
     owner: String,
     repo: String,
-) -> Result<Vec<models::timelines::TimelineEvent>, octocrab::Error> {
-    let mut events = Vec::<models::timelines::TimelineEvent>::new();
+    progress: &mut PageProgress,
+) -> Result<(), FetchError> {
+    if progress.truncated {
+        return Ok(());
+    }
+    for page in (progress.pages + 1)..u32::MAX {
+        let (mut comments_page, etag) =
+            get_pull_comments_page(number, page, owner.clone(), repo.clone(), Attempt::FIRST)
+                .await?;
+        let last = comments_page.next.is_none();
+        record_page_etag(progress, etag, comments_page.items.len(), last);
+        progress.items.append(&mut comments_page.take_items());
+        progress.pages = page;
 
-    for page in 1..u32::MAX {
-        match get_timeline_page(number, page, owner.clone(), repo.clone(), 0).await {
-            Ok(mut events_page) => {
-                events.append(&mut events_page.take_items());
+        debug!(
+            "Loaded {} comments for pull {} in {}:{}",
+            progress.items.len(),
+            number,
+            owner,
+            repo
+        );
 
-                debug!(
-                    "loaded {} events for issue {} in {}:{}",
-                    events.len(),
-                    number,
-                    owner,
-                    repo
-                );
+        if comments_page.next.is_none() {
+            break;
+        }
+        if args().max_comment_pages.is_some_and(|max| page >= max) {
+            warn!(
+                "Pull {} in {}:{} has more than {} pages of review comments: only these are kept (--max-comment-pages)",
+                number, owner, repo, page
+            );
+            progress.truncated = true;
+            break;
+        }
+    }
+    Ok(())
+}
 
-                if events_page.next.is_none() {
-                    return Ok(events);
+/// Removes the raw `items` that have the same `key` as an earlier one,
+/// keeping the latest version by `updated_at` in place of the first, or the
+/// first if they have no `updated_at`. Items without a key are kept. Returns
+/// the number of dropped items.
+fn dedupe_items(
+    items: &mut Vec<serde_json::Value>,
+    key: impl Fn(&serde_json::Value) -> Option<String>,
+) -> usize {
+    let mut first: BTreeMap<String, usize> = BTreeMap::new();
+    let mut duplicates: Vec<usize> = vec![];
+    for i in 0..items.len() {
+        let id = match key(&items[i]) {
+            Some(id) => id,
+            None => continue,
+        };
+        match first.get(&id) {
+            Some(&j) => {
+                if items[i]["updated_at"].as_str() > items[j]["updated_at"].as_str() {
+                    items.swap(i, j);
                 }
+                duplicates.push(i);
+            }
+            None => {
+                first.insert(id, i);
             }
-            Err(e) => return Err(e),
         }
     }
+    for i in duplicates.iter().rev() {
+        items.remove(*i);
+    }
+    duplicates.len()
+}
 
-    Ok(events)
+async fn get_pull_comments(
+    number: u64,
+    owner: String,
+    repo: String,
+    progress: &mut PageProgress,
+) -> Result<Vec<models::pulls::Comment>, FetchError> {
+    get_pull_comments_raw(number, owner, repo, progress).await?;
+    if args().dedupe_comments {
+        let dropped = dedupe_items(&mut progress.items, |comment| {
+            comment["id"].as_u64().map(|id| id.to_string())
+        });
+        if dropped > 0 {
+            warn!(
+                "Dropped {} duplicated review comments of pull #{}",
+                dropped, number
+            );
+        }
+    }
+    progress
+        .items
+        .iter()
+        .map(|comment| {
+            parse_checked(
+                &format!("A review comment of pull #{}", number),
+                comment.clone(),
+            )
+        })
+        .collect()
 }
 
 #[async_recursion]
-async fn get_issue_page(
+async fn get_check_runs_page(
+    sha: String,
     page: u32,
-    since: Option<DateTime<Utc>>,
     owner: String,
     repo: String,
-    attempt: u8,
-) -> octocrab::Result<Page<octocrab::models::issues::Issue>> {
-    let mut sort = params::issues::Sort::Created;
-    // if we have a since DateTime, sort by when the Issue was last updated
-    if since.is_some() {
-        sort = params::issues::Sort::Updated;
-    }
-
-    match octocrab::instance()
-        .issues(&owner, &repo)
-        .list()
-        .per_page(100)
-        .direction(params::Direction::Ascending)
-        .sort(sort)
-        .since(since.unwrap_or_default())
-        .state(params::State::All)
-        .page(page)
-        .send()
-        .await
+    attempt: Attempt,
+) -> Result<CheckRunsPage, FetchError> {
+    match api_get(format!(
+        "/repos/{}/{}/commits/{}/check-runs?per_page={}&page={}",
+        owner, repo, sha, MAX_PER_PAGE, page
+    ))
+    .await
     {
         Ok(p) => Ok(p),
         Err(e) => {
-            match e {
-                octocrab::Error::GitHub { .. } => {
-                    if attempt > 0 {
-                        return Err(e);
-                    }
-                    // retry once incase we hit the rate-limiting
-                    wait_on_ratelimit().await;
-                    get_issue_page(page, since, owner, repo, attempt + 1).await
-                }
-                _ => Err(e),
-            }
+            let Some(attempt) = retry_after_error(&e, attempt).await else {
+                return Err(e);
+            };
+            get_check_runs_page(sha, page, owner, repo, attempt).await
         }
     }
 }
 
-async fn get_pull(
-    number: u64,
+#[async_recursion]
+async fn get_combined_status(
+    sha: String,
     owner: String,
     repo: String,
-) -> Result<EntryWithMetadata, octocrab::Error> {
-    let body_future = get_pull_body(number, owner.clone(), repo.clone(), 0);
-    let events_future = get_timeline(number, owner.clone(), repo.clone());
-    let comments_future = get_pull_comments(number, owner, repo);
-
-    let pull = match body_future.await {
-        Ok(pull) => pull,
-        Err(e) => {
-            error!("Error in get_pull_body() for pull={}: {}", number, e);
-            return Err(e);
-        }
-    };
-    let events = match events_future.await {
-        Ok(events) => events,
-        Err(e) => {
-            error!("Error in get_timeline() for pull={}: {}", number, e);
-            return Err(e);
-        }
-    };
-    let comments = match comments_future.await {
-        Ok(events) => events,
+    attempt: Attempt,
+) -> Result<serde_json::Value, FetchError> {
+    match api_get(format!(
+        "/repos/{}/{}/commits/{}/status?per_page={}",
+        owner, repo, sha, MAX_PER_PAGE
+    ))
+    .await
+    {
+        Ok(s) => Ok(s),
         Err(e) => {
-            error!("Error in get_pull_comments() for pull={}: {}", number, e);
-            return Err(e);
+            let Some(attempt) = retry_after_error(&e, attempt).await else {
+                return Err(e);
+            };
+            get_combined_status(sha, owner, repo, attempt).await
         }
-    };
+    }
+}
 
-    Ok(EntryWithMetadata::Pull(PullWithMetadata::new(
-        pull, events, comments,
-    )))
+/// Returns true if the error is GitHub reporting that a commit doesn't
+/// exist (anymore).
+fn is_missing_commit(e: &FetchError) -> bool {
+    match e {
+        FetchError::Octocrab(e) => match e.as_ref() {
+            octocrab::Error::GitHub { source, .. } => {
+                source.status_code == http::StatusCode::NOT_FOUND
+                    || source.status_code == http::StatusCode::UNPROCESSABLE_ENTITY
+            }
+            _ => false,
+        },
+        _ => false,
+    }
 }
 
-async fn get_issue(
-    issue: issues::Issue,
-    number: u64,
+#[async_recursion]
+async fn get_commit(
+    sha: String,
     owner: String,
     repo: String,
-) -> Result<EntryWithMetadata, octocrab::Error> {
-    let events_future = get_timeline(number, owner.clone(), repo.clone());
-
-    let events = match events_future.await {
-        Ok(events) => events,
+    attempt: Attempt,
+) -> Result<serde_json::Value, FetchError> {
+    match api_get(format!("/repos/{}/{}/commits/{}", owner, repo, sha)).await {
+        Ok(c) => Ok(c),
         Err(e) => {
-            error!("Error in get_timeline() for issue={}: {}", number, e);
-            return Err(e);
+            let Some(attempt) = retry_after_error(&e, attempt).await else {
+                return Err(e);
+            };
+            get_commit(sha, owner, repo, attempt).await
         }
-    };
+    }
+}
 
-    Ok(EntryWithMetadata::Issue(IssueWithMetadata::new(
-        issue, events,
-    )))
+/// Fetches the messages of the commits that referenced an issue or
+/// pull-request. A commit that isn't available anymore, for example because
+/// it was only pushed to a since deleted fork, keeps no message.
+async fn resolve_commit_messages(references: &mut [CommitReference]) -> Result<(), FetchError> {
+    for reference in references.iter_mut() {
+        let (Some(sha), Some(repository)) = (&reference.commit_id, &reference.repository) else {
+            continue;
+        };
+        match get_commit(
+            sha.clone(),
+            repository.owner.clone(),
+            repository.name.clone(),
+            Attempt::FIRST,
+        )
+        .await
+        {
+            Ok(commit) => {
+                reference.message = commit["commit"]["message"].as_str().map(str::to_string)
+            }
+            Err(e) if is_missing_commit(&e) => warn!(
+                "Commit {} in {}/{} is not available: not recording its message.",
+                sha, repository.owner, repository.name
+            ),
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(())
 }
 
-async fn get_issues_and_pulls(
-    sender: mpsc::Sender<EntryWithMetadata>,
-    since: Option<DateTime<Utc>>,
+/// Fetches the check-runs and the combined commit status of the head commit
+/// of a pull-request. Returns `None` if the head commit isn't available
+/// anymore, for example when the pull-request was opened from a since
+/// deleted fork and the commit was garbage collected.
+async fn get_pr_checks(
+    number: u64,
+    sha: String,
     owner: String,
     repo: String,
-) -> Result<(), octocrab::Error> {
-    let mut loaded_issues: usize = 0;
-    let mut loaded_pulls: usize = 0;
-    let mut failed_issues: Vec<u64> = Vec::new();
-    let mut failed_pulls: Vec<u64> = Vec::new();
-    info!(
-        "Start to load issues and pulls for {}:{} from GitHub",
-        owner, repo
+) -> Result<Option<PullChecks>, FetchError> {
+    let mut check_runs = Vec::<serde_json::Value>::new();
+
+    for page in 1..u32::MAX {
+        match get_check_runs_page(
+            sha.clone(),
+            page,
+            owner.clone(),
+            repo.clone(),
+            Attempt::FIRST,
+        )
+        .await
+        {
+            Ok(mut check_runs_page) => {
+                let count = check_runs_page.check_runs.len();
+                check_runs.append(&mut check_runs_page.check_runs);
+                if count < MAX_PER_PAGE as usize {
+                    break;
+                }
+            }
+            Err(e) if is_missing_commit(&e) => {
+                warn!(
+                    "Head commit {} of pull {} in {}:{} is not available: not recording checks.",
+                    sha, number, owner, repo
+                );
+                return Ok(None);
+            }
+            Err(e) => return Err(e),
+        }
+    }
+
+    let combined_status = match get_combined_status(sha.clone(), owner, repo, Attempt::FIRST).await
+    {
+        Ok(status) => status,
+        Err(e) if is_missing_commit(&e) => return Ok(None),
+        Err(e) => return Err(e),
+    };
+
+    Ok(Some(PullChecks {
+        sha,
+        check_runs,
+        combined_status,
+    }))
+}
+
+/// Identifies a raw timeline event for `--dedupe-events`: by its `node_id`,
+/// its `id`, or the `sha` of a `committed` event, which has neither.
+fn event_key(event: &serde_json::Value) -> Option<String> {
+    event["node_id"]
+        .as_str()
+        .map(str::to_string)
+        .or_else(|| event["id"].as_u64().map(|id| id.to_string()))
+        .or_else(|| {
+            Some(format!(
+                "{}:{}",
+                event["event"].as_str()?,
+                event["sha"].as_str()?
+            ))
+        })
+}
+
+async fn get_timeline(
+    number: u64,
+    owner: String,
+    repo: String,
+    progress: &mut PageProgress,
+) -> Result<Vec<models::timelines::TimelineEvent>, FetchError> {
+    get_timeline_raw(number, owner, repo, progress).await?;
+    if args().dedupe_events {
+        let dropped = dedupe_items(&mut progress.items, event_key);
+        if dropped > 0 {
+            warn!(
+                "Dropped {} duplicated timeline events of #{}",
+                dropped, number
+            );
+        }
+    } else if args().dedupe_comments {
+        let dropped = dedupe_items(&mut progress.items, |event| {
+            match event["event"] == "commented" {
+                true => event["id"].as_u64().map(|id| id.to_string()),
+                false => None,
+            }
+        });
+        if dropped > 0 {
+            warn!("Dropped {} duplicated comments of #{}", dropped, number);
+        }
+    }
+    let mut events = timeline_events(number, &progress.items)?;
+    if !args().event_types.is_empty() {
+        events.retain(|event| keep_event(&event_name(&event.event)));
+    }
+    Ok(events)
+}
+
+/// Parses the raw timeline `items` of #`number`. Events of types octocrab
+/// doesn't know are skipped with a warning, as [unknown_events] keeps them.
+fn timeline_events(
+    number: u64,
+    items: &[serde_json::Value],
+) -> Result<Vec<models::timelines::TimelineEvent>, FetchError> {
+    let mut events = Vec::<models::timelines::TimelineEvent>::new();
+    for event in items.iter() {
+        let name = event["event"].as_str().unwrap_or_default();
+        if !is_known_event(name) {
+            warn!(
+                "Keeping a timeline event of #{} with the unknown type '{}' as raw JSON",
+                number, name
+            );
+            continue;
+        }
+        events.push(parse_checked(
+            &format!("A timeline event of #{}", number),
+            event.clone(),
+        )?);
+    }
+    Ok(events)
+}
+
+/// Deserializes a raw API response into an octocrab model. With `--strict`,
+/// this fails if the model doesn't capture all fields of the response. With
+/// `--detect-schema-changes`, the fields it doesn't capture are recorded for
+/// the report.
+fn parse_checked<T: DeserializeOwned + Serialize>(
+    what: &str,
+    raw: serde_json::Value,
+) -> Result<T, FetchError> {
+    if !args().strict && !args().detect_schema_changes {
+        return Ok(serde_json::from_value(raw)?);
+    }
+    let typed: T = serde_json::from_value(raw.clone())?;
+    let dropped = fidelity::dropped_fields(&raw, &serde_json::to_value(&typed)?);
+    if args().detect_schema_changes {
+        let model = std::any::type_name::<T>()
+            .rsplit("::")
+            .next()
+            .unwrap_or_default();
+        fidelity::record(model, &dropped);
+    }
+    if args().strict && !dropped.is_empty() {
+        error!("{} would lose these fields: {}", what, dropped.join(", "));
+        return Err(FetchError::DroppedFields(format!(
+            "{}: {}",
+            what,
+            dropped.join(", ")
+        )));
+    }
+    Ok(typed)
+}
+
+/// Returns the name of a timeline event type as used by the GitHub API.
+fn event_name(event: &models::Event) -> String {
+    match serde_json::to_value(event) {
+        Ok(serde_json::Value::String(name)) => name,
+        _ => format!("{:?}", event),
+    }
+}
+
+/// Returns true if octocrab knows the timeline event type `name`.
+fn is_known_event(name: &str) -> bool {
+    serde_json::from_value::<models::Event>(serde_json::Value::String(name.to_string())).is_ok()
+}
+
+/// Returns who closed an entry that `is_closed`: the actor of the latest
+/// `closed` timeline event, or the `closed_by` the API `reported` if the
+/// timeline has none. A mismatch between the two is logged.
+fn closed_by(
+    number: u64,
+    is_closed: bool,
+    reported: Option<serde_json::Value>,
+    events: &[serde_json::Value],
+) -> Option<serde_json::Value> {
+    if !is_closed {
+        return None;
+    }
+    let closed_by = history::closed_by(events);
+    if let (Some(actor), Some(reported)) = (&closed_by, &reported) {
+        if actor["login"] != reported["login"] {
+            warn!(
+                "#{} was closed by {} according to its timeline, but by {} according to the API",
+                number, actor["login"], reported["login"]
+            );
+        }
+    }
+    if closed_by.is_none() {
+        warn!(
+            "#{} is closed, but its timeline has no closed event",
+            number
+        );
+    }
+    closed_by.or(reported)
+}
+
+/// Logs if the `locked` state the API reported for an entry doesn't match the
+/// one of its last `locked` or `unlocked` timeline event in the `history`.
+fn check_locked(number: u64, locked: bool, history: &[types::LockEvent]) {
+    match history.last() {
+        Some(last) if last.locked != locked => warn!(
+            "#{} is {} according to the API, but its last timeline event is {}",
+            number,
+            if locked { "locked" } else { "unlocked" },
+            last.event
+        ),
+        None if locked => warn!(
+            "#{} is locked, but its timeline has no locked event",
+            number
+        ),
+        _ => {}
+    }
+}
+
+/// Returns the raw timeline events of types octocrab doesn't know, which
+/// `get_timeline()` can't parse. With `--event-types`, they are only kept
+/// with `--keep-unknown-events`.
+fn unknown_events(events: &[serde_json::Value]) -> Vec<serde_json::Value> {
+    events
+        .iter()
+        .filter(|event| {
+            let name = event["event"].as_str().unwrap_or_default();
+            !is_known_event(name) && (args().event_types.is_empty() || keep_event(name))
+        })
+        .cloned()
+        .collect()
+}
+
+/// Decides if a timeline event of type `name` is stored with the
+/// `--event-types` allowlist.
+fn keep_event(name: &str) -> bool {
+    args().event_types.iter().any(|t| t == name)
+        || (args().keep_unknown_events && !is_known_event(name))
+}
+
+#[async_recursion]
+async fn get_timeline_page_raw(
+    number: u64,
+    page: u32,
+    owner: String,
+    repo: String,
+    attempt: Attempt,
+) -> Result<(Page<serde_json::Value>, Option<String>), FetchError> {
+    match api_get_tagged(timeline_route(&owner, &repo, number, page), None).await {
+        Ok(p) => Ok(p),
+        Err(e) => {
+            let Some(attempt) = retry_after_error(&e, attempt).await else {
+                return Err(e);
+            };
+            get_timeline_page_raw(number, page, owner, repo, attempt).await
+        }
+    }
+}
+
+/// Like [get_timeline], but fetches the events as raw JSON into `progress`,
+/// starting after the pages fetched before. This works for event types
+/// octocrab doesn't know (yet), which make [get_timeline] fail.
+async fn get_timeline_raw(
+    number: u64,
+    owner: String,
+    repo: String,
+    progress: &mut PageProgress,
+) -> Result<(), FetchError> {
+    if progress.truncated {
+        return Ok(());
+    }
+    for page in (progress.pages + 1)..u32::MAX {
+        let (mut events_page, etag) =
+            get_timeline_page_raw(number, page, owner.clone(), repo.clone(), Attempt::FIRST)
+                .await?;
+        let last = events_page.next.is_none();
+        record_page_etag(progress, etag, events_page.items.len(), last);
+        progress.items.append(&mut events_page.take_items());
+        progress.pages = page;
+
+        debug!(
+            "loaded {} raw events for issue {} in {}:{}",
+            progress.items.len(),
+            number,
+            owner,
+            repo
+        );
+
+        if events_page.next.is_none() {
+            break;
+        }
+        if args().max_event_pages.is_some_and(|max| page >= max) {
+            warn!(
+                "#{} in {}:{} has more than {} timeline pages: only these are kept (--max-event-pages)",
+                number, owner, repo, page
+            );
+            progress.truncated = true;
+            break;
+        }
+    }
+    Ok(())
+}
+
+/// Whether the issue pages are listed in the order of the last update of the
+/// issues, rather than their creation.
+fn is_sorted_by_update(since: Option<DateTime<Utc>>) -> bool {
+    match args().fetch_order {
+        // Issue and pull-request numbers are assigned on creation.
+        Some(FetchOrder::Created) | Some(FetchOrder::Number) => false,
+        Some(FetchOrder::Updated) => true,
+        // if we have a since DateTime, sort by when the Issue was last updated
+        None => since.is_some(),
+    }
+}
+
+#[async_recursion]
+async fn get_issue_page(
+    page: u32,
+    since: Option<DateTime<Utc>>,
+    owner: String,
+    repo: String,
+    attempt: Attempt,
+) -> Result<Page<serde_json::Value>, FetchError> {
+    let sort = match is_sorted_by_update(since) {
+        true => "updated",
+        false => "created",
+    };
+
+    match api_get(format!(
+        "/repos/{}/{}/issues?per_page={}&direction=asc&sort={}&since={}&state=all&page={}",
+        owner,
+        repo,
+        MAX_PER_PAGE,
+        sort,
+        since
+            .unwrap_or_default()
+            .to_rfc3339_opts(SecondsFormat::Secs, true),
+        page
+    ))
+    .await
+    {
+        Ok(p) => Ok(p),
+        Err(e) => {
+            let Some(attempt) = retry_after_error(&e, attempt).await else {
+                return Err(e);
+            };
+            get_issue_page(page, since, owner, repo, attempt).await
+        }
+    }
+}
+
+#[async_recursion]
+async fn get_links_page(
+    number: u64,
+    first: u8,
+    after: Option<String>,
+    owner: String,
+    repo: String,
+    attempt: Attempt,
+) -> Result<serde_json::Value, FetchError> {
+    let variables = serde_json::json!({
+        "owner": owner,
+        "repo": repo,
+        "number": number,
+        "first": first,
+        "after": after,
+    });
+    match api_graphql(history::LINKS_QUERY, variables).await {
+        Ok(data) => Ok(data),
+        Err(e) => {
+            let Some(attempt) = retry_after_error(&e, attempt).await else {
+                return Err(e);
+            };
+            get_links_page(number, first, after, owner, repo, attempt).await
+        }
+    }
+}
+
+/// Fetches the issues and pull-requests linked to the entry `number`, with a
+/// request per `--graphql-page-size` link events.
+async fn get_links(number: u64, owner: String, repo: String) -> Result<history::Links, FetchError> {
+    let mut pages = vec![];
+    let mut after = None;
+    let mut first = args().graphql_page_size;
+    loop {
+        let page = match get_links_page(
+            number,
+            first,
+            after.clone(),
+            owner.clone(),
+            repo.clone(),
+            Attempt::FIRST,
+        )
+        .await
+        {
+            Ok(page) => page,
+            Err(e) if is_graphql_limit(&e) && first > 1 => {
+                first /= 2;
+                warn!(
+                    "The links query of #{} exceeds the GraphQL limits. Retrying with pages of {}.",
+                    number, first
+                );
+                continue;
+            }
+            Err(e) => return Err(e),
+        };
+        after = history::links_next_page(&page);
+        pages.push(page);
+        if after.is_none() {
+            return Ok(history::links(&pages));
+        }
+    }
+}
+
+#[async_recursion]
+async fn get_cross_refs_page(
+    number: u64,
+    first: u8,
+    after: Option<String>,
+    owner: String,
+    repo: String,
+    attempt: Attempt,
+) -> Result<serde_json::Value, FetchError> {
+    let variables = serde_json::json!({
+        "owner": owner,
+        "repo": repo,
+        "number": number,
+        "first": first,
+        "after": after,
+    });
+    match api_graphql(history::CROSS_REFS_QUERY, variables).await {
+        Ok(data) => Ok(data),
+        Err(e) => {
+            let Some(attempt) = retry_after_error(&e, attempt).await else {
+                return Err(e);
+            };
+            get_cross_refs_page(number, first, after, owner, repo, attempt).await
+        }
+    }
+}
+
+/// Whether a GraphQL query was rejected for requesting too many nodes or
+/// exceeding the resource limits. A smaller page size can succeed.
+fn is_graphql_limit(e: &FetchError) -> bool {
+    match e {
+        FetchError::GraphQL(errors) => {
+            errors.contains("MAX_NODE_LIMIT_EXCEEDED")
+                || errors.contains("RESOURCE_LIMITS_EXCEEDED")
+        }
+        _ => false,
+    }
+}
+
+/// Fetches the issues and pull-requests referencing an issue or
+/// pull-request, including those in other repositories.
+async fn get_cross_refs(
+    number: u64,
+    owner: String,
+    repo: String,
+) -> Result<Vec<CrossReference>, FetchError> {
+    let mut references = vec![];
+    let mut after = None;
+    let mut first = args().graphql_page_size;
+    loop {
+        let data = match get_cross_refs_page(
+            number,
+            first,
+            after.clone(),
+            owner.clone(),
+            repo.clone(),
+            Attempt::FIRST,
+        )
+        .await
+        {
+            Ok(data) => data,
+            Err(e) if is_graphql_limit(&e) && first > 1 => {
+                first /= 2;
+                warn!(
+                    "The cross-references query of #{} exceeds the GraphQL limits. Retrying with pages of {}.",
+                    number, first
+                );
+                continue;
+            }
+            Err(e) => return Err(e),
+        };
+        let (mut page, next) = history::cross_references(&data);
+        references.append(&mut page);
+        match next {
+            Some(next) => after = Some(next),
+            None => break,
+        }
+    }
+    debug!(
+        "Loaded {} cross-references to #{} in {}:{}",
+        references.len(),
+        number,
+        owner,
+        repo
+    );
+    Ok(references)
+}
+
+#[async_recursion]
+async fn get_transfers(
+    number: u64,
+    owner: String,
+    repo: String,
+    attempt: Attempt,
+) -> Result<serde_json::Value, FetchError> {
+    let variables = serde_json::json!({ "owner": owner, "repo": repo, "number": number });
+    match api_graphql(history::TRANSFERS_QUERY, variables).await {
+        Ok(data) => Ok(data),
+        Err(e) => {
+            let Some(attempt) = retry_after_error(&e, attempt).await else {
+                return Err(e);
+            };
+            get_transfers(number, owner, repo, attempt).await
+        }
+    }
+}
+
+#[async_recursion]
+async fn get_comment_moderation_page(
+    query: &'static str,
+    number: u64,
+    first: u8,
+    after: Option<String>,
+    owner: String,
+    repo: String,
+    attempt: Attempt,
+) -> Result<serde_json::Value, FetchError> {
+    let variables = serde_json::json!({
+        "owner": owner,
+        "repo": repo,
+        "number": number,
+        "first": first,
+        "after": after,
+    });
+    match api_graphql(query, variables).await {
+        Ok(data) => Ok(data),
+        Err(e) => {
+            let Some(attempt) = retry_after_error(&e, attempt).await else {
+                return Err(e);
+            };
+            get_comment_moderation_page(query, number, first, after, owner, repo, attempt).await
+        }
+    }
+}
+
+/// Fetches the minimized comments of an issue or pull-request. The comments
+/// on the conversation are queried for both, the review comments only for
+/// pull-requests.
+async fn get_minimized_comments(
+    number: u64,
+    is_pull: bool,
+    owner: String,
+    repo: String,
+) -> Result<Vec<MinimizedComment>, FetchError> {
+    let mut minimized = vec![];
+    let mut unknown = 0;
+    let queries = match is_pull {
+        true => vec![
+            (history::COMMENT_MODERATION_QUERY, false),
+            (history::REVIEW_COMMENT_MODERATION_QUERY, true),
+        ],
+        false => vec![(history::COMMENT_MODERATION_QUERY, false)],
+    };
+    for (query, review) in queries {
+        let mut after = None;
+        let mut first = args().graphql_page_size;
+        loop {
+            let data = match get_comment_moderation_page(
+                query,
+                number,
+                first,
+                after.clone(),
+                owner.clone(),
+                repo.clone(),
+                Attempt::FIRST,
+            )
+            .await
+            {
+                Ok(data) => data,
+                Err(e) if is_graphql_limit(&e) && first > 1 => {
+                    first /= 2;
+                    warn!(
+                        "The comment moderation query of #{} exceeds the GraphQL limits. Retrying with pages of {}.",
+                        number, first
+                    );
+                    continue;
+                }
+                Err(e) => return Err(e),
+            };
+            let mut page = history::minimized_comments(&data, review);
+            minimized.append(&mut page.minimized);
+            unknown += page.unknown;
+            if page.truncated {
+                warn!(
+                    "A review thread of #{} in {}:{} has more than 100 comments: only the first 100 are checked for being minimized.",
+                    number, owner, repo
+                );
+            }
+            match page.next {
+                Some(next) => after = Some(next),
+                None => break,
+            }
+        }
+    }
+    if unknown > 0 {
+        debug!(
+            "GitHub didn't report whether {} comments of #{} in {}:{} are minimized",
+            unknown, number, owner, repo
+        );
+    }
+    Ok(minimized)
+}
+
+async fn get_pull(
+    number: u64,
+    owner: String,
+    repo: String,
+    checkpoint: &mut Checkpoint,
+) -> Result<EntryWithMetadata, FetchError> {
+    let body_future = get_pull_body(number, owner.clone(), repo.clone(), Attempt::FIRST);
+    let events_future = get_timeline(
+        number,
+        owner.clone(),
+        repo.clone(),
+        &mut checkpoint.timeline,
+    );
+    let comments_future = get_pull_comments(
+        number,
+        owner.clone(),
+        repo.clone(),
+        &mut checkpoint.comments,
+    );
+
+    let reviews_future = get_pull_reviews(number, owner.clone(), repo.clone());
+
+    let (pull, events, comments, listed_reviews) =
+        tokio::join!(body_future, events_future, comments_future, reviews_future);
+    let (pull, pull_etag) = match pull {
+        Ok((pull, etag)) => (Ok(pull), etag),
+        Err(e) => (Err(e), None),
+    };
+
+    let raw_pull = match (&pull, &args().dump_raw_dir) {
+        (Ok(pull), Some(_)) => Some(pull.clone()),
+        _ => None,
+    };
+    let pull: pulls::PullRequest =
+        match pull.and_then(|pull| parse_checked(&format!("Pull-request #{}", number), pull)) {
+            Ok(pull) => pull,
+            Err(e) => {
+                error!("Error in get_pull_body() for pull={}: {}", number, e);
+                return Err(e);
+            }
+        };
+    let events = match events {
+        Ok(events) => events,
+        Err(e) => {
+            error!("Error in get_timeline() for pull={}: {}", number, e);
+            return Err(e);
+        }
+    };
+    let comments = match comments {
+        Ok(events) => events,
+        Err(e) => {
+            error!("Error in get_pull_comments() for pull={}: {}", number, e);
+            return Err(e);
+        }
+    };
+    let listed_reviews = match listed_reviews {
+        Ok(reviews) => reviews,
+        Err(e) => {
+            error!("Error in get_pull_reviews() for pull={}: {}", number, e);
+            return Err(e);
+        }
+    };
+
+    let head_sha = pull.head.sha.clone();
+    let base_sha = pull.base.sha.clone();
+    let head_ref = pull.head.ref_field.clone();
+    let base_ref = pull.base.ref_field.clone();
+    let repository = RepositoryRef {
+        owner: owner.clone(),
+        name: repo.clone(),
+    };
+    let mut pull = PullWithMetadata::new(repository, pull, events, comments);
+    // Completed with the other ETags by fetch_entry().
+    if args().use_etags {
+        pull.etags = Some(EntryEtags {
+            pull: pull_etag,
+            ..Default::default()
+        });
+    }
+    pull.project_history = history::project_history(&checkpoint.timeline.items);
+    pull.assignment_history = history::assignment_history(&checkpoint.timeline.items);
+    pull.milestone_history = history::milestone_history(&checkpoint.timeline.items);
+    pull.label_history = history::label_history(&checkpoint.timeline.items, args().keep_raw_events);
+    pull.closed_by = closed_by(
+        number,
+        pull.pull.state == Some(models::IssueState::Closed),
+        None,
+        &checkpoint.timeline.items,
+    );
+    pull.unknown_events = unknown_events(&checkpoint.timeline.items);
+    pull.performed_via_github_app =
+        history::github_apps(&checkpoint.timeline.items, &checkpoint.comments.items);
+    pull.moderation_history =
+        history::moderation_history(&checkpoint.timeline.items, args().keep_raw_events);
+    pull.deployment_history =
+        history::deployment_history(&checkpoint.timeline.items, args().keep_raw_events);
+    pull.ref_history = history::ref_history(
+        &checkpoint.timeline.items,
+        &head_ref,
+        &base_ref,
+        args().keep_raw_events,
+    );
+    pull.draft_history = history::draft_history(&checkpoint.timeline.items, args().keep_raw_events);
+    pull.lock_history = history::lock_history(&checkpoint.timeline.items, args().keep_raw_events);
+    if !checkpoint.timeline.truncated {
+        check_locked(number, pull.pull.locked, &pull.lock_history);
+    }
+    pull.commit_references =
+        history::commit_references(&checkpoint.timeline.items, args().keep_raw_events);
+    if args().resolve_referencing_commits {
+        if let Err(e) = resolve_commit_messages(&mut pull.commit_references).await {
+            error!(
+                "Error in resolve_commit_messages() for pull={}: {}",
+                number, e
+            );
+            return Err(e);
+        }
+    }
+    let (reviews, unmatched) = history::reviews(&listed_reviews, &checkpoint.comments.items);
+    if !unmatched.is_empty() {
+        warn!(
+            "Pull-request #{} has review comments of reviews that are missing from its reviews: {:?}",
+            number, unmatched
+        );
+    }
+    pull.reviews = reviews;
+    if args().head_sha_resolve {
+        pull.sha_history = Some(history::sha_history(
+            &checkpoint.timeline.items,
+            &base_sha,
+            &head_sha,
+        ));
+    }
+    pull.truncated = checkpoint.timeline.truncated || checkpoint.comments.truncated;
+    if args().include_timeline_pagination_meta {
+        pull.meta = Some(PaginationMeta {
+            timeline_pages: checkpoint.timeline.pages,
+            timeline_events: checkpoint.timeline.items.len(),
+            comments_pages: Some(checkpoint.comments.pages),
+            comments: Some(checkpoint.comments.items.len()),
+        });
+    }
+    if raw_pull.is_some() {
+        pull.raw = Some(RawEntry {
+            pull: raw_pull,
+            ..Default::default()
+        });
+    }
+    if args().include_checks {
+        match get_pr_checks(number, head_sha, owner.clone(), repo.clone()).await {
+            Ok(checks) => pull.checks = checks,
+            Err(e) => {
+                error!("Error in get_pr_checks() for pull={}: {}", number, e);
+                return Err(e);
+            }
+        }
+    }
+    if args().render_html {
+        if let Err(e) = render_pull_html(&mut pull, &owner, &repo).await {
+            error!("Error in render_pull_html() for pull={}: {}", number, e);
+            return Err(e);
+        }
+    }
+    if args().resolve_cross_refs {
+        match get_cross_refs(number, owner.clone(), repo.clone()).await {
+            Ok(references) => pull.cross_references = Some(references),
+            Err(e) => {
+                error!("Error in get_cross_refs() for pull={}: {}", number, e);
+                return Err(e);
+            }
+        }
+    }
+    if args().include_comment_moderation {
+        match get_minimized_comments(number, true, owner.clone(), repo.clone()).await {
+            Ok(minimized) => pull.minimized_comments = Some(minimized),
+            Err(e) => {
+                error!(
+                    "Error in get_minimized_comments() for pull={}: {}",
+                    number, e
+                );
+                return Err(e);
+            }
+        }
+    }
+    if args().include_links {
+        match get_links(number, owner, repo).await {
+            Ok(links) => {
+                pull.linked_issues = Some(links.issues);
+                pull.linked_prs = Some(links.pulls);
+            }
+            Err(e) => {
+                error!("Error in get_links() for pull={}: {}", number, e);
+                return Err(e);
+            }
+        }
+    }
+
+    Ok(EntryWithMetadata::Pull(Box::new(pull)))
+}
+
+async fn get_issue(
+    issue: issues::Issue,
+    number: u64,
+    owner: String,
+    repo: String,
+    checkpoint: &mut Checkpoint,
+) -> Result<EntryWithMetadata, FetchError> {
+    let events_future = get_timeline(
+        number,
+        owner.clone(),
+        repo.clone(),
+        &mut checkpoint.timeline,
+    );
+
+    let events = match events_future.await {
+        Ok(events) => events,
+        Err(e) => {
+            error!("Error in get_timeline() for issue={}: {}", number, e);
+            return Err(e);
+        }
+    };
+
+    let repository = RepositoryRef {
+        owner: owner.clone(),
+        name: repo.clone(),
+    };
+    let mut issue = IssueWithMetadata::new(repository, issue, events);
+    issue.project_history = history::project_history(&checkpoint.timeline.items);
+    issue.assignment_history = history::assignment_history(&checkpoint.timeline.items);
+    issue.milestone_history = history::milestone_history(&checkpoint.timeline.items);
+    issue.label_history =
+        history::label_history(&checkpoint.timeline.items, args().keep_raw_events);
+    issue.closed_by = closed_by(
+        number,
+        issue.issue.state == models::IssueState::Closed,
+        serde_json::to_value(&issue.issue.closed_by)
+            .ok()
+            .filter(serde_json::Value::is_object),
+        &checkpoint.timeline.items,
+    );
+    issue.unknown_events = unknown_events(&checkpoint.timeline.items);
+    issue.performed_via_github_app = history::github_apps(&checkpoint.timeline.items, &[]);
+    issue.moderation_history =
+        history::moderation_history(&checkpoint.timeline.items, args().keep_raw_events);
+    issue.lock_history = history::lock_history(&checkpoint.timeline.items, args().keep_raw_events);
+    if !checkpoint.timeline.truncated {
+        check_locked(number, issue.issue.locked, &issue.lock_history);
+    }
+    issue.pin_history = history::pin_history(&checkpoint.timeline.items, args().keep_raw_events);
+    issue.pinned = issue.pin_history.last().map(|event| event.pinned);
+    issue.commit_references =
+        history::commit_references(&checkpoint.timeline.items, args().keep_raw_events);
+    if checkpoint
+        .timeline
+        .items
+        .iter()
+        .any(|event| event["event"].as_str() == Some("transferred"))
+    {
+        // The REST timeline doesn't say where the issue came from.
+        let transfers =
+            match get_transfers(number, owner.clone(), repo.clone(), Attempt::FIRST).await {
+                Ok(data) => Some(data),
+                Err(e @ FetchError::RequestBudget(_)) => return Err(e),
+                Err(e) => {
+                    warn!(
+                        "Could not resolve where issue #{} in {}:{} was transferred from: {}",
+                        number, owner, repo, e
+                    );
+                    None
+                }
+            };
+        issue.transfer_history = history::transfer_history(
+            &checkpoint.timeline.items,
+            transfers.as_ref(),
+            &format!("{}/{}", owner, repo),
+            args().keep_raw_events,
+        );
+    }
+    if args().resolve_referencing_commits {
+        if let Err(e) = resolve_commit_messages(&mut issue.commit_references).await {
+            error!(
+                "Error in resolve_commit_messages() for issue={}: {}",
+                number, e
+            );
+            return Err(e);
+        }
+    }
+    issue.truncated = checkpoint.timeline.truncated;
+    if args().include_timeline_pagination_meta {
+        issue.meta = Some(PaginationMeta {
+            timeline_pages: checkpoint.timeline.pages,
+            timeline_events: checkpoint.timeline.items.len(),
+            comments_pages: None,
+            comments: None,
+        });
+    }
+    if args().render_html {
+        if let Err(e) = render_issue_html(&mut issue, &owner, &repo).await {
+            error!("Error in render_issue_html() for issue={}: {}", number, e);
+            return Err(e);
+        }
+    }
+    if args().resolve_cross_refs {
+        match get_cross_refs(number, owner.clone(), repo.clone()).await {
+            Ok(references) => issue.cross_references = Some(references),
+            Err(e) => {
+                error!("Error in get_cross_refs() for issue={}: {}", number, e);
+                return Err(e);
+            }
+        }
+    }
+    if args().include_comment_moderation {
+        match get_minimized_comments(number, false, owner.clone(), repo.clone()).await {
+            Ok(minimized) => issue.minimized_comments = Some(minimized),
+            Err(e) => {
+                error!(
+                    "Error in get_minimized_comments() for issue={}: {}",
+                    number, e
+                );
+                return Err(e);
+            }
+        }
+    }
+    if args().include_links {
+        match get_links(number, owner, repo).await {
+            Ok(links) => {
+                issue.linked_issues = Some(links.issues);
+                issue.linked_prs = Some(links.pulls);
+            }
+            Err(e) => {
+                error!("Error in get_links() for issue={}: {}", number, e);
+                return Err(e);
+            }
+        }
+    }
+
+    Ok(EntryWithMetadata::Issue(Box::new(issue)))
+}
+
+#[async_recursion]
+async fn render_markdown(
+    text: String,
+    owner: String,
+    repo: String,
+    attempt: Attempt,
+) -> Result<String, FetchError> {
+    match api_render_markdown(&text, &format!("{}/{}", owner, repo)).await {
+        Ok(html) => Ok(html),
+        Err(e) => {
+            let Some(attempt) = retry_after_error(&e, attempt).await else {
+                return Err(e);
+            };
+            render_markdown(text, owner, repo, attempt).await
+        }
+    }
+}
+
+/// Renders a markdown body to HTML. Empty bodies aren't rendered to save
+/// API requests.
+async fn render_body(
+    body: Option<&str>,
+    owner: &str,
+    repo: &str,
+) -> Result<Option<String>, FetchError> {
+    match body {
+        Some(body) if !body.is_empty() => Ok(Some(
+            render_markdown(
+                body.to_string(),
+                owner.to_string(),
+                repo.to_string(),
+                Attempt::FIRST,
+            )
+            .await?,
+        )),
+        _ => Ok(None),
+    }
+}
+
+/// Renders the bodies of timeline events (comments and reviews) to HTML,
+/// keyed by the `node_id` of the event.
+async fn render_events_html(
+    events: &[models::timelines::TimelineEvent],
+    owner: &str,
+    repo: &str,
+) -> Result<BTreeMap<String, String>, FetchError> {
+    let mut html = BTreeMap::new();
+    for event in events {
+        if let Some(node_id) = &event.node_id {
+            if let Some(body_html) = render_body(event.body.as_deref(), owner, repo).await? {
+                html.insert(node_id.clone(), body_html);
+            }
+        }
+    }
+    Ok(html)
+}
+
+async fn render_issue_html(
+    issue: &mut IssueWithMetadata,
+    owner: &str,
+    repo: &str,
+) -> Result<(), FetchError> {
+    issue.issue.body_html = render_body(issue.issue.body.as_deref(), owner, repo).await?;
+    issue.events_body_html = Some(render_events_html(&issue.events, owner, repo).await?);
+    Ok(())
+}
+
+async fn render_pull_html(
+    pull: &mut PullWithMetadata,
+    owner: &str,
+    repo: &str,
+) -> Result<(), FetchError> {
+    pull.pull.body_html = render_body(pull.pull.body.as_deref(), owner, repo).await?;
+    pull.events_body_html = Some(render_events_html(&pull.events, owner, repo).await?);
+    let mut comments_html = BTreeMap::new();
+    for comment in pull.comments.iter() {
+        if let Some(body_html) = render_body(Some(&comment.body), owner, repo).await? {
+            comments_html.insert(comment.node_id.clone(), body_html);
+        }
+    }
+    pull.comments_body_html = Some(comments_html);
+    Ok(())
+}
+
+/// Returns how much of the writer buffer an entry occupies. Capped at
+/// `--max-buffered-items` so that a single huge entry can always be sent.
+fn buffer_weight(entry: &EntryWithMetadata) -> u32 {
+    entry.item_count().min(args().max_buffered_items as usize) as u32
+}
+
+/// Sends a fetched entry to the writer. If the entries waiting to be written
+/// already hold `--max-buffered-items` events and comments, this waits until
+/// the writer frees enough of the buffer.
+async fn send_to_writer(
+    sender: &mpsc::Sender<EntryWithMetadata>,
+    buffer: &Semaphore,
+    entry: EntryWithMetadata,
+) {
+    buffer
+        .acquire_many(buffer_weight(&entry))
+        .await
+        .expect("the writer buffer semaphore is never closed")
+        .forget();
+    sender.send(entry).await.unwrap();
+}
+
+#[async_recursion]
+async fn get_issue_by_number(
+    number: u64,
+    owner: String,
+    repo: String,
+    attempt: Attempt,
+) -> Result<serde_json::Value, FetchError> {
+    match api_get(format!("/repos/{}/{}/issues/{}", owner, repo, number)).await {
+        Ok(i) => Ok(i),
+        Err(e) => {
+            let Some(attempt) = retry_after_error(&e, attempt).await else {
+                return Err(e);
+            };
+            get_issue_by_number(number, owner, repo, attempt).await
+        }
+    }
+}
+
+/// Fetches a page of the reactions to the `subject` of a repository, e.g.
+/// `issues/123` or `pulls/comments/456`.
+#[async_recursion]
+async fn get_reactions_page(
+    subject: String,
+    page: u32,
+    owner: String,
+    repo: String,
+    attempt: Attempt,
+) -> Result<Page<serde_json::Value>, FetchError> {
+    match api_get(format!(
+        "/repos/{}/{}/{}/reactions?per_page={}&page={}",
+        owner, repo, subject, MAX_PER_PAGE, page
+    ))
+    .await
+    {
+        Ok(p) => Ok(p),
+        Err(e) => {
+            let Some(attempt) = retry_after_error(&e, attempt).await else {
+                return Err(e);
+            };
+            get_reactions_page(subject, page, owner, repo, attempt).await
+        }
+    }
+}
+
+#[async_recursion]
+async fn get_sub_issues_page(
+    number: u64,
+    page: u32,
+    owner: String,
+    repo: String,
+    attempt: Attempt,
+) -> Result<Page<serde_json::Value>, FetchError> {
+    match api_get(format!(
+        "/repos/{}/{}/issues/{}/sub_issues?per_page={}&page={}",
+        owner, repo, number, MAX_PER_PAGE, page
+    ))
+    .await
+    {
+        Ok(p) => Ok(p),
+        Err(e) => {
+            let Some(attempt) = retry_after_error(&e, attempt).await else {
+                return Err(e);
+            };
+            get_sub_issues_page(number, page, owner, repo, attempt).await
+        }
+    }
+}
+
+async fn get_sub_issues(
+    number: u64,
+    owner: String,
+    repo: String,
+) -> Result<Vec<serde_json::Value>, FetchError> {
+    let mut sub_issues = vec![];
+    for page in 1..u32::MAX {
+        let mut sub_issues_page =
+            get_sub_issues_page(number, page, owner.clone(), repo.clone(), Attempt::FIRST).await?;
+        sub_issues.append(&mut sub_issues_page.take_items());
+
+        if sub_issues_page.next.is_none() {
+            break;
+        }
+    }
+    debug!(
+        "Loaded {} sub-issues of #{} in {}:{}",
+        sub_issues.len(),
+        number,
+        owner,
+        repo
+    );
+    Ok(sub_issues)
+}
+
+/// Fetches the users who reacted to the `subject` (see `get_reactions_page()`),
+/// keyed by the reaction (e.g. `+1` or `heart`).
+async fn get_reaction_users(
+    subject: String,
+    owner: String,
+    repo: String,
+) -> Result<BTreeMap<String, Vec<serde_json::Value>>, FetchError> {
+    let mut users: BTreeMap<String, Vec<serde_json::Value>> = BTreeMap::new();
+    for page in 1..u32::MAX {
+        let mut reactions_page = get_reactions_page(
+            subject.clone(),
+            page,
+            owner.clone(),
+            repo.clone(),
+            Attempt::FIRST,
+        )
+        .await?;
+        for mut reaction in reactions_page.take_items() {
+            let content = reaction["content"].as_str().unwrap_or_default().to_string();
+            users
+                .entry(content)
+                .or_default()
+                .push(reaction["user"].take());
+        }
+
+        if reactions_page.next.is_none() {
+            break;
+        }
+    }
+    debug!(
+        "Loaded the reaction users of {} in {}:{}",
+        subject, owner, repo
+    );
+    Ok(users)
+}
+
+/// Fetches the users who reacted to each of the raw `comments` that has
+/// reactions, keyed by the id of the comment. `kind` is the path of the
+/// comments in the API, `issues/comments` or `pulls/comments`. Comments
+/// without the inline reaction totals are fetched too, as they might have
+/// reactions. Returns None if none of the comments has reactions.
+async fn get_comment_reactions(
+    comments: &[serde_json::Value],
+    kind: &str,
+    owner: &str,
+    repo: &str,
+) -> Result<Option<CommentReactions>, FetchError> {
+    let mut reactions = CommentReactions::new();
+    for comment in comments {
+        let Some(id) = comment["id"].as_u64() else {
+            continue;
+        };
+        if comment["reactions"]["total_count"].as_u64() == Some(0) {
+            continue;
+        }
+        let users = get_reaction_users(
+            format!("{}/{}", kind, id),
+            owner.to_string(),
+            repo.to_string(),
+        )
+        .await?;
+        if !users.is_empty() {
+            reactions.insert(id, users);
+        }
+    }
+    Ok(Some(reactions).filter(|reactions| !reactions.is_empty()))
+}
+
+/// Removes the field `key` from the `raw` issue, returning it unless it's
+/// missing or null.
+fn take_field(raw: &mut serde_json::Value, key: &str) -> Option<serde_json::Value> {
+    raw.as_object_mut()
+        .and_then(|r| r.remove(key))
+        .filter(|value| !value.is_null())
+}
+
+/// An issue or pull-request that failed to load, with the timeline and
+/// review comments fetched before the failure.
+type Failure = (FailedEntry, Checkpoint);
+
+/// Fetches the issue or pull-request with the listing `raw` from the issues
+/// API. Resumes the pagination of the timeline and review comments from the
+/// `checkpoint`, and returns the progress made if it fails.
+async fn fetch_entry(
+    mut raw: serde_json::Value,
+    owner: &str,
+    repo: &str,
+    mut checkpoint: Checkpoint,
+) -> Result<EntryWithMetadata, (FetchError, Failure)> {
+    let number = raw["number"].as_u64().unwrap_or_default();
+    let r#type = if raw.get("pull_request").is_some_and(|p| !p.is_null()) {
+        "pull"
+    } else {
+        "issue"
+    };
+    let raw_issue = args().dump_raw_dir.as_ref().map(|_| raw.clone());
+    let listed = args().use_etags.then(|| listed_hash(&raw));
+    // Only entries with reactions are worth the requests for the users.
+    let has_reactions = raw["reactions"]["total_count"]
+        .as_u64()
+        .is_some_and(|count| count > 0);
+    // The reaction totals are only part of the issues API, not the pulls API.
+    // The issue model drops them, so they are taken out before parsing. They
+    // come at no extra cost, so they are always kept.
+    let reactions = take_field(&mut raw, "reactions");
+    // The issue model drops the sub-issue progress too. Repositories without
+    // sub-issues don't return it.
+    let sub_issues_summary = take_field(&mut raw, "sub_issues_summary");
+    let has_sub_issues = sub_issues_summary
+        .as_ref()
+        .and_then(|s| s["total"].as_u64())
+        .is_some_and(|total| total > 0);
+    let entry: issues::Issue = match parse_checked(&format!("Issue #{}", number), raw) {
+        Ok(entry) => entry,
+        Err(e) => {
+            return Err((
+                e,
+                (FailedEntry::new(number, r#type, &checkpoint), checkpoint),
+            ))
+        }
+    };
+
+    let result = if entry.pull_request.is_none() {
+        get_issue(
+            entry,
+            number,
+            owner.to_string(),
+            repo.to_string(),
+            &mut checkpoint,
+        )
+        .await
+    } else {
+        get_pull(number, owner.to_string(), repo.to_string(), &mut checkpoint).await
+    };
+    let reaction_users = match result {
+        Ok(_) if (args().include_reaction_users || args().with_reactions) && has_reactions => {
+            match get_reaction_users(
+                format!("issues/{}", number),
+                owner.to_string(),
+                repo.to_string(),
+            )
+            .await
+            {
+                Ok(users) => Some(users),
+                Err(e) => {
+                    return Err((
+                        e,
+                        (FailedEntry::new(number, r#type, &checkpoint), checkpoint),
+                    ))
+                }
+            }
+        }
+        _ => None,
+    };
+    let (comment_reactions, review_comment_reactions) = match result {
+        Ok(_) if args().with_reactions => {
+            let comments: Vec<serde_json::Value> = checkpoint
+                .timeline
+                .items
+                .iter()
+                .filter(|event| event["event"] == "commented")
+                .cloned()
+                .collect();
+            let reactions =
+                match get_comment_reactions(&comments, "issues/comments", owner, repo).await {
+                    Ok(reactions) => reactions,
+                    Err(e) => {
+                        return Err((
+                            e,
+                            (FailedEntry::new(number, r#type, &checkpoint), checkpoint),
+                        ))
+                    }
+                };
+            let review_reactions = match get_comment_reactions(
+                &checkpoint.comments.items,
+                "pulls/comments",
+                owner,
+                repo,
+            )
+            .await
+            {
+                Ok(reactions) => reactions,
+                Err(e) => {
+                    return Err((
+                        e,
+                        (FailedEntry::new(number, r#type, &checkpoint), checkpoint),
+                    ))
+                }
+            };
+            (reactions, review_reactions)
+        }
+        _ => (None, None),
+    };
+    let sub_issues = match result {
+        Ok(_) if args().include_sub_issues && has_sub_issues => {
+            match get_sub_issues(number, owner.to_string(), repo.to_string()).await {
+                Ok(sub_issues) => Some(sub_issues),
+                Err(e) => {
+                    return Err((
+                        e,
+                        (FailedEntry::new(number, r#type, &checkpoint), checkpoint),
+                    ))
+                }
+            }
+        }
+        _ => None,
+    };
+    match result {
+        Ok(mut entry) => {
+            let raw = match &mut entry {
+                EntryWithMetadata::Issue(i) => {
+                    i.reactions = reactions;
+                    i.reaction_users = reaction_users;
+                    i.comment_reactions = comment_reactions;
+                    i.sub_issues_summary = sub_issues_summary;
+                    i.sub_issues = sub_issues;
+                    i.etags = listed.and_then(|listed| {
+                        Some(EntryEtags {
+                            listed,
+                            timeline: checkpoint.timeline.complete_etags()?,
+                            ..Default::default()
+                        })
+                    });
+                    &mut i.raw
+                }
+                EntryWithMetadata::Pull(p) => {
+                    p.reactions = reactions;
+                    p.reaction_users = reaction_users;
+                    p.comment_reactions = comment_reactions;
+                    p.review_comment_reactions = review_comment_reactions;
+                    let pull_etag = p.etags.take().and_then(|etags| etags.pull);
+                    p.etags = listed.and_then(|listed| {
+                        Some(EntryEtags {
+                            listed,
+                            timeline: checkpoint.timeline.complete_etags()?,
+                            comments: checkpoint.comments.complete_etags()?,
+                            pull: Some(pull_etag?),
+                        })
+                    });
+                    &mut p.raw
+                }
+            };
+            if let Some(issue) = raw_issue {
+                let mut entry_raw = raw.take().unwrap_or_default();
+                entry_raw.issue = issue;
+                entry_raw.timeline = checkpoint.timeline.items;
+                if r#type == "pull" {
+                    entry_raw.comments = Some(checkpoint.comments.items);
+                }
+                *raw = Some(entry_raw);
+            }
+            Ok(entry)
+        }
+        Err(e) => Err((
+            e,
+            (FailedEntry::new(number, r#type, &checkpoint), checkpoint),
+        )),
+    }
+}
+
+/// The hex-encoded SHA-256 of the JSON `value`.
+fn sha256_hex(value: &serde_json::Value) -> String {
+    Sha256::digest(value.to_string().as_bytes())
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+/// The SHA-256 of an issue as listed by the issues API, which tells
+/// `--use-etags` whether it changed.
+fn listed_hash(raw: &serde_json::Value) -> String {
+    sha256_hex(raw)
+}
+
+/// The SHA-256 of an entry as it's written, but without its `updated_at` and
+/// `generated_by`, which tells `--diff-only-state` whether the content of an
+/// entry fetched again changed, or only its timestamps.
+fn content_hash(x: &EntryWithMetadata) -> Result<String, WriteError> {
+    let cleared = GeneratedBy {
+        tool_version: String::new(),
+        octocrab_version: String::new(),
+        generated_at: DateTime::<Utc>::UNIX_EPOCH,
+    };
+    let mut x = x.clone();
+    match &mut x {
+        EntryWithMetadata::Issue(i) => {
+            i.issue.updated_at = DateTime::<Utc>::UNIX_EPOCH;
+            i.generated_by = cleared;
+        }
+        EntryWithMetadata::Pull(p) => {
+            p.pull.updated_at = None;
+            p.generated_by = cleared;
+        }
+    }
+    Ok(sha256_hex(&serialize(&x)?))
+}
+
+/// Checks if the issue or pull-request `number` changed since it was fetched
+/// with the `etags`, with a conditional request for the pull-request itself
+/// and each of its timeline and review comment pages. Returns the route of
+/// the first changed response, whose body is [PREFETCHED] for the fetch of
+/// the entry, or None if all of them answer with 304 Not Modified.
+async fn changed_route(
+    number: u64,
+    etags: &EntryEtags,
+    owner: &str,
+    repo: &str,
+) -> Result<Option<String>, FetchError> {
+    let timeline = etags.timeline.iter().enumerate().map(|(i, etag)| {
+        (
+            timeline_route(owner, repo, number, i as u32 + START_PAGE),
+            etag,
+        )
+    });
+    let comments = etags.comments.iter().enumerate().map(|(i, etag)| {
+        (
+            pull_comments_route(owner, repo, number, i as u32 + START_PAGE),
+            etag,
+        )
+    });
+    let pull = etags
+        .pull
+        .iter()
+        .map(|etag| (pull_route(owner, repo, number), etag));
+    for (route, etag) in pull.chain(timeline).chain(comments) {
+        if !is_not_modified(route.clone(), etag.clone(), Attempt::FIRST).await? {
+            return Ok(Some(route));
+        }
+    }
+    Ok(None)
+}
+
+/// What `get_issues_and_pulls` loaded.
+struct Loaded {
+    /// The issues and pull-requests that failed to load.
+    failed: Vec<Failure>,
+    /// Set if the `--request-budget` was used up before all issues and
+    /// pull-requests were loaded. Holds the `updated_at` of the last listed
+    /// one if the issues are listed in the order of their last update, as the
+    /// next backup can resume from there.
+    exhausted: Option<Option<DateTime<Utc>>>,
+    /// The numbers of all issues and pull-requests in the repository. Only
+    /// set if the backup listed all of them, i.e. it isn't incremental and
+    /// wasn't stopped by the `--request-budget`.
+    listed: Option<BTreeSet<u64>>,
+}
+
+/// A fetched entry, or None if `--use-etags` found it unchanged.
+type FetchResult = Result<Option<EntryWithMetadata>, (FetchError, Failure)>;
+
+/// Tracks which of the listed issues and pull-requests are persisted, so the
+/// state checkpoints of `--checkpoint-interval` never record a cursor past an
+/// entry that is still being fetched or written.
+#[derive(Default)]
+struct Watermark {
+    /// The `updated_at` of the listed entries that are neither written nor
+    /// failed yet, by number.
+    pending: BTreeMap<u64, DateTime<Utc>>,
+    /// The `updated_at` of the last listed entry.
+    listed: Option<DateTime<Utc>>,
+    /// The entries that failed to load or to be written so far.
+    failed: Vec<FailedEntry>,
+    /// Number of entries written so far.
+    written: usize,
+}
+
+impl Watermark {
+    /// Records the failed `entry`, which is retried by the next backup.
+    fn fail(&mut self, entry: &FailedEntry) {
+        self.pending.remove(&entry.number);
+        self.failed.push(entry.clone());
+    }
+
+    /// The cursor a backup listing the issues in the order of their last
+    /// update can resume from without skipping an entry that isn't persisted:
+    /// the oldest pending one, or the last listed one. None if nothing was
+    /// listed yet.
+    fn cursor(&self) -> Option<DateTime<Utc>> {
+        self.pending.values().min().copied().or(self.listed)
+    }
+}
+
+/// The state of a backup that is still running, written every
+/// `--checkpoint-interval` entries. The cursors don't go past the `watermark`,
+/// and the entries that failed in the previous backup stay failed until the
+/// final state is written. None if no entry was listed yet.
+fn checkpoint_state(
+    watermark: &Watermark,
+    previous_failed: &[FailedEntry],
+    type_since: (Option<DateTime<Utc>>, Option<DateTime<Utc>>),
+    followed: &Option<(u64, RepositoryRef)>,
+) -> Option<BackupState> {
+    let cursor = watermark.cursor()?;
+    let advance = |is_pull: bool, previous: Option<DateTime<Utc>>| match is_skipped_type(is_pull) {
+        true => previous,
+        false => Some(cursor),
+    };
+    let last_backup_issues = advance(false, type_since.0);
+    let last_backup_pulls = advance(true, type_since.1);
+    let last_backup = match (last_backup_issues, last_backup_pulls) {
+        (Some(issues), Some(pulls)) => issues.min(pulls),
+        _ => DateTime::UNIX_EPOCH,
+    };
+    let mut failed: Vec<FailedEntry> = previous_failed.to_vec();
+    for entry in watermark.failed.iter() {
+        if !failed.iter().any(|f| f.number == entry.number) {
+            failed.push(entry.clone());
+        }
+    }
+    Some(BackupState {
+        version: STATE_VERSION,
+        last_backup,
+        last_backup_issues,
+        last_backup_pulls,
+        failed,
+        repository_id: followed.as_ref().map(|(id, _)| *id),
+        repository: followed.as_ref().map(|(_, repository)| repository.clone()),
+    })
+}
+
+/// Tallies the fetched issues and pull-requests of a repository.
+#[derive(Default)]
+struct FetchTally {
+    /// The repository as `owner/repo`.
+    repository: String,
+    loaded_issues: usize,
+    loaded_pulls: usize,
+    /// Number of entries `--use-etags` found unchanged.
+    unchanged: usize,
+    failed: Vec<Failure>,
+    watermark: Arc<std::sync::Mutex<Watermark>>,
+}
+
+impl FetchTally {
+    /// Counts a fetched entry, or logs and records a failed one. Returns the
+    /// errors that stop the loading: dropped fields with `--strict`, and a
+    /// used up `--request-budget`.
+    fn handle(&mut self, result: FetchResult) -> Result<Option<EntryWithMetadata>, FetchError> {
+        match result {
+            Ok(None) => {
+                self.unchanged += 1;
+                Ok(None)
+            }
+            Ok(Some(entry)) => {
+                match entry {
+                    EntryWithMetadata::Issue(_) => self.loaded_issues += 1,
+                    EntryWithMetadata::Pull(_) => self.loaded_pulls += 1,
+                }
+                Ok(Some(entry))
+            }
+            Err((e @ FetchError::DroppedFields(_), _)) => Err(e),
+            Err((e @ FetchError::RequestBudget(_), failure)) => {
+                self.watermark.lock().unwrap().fail(&failure.0);
+                self.failed.push(failure);
+                Err(e)
+            }
+            Err((e, failure)) => {
+                error!(
+                    "Could not get {} #{}: {}",
+                    failure.0.r#type, failure.0.number, e
+                );
+                error_stream::emit(
+                    &self.repository,
+                    Some(failure.0.number),
+                    &failure.0.r#type,
+                    "fetch",
+                    &e,
+                );
+                self.watermark.lock().unwrap().fail(&failure.0);
+                self.failed.push(failure);
+                Ok(None)
+            }
+        }
+    }
+}
+
+/// Waits for the next of the `tasks` fetching an issue or pull-request and
+/// sends its entry to the writer. A panicking fetch is propagated.
+async fn settle_next(
+    tasks: &mut task::JoinSet<FetchResult>,
+    tally: &mut FetchTally,
+    sender: &mpsc::Sender<EntryWithMetadata>,
+    buffer: &Semaphore,
+) -> Result<(), FetchError> {
+    let result = match tasks.join_next().await {
+        Some(Ok(result)) => result,
+        Some(Err(e)) => std::panic::resume_unwind(e.into_panic()),
+        None => return Ok(()),
+    };
+    if let Some(entry) = tally.handle(result)? {
+        send_to_writer(sender, buffer, entry).await;
+    }
+    Ok(())
+}
+
+/// Loads the issues and pull-requests updated since `since`, after retrying
+/// the `retries` that failed in the previous backup. Issues updated before
+/// the first and pull-requests updated before the second of `type_since` are
+/// skipped, as are those of the type skipped with `--only-issues` or
+/// `--only-pulls`. With `--skip-existing`, the listed issues and
+/// pull-requests that already have a file in `destination` are skipped too.
+/// Up to `--concurrency` issues and pull-requests are fetched at the same
+/// time. Stops early once the `--request-budget` is used up: the retries that
+/// weren't attempted are kept as failed. The listed entries are tracked in the
+/// `watermark` until they are written or failed. With `--use-etags`, the
+/// listed entries that conditional requests with their previous `etags` show
+/// unchanged are skipped.
+#[allow(clippy::too_many_arguments)]
+async fn get_issues_and_pulls(
+    sender: mpsc::Sender<EntryWithMetadata>,
+    buffer: Arc<Semaphore>,
+    since: Option<DateTime<Utc>>,
+    type_since: (Option<DateTime<Utc>>, Option<DateTime<Utc>>),
+    owner: String,
+    repo: String,
+    retries: Vec<Failure>,
+    destination: PathBuf,
+    watermark: Arc<std::sync::Mutex<Watermark>>,
+    etags: Arc<BTreeMap<u64, EntryEtags>>,
+) -> Result<Loaded, FetchError> {
+    let mut tally = FetchTally {
+        repository: format!("{}/{}", owner, repo),
+        watermark: watermark.clone(),
+        ..Default::default()
+    };
+    let mut retried: BTreeSet<u64> = BTreeSet::new();
+    let mut listed: BTreeSet<u64> = BTreeSet::new();
+    let mut exhausted = false;
+    let mut last_updated: Option<DateTime<Utc>> = None;
+    let mut existing: usize = 0;
+    let concurrency = args().concurrency as usize;
+    let mut tasks: task::JoinSet<FetchResult> = task::JoinSet::new();
+
+    if !retries.is_empty() {
+        info!(
+            "Retrying {} issues and pulls that failed to load in the previous backup",
+            retries.len()
+        );
+    }
+    // The retries of a type skipped with --only-issues or --only-pulls, and
+    // those not attempted before the --request-budget was used up.
+    let mut unattempted: Vec<Failure> = vec![];
+    let mut retries = retries.into_iter();
+    loop {
+        if tasks.len() >= concurrency {
+            match settle_next(&mut tasks, &mut tally, &sender, &buffer).await {
+                Ok(()) => (),
+                Err(FetchError::RequestBudget(_)) => {
+                    exhausted = true;
+                    break;
+                }
+                Err(e) => return Err(e),
+            }
+        }
+        let Some((entry, checkpoint)) = retries.next() else {
+            break;
+        };
+        retried.insert(entry.number);
+        if is_skipped_type(entry.r#type == "pull") {
+            unattempted.push((entry, checkpoint));
+            continue;
+        }
+        let (owner, repo) = (owner.clone(), repo.clone());
+        tasks.spawn(async move {
+            match get_issue_by_number(entry.number, owner.clone(), repo.clone(), Attempt::FIRST)
+                .await
+            {
+                Ok(raw) => fetch_entry(raw, &owner, &repo, checkpoint).await.map(Some),
+                Err(e) => Err((e, (entry, checkpoint))),
+            }
+        });
+    }
+    unattempted.extend(retries);
+    // The retries have to be done before the listing is started, as a used up
+    // --request-budget skips it.
+    while !tasks.is_empty() {
+        match settle_next(&mut tasks, &mut tally, &sender, &buffer).await {
+            Ok(()) => (),
+            Err(FetchError::RequestBudget(_)) => exhausted = true,
+            Err(e) => return Err(e),
+        }
+    }
+
+    // With --retry-failed-only, only the retries are loaded.
+    let pages = match args().retry_failed_only || exhausted {
+        true => 0..0,
+        false => START_PAGE..u32::MAX,
+    };
+    if !pages.is_empty() {
+        info!(
+            "Start to load issues and pulls for {}:{} from GitHub",
+            owner, repo
+        );
+    }
+    'pages: for page_num in pages {
+        let page = match get_issue_page(
+            page_num,
+            since,
+            owner.clone(),
+            repo.clone(),
+            Attempt::FIRST,
+        )
+        .await
+        {
+            Ok(page) => page,
+            Err(FetchError::RequestBudget(_)) => {
+                exhausted = true;
+                break;
+            }
+            Err(e) => {
+                error!(
+                    "Could not load issue page {} for {}:{} from GitHub: {}",
+                    page_num, owner, repo, e
+                );
+                error_stream::emit(&tally.repository, None, "page", "listing", &e);
+                return Err(e);
+            }
+        };
+
+        for raw in page.items {
+            if let Some(number) = raw["number"].as_u64() {
+                listed.insert(number);
+            }
+            if retried.contains(&raw["number"].as_u64().unwrap_or_default()) {
+                continue;
+            }
+            let updated_at = raw["updated_at"]
+                .as_str()
+                .and_then(|t| t.parse::<DateTime<Utc>>().ok());
+            last_updated = updated_at.or(last_updated);
+            watermark.lock().unwrap().listed = last_updated;
+            let is_pull = raw.get("pull_request").is_some();
+            let skip_before = match is_pull {
+                true => type_since.1,
+                false => type_since.0,
+            };
+            if is_skipped_type(is_pull)
+                || skip_before.is_some_and(|skip_before| {
+                    updated_at.is_some_and(|updated_at| updated_at < skip_before)
+                })
+            {
+                continue;
+            }
+            if args().skip_existing
+                && is_on_disk(
+                    &destination,
+                    is_pull,
+                    raw["number"].as_u64().unwrap_or_default(),
+                )
+            {
+                existing += 1;
+                continue;
+            }
+            if let Some(updated_at) = updated_at.filter(|t| is_beyond_slack(*t)) {
+                warn!(
+                    "#{} in {}:{} was updated at {}, which is in the future. The cursor of the next backup is the start time of this one, so it's not affected.",
+                    raw["number"],
+                    owner,
+                    repo,
+                    updated_at.to_rfc3339()
+                );
+            }
+            if tasks.len() >= concurrency {
+                match settle_next(&mut tasks, &mut tally, &sender, &buffer).await {
+                    Ok(()) => (),
+                    Err(FetchError::RequestBudget(_)) => {
+                        exhausted = true;
+                        break 'pages;
+                    }
+                    Err(e) => return Err(e),
+                }
+            }
+            // An entry without a valid updated_at is at the position of the
+            // one listed before.
+            if let Some(listed) = last_updated.or(since) {
+                let number = raw["number"].as_u64().unwrap_or_default();
+                watermark.lock().unwrap().pending.insert(number, listed);
+            }
+            // With --use-etags, an entry listed the same as when its file was
+            // written is checked with conditional requests first.
+            let number = raw["number"].as_u64().unwrap_or_default();
+            let previous = etags
+                .get(&number)
+                .filter(|previous| args().use_etags && previous.listed == listed_hash(&raw))
+                .filter(|_| is_on_disk(&destination, is_pull, number))
+                .cloned();
+            let (owner, repo) = (owner.clone(), repo.clone());
+            let watermark = watermark.clone();
+            tasks.spawn(async move {
+                let mut prefetched = None;
+                if let Some(previous) = previous {
+                    match changed_route(number, &previous, &owner, &repo).await {
+                        Ok(None) => {
+                            debug!("#{} in {}:{} is unchanged", number, owner, repo);
+                            watermark.lock().unwrap().pending.remove(&number);
+                            return Ok(None);
+                        }
+                        Ok(Some(route)) => prefetched = Some(route),
+                        Err(e) => debug!(
+                            "Could not check if #{} in {}:{} changed: {}",
+                            number, owner, repo, e
+                        ),
+                    }
+                }
+                let result = fetch_entry(raw, &owner, &repo, Checkpoint::default()).await;
+                // The fetch might have failed before taking the response,
+                // which would be stale by the next backup.
+                if let Some(route) = prefetched {
+                    PREFETCHED.lock().unwrap().remove(&route);
+                }
+                result.map(Some)
+            });
+        }
+
+        if page.next.is_none() {
+            break;
+        }
+    }
+    // The entries still being fetched are kept, even once the
+    // --request-budget is used up: they fail and are retried if it is.
+    while !tasks.is_empty() {
+        match settle_next(&mut tasks, &mut tally, &sender, &buffer).await {
+            Ok(()) => (),
+            Err(FetchError::RequestBudget(_)) => exhausted = true,
+            Err(e) => return Err(e),
+        }
+    }
+    let FetchTally {
+        loaded_issues,
+        loaded_pulls,
+        unchanged,
+        mut failed,
+        ..
+    } = tally;
+    info!(
+        "Loaded {} issues and {} pulls from {}:{}",
+        loaded_issues, loaded_pulls, owner, repo
+    );
+    if unchanged > 0 {
+        info!(
+            "Skipped {} unchanged issues and pulls (--use-etags)",
+            unchanged
+        );
+    }
+    if existing > 0 {
+        info!(
+            "Skipped {} issues and pulls already on disk (--skip-existing)",
+            existing
+        );
+    }
+    for r#type in ["issue", "pull"] {
+        let numbers: Vec<u64> = failed
+            .iter()
+            .filter(|(entry, _)| entry.r#type == r#type)
+            .map(|(entry, _)| entry.number)
+            .collect();
+        if !numbers.is_empty() {
+            warn!(
+                "The following {} {}s failed to load and are retried with the next backup: {:?}",
+                numbers.len(),
+                r#type,
+                numbers
+            );
+        }
+    }
+
+    failed.extend(unattempted);
+    let complete =
+        !exhausted && !args().retry_failed_only && since.is_none() && type_since == (None, None);
+    let exhausted = exhausted.then(|| match is_sorted_by_update(since) {
+        true => last_updated,
+        false => None,
+    });
+    Ok(Loaded {
+        failed,
+        exhausted,
+        listed: complete.then_some(listed),
+    })
+}
+
+/// Prints the issues and pull-requests the state file in `destination` lists
+/// as failed, without any network access.
+fn list_open_failures(destination: &Path, json: bool) -> ExitCode {
+    let state = match load_backup_state(destination) {
+        Ok(Some(state)) => state,
+        Ok(None) => {
+            error!(
+                "There's no readable state file in {}",
+                destination.display()
+            );
+            return ExitCode::FAILURE;
+        }
+        Err(version) => {
+            error!(
+                "The state file in {} has version {}, but this version of github-metadata-backup only supports version {}.",
+                destination.display(),
+                version,
+                STATE_VERSION
+            );
+            return ExitCode::from(EXIT_STATE_VERSION);
+        }
+    };
+    if json {
+        let failures = serde_json::json!({
+            "last_backup": state.last_backup,
+            "failed": state.failed,
+        });
+        match serde_json::to_string_pretty(&failures) {
+            Ok(json) => println!("{}", json),
+            Err(e) => {
+                error!("Could not serialize the failures: {}", e);
+                return ExitCode::FAILURE;
+            }
+        }
+        return ExitCode::SUCCESS;
+    }
+    for entry in state.failed.iter() {
+        println!(
+            "{:<5} #{:<6} fetched {} timeline and {} review comment pages",
+            entry.r#type, entry.number, entry.timeline_pages, entry.comments_pages
+        );
+    }
+    println!(
+        "{} issues and pull-requests failed in the backup of {}",
+        state.failed.len(),
+        state.last_backup
+    );
+    ExitCode::SUCCESS
+}
+
+/// Fetches the timelines of all issues and pull-requests as raw JSON and
+/// prints how often each `event` type occurs. Event types octocrab doesn't
+/// know are marked, as they make the backup fail. Nothing is written.
+async fn list_events_seen(owner: String, repo: String) -> ExitCode {
+    let mut seen: BTreeMap<String, usize> = BTreeMap::new();
+    info!(
+        "Tallying the timeline event types of {}:{} on GitHub",
+        owner, repo
+    );
+    for page_num in START_PAGE..u32::MAX {
+        let page = match get_issue_page(page_num, None, owner.clone(), repo.clone(), Attempt::FIRST)
+            .await
+        {
+            Ok(page) => page,
+            Err(e) => {
+                error!(
+                    "Could not load issue page {} for {}:{} from GitHub: {}",
+                    page_num, owner, repo, e
+                );
+                return ExitCode::from(EXIT_API_ERROR);
+            }
+        };
+
+        for entry in page.items.iter() {
+            let number = entry["number"].as_u64().unwrap_or_default();
+            let mut progress = PageProgress::default();
+            match get_timeline_raw(number, owner.clone(), repo.clone(), &mut progress).await {
+                Ok(()) => {
+                    for event in progress.items {
+                        let name = event
+                            .get("event")
+                            .and_then(|e| e.as_str())
+                            .unwrap_or("<none>")
+                            .to_string();
+                        *seen.entry(name).or_default() += 1;
+                    }
+                }
+                Err(e) => {
+                    error!("Could not get the timeline of #{}: {}", number, e);
+                    return ExitCode::from(EXIT_API_ERROR);
+                }
+            }
+        }
+
+        if page.next.is_none() {
+            break;
+        }
+    }
+
+    let mut counts: Vec<(String, usize)> = seen.into_iter().collect();
+    counts.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    for (name, count) in counts {
+        if is_known_event(&name) {
+            println!("{:>8}  {}", count, name);
+        } else {
+            println!("{:>8}  {} (unknown to octocrab)", count, name);
+        }
+    }
+    ExitCode::SUCCESS
+}
+
+/// Counts the issues or pull-requests (`kind` "issue" or "pr") of a
+/// repository updated since `since` with the search API.
+async fn count_entries(
+    owner: &str,
+    repo: &str,
+    kind: &str,
+    since: Option<DateTime<Utc>>,
+) -> Result<u64, FetchError> {
+    let mut query = format!("repo:{}/{}+is:{}", owner, repo, kind);
+    if let Some(since) = since {
+        query.push_str(&format!(
+            "+updated:>={}",
+            since.to_rfc3339_opts(SecondsFormat::Secs, true)
+        ));
+    }
+    let result: serde_json::Value =
+        api_get(format!("/search/issues?q={}&per_page=1", query)).await?;
+    Ok(result["total_count"].as_u64().unwrap_or_default())
+}
+
+/// Prints an estimate of the requests a backup of `owner`/`repo` to
+/// `destination` would take with the enabled flags, broken down by phase, and
+/// how long the rate-limit makes it take. Only the issues and pull-requests
+/// are counted, which costs two search requests. Nothing is written.
+async fn plan_backup(owner: String, repo: String, destination: PathBuf) -> ExitCode {
+    let since = match args().no_state {
+        true => None,
+        false => initial_cursor(&destination),
+    };
+    let counts = tokio::try_join!(
+        count_entries(&owner, &repo, "issue", since),
+        count_entries(&owner, &repo, "pr", since),
+    );
+    let (issues, pulls) = match counts {
+        Ok(counts) => counts,
+        Err(e) => {
+            error!(
+                "Could not count the issues and pull-requests of {}:{}: {}",
+                owner, repo, e
+            );
+            return fetch_exit_code(&e);
+        }
+    };
+    match since {
+        Some(since) => println!(
+            "{} issues and {} pull-requests of {}/{} were updated since the last backup at {}.",
+            issues,
+            pulls,
+            owner,
+            repo,
+            since.to_rfc3339()
+        ),
+        None => println!(
+            "{}/{} has {} issues and {} pull-requests.",
+            owner, repo, issues, pulls
+        ),
+    }
+
+    let phases = plan::estimate(args(), issues, pulls);
+    let mut rest = 0;
+    let mut graphql = 0;
+    for phase in phases.iter() {
+        let api = match phase.graphql {
+            true => "GraphQL",
+            false => "REST",
+        };
+        let bound = match phase.at_least {
+            true => ">=",
+            false => "",
+        };
+        println!(
+            "{:>2}{:>9}  {:<8} {}",
+            bound, phase.requests, api, phase.name
+        );
+        match phase.graphql {
+            true => graphql += phase.requests,
+            false => rest += phase.requests,
+        }
+    }
+    println!(
+        "At least {} REST requests and {} GraphQL queries in total.",
+        rest, graphql
+    );
+    if let Some(budget) = args().request_budget {
+        if rest + graphql > budget {
+            println!(
+                "The --request-budget of {} stops the backup before it's complete.",
+                budget
+            );
+        }
+    }
+
+    match octocrab::instance().ratelimit().get().await {
+        Ok(ratelimit) => {
+            let core = ratelimit.resources.core;
+            let reset_in = (core.reset as i64 - Utc::now().timestamp()).max(0) as u64;
+            let duration = plan::rate_limited_duration(
+                rest,
+                core.remaining as u64,
+                core.limit as u64,
+                Duration::from_secs(reset_in),
+            );
+            if duration.is_zero() {
+                println!(
+                    "The {} requests left of the rate-limit ({} per hour) are enough.",
+                    core.remaining, core.limit
+                );
+            } else {
+                println!(
+                    "With {} requests left of the rate-limit ({} per hour), the backup waits for the rate-limit for about {:.1} hours.",
+                    core.remaining,
+                    core.limit,
+                    duration.as_secs_f64() / 3600.0
+                );
+            }
+        }
+        Err(e) => warn!("Could not get the rate-limit: {}", e),
+    }
+    ExitCode::SUCCESS
+}
+
+/// Returns true if the API error means the issue or pull-request was deleted
+/// or isn't accessible anymore.
+fn is_removed(e: &FetchError) -> bool {
+    match e {
+        FetchError::Octocrab(e) => match e.as_ref() {
+            octocrab::Error::GitHub { source, .. } => {
+                source.status_code == http::StatusCode::NOT_FOUND
+                    || source.status_code == http::StatusCode::GONE
+            }
+            _ => false,
+        },
+        _ => false,
+    }
+}
+
+/// Picks up to `n` random elements of `items`.
+fn sample<T>(mut items: Vec<T>, n: usize) -> Vec<T> {
+    let n = n.min(items.len());
+    for i in 0..n {
+        let mut bytes = [0u8; 8];
+        if let Err(e) = getrandom::getrandom(&mut bytes) {
+            warn!("Could not get random bytes, the sample isn't random: {}", e);
+        }
+        let j = i + (u64::from_le_bytes(bytes) % (items.len() - i) as u64) as usize;
+        items.swap(i, j);
+    }
+    items.truncate(n);
+    items
+}
+
+#[async_recursion]
+async fn get_issue_events_page(
+    page: u32,
+    owner: String,
+    repo: String,
+    attempt: Attempt,
+) -> Result<Page<serde_json::Value>, FetchError> {
+    match api_get(format!(
+        "/repos/{}/{}/issues/events?per_page={}&page={}",
+        owner, repo, MAX_PER_PAGE, page
+    ))
+    .await
+    {
+        Ok(p) => Ok(p),
+        Err(e) => {
+            let Some(attempt) = retry_after_error(&e, attempt).await else {
+                return Err(e);
+            };
+            get_issue_events_page(page, owner, repo, attempt).await
+        }
+    }
+}
+
+/// Scans the issue events of the repository since `since` (newest first) for
+/// `deleted` and `transferred` events. Each candidate is fetched again to
+/// confirm it's gone, as it might have been transferred back or restored.
+async fn detect_removals(
+    owner: &str,
+    repo: &str,
+    since: Option<DateTime<Utc>>,
+) -> Result<BTreeMap<u64, Removal>, FetchError> {
+    let mut candidates = BTreeSet::new();
+    'pages: for page_num in START_PAGE..u32::MAX {
+        let page = get_issue_events_page(
+            page_num,
+            owner.to_string(),
+            repo.to_string(),
+            Attempt::FIRST,
+        )
+        .await?;
+        for event in page.items.iter() {
+            let created_at = event["created_at"]
+                .as_str()
+                .and_then(|t| t.parse::<DateTime<Utc>>().ok());
+            if let (Some(since), Some(created_at)) = (since, created_at) {
+                if created_at < since {
+                    break 'pages;
+                }
+            }
+            if matches!(event["event"].as_str(), Some("deleted" | "transferred")) {
+                if let Some(number) = event["issue"]["number"].as_u64() {
+                    candidates.insert(number);
+                }
+            }
+        }
+        if page.next.is_none() {
+            break;
+        }
+    }
+
+    let mut removals = BTreeMap::new();
+    let repository_url = format!("/repos/{}/{}", owner, repo).to_lowercase();
+    for number in candidates {
+        let removal =
+            match get_issue_by_number(number, owner.to_string(), repo.to_string(), Attempt::FIRST)
+                .await
+            {
+                // A transferred issue is redirected to its new repository.
+                Ok(issue)
+                    if !issue["repository_url"]
+                        .as_str()
+                        .unwrap_or_default()
+                        .to_lowercase()
+                        .ends_with(&repository_url) =>
+                {
+                    Removal {
+                        reason: "transferred".to_string(),
+                        detected_at: Utc::now(),
+                        new_url: issue["html_url"].as_str().map(str::to_string),
+                    }
+                }
+                Ok(_) => continue,
+                Err(e) if is_removed(&e) => Removal {
+                    reason: "deleted".to_string(),
+                    detected_at: Utc::now(),
+                    new_url: None,
+                },
+                Err(e) => return Err(e),
+            };
+        removals.insert(number, removal);
+    }
+    Ok(removals)
+}
+
+/// Applies the `--on-deletion` policy to the files of the removed issues and
+/// pull-requests. Files that were already annotated are left as is.
+fn reconcile_removals(
+    removals: BTreeMap<u64, Removal>,
+    destination: &Path,
+    index: &mut index::Index,
+) -> Result<(), WriteError> {
+    let args = args();
+    for (number, removal) in removals {
+        let paths = [&args.issues_dir_name, &args.pulls_dir_name]
+            .into_iter()
+            .flat_map(|dir| entry_file_candidates(&destination.join(dir), number));
+        for path in paths {
+            if !path.exists() {
+                continue;
+            }
+            match args.on_deletion {
+                OnDeletion::Prune => {
+                    fs::remove_file(&path)?;
+                    index.remove(number);
+                    info!(
+                        "Removed {} as #{} was {}",
+                        path.display(),
+                        number,
+                        removal.reason
+                    );
+                }
+                OnDeletion::Annotate => {
+                    if annotate_removal(&path, &removal)? {
+                        info!("Marked {} as {}", path.display(), removal.reason);
+                    }
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Adds the `removal` as `removed` to the entry file at `path`, keeping its
+/// format and compression. False if the file is already marked as removed.
+fn annotate_removal(path: &Path, removal: &Removal) -> Result<bool, WriteError> {
+    let Some((_, format)) = parse_entry_file_name(path) else {
+        return Ok(false);
+    };
+    let mut value = sink::decode(&read_maybe_compressed(path)?, format)?;
+    if value.get("removed").is_some() {
+        return Ok(false);
+    }
+    if let serde_json::Value::Object(map) = &mut value {
+        map.insert("removed".to_string(), serde_json::to_value(removal)?);
+    }
+    let mut bytes = sink::encode(&value, format)?;
+    if path.extension().is_some_and(|ext| ext == "gz") {
+        bytes = gzip(&bytes)?;
+    }
+    permissions::write_atomically(path, &bytes)?;
+    Ok(true)
+}
+
+/// Prints the issues and pull-requests whose `updated_at` on GitHub is newer
+/// than in the index of the backup, and those that are only on GitHub or only
+/// in the backup. Without `--compare-sample`, all entries are listed with the
+/// issues API. With it, only a random sample of the entries in the index is
+/// fetched, one request each, so new entries on GitHub aren't found.
+async fn compare_api_vs_disk(owner: String, repo: String, destination: PathBuf) -> ExitCode {
+    let args = args();
+    if !destination.join(index::INDEX_FILE).exists() {
+        warn!(
+            "There is no {} in {} to compare with",
+            index::INDEX_FILE,
+            destination.display()
+        );
+    }
+    let index = index::Index::load(&destination, &args.issues_dir_name, &args.pulls_dir_name);
+    let indexed = |is_pull: bool, number: u64| match is_pull {
+        true => index.pulls.get(&number),
+        false => index.issues.get(&number),
+    };
+    let name = |is_pull: bool| if is_pull { "pull" } else { "issue" };
+    info!(
+        "Comparing {}:{} on GitHub with the backup in {}",
+        owner,
+        repo,
+        destination.display()
+    );
+
+    // The listed or fetched entries, with their number and updated_at.
+    let mut on_github: Vec<(bool, u64, Option<DateTime<Utc>>)> = vec![];
+    // Entries of the index that aren't on GitHub (anymore).
+    let mut removed: Vec<(bool, u64)> = vec![];
+    let mut checked = 0;
+    let updated_at = |entry: &serde_json::Value| {
+        entry["updated_at"]
+            .as_str()
+            .and_then(|t| t.parse::<DateTime<Utc>>().ok())
+    };
+    let is_pull =
+        |entry: &serde_json::Value| entry.get("pull_request").is_some_and(|p| !p.is_null());
+
+    match args.compare_sample {
+        Some(n) => {
+            let numbers: Vec<(bool, u64)> = index
+                .issues
+                .keys()
+                .map(|n| (false, *n))
+                .chain(index.pulls.keys().map(|n| (true, *n)))
+                .collect();
+            for (pull, number) in sample(numbers, n as usize) {
+                checked += 1;
+                match get_issue_by_number(number, owner.clone(), repo.clone(), Attempt::FIRST).await
+                {
+                    Ok(entry) => on_github.push((is_pull(&entry), number, updated_at(&entry))),
+                    Err(e) if is_removed(&e) => removed.push((pull, number)),
+                    Err(e) => {
+                        error!("Could not load #{} from GitHub: {}", number, e);
+                        return ExitCode::from(EXIT_API_ERROR);
+                    }
+                }
+            }
+        }
+        None => {
+            for page_num in START_PAGE..u32::MAX {
+                let page = match get_issue_page(
+                    page_num,
+                    None,
+                    owner.clone(),
+                    repo.clone(),
+                    Attempt::FIRST,
+                )
+                .await
+                {
+                    Ok(page) => page,
+                    Err(e) => {
+                        error!(
+                            "Could not load issue page {} for {}:{} from GitHub: {}",
+                            page_num, owner, repo, e
+                        );
+                        return ExitCode::from(EXIT_API_ERROR);
+                    }
+                };
+                for entry in page.items.iter() {
+                    let number = entry["number"].as_u64().unwrap_or_default();
+                    on_github.push((is_pull(entry), number, updated_at(entry)));
+                }
+                if page.next.is_none() {
+                    break;
+                }
+            }
+            checked = on_github.len();
+            let listed: BTreeSet<(bool, u64)> =
+                on_github.iter().map(|(p, n, _)| (*p, *n)).collect();
+            removed = index
+                .issues
+                .keys()
+                .map(|n| (false, *n))
+                .chain(index.pulls.keys().map(|n| (true, *n)))
+                .filter(|entry| !listed.contains(entry))
+                .collect();
+        }
+    }
+
+    let mut drifted = 0;
+    for (pull, number, updated_at) in on_github {
+        match indexed(pull, number) {
+            None => {
+                println!("missing   {} #{} is not in the backup", name(pull), number);
+                drifted += 1;
+            }
+            Some(entry) if updated_at > entry.updated_at => {
+                println!(
+                    "outdated  {} #{} was updated on GitHub at {}, the backup is from {}",
+                    name(pull),
+                    number,
+                    updated_at.map(|t| t.to_rfc3339()).unwrap_or_default(),
+                    entry
+                        .updated_at
+                        .map(|t| t.to_rfc3339())
+                        .unwrap_or_else(|| "an unknown time".to_string())
+                );
+                drifted += 1;
+            }
+            Some(_) => (),
+        }
+    }
+    for (pull, number) in removed.iter() {
+        println!(
+            "removed   {} #{} is not on GitHub anymore",
+            name(*pull),
+            number
+        );
+        drifted += 1;
+    }
+    println!(
+        "{} of {} checked issues and pull-requests drifted",
+        drifted, checked
+    );
+    if drifted > 0 {
+        ExitCode::from(EXIT_DRIFT)
+    } else {
+        ExitCode::SUCCESS
+    }
+}
+
+/// The fields of the issue or pull-request in its file that
+/// `--only-metadata-refresh` updates from the issue list.
+const REFRESHED_FIELDS: [&str; 6] = [
+    "title",
+    "state",
+    "state_reason",
+    "labels",
+    "updated_at",
+    "closed_at",
+];
+
+/// Updates the title, state, labels and timestamps of the issues and
+/// pull-requests that have a file in `destination` from the issue list, which
+/// costs one request per 100 entries. The timelines, comments and everything
+/// else in the files are left as they are. Only the fields a file already has
+/// are updated, and files without changes aren't rewritten.
+async fn refresh_metadata(owner: &str, repo: &str, destination: &Path) -> ExitCode {
+    let args = args();
+    let mut index = index::Index::load(destination, &args.issues_dir_name, &args.pulls_dir_name);
+    info!(
+        "Refreshing the metadata of the backup of {}:{} in {}",
+        owner,
+        repo,
+        destination.display()
+    );
+    let (mut listed, mut refreshed) = (0, 0);
+    for page_num in START_PAGE..u32::MAX {
+        let page = match get_issue_page(
+            page_num,
+            None,
+            owner.to_string(),
+            repo.to_string(),
+            Attempt::FIRST,
+        )
+        .await
+        {
+            Ok(page) => page,
+            Err(e) => {
+                error!(
+                    "Could not load issue page {} for {}:{} from GitHub: {}",
+                    page_num, owner, repo, e
+                );
+                return ExitCode::from(EXIT_API_ERROR);
+            }
+        };
+        for raw in page.items.iter() {
+            listed += 1;
+            match refresh_entry_file(raw, destination, &mut index) {
+                Ok(true) => refreshed += 1,
+                Ok(false) => (),
+                Err(e) => {
+                    error!(
+                        "Could not refresh #{} in {}: {}",
+                        raw["number"],
+                        destination.display(),
+                        e
+                    );
+                    return ExitCode::from(EXIT_WRITING);
+                }
+            }
+        }
+        if page.next.is_none() {
+            break;
+        }
+    }
+    if let Err(e) = index.write(destination) {
+        error!(
+            "Failed to write {} to {}: {}",
+            index::INDEX_FILE,
+            destination.display(),
+            e
+        );
+        return ExitCode::from(EXIT_WRITING);
+    }
+    info!(
+        "Refreshed the metadata of {} of {} listed issues and pull-requests",
+        refreshed, listed
+    );
+    ExitCode::SUCCESS
+}
+
+/// Patches the file of the `raw` listed issue or pull-request with its
+/// REFRESHED_FIELDS, and for pull-requests its `merged_at`, and updates its
+/// entry in the `index`. Returns false if there's no file or it's up to date.
+fn refresh_entry_file(
+    raw: &serde_json::Value,
+    destination: &Path,
+    index: &mut index::Index,
+) -> Result<bool, WriteError> {
+    let number = raw["number"].as_u64().unwrap_or_default();
+    let is_pull = raw.get("pull_request").is_some_and(|p| !p.is_null());
+    let (dir, key) = match is_pull {
+        true => (&args().pulls_dir_name, "pull"),
+        false => (&args().issues_dir_name, "issue"),
+    };
+    let Some((path, format)) = entry_file_candidates(&destination.join(dir), number)
+        .into_iter()
+        .filter(|path| path.exists())
+        .find_map(|path| Some((path.clone(), parse_entry_file_name(&path)?.1)))
+    else {
+        debug!("#{} has no file in the backup", number);
+        return Ok(false);
+    };
+    let mut file = sink::decode(&read_maybe_compressed(&path)?, format)?;
+    let Some(model) = file.get_mut(key).and_then(|m| m.as_object_mut()) else {
+        return Ok(false);
+    };
+    let mut updates: Vec<(&str, &serde_json::Value)> = REFRESHED_FIELDS
+        .iter()
+        .filter_map(|field| Some((*field, raw.get(*field)?)))
+        .collect();
+    if is_pull {
+        updates.push(("merged_at", &raw["pull_request"]["merged_at"]));
+    }
+    let mut changed = false;
+    for (field, value) in updates {
+        if let Some(current) = model.get_mut(field) {
+            if current != value {
+                *current = value.clone();
+                changed = true;
+            }
+        }
+    }
+    if let Some(entry) = match is_pull {
+        true => index.pulls.get_mut(&number),
+        false => index.issues.get_mut(&number),
+    } {
+        if let Some(title) = raw["title"].as_str() {
+            entry.title = title.to_string();
+        }
+        if let Some(state) = raw["state"].as_str() {
+            entry.state = state.to_string();
+        }
+        entry.updated_at = raw["updated_at"]
+            .as_str()
+            .and_then(|t| t.parse().ok())
+            .or(entry.updated_at);
+        if is_pull {
+            entry.merged = Some(!raw["pull_request"]["merged_at"].is_null());
+        }
+    }
+    if !changed {
+        return Ok(false);
+    }
+    // The patched labels have the API URLs as returned.
+    rewrite_api_urls(&mut file);
+    let mut bytes = sink::encode(&file, format)?;
+    if path.extension().is_some_and(|ext| ext == "gz") {
+        bytes = gzip(&bytes)?;
+    }
+    let target = state_dir_file(&path, raw["state"].as_str());
+    if let Some(parent) = target.parent() {
+        permissions::create_dir_all(parent)?;
+    }
+    permissions::write_atomically(&target, &bytes)?;
+    if target != path {
+        fs::remove_file(&path)?;
+        info!("Moved {} to {}", path.display(), target.display());
+    }
+    debug!("Refreshed {}", target.display());
+    Ok(true)
+}
+
+/// The place of the entry file at `path` in the subdirectory of its current
+/// `state`, if it's in the subdirectory of another state of `--layout
+/// split-by-state`. An entry closed or reopened since the last backup moves
+/// there.
+fn state_dir_file(path: &Path, state: Option<&str>) -> PathBuf {
+    let (Some(parent), Some(name), Some(state)) = (path.parent(), path.file_name(), state) else {
+        return path.to_path_buf();
+    };
+    let current = parent.file_name().and_then(|n| n.to_str());
+    match current {
+        Some(current) if STATE_DIRS.contains(&current) && STATE_DIRS.contains(&state) => {
+            parent.with_file_name(state).join(name)
+        }
+        _ => path.to_path_buf(),
+    }
+}
+
+/// Serializes the entry as written, with the `--fields`, `--fields-file` and
+/// `--anonymize-users` transformations applied.
+fn serialize(x: &EntryWithMetadata) -> Result<serde_json::Value, WriteError> {
+    let mut value = match x {
+        EntryWithMetadata::Issue(i) => serde_json::to_value(i)?,
+        EntryWithMetadata::Pull(p) => serde_json::to_value(p)?,
+    };
+    // Validated before the fields are projected, which can drop required
+    // fields on purpose.
+    if args().validate_output {
+        let schema = OUTPUT_SCHEMA.get_or_init(|| {
+            serde_json::to_value(entry_schema()).expect("the JSON Schema can be serialized")
+        });
+        let errors = validate::validate(schema, &value);
+        if !errors.is_empty() {
+            return Err(WriteError::InvalidOutput(errors));
+        }
+    }
+    if !args().fields.is_empty() {
+        transform::project_fields(&mut value, &args().fields);
+    }
+    if let Some(spec) = &args().fields_file {
+        spec.apply(&mut value);
+    }
+    if let Some(salt) = ANONYMIZE_SALT.get() {
+        transform::anonymize_users(&mut value, salt);
+    }
+    if args().normalize_timestamps {
+        transform::normalize_timestamps(&mut value);
+    }
+    rewrite_api_urls(&mut value);
+    if args().flatten_nested_json {
+        transform::flatten(&mut value, &args().flatten_separator, args().flatten_arrays);
+    }
+    Ok(value)
+}
+
+/// Rewrites the API URLs in the `value` of an entry with `--rewrite-api-urls`.
+fn rewrite_api_urls(value: &mut serde_json::Value) {
+    let Some(base) = &args().rewrite_api_urls else {
+        return;
+    };
+    let api = args().base_url.as_deref().unwrap_or(GITHUB_API_URL);
+    let base = match base.as_str() {
+        RELATIVE_API_URLS => "",
+        base => base,
+    };
+    transform::rewrite_api_urls(value, api.trim_end_matches('/'), base.trim_end_matches('/'));
+}
+
+fn write(x: EntryWithMetadata, destination: PathBuf) -> Result<(), WriteError> {
+    let value = serialize(&x)?;
+    FilesSink {
+        destination,
+        layout: args().layout(),
+        compress: args().compress,
+    }
+    .write(&x, &value)
+}
+
+/// The file an entry is written to, preferring its own file if the `files`
+/// sink is used.
+fn entry_path(destination: &Path, x: &EntryWithMetadata) -> PathBuf {
+    let sinks = args().sinks();
+    if sinks.contains(&SinkKind::Files) {
+        return entry_file(destination, x);
+    }
+    match sinks.first() {
+        Some(SinkKind::SingleFile) => destination.join(single_file::SINGLE_FILE),
+        _ => destination.join(sink::NDJSON_FILE),
+    }
+}
+
+/// The subdirectories of the issues and pulls directories with
+/// `--layout split-by-state`.
+const STATE_DIRS: [&str; 2] = ["open", "closed"];
+
+/// The directory of the entry in the `files` sink: `<destination>/issues` or
+/// `<destination>/pulls`.
+fn entry_dir(destination: &Path, x: &EntryWithMetadata) -> PathBuf {
+    match x {
+        EntryWithMetadata::Issue(_) => destination.join(&args().issues_dir_name),
+        EntryWithMetadata::Pull(_) => destination.join(&args().pulls_dir_name),
+    }
+}
+
+/// The file of the entry in the `files` sink: `<destination>/issues/<number>.json`
+/// or `<destination>/pulls/<number>.json`, and with `--layout split-by-state`
+/// or `sharded` in the subdirectory of its state or shard. The extension
+/// follows the `--serialization`.
+fn entry_file(destination: &Path, x: &EntryWithMetadata) -> PathBuf {
+    entry_file_in(destination, x, args().layout(), args().compress)
+}
+
+/// Like [entry_file], but for the `layout`, and gzip-compressed if `compress`
+/// is set.
+fn entry_file_in(
+    destination: &Path,
+    x: &EntryWithMetadata,
+    layout: Layout,
+    compress: bool,
+) -> PathBuf {
+    let mut path = entry_dir(destination, x);
+    match layout {
+        Layout::SplitByState => path.push(x.state_name()),
+        Layout::Sharded => path.push(sink::shard_dir(x.number())),
+        Layout::ByDate => path.push(x.date_bucket()),
+        Layout::PerNumber | Layout::Flat => (),
+    }
+    path.push(format!(
+        "{}.{}{}",
+        x.number(),
+        args().serialization.extension(),
+        if compress { ".gz" } else { "" }
+    ));
+    path
+}
+
+/// The path of the entry's file relative to `destination` as recorded in the
+/// index. Only set for `--layout by-date`, as the path of the other layouts
+/// follows from the number.
+fn index_path(destination: &Path, x: &EntryWithMetadata) -> Option<String> {
+    if args().layout() != Layout::ByDate || !args().sinks().contains(&SinkKind::Files) {
+        return None;
+    }
+    let path = entry_file(destination, x);
+    let relative = path.strip_prefix(destination).ok()?;
+    Some(
+        relative
+            .components()
+            .map(|c| c.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/"),
+    )
+}
+
+/// All places the file of entry `number` can be in the directory `dir`: the
+/// per-number layout, the `open` and `closed` subdirectories of
+/// `--layout split-by-state` and the shard of `--layout sharded`. With
+/// `--layout by-date`, the existing `YYYY/MM` subdirectories are searched too.
+/// Files of every `--serialization` format are included.
+fn entry_file_candidates(dir: &Path, number: u64) -> Vec<PathBuf> {
+    let mut dirs = vec![dir.to_path_buf()];
+    dirs.extend(STATE_DIRS.iter().map(|state| dir.join(state)));
+    dirs.push(dir.join(sink::shard_dir(number)));
+    if args().layout() == Layout::ByDate {
+        dirs.extend(date_buckets(dir));
+    }
+    dirs.iter()
+        .flat_map(|dir| {
+            Serialization::ALL.iter().flat_map(move |format| {
+                ["", ".gz"].map(|gz| dir.join(format!("{}.{}{}", number, format.extension(), gz)))
+            })
+        })
+        .collect()
+}
+
+/// The `YYYY/MM` subdirectories of `--layout by-date` in the directory `dir`.
+fn date_buckets(dir: &Path) -> Vec<PathBuf> {
+    let subdirs = |dir: &Path, digits: usize| -> Vec<PathBuf> {
+        fs::read_dir(dir)
+            .into_iter()
+            .flatten()
+            .flatten()
+            .map(|entry| entry.path())
+            .filter(|path| {
+                path.is_dir()
+                    && path
+                        .file_name()
+                        .and_then(|n| n.to_str())
+                        .is_some_and(|n| n.len() == digits && n.chars().all(|c| c.is_ascii_digit()))
+            })
+            .collect()
+    };
+    subdirs(dir, 4)
+        .iter()
+        .flat_map(|year| subdirs(year, 2))
+        .collect()
+}
+
+/// Whether the issue or pull-request `number` already has a file in the
+/// `files` sink layout of `destination`.
+fn is_on_disk(destination: &Path, is_pull: bool, number: u64) -> bool {
+    let dir = match is_pull {
+        true => &args().pulls_dir_name,
+        false => &args().issues_dir_name,
+    };
+    entry_file_candidates(&destination.join(dir), number)
+        .iter()
+        .any(|path| path.exists())
+}
+
+/// Writes each entry to `<destination>/issues/<number>.json` or
+/// `<destination>/pulls/<number>.json`, honoring `--on-conflict`, in the
+/// `--serialization` format and gzip-compressed with `--compress`.
+struct FilesSink {
+    destination: PathBuf,
+    layout: Layout,
+    compress: bool,
+}
+
+impl FilesSink {
+    /// With --layout split-by-state, an entry that was closed or reopened
+    /// since the last backup moves to the other subdirectory. The copy in
+    /// the other one, or in another layout the backup was written with
+    /// before, is stale once the entry's file is at `path`. It's only removed
+    /// after the new file is written, so a failed write doesn't lose the
+    /// entry.
+    fn remove_stale(&self, x: &EntryWithMetadata, path: &Path) -> Result<(), WriteError> {
+        for stale in entry_file_candidates(&entry_dir(&self.destination, x), x.number()) {
+            if stale != path && stale.exists() {
+                fs::remove_file(&stale)?;
+                info!("Removed the stale {} of {}", stale.display(), x);
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Sink for FilesSink {
+    fn name(&self) -> &'static str {
+        "files"
+    }
+
+    fn write(&self, x: &EntryWithMetadata, value: &serde_json::Value) -> Result<(), WriteError> {
+        let path = entry_file_in(&self.destination, x, self.layout, self.compress);
+        // The directory might have been removed since it was created on startup.
+        if let Some(parent) = path.parent() {
+            permissions::create_dir_all(parent)?;
+        }
+        if path.exists() {
+            match args().on_conflict {
+                OnConflict::Overwrite => (),
+                OnConflict::Skip => {
+                    info!("Skipped {} as it already exists", path.display());
+                    return self.remove_stale(x, &path);
+                }
+                OnConflict::Backup => {
+                    let mut backup = path.clone().into_os_string();
+                    backup.push(".bak");
+                    fs::rename(&path, &backup)?;
+                    debug!("Moved existing {} to {:?}", path.display(), backup);
+                }
+            }
+        }
+        // The entry is encoded (and compressed) straight into the file, so a
+        // huge entry isn't held in memory a second time as bytes.
+        permissions::write_atomically_with(&path, |file| match self.compress {
+            true => {
+                let mut encoder = GzEncoder::new(file, Compression::default());
+                sink::encode_into(value, args().serialization, &mut encoder)?;
+                encoder.finish()?;
+                Ok(())
+            }
+            false => sink::encode_into(value, args().serialization, file),
+        })?;
+        info!("Written {}", path.display());
+        self.remove_stale(x, &path)
+    }
+}
+
+/// Writes a copy of the entry to `<destination>/rewritten/issues/<number>.json`
+/// or `<destination>/rewritten/pulls/<number>.json`, with the URLs of the
+/// downloaded attachments replaced by their relative path.
+fn write_rewritten(
+    x: &EntryWithMetadata,
+    destination: &Path,
+    files: &BTreeMap<String, String>,
+) -> Result<(), WriteError> {
+    let dir = match x {
+        EntryWithMetadata::Issue(_) => &args().issues_dir_name,
+        EntryWithMetadata::Pull(_) => &args().pulls_dir_name,
+    };
+    let dir = destination.join(REWRITTEN_DIR).join(dir);
+    let mut value = serialize(x)?;
+    attachments::rewrite_urls(
+        &mut value,
+        files,
+        &format!("../../{}", attachments::ATTACHMENTS_DIR),
+    );
+    permissions::create_dir_all(&dir)?;
+    let path = dir.join(format!("{}.json", x.number()));
+    permissions::write_atomically(&path, serde_json::to_string_pretty(&value)?.as_bytes())?;
+    debug!("Written {}", path.display());
+    Ok(())
+}
+
+/// Writes the raw API responses of the entry to `<dir>/issues/<number>.json`
+/// or `<dir>/pulls/<number>.json`. Users are anonymized like in the typed
+/// files, but all fields are kept.
+fn write_raw(x: &EntryWithMetadata, dir: &Path) -> Result<(), WriteError> {
+    let (path, raw) = match x {
+        EntryWithMetadata::Issue(i) => (
+            dir.join(&args().issues_dir_name)
+                .join(format!("{}.json", i.issue.number)),
+            &i.raw,
+        ),
+        EntryWithMetadata::Pull(p) => (
+            dir.join(&args().pulls_dir_name)
+                .join(format!("{}.json", p.pull.number)),
+            &p.raw,
+        ),
+    };
+    let mut value = match raw {
+        Some(raw) => serde_json::to_value(raw)?,
+        None => return Ok(()),
+    };
+    if let Some(salt) = ANONYMIZE_SALT.get() {
+        transform::anonymize_users(&mut value, salt);
+    }
+    if let Some(parent) = path.parent() {
+        permissions::create_dir_all(parent)?;
+    }
+    let json = serde_json::to_string_pretty(&value)?;
+    permissions::write_atomically(&path, json.as_bytes())?;
+    debug!("Written raw responses to {}", path.display());
+    Ok(())
+}
+
+/// Returns the number of bytes available to unprivileged users on the
+/// filesystem containing `path`.
+#[cfg(unix)]
+fn available_disk_space(path: &Path) -> std::io::Result<u64> {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+
+    let c_path = CString::new(path.as_os_str().as_bytes())?;
+    let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+    if unsafe { libc::statvfs(c_path.as_ptr(), &mut stat) } != 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(stat.f_bavail as u64 * stat.f_frsize as u64)
+}
+
+#[cfg(not(unix))]
+fn available_disk_space(_path: &Path) -> std::io::Result<u64> {
+    Err(std::io::Error::new(
+        std::io::ErrorKind::Unsupported,
+        "checking the free disk space is only supported on unix",
+    ))
+}
+
+/// Checks that at least `--min-free-disk` bytes are available on the
+/// destination filesystem. Returns false if there is less space left.
+fn enough_disk_space(destination: &Path) -> bool {
+    let min_free = match args().min_free_disk {
+        Some(min_free) => min_free,
+        None => return true,
+    };
+    match available_disk_space(destination) {
+        Ok(available) if available < min_free => {
+            error!(
+                "Only {} bytes of free disk space left in {} (--min-free-disk is {} bytes)",
+                available,
+                destination.display(),
+                min_free
+            );
+            false
+        }
+        Ok(_) => true,
+        Err(e) => {
+            warn!(
+                "Could not check the free disk space in {}: {}",
+                destination.display(),
+                e
+            );
+            true
+        }
+    }
+}
+
+/// Backs up the wiki of the repository into the `wiki/` directory, if the
+/// repository has the wiki feature enabled. A failing wiki backup is logged
+/// but doesn't fail the backup of the issues and pull-requests.
+async fn backup_wiki(owner: String, repo: String, destination: PathBuf, pat: String) {
+    let repository: models::Repository = match api_get(format!("/repos/{}/{}", owner, repo)).await {
+        Ok(repository) => repository,
+        Err(e) => {
+            error!("Could not check if {}:{} has a wiki: {}", owner, repo, e);
+            return;
+        }
+    };
+    if repository.has_wiki != Some(true) {
+        info!("The wiki of {}:{} is disabled - skipping it.", owner, repo);
+        return;
+    }
+
+    let wiki_dir = destination.join(wiki::WIKI_DIR);
+    match task::spawn_blocking(move || wiki::backup_wiki(&owner, &repo, &wiki_dir, &pat)).await {
+        Ok(Ok(true)) => info!("Backed up the wiki"),
+        Ok(Ok(false)) => (),
+        Ok(Err(e)) => error!("Could not backup the wiki: {}", e),
+        Err(e) => error!("Could not backup the wiki: {}", e),
+    }
+}
+
+#[async_recursion]
+async fn get_repository(
+    owner: String,
+    repo: String,
+    attempt: Attempt,
+) -> Result<serde_json::Value, FetchError> {
+    match api_get(format!("/repos/{}/{}", owner, repo)).await {
+        Ok(r) => Ok(r),
+        Err(e) => {
+            let Some(attempt) = retry_after_error(&e, attempt).await else {
+                return Err(e);
+            };
+            get_repository(owner, repo, attempt).await
+        }
+    }
+}
+
+#[async_recursion]
+async fn get_repository_by_id(id: u64, attempt: Attempt) -> Result<serde_json::Value, FetchError> {
+    match api_get(format!("/repositories/{}", id)).await {
+        Ok(r) => Ok(r),
+        Err(e) => {
+            let Some(attempt) = retry_after_error(&e, attempt).await else {
+                return Err(e);
+            };
+            get_repository_by_id(id, attempt).await
+        }
+    }
+}
+
+/// Resolves the repository to back up with `--follow-renames` to its id and
+/// current owner and name. If `owner/repo` doesn't exist anymore, the
+/// repository with the `stored_id` of the previous backup is followed, as the
+/// id stays the same when a repository is renamed or transferred.
+async fn follow_repository(
+    owner: String,
+    repo: String,
+    stored_id: Option<u64>,
+) -> Result<(u64, RepositoryRef), FetchError> {
+    let repository = match (
+        get_repository(owner.clone(), repo.clone(), Attempt::FIRST).await,
+        stored_id,
+    ) {
+        (Ok(repository), _) => repository,
+        (Err(e), Some(id)) if is_removed(&e) => {
+            info!(
+                "{}:{} doesn't exist anymore. Looking up the repository with id {} of the previous backup.",
+                owner, repo, id
+            );
+            get_repository_by_id(id, Attempt::FIRST).await?
+        }
+        (Err(e), _) => return Err(e),
+    };
+    let id = repository["id"].as_u64().unwrap_or_default();
+    let followed = RepositoryRef {
+        owner: repository["owner"]["login"]
+            .as_str()
+            .unwrap_or(&owner)
+            .to_string(),
+        name: repository["name"].as_str().unwrap_or(&repo).to_string(),
+    };
+    // Owner and repository names on GitHub are case-insensitive.
+    if !followed.owner.eq_ignore_ascii_case(&owner) || !followed.name.eq_ignore_ascii_case(&repo) {
+        warn!(
+            "The repository {}:{} was renamed or transferred to {}:{} (id {}). Backing up {}:{} instead. Update the --owner and --repo to silence this warning.",
+            owner, repo, followed.owner, followed.name, id, followed.owner, followed.name
+        );
+    }
+    match stored_id {
+        Some(stored_id) if stored_id != id => warn!(
+            "{}:{} has the id {}, but the previous backup was of the repository with id {}. It might have been deleted and re-created.",
+            followed.owner, followed.name, id, stored_id
+        ),
+        _ => (),
+    }
+    Ok((id, followed))
+}
+
+#[async_recursion]
+async fn get_languages(
+    owner: String,
+    repo: String,
+    attempt: Attempt,
+) -> Result<BTreeMap<String, u64>, FetchError> {
+    match api_get(format!("/repos/{}/{}/languages", owner, repo)).await {
+        Ok(l) => Ok(l),
+        Err(e) => {
+            let Some(attempt) = retry_after_error(&e, attempt).await else {
+                return Err(e);
+            };
+            get_languages(owner, repo, attempt).await
+        }
+    }
+}
+
+/// Fetches the public profile of the user or organization `owner`. The users
+/// API also lists organizations, but only the organizations API has their
+/// description, so organizations are fetched from there.
+#[async_recursion]
+async fn get_owner_profile(
+    owner: String,
+    attempt: Attempt,
+) -> Result<serde_json::Value, FetchError> {
+    let result = match api_get::<serde_json::Value>(format!("/users/{}", owner)).await {
+        Ok(user) if user["type"] == "Organization" => api_get(format!("/orgs/{}", owner)).await,
+        result => result,
+    };
+    match result {
+        Ok(profile) => Ok(profile),
+        Err(e) => {
+            let Some(attempt) = retry_after_error(&e, attempt).await else {
+                return Err(e);
+            };
+            get_owner_profile(owner, attempt).await
+        }
+    }
+}
+
+/// Backs up the metadata of the repository into `repository.json`, and the
+/// profile of its owner into `owner.json`. Like the wiki, a failing
+/// repository metadata backup is logged but doesn't fail the backup of the
+/// issues and pull-requests.
+async fn backup_repo_metadata(owner: String, repo: String, destination: PathBuf) {
+    let repository = match get_repository(owner.clone(), repo.clone(), Attempt::FIRST).await {
+        Ok(repository) => repository,
+        Err(e) => {
+            error!("Could not get the repository {}:{}: {}", owner, repo, e);
+            return;
+        }
+    };
+    let languages = match get_languages(owner.clone(), repo.clone(), Attempt::FIRST).await {
+        Ok(languages) => languages,
+        Err(e) => {
+            error!("Could not get the languages of {}:{}: {}", owner, repo, e);
+            return;
+        }
+    };
+
+    let path = destination.join(REPOSITORY_FILE);
+    if let Err(e) = write_repository(RepositoryWithMetadata::new(repository, languages), &path) {
+        error!("Could not write {}: {}", path.display(), e);
+    }
+
+    let profile = match get_owner_profile(owner.clone(), Attempt::FIRST).await {
+        Ok(profile) => profile,
+        Err(e) => {
+            error!("Could not get the profile of the owner {}: {}", owner, e);
+            return;
+        }
+    };
+    let path = destination.join(OWNER_FILE);
+    let result = serde_json::to_string_pretty(&profile)
+        .map_err(WriteError::from)
+        .and_then(|json| Ok(permissions::write_atomically(&path, json.as_bytes())?));
+    match result {
+        Ok(()) => info!("Written {}", path.display()),
+        Err(e) => error!("Could not write {}: {}", path.display(), e),
+    }
+}
+
+#[async_recursion]
+async fn get_milestones_page(
+    page: u32,
+    owner: String,
+    repo: String,
+    attempt: Attempt,
+) -> Result<Page<serde_json::Value>, FetchError> {
+    match api_get(format!(
+        "/repos/{}/{}/milestones?state=all&per_page={}&page={}",
+        owner, repo, MAX_PER_PAGE, page
+    ))
+    .await
+    {
+        Ok(p) => Ok(p),
+        Err(e) => {
+            let Some(attempt) = retry_after_error(&e, attempt).await else {
+                return Err(e);
+            };
+            get_milestones_page(page, owner, repo, attempt).await
+        }
+    }
+}
+
+/// Backs up the open and closed milestones of the repository into
+/// `milestones.json` and returns their numbers by title. Like the repository
+/// metadata, a failing milestone backup is logged but doesn't fail the backup
+/// of the issues and pull-requests.
+async fn backup_milestones(
+    owner: String,
+    repo: String,
+    destination: &Path,
+) -> Option<BTreeMap<String, u64>> {
+    let mut milestones = vec![];
+    for page in 1..u32::MAX {
+        let mut milestones_page =
+            match get_milestones_page(page, owner.clone(), repo.clone(), Attempt::FIRST).await {
+                Ok(page) => page,
+                Err(e) => {
+                    error!("Could not get the milestones of {}:{}: {}", owner, repo, e);
+                    return None;
+                }
+            };
+        milestones.append(&mut milestones_page.take_items());
+        if milestones_page.next.is_none() {
+            break;
+        }
+    }
+    let numbers = milestones
+        .iter()
+        .filter_map(|m| Some((m["title"].as_str()?.to_string(), m["number"].as_u64()?)))
+        .collect();
+
+    let path = destination.join(MILESTONES_FILE);
+    let mut value = serde_json::Value::Array(milestones);
+    if let Some(salt) = ANONYMIZE_SALT.get() {
+        transform::anonymize_users(&mut value, salt);
+    }
+    let result = serde_json::to_string_pretty(&value)
+        .map_err(WriteError::from)
+        .and_then(|json| Ok(permissions::write_atomically(&path, json.as_bytes())?));
+    match result {
+        Ok(()) => info!("Written {}", path.display()),
+        Err(e) => error!("Could not write {}: {}", path.display(), e),
+    }
+    Some(numbers)
+}
+
+#[async_recursion]
+async fn get_labels_page(
+    page: u32,
+    owner: String,
+    repo: String,
+    attempt: Attempt,
+) -> Result<Page<serde_json::Value>, FetchError> {
+    match api_get(format!(
+        "/repos/{}/{}/labels?per_page={}&page={}",
+        owner, repo, MAX_PER_PAGE, page
+    ))
+    .await
+    {
+        Ok(p) => Ok(p),
+        Err(e) => {
+            let Some(attempt) = retry_after_error(&e, attempt).await else {
+                return Err(e);
+            };
+            get_labels_page(page, owner, repo, attempt).await
+        }
+    }
+}
+
+/// Backs up the label definitions of the repository into `labels.json`, which
+/// keeps labels that were renamed or deleted since. Like the milestones, a
+/// failing label backup is logged but doesn't fail the backup of the issues and
+/// pull-requests.
+async fn backup_labels(owner: String, repo: String, destination: &Path) {
+    let mut labels = vec![];
+    for page in 1..u32::MAX {
+        let mut labels_page =
+            match get_labels_page(page, owner.clone(), repo.clone(), Attempt::FIRST).await {
+                Ok(page) => page,
+                Err(e) => {
+                    error!("Could not get the labels of {}:{}: {}", owner, repo, e);
+                    return;
+                }
+            };
+        labels.append(&mut labels_page.take_items());
+        if labels_page.next.is_none() {
+            break;
+        }
+    }
+
+    let path = destination.join(LABELS_FILE);
+    let result = serde_json::to_string_pretty(&labels)
+        .map_err(WriteError::from)
+        .and_then(|json| Ok(permissions::write_atomically(&path, json.as_bytes())?));
+    match result {
+        Ok(()) => info!("Written {}", path.display()),
+        Err(e) => error!("Could not write {}: {}", path.display(), e),
+    }
+}
+
+#[async_recursion]
+async fn get_discussions_data(
+    query: String,
+    variables: serde_json::Value,
+    attempt: Attempt,
+) -> Result<serde_json::Value, FetchError> {
+    match api_graphql(&query, variables.clone()).await {
+        Ok(data) => Ok(data),
+        Err(e) => {
+            let Some(attempt) = retry_after_error(&e, attempt).await else {
+                return Err(e);
+            };
+            get_discussions_data(query, variables, attempt).await
+        }
+    }
+}
+
+/// Completes the comments of a `discussion` and the replies of each comment.
+/// The first ones come with the discussion, the rest is fetched page by page.
+/// The connections are replaced by plain arrays.
+async fn complete_discussion(
+    discussion: &mut serde_json::Value,
+    owner: &str,
+    repo: &str,
+) -> Result<(), FetchError> {
+    let number = discussion["number"].as_u64().unwrap_or_default();
+    let mut page = discussions::take_connection(&mut discussion["comments"]);
+    let mut comments = vec![];
+    loop {
+        comments.append(&mut page.nodes);
+        let Some(after) = page.next else {
+            break;
+        };
+        let variables = serde_json::json!({
+            "owner": owner,
+            "repo": repo,
+            "number": number,
+            "after": after,
+        });
+        let mut data =
+            get_discussions_data(discussions::comments_query(), variables, Attempt::FIRST).await?;
+        page = discussions::take_connection(&mut data["repository"]["discussion"]["comments"]);
+    }
+    for comment in comments.iter_mut() {
+        let mut page = discussions::take_connection(&mut comment["replies"]);
+        let mut replies = vec![];
+        loop {
+            replies.append(&mut page.nodes);
+            let Some(after) = page.next else {
+                break;
+            };
+            let variables = serde_json::json!({ "id": comment["id"], "after": after });
+            let mut data =
+                get_discussions_data(discussions::replies_query(), variables, Attempt::FIRST)
+                    .await?;
+            page = discussions::take_connection(&mut data["node"]["replies"]);
+        }
+        replies.iter_mut().for_each(discussions::normalize);
+        discussions::normalize(comment);
+        comment["replies"] = replies.into();
+    }
+    discussions::normalize(discussion);
+    discussion["comments"] = comments.into();
+    Ok(())
+}
+
+/// Backs up each discussion of the repository updated since `since` into
+/// `discussions/<number>.json`, with all comments and their replies, and the
+/// reactions and author association of each. Like the labels, a failing
+/// discussion backup is logged but doesn't fail the backup of the issues and
+/// pull-requests.
+async fn backup_discussions(
+    owner: String,
+    repo: String,
+    destination: &Path,
+    since: Option<DateTime<Utc>>,
+) {
+    let dir = destination.join(discussions::DISCUSSIONS_DIR);
+    if let Err(e) = permissions::create_dir_all(&dir) {
+        error!("Could not create {}: {}", dir.display(), e);
+        return;
+    }
+    let mut written = 0;
+    let mut after = None;
+    let mut first = discussions::PAGE_SIZE;
+    'pages: loop {
+        let variables = serde_json::json!({
+            "owner": owner,
+            "repo": repo,
+            "first": first,
+            "after": after,
+        });
+        let mut data = match get_discussions_data(
+            discussions::discussions_query(),
+            variables,
+            Attempt::FIRST,
+        )
+        .await
+        {
+            Ok(data) => data,
+            Err(e) if is_graphql_limit(&e) && first > 1 => {
+                first /= 2;
+                warn!(
+                        "The discussions query of {}:{} exceeds the GraphQL limits. Retrying with pages of {}.",
+                        owner, repo, first
+                    );
+                continue;
+            }
+            Err(e) => {
+                error!("Could not get the discussions of {}:{}: {}", owner, repo, e);
+                break;
+            }
+        };
+        let page = discussions::take_connection(&mut data["repository"]["discussions"]);
+        for mut discussion in page.nodes {
+            let updated_at = discussion["updatedAt"]
+                .as_str()
+                .and_then(|t| t.parse::<DateTime<Utc>>().ok());
+            if let (Some(since), Some(updated_at)) = (since, updated_at) {
+                // The discussions are ordered by their last update.
+                if updated_at < since {
+                    break 'pages;
+                }
+            }
+            let number = discussion["number"].as_u64().unwrap_or_default();
+            if let Err(e) = complete_discussion(&mut discussion, &owner, &repo).await {
+                error!(
+                    "Could not get the comments of discussion #{} in {}:{}: {}",
+                    number, owner, repo, e
+                );
+                continue;
+            }
+            let path = dir.join(format!("{}.json", number));
+            let result = serde_json::to_string_pretty(&discussion)
+                .map_err(WriteError::from)
+                .and_then(|json| Ok(permissions::write_atomically(&path, json.as_bytes())?));
+            match result {
+                Ok(()) => {
+                    written += 1;
+                    debug!("Written {}", path.display());
+                }
+                Err(e) => error!("Could not write {}: {}", path.display(), e),
+            }
+        }
+        match page.next {
+            Some(next) => after = Some(next),
+            None => break,
+        }
+    }
+    info!(
+        "Written {} discussions of {}:{} to {}",
+        written,
+        owner,
+        repo,
+        dir.display()
+    );
+}
+
+#[async_recursion]
+async fn get_releases_page(
+    page: u32,
+    owner: String,
+    repo: String,
+    attempt: Attempt,
+) -> Result<Page<serde_json::Value>, FetchError> {
+    match api_get(format!(
+        "/repos/{}/{}/releases?per_page={}&page={}",
+        owner, repo, MAX_PER_PAGE, page
+    ))
+    .await
+    {
+        Ok(p) => Ok(p),
+        Err(e) => {
+            let Some(attempt) = retry_after_error(&e, attempt).await else {
+                return Err(e);
+            };
+            get_releases_page(page, owner, repo, attempt).await
+        }
+    }
+}
+
+/// Backs up each release of the repository into `releases/{id}/release.json`
+/// and downloads its assets into `releases/{id}/assets/`. Like the wiki, a
+/// failing release backup is logged but doesn't fail the backup of the issues
+/// and pull-requests.
+async fn backup_releases(owner: String, repo: String, destination: &Path, pat: &str) {
+    let downloader = match releases::AssetDownloader::new(
+        pat,
+        args().max_retries,
+        args().release_assets_max_bytes,
+    ) {
+        Ok(downloader) => downloader,
+        Err(e) => {
+            error!("Could not download the release assets: {}", e);
+            return;
+        }
+    };
+    let mut releases = vec![];
+    for page in 1..u32::MAX {
+        let mut releases_page =
+            match get_releases_page(page, owner.clone(), repo.clone(), Attempt::FIRST).await {
+                Ok(page) => page,
+                Err(e) => {
+                    error!("Could not get the releases of {}:{}: {}", owner, repo, e);
+                    return;
+                }
+            };
+        releases.append(&mut releases_page.take_items());
+        if releases_page.next.is_none() {
+            break;
+        }
+    }
+
+    for release in releases {
+        let Some(id) = release["id"].as_u64() else {
+            continue;
+        };
+        let dir = destination
+            .join(releases::RELEASES_DIR)
+            .join(id.to_string());
+        let path = dir.join(releases::RELEASE_FILE);
+        let mut value = release.clone();
+        if let Some(salt) = ANONYMIZE_SALT.get() {
+            transform::anonymize_users(&mut value, salt);
+        }
+        let result = permissions::create_dir_all(&dir)
+            .map_err(WriteError::from)
+            .and_then(|()| Ok(serde_json::to_string_pretty(&value)?))
+            .and_then(|json| Ok(permissions::write_atomically(&path, json.as_bytes())?));
+        if let Err(e) = result {
+            error!("Could not write {}: {}", path.display(), e);
+            continue;
+        }
+        if let Err(e) = downloader.download_release(&release, &dir).await {
+            error!("Could not download the assets of the release {}: {}", id, e);
+        }
+    }
+    info!("Backed up the releases of {}:{}", owner, repo);
+}
+
+fn write_repository(repository: RepositoryWithMetadata, path: &Path) -> Result<(), WriteError> {
+    let mut value = serde_json::to_value(&repository)?;
+    if let Some(salt) = ANONYMIZE_SALT.get() {
+        transform::anonymize_users(&mut value, salt);
+    }
+    let json = serde_json::to_string_pretty(&value)?;
+    permissions::write_atomically(path, json.as_bytes())?;
+    info!("Written {}", path.display());
+    Ok(())
+}
+
+/// Whether entries of this type are skipped with `--only-issues` or
+/// `--only-pulls`.
+fn is_skipped_type(is_pull: bool) -> bool {
+    match is_pull {
+        true => args().only_issues,
+        false => args().only_pulls,
+    }
+}
+
+fn write_backup_state(state: &BackupState, mut destination: PathBuf) -> Result<(), WriteError> {
+    let json = serde_json::to_string_pretty(state)?;
+    let (name, stale) = if args().compress_state {
+        (STATE_FILE_GZ, STATE_FILE)
+    } else {
+        (STATE_FILE, STATE_FILE_GZ)
+    };
+    let stale = destination.join(stale);
+    destination.push(name);
+    if args().compress_state {
+        permissions::write_atomically(&destination, &gzip(json.as_bytes())?)?;
+    } else {
+        permissions::write_atomically(&destination, json.as_bytes())?;
+    }
+    // Only keep one state file, so the next run can't load an outdated one.
+    if stale.exists() {
+        fs::remove_file(&stale)?;
+    }
+    info!("Written backup state to {}", destination.display());
+    Ok(())
+}
+
+/// Removes the `.partial` files below `dir` left behind by a backup that was
+/// interrupted while writing them.
+fn remove_partial_files(dir: &Path) -> Result<(), WriteError> {
+    let mut dirs = vec![dir.to_path_buf()];
+    while let Some(dir) = dirs.pop() {
+        for entry in fs::read_dir(&dir)? {
+            let path = entry?.path();
+            if path.is_dir() {
+                dirs.push(path);
+            } else if path.extension().is_some_and(|ext| ext == "partial") {
+                fs::remove_file(&path)?;
+                info!(
+                    "Removed {} left behind by an interrupted backup",
+                    path.display()
+                );
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Removes the files of the issues and pull-requests that aren't among the
+/// `listed` ones anymore, as they were deleted, transferred or converted to a
+/// discussion, from the issues and pulls directories and the index. With
+/// `--prune-mode move`, they are moved into a `deleted/` directory instead.
+/// Returns the number of pruned files.
+fn prune_orphans(
+    listed: &BTreeSet<u64>,
+    destination: &Path,
+    index: &mut index::Index,
+) -> Result<u64, WriteError> {
+    let args = args();
+    let mut pruned = 0;
+    for (is_pull, dir_name) in [(false, &args.issues_dir_name), (true, &args.pulls_dir_name)] {
+        if is_skipped_type(is_pull) {
+            continue;
+        }
+        let dir = destination.join(dir_name);
+        let pruned_dir = dir.join(PRUNED_DIR);
+        let mut dirs = vec![dir];
+        while let Some(dir) = dirs.pop() {
+            let entries = match fs::read_dir(&dir) {
+                Ok(entries) => entries,
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+                Err(e) => return Err(e.into()),
+            };
+            for entry in entries {
+                let path = entry?.path();
+                if path.is_dir() {
+                    if path != pruned_dir {
+                        dirs.push(path);
+                    }
+                    continue;
+                }
+                let Some((number, _)) = parse_entry_file_name(&path) else {
+                    continue;
+                };
+                if listed.contains(&number) {
+                    continue;
+                }
+                match args.prune_mode {
+                    PruneMode::Delete => {
+                        fs::remove_file(&path)?;
+                        info!(
+                            "Removed {} as #{} is no longer in the repository",
+                            path.display(),
+                            number
+                        );
+                    }
+                    PruneMode::Move => {
+                        permissions::create_dir_all(&pruned_dir)?;
+                        let target = pruned_dir.join(path.file_name().unwrap_or_default());
+                        fs::rename(&path, &target)?;
+                        info!(
+                            "Moved {} to {} as #{} is no longer in the repository",
+                            path.display(),
+                            target.display(),
+                            number
+                        );
+                    }
+                }
+                index.remove(number);
+                pruned += 1;
+            }
+        }
+    }
+    Ok(pruned)
+}
+
+/// The number of the issue or pull-request and the format of an entry file
+/// named like `123.json` or `123.msgpack.gz`. Other files, like `.bak` and
+/// `.partial` ones, have none.
+fn parse_entry_file_name(path: &Path) -> Option<(u64, Serialization)> {
+    let name = path.file_name()?.to_str()?;
+    let name = name.strip_suffix(".gz").unwrap_or(name);
+    let (number, extension) = name.split_once('.')?;
+    let format = Serialization::ALL
+        .into_iter()
+        .find(|format| format.extension() == extension)?;
+    Some((number.parse().ok()?, format))
+}
+
+/// Loads the ETags kept by `--use-etags` in `destination`. Starts without
+/// any if there are none or they can't be read, so every entry is fetched.
+fn load_etags(destination: &Path) -> BTreeMap<u64, EntryEtags> {
+    let path = destination.join(ETAGS_FILE);
+    let contents = match fs::read_to_string(&path) {
+        Ok(contents) => contents,
+        Err(_) => return BTreeMap::new(),
+    };
+    let mut file: serde_json::Value = match serde_json::from_str(&contents) {
+        Ok(file) => file,
+        Err(e) => {
+            warn!("Could not read {}: {}", path.display(), e);
+            return BTreeMap::new();
+        }
+    };
+    if file["version"] != ETAGS_VERSION {
+        warn!(
+            "{} has version {}, but only version {} is supported. Fetching all listed entries.",
+            path.display(),
+            file["version"],
+            ETAGS_VERSION
+        );
+        return BTreeMap::new();
+    }
+    serde_json::from_value(file["entries"].take()).unwrap_or_else(|e| {
+        warn!("Could not read {}: {}", path.display(), e);
+        BTreeMap::new()
+    })
+}
+
+fn write_etags(etags: &BTreeMap<u64, EntryEtags>, destination: &Path) -> Result<(), WriteError> {
+    let path = destination.join(ETAGS_FILE);
+    let json = serde_json::to_string(&serde_json::json!({
+        "version": ETAGS_VERSION,
+        "entries": etags,
+    }))?;
+    permissions::write_atomically(&path, json.as_bytes())?;
+    info!("Written {} ETags to {}", etags.len(), path.display());
+    Ok(())
+}
+
+/// Reads a state file, decompressing it if it's gzip-compressed.
+fn read_state_file(path: &Path) -> std::io::Result<String> {
+    String::from_utf8(read_maybe_compressed(path)?).map_err(std::io::Error::other)
+}
+
+/// Reads a file, decompressing it if it's gzip-compressed.
+fn read_maybe_compressed(path: &Path) -> std::io::Result<Vec<u8>> {
+    let bytes = fs::read(path)?;
+    if bytes.starts_with(&GZIP_MAGIC) {
+        let mut contents = vec![];
+        GzDecoder::new(bytes.as_slice()).read_to_end(&mut contents)?;
+        Ok(contents)
+    } else {
+        Ok(bytes)
+    }
+}
+
+/// Compresses `bytes` with gzip.
+fn gzip(bytes: &[u8]) -> std::io::Result<Vec<u8>> {
+    let mut encoder = GzEncoder::new(vec![], Compression::default());
+    encoder.write_all(bytes)?;
+    encoder.finish()
+}
+
+/// Creates an octocrab instance authenticated with the `pat`, for the API at
+/// the `base_uri` if set, that sends the custom `headers` with each request.
+fn build_octocrab(
+    pat: String,
+    base_uri: Option<&http::Uri>,
+    headers: &[Header],
+) -> Result<octocrab::Octocrab, String> {
+    let mut builder = octocrab::OctocrabBuilder::default().personal_token(pat);
+    if let Some(base_uri) = base_uri {
+        builder = builder
+            .base_uri(base_uri.clone())
+            .map_err(|e| e.to_string())?;
+    }
+    for header in headers.iter() {
+        builder = builder.add_header(header.name.clone(), header.value.clone());
+    }
+    builder.build().map_err(|e| e.to_string())
+}
+
+/// Parses the `--base-url`, which must be an absolute http or https URL.
+fn parse_base_url(url: &str) -> Result<http::Uri, String> {
+    let uri: http::Uri = url
+        .trim_end_matches('/')
+        .parse()
+        .map_err(|e| format!("'{}': {}", url, e))?;
+    match (uri.scheme_str(), uri.host()) {
+        (Some("http" | "https"), Some(_)) => Ok(uri),
+        _ => Err(format!("'{}' is not an http or https URL", url)),
+    }
+}
+
+/// The base URI of the GraphQL API of a GitHub Enterprise Server, whose REST
+/// API is below `/api/v3` and GraphQL API at `/api/graphql`. None if the
+/// GraphQL API is below the `base_uri`, like for api.github.com.
+fn graphql_base_uri(base_uri: &http::Uri) -> Option<http::Uri> {
+    let path = base_uri.path().trim_end_matches('/').strip_suffix("/v3")?;
+    format!(
+        "{}://{}{}",
+        base_uri.scheme_str()?,
+        base_uri.authority()?,
+        path
+    )
+    .parse()
+    .ok()
+}
+
+/// Reads the salt for `--anonymize-users` from `path`, or generates a random
+/// salt and stores it there if the file doesn't exist yet. The salt is stored
+/// hex-encoded and, on unix, only readable by the current user.
+fn load_or_create_salt(path: &Path) -> Result<Vec<u8>, WriteError> {
+    if path.exists() {
+        let contents = fs::read_to_string(path)?;
+        let hex = contents.trim();
+        if hex.len() < 32 || hex.len() % 2 != 0 {
+            return Err(std::io::Error::other("salt must be at least 16 hex-encoded bytes").into());
+        }
+        return (0..hex.len())
+            .step_by(2)
+            .map(|i| {
+                u8::from_str_radix(&hex[i..i + 2], 16)
+                    .map_err(|e| std::io::Error::other(format!("invalid salt: {}", e)).into())
+            })
+            .collect();
+    }
+
+    let mut salt = vec![0u8; 32];
+    getrandom::getrandom(&mut salt).map_err(|e| std::io::Error::other(e.to_string()))?;
+    let hex: String = salt.iter().map(|b| format!("{:02x}", b)).collect();
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let mut file = options.open(path)?;
+    file.write_all(hex.as_bytes())?;
+    info!("Generated a new anonymization salt in {}", path.display());
+    Ok(salt)
+}
+
+/// Reads the incremental backup cursor from the `--since-file`. The file
+/// contains a single RFC 3339 timestamp.
+fn read_since_file(path: &Path) -> Option<DateTime<Utc>> {
+    info!("Trying to read the since-file {}", path.display());
+    match fs::read_to_string(path) {
+        Ok(contents) => match DateTime::parse_from_rfc3339(contents.trim()) {
+            Ok(since) => {
+                info!(
+                    "Doing an incremental GitHub backup starting from {} (read from {}).",
+                    since,
+                    path.display()
+                );
+                Some(since.with_timezone(&Utc))
+            }
+            Err(e) => {
+                warn!(
+                    "The since-file {} does not contain a RFC 3339 timestamp: {}",
+                    path.display(),
+                    e
+                );
+                None
+            }
+        },
+        Err(e) => {
+            info!("The since-file {} could not be read: {}", path.display(), e);
+            None
+        }
+    }
+}
+
+fn write_since_file(start_time: DateTime<Utc>, path: &Path) -> Result<(), WriteError> {
+    permissions::write_atomically(path, start_time.to_rfc3339().as_bytes())?;
+    info!(
+        "Written the backup cursor to the since-file {}",
+        path.display()
+    );
+    Ok(())
+}
+
+/// Version 2 has separate cursors for issues and pull-requests. Both start at
+/// the single cursor of version 1.
+fn split_cursor(mut state: serde_json::Value) -> serde_json::Value {
+    state["last_backup_issues"] = state["last_backup"].clone();
+    state["last_backup_pulls"] = state["last_backup"].clone();
+    state
+}
+
+/// Upgrades a `state` file of an older `version` to the STATE_VERSION with
+/// the STATE_MIGRATIONS. Returns None if a step is missing.
+fn migrate_state(mut state: serde_json::Value, version: u32) -> Option<serde_json::Value> {
+    for from in version..STATE_VERSION {
+        let (_, migrate) = STATE_MIGRATIONS.iter().find(|(v, _)| *v == from)?;
+        state = migrate(state);
+        info!(
+            "Migrated the BackupState from version {} to {}",
+            from,
+            from + 1
+        );
+    }
+    state["version"] = STATE_VERSION.into();
+    Some(state)
+}
+
+/// Loads the state of the last backup from the `destination`. Both a plain and
+/// a gzip-compressed state file are read, independent of `--compress-state`.
+/// Older versions of the state file are migrated, and with --upgrade-state
+/// rewritten right away. Returns the version as error if the state file was
+/// written by a newer version of the tool, unless
+/// --ignore-state-version-mismatch is set.
+fn load_backup_state(destination: &Path) -> Result<Option<BackupState>, u32> {
+    let (contents, path) = match &args().resume_token {
+        // The token is checked on startup.
+        Some(token) => match resume_token::decode(token).ok().flatten() {
+            Some(contents) => (contents, PathBuf::from("--resume-token")),
+            None => return Ok(None),
+        },
+        None => {
+            let path = match destination.join(STATE_FILE_GZ) {
+                gz if gz.exists() => gz,
+                _ => destination.join(STATE_FILE),
+            };
+            info!("Trying to read {} file", path.display());
+            match read_state_file(&path) {
+                Ok(contents) => (contents, path),
+                Err(e) => {
+                    info!(
+                        "BackupState file {} could not be found: {}",
+                        path.display(),
+                        e
+                    );
+                    return Ok(None);
+                }
+            }
+        }
+    };
+    info!("Trying deserialize {} file", path.display());
+    let state = serde_json::from_str::<serde_json::Value>(&contents).map(|state| {
+        let version = state["version"].as_u64().unwrap_or_default() as u32;
+        (state, version)
+    });
+    let mut migrated_from = None;
+    let state = match state {
+        Ok((state, STATE_VERSION)) => Some(state),
+        Ok((_, version)) if version > STATE_VERSION => {
+            if !args().ignore_state_version_mismatch {
+                return Err(version);
+            }
+            warn!(
+                "BackupState version {} is newer than the supported version {}. Ignoring it and doing a full backup.",
+                version, STATE_VERSION
+            );
+            None
+        }
+        Ok((state, version)) => {
+            let migrated = migrate_state(state, version);
+            match migrated {
+                Some(_) => migrated_from = Some(version),
+                None => warn!("BackupState version {} is unknown.", version),
+            }
+            migrated
+        }
+        Err(e) => {
+            warn!(
+                "BackupState file {} could not be deserialized: {}",
+                path.display(),
+                e
+            );
+            None
+        }
+    };
+    match state.map(serde_json::from_value::<BackupState>) {
+        Some(Ok(state)) => {
+            if let (Some(version), true, None) =
+                (migrated_from, args().upgrade_state, &args().resume_token)
+            {
+                upgrade_state_file(&state, version, destination);
+            }
+            Ok(Some(state))
+        }
+        Some(Err(e)) => {
+            warn!(
+                "BackupState file {} could not be deserialized: {}",
+                path.display(),
+                e
+            );
+            Ok(None)
+        }
+        None => Ok(None),
+    }
+}
+
+/// Rewrites the state file in the `destination`, which was migrated from the
+/// older `version`, with the `state` of the STATE_VERSION for
+/// --upgrade-state. A crash later in the run then can't leave a file of the
+/// old version behind. A failed rewrite is logged, as the run can still
+/// continue with the migrated state.
+fn upgrade_state_file(state: &BackupState, version: u32, destination: &Path) {
+    match write_backup_state(state, destination.to_path_buf()) {
+        Ok(()) => info!(
+            "Upgraded the BackupState file in {} from version {} to {}",
+            destination.display(),
+            version,
+            STATE_VERSION
+        ),
+        Err(e) => warn!(
+            "Could not upgrade the BackupState file in {} from version {}: {}",
+            destination.display(),
+            version,
+            e
+        ),
+    }
+}
+
+fn get_last_backup_time(destination: PathBuf) -> Option<DateTime<Utc>> {
+    // A newer state version is reported when the backup starts.
+    let state = load_backup_state(&destination).ok().flatten()?;
+    info!(
+        "Doing an incremental GitHub backup starting from {}.",
+        state.last_backup
+    );
+    Some(state.last_backup)
+}
+
+/// Reads the checkpoints of the issues and pull-requests that failed to load
+/// in the last backup. Without a readable checkpoint, the failed entry is
+/// fetched from the start.
+fn read_checkpoints(destination: &Path, failed: Vec<FailedEntry>) -> Vec<Failure> {
+    failed
+        .into_iter()
+        .map(|entry| {
+            let path = destination
+                .join(PARTIAL_DIR)
+                .join(format!("{}.json", entry.number));
+            let checkpoint = fs::read_to_string(&path)
+                .ok()
+                .and_then(|contents| serde_json::from_str::<Checkpoint>(&contents).ok());
+            match checkpoint {
+                Some(checkpoint) => (entry, checkpoint),
+                None => {
+                    let entry =
+                        FailedEntry::new(entry.number, &entry.r#type, &Checkpoint::default());
+                    (entry, Checkpoint::default())
+                }
+            }
+        })
+        .collect()
+}
+
+/// Replaces the checkpoints in the `partial/` directory with the ones of the
+/// issues and pull-requests that failed to load in this backup.
+fn write_checkpoints(destination: &Path, failed: &[Failure]) -> Result<(), WriteError> {
+    let dir = destination.join(PARTIAL_DIR);
+    if dir.exists() {
+        fs::remove_dir_all(&dir)?;
+    }
+    for (entry, checkpoint) in failed {
+        if checkpoint.timeline.pages == 0 && checkpoint.comments.pages == 0 {
+            continue;
+        }
+        permissions::create_dir_all(&dir)?;
+        let path = dir.join(format!("{}.json", entry.number));
+        permissions::write_atomically(&path, serde_json::to_string(checkpoint)?.as_bytes())?;
+        debug!("Written checkpoint {}", path.display());
+    }
+    Ok(())
+}
+
+/// Reads the tokens of a `--token-pool-file`: one per line, ignoring empty
+/// lines and `#` comments. The personal access token comes first and tokens
+/// listed twice are only used once.
+fn token_pool_tokens(path: &Path, pat: &str) -> io::Result<Vec<String>> {
+    let mut tokens = vec![pat.to_string()];
+    for line in fs::read_to_string(path)?.lines() {
+        let token = line.trim();
+        if !token.is_empty() && !token.starts_with('#') && !tokens.iter().any(|t| t == token) {
+            tokens.push(token.to_string());
+        }
+    }
+    Ok(tokens)
+}
+
+fn personal_access_token(args: &Args) -> Option<String> {
+    if let Some(pat) = &args.personal_access_token {
+        info!("Using the GitHub personal access token specified on the command line");
+        return Some(pat.clone());
+    } else if let Some(pat_file) = &args.personal_access_token_file {
+        info!(
+            "Reading the GitHub personal access token from '{}'",
+            pat_file.display()
+        );
+        match fs::read_to_string(pat_file.clone()) {
+            Ok(pat) => {
+                return Some(pat.trim().to_string());
+            }
+            Err(e) => {
+                error!(
+                    "Could not read GitHub personal access token from '{}': {}",
+                    pat_file.display(),
+                    e
+                );
+                return None;
+            }
+        }
+    }
+    None
+}
+
+/// The JSON Schema of the written issue and pull-request files.
+fn entry_schema() -> schemars::schema::RootSchema {
+    let mut schema = schemars::schema_for!(EntryWithMetadata);
+    schema.schema.metadata().title = Some(format!(
+        "github-metadata-backup entry (schema_version {})",
+        SCHEMA_VERSION
+    ));
+    schema.schema.metadata().description = Some(
+        "Each file starts with the schema_version, type, repository, number and generated_by \
+         fields, in this order, followed by the issue or pull-request and its history."
+            .to_string(),
+    );
+    schema
+}
+
+/// Prints the JSON Schema of the written issue and pull-request files.
+fn print_schema() -> ExitCode {
+    match serde_json::to_string_pretty(&entry_schema()) {
+        Ok(json) => {
+            println!("{}", json);
+            ExitCode::SUCCESS
+        }
+        Err(e) => {
+            error!("Could not serialize the JSON Schema: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn export_migration(directory: &Path, output: &Path) -> ExitCode {
+    info!(
+        "Converting the backup in {} into a migration archive in {}",
+        directory.display(),
+        output.display()
+    );
+    let (archive, skipped) =
+        match migration::from_backup(directory, &args().issues_dir_name, &args().pulls_dir_name) {
+            Ok(archive) => archive,
+            Err(e) => {
+                error!("Could not read {}: {}", directory.display(), e);
+                return ExitCode::FAILURE;
+            }
+        };
+    for path in skipped {
+        warn!(
+            "Skipped {} as it's not a readable issue or pull-request file",
+            path.display()
+        );
+    }
+    match archive.write(output) {
+        Ok(written) => {
+            for path in written {
+                info!("Written {}", path.display());
+            }
+            ExitCode::SUCCESS
+        }
+        Err(e) => {
+            error!(
+                "Could not write the migration archive to {}: {}",
+                output.display(),
+                e
+            );
+            ExitCode::from(EXIT_WRITING)
+        }
+    }
+}
+
+fn run_self_test(directory: &Path) -> ExitCode {
+    info!("Checking the files in {}", directory.display());
+    let report =
+        match self_test::self_test(directory, &args().issues_dir_name, &args().pulls_dir_name) {
+            Ok(report) => report,
+            Err(e) => {
+                error!("Could not read {}: {}", directory.display(), e);
+                return ExitCode::FAILURE;
+            }
+        };
+    for (path, problems) in report.incompatible.iter() {
+        println!("FAIL {}", path.display());
+        for problem in problems {
+            println!("     {}", problem);
+        }
+    }
+    println!(
+        "{} of {} files passed, {} are incompatible with this version",
+        report.checked - report.incompatible.len(),
+        report.checked,
+        report.incompatible.len()
+    );
+    if report.incompatible.is_empty() {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::FAILURE
+    }
+}
+
+fn run_compact(directory: &Path, shard_size: u64) -> ExitCode {
+    info!("Compacting the files in {}", directory.display());
+    match compact::compact(
+        directory,
+        &args().issues_dir_name,
+        &args().pulls_dir_name,
+        shard_size,
+    ) {
+        Ok(report) => {
+            println!(
+                "Merged {} files into {} shards",
+                report.compacted, report.shards
+            );
+            ExitCode::SUCCESS
+        }
+        Err(e) => {
+            error!("Could not compact {}: {}", directory.display(), e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn run_migrate(directory: &Path, repository: &Option<RepositoryRef>) -> ExitCode {
+    info!("Migrating the files in {}", directory.display());
+    let report = match upgrade::migrate(
+        directory,
+        &args().issues_dir_name,
+        &args().pulls_dir_name,
+        repository,
+    ) {
+        Ok(report) => report,
+        Err(e) => {
+            error!("Could not migrate {}: {}", directory.display(), e);
+            return ExitCode::from(EXIT_WRITING);
+        }
+    };
+    for (entry, reason) in report.failed.iter() {
+        println!("FAIL {}: {}", entry, reason);
+    }
+    println!(
+        "Migrated {} entries to schema_version {}, {} were up to date and {} failed",
+        report.migrated,
+        SCHEMA_VERSION,
+        report.current,
+        report.failed.len()
+    );
+    if report.failed.is_empty() {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::FAILURE
+    }
+}
+
+async fn run_validate_links(directory: &Path, external_hosts: bool, delay: Duration) -> ExitCode {
+    info!("Checking the links in {}", directory.display());
+    let token = personal_access_token(args());
+    let report = match links::validate_links(
+        directory,
+        &args().issues_dir_name,
+        &args().pulls_dir_name,
+        external_hosts,
+        delay,
+        token.as_deref(),
+    )
+    .await
+    {
+        Ok(report) => report,
+        Err(e) => {
+            error!("Could not read {}: {}", directory.display(), e);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let path = directory.join(links::DEAD_LINKS_FILE);
+    let result = serde_json::to_string_pretty(&report.dead)
+        .map_err(WriteError::from)
+        .and_then(|json| Ok(permissions::write_atomically(&path, json.as_bytes())?));
+    if let Err(e) = result {
+        error!("Could not write {}: {}", path.display(), e);
+        return ExitCode::from(EXIT_WRITING);
+    }
+    println!(
+        "Checked {} links in {} files: {} are dead, {} could not be checked and {} on other hosts were skipped. Written {}",
+        report.checked,
+        report.files,
+        report.dead.len(),
+        report.failed,
+        report.skipped,
+        path.display()
+    );
+    ExitCode::SUCCESS
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    let args: &Args = ARGS.get_or_init(Args::parse);
+
+    let mut logger = env_logger::Builder::from_env(Env::default().default_filter_or("info"));
+    if let Some(tz) = args.timezone {
+        logger.format(move |buf, record| {
+            writeln!(
+                buf,
+                "[{} {:<5} {}] {}",
+                Utc::now().with_timezone(&tz).format("%Y-%m-%dT%H:%M:%S%:z"),
+                buf.default_styled_level(record.level()),
+                record.target(),
+                record.args()
+            )
+        });
+    }
+    logger.init();
+
+    if !permissions::init(args.output_file_mode, args.output_dir_mode)
+        && (args.output_file_mode.is_some() || args.output_dir_mode.is_some())
+    {
+        warn!("--output-file-mode and --output-dir-mode are ignored on non-unix systems");
+    }
+
+    if let Some(command) = &args.command {
+        return match command {
+            Command::Schema => print_schema(),
+            Command::SelfTest { directory } => run_self_test(directory),
+            Command::ExportMigration { directory, output } => export_migration(directory, output),
+            Command::Compact {
+                directory,
+                shard_size,
+            } => run_compact(directory, *shard_size),
+            Command::Migrate {
+                directory,
+                repository,
+            } => run_migrate(directory, repository),
+            Command::ValidateLinks {
+                directory,
+                external_hosts,
+                delay,
+            } => run_validate_links(directory, *external_hosts, *delay).await,
+        };
+    }
+
+    if let Err(e) = args.repositories() {
+        error!("Invalid --repo: {}", e);
+        return ExitCode::FAILURE;
+    }
+    if args.repo.len() > 1 && !args.single_repo_flags().is_empty() {
+        error!(
+            "{} can't be used with several --repo's.",
+            args.single_repo_flags().join(", ")
+        );
+        return ExitCode::FAILURE;
+    }
+    if let Some(Err(e)) = args.resume_token.as_deref().map(resume_token::decode) {
+        error!("Invalid --resume-token: {}", e);
+        return ExitCode::FAILURE;
+    }
+    if args.list_open_failures {
+        return list_open_failures(&args.destination(), args.json);
+    }
+    let pat = match personal_access_token(args) {
+        Some(pat) => pat,
+        None => {
+            error!("No GitHub personal access token present - exiting.");
+            return ExitCode::from(EXIT_NO_PAT);
+        }
+    };
+
+    let base_uri = match args.base_url.as_deref().map(parse_base_url).transpose() {
+        Ok(base_uri) => base_uri,
+        Err(e) => {
+            error!("Invalid --base-url: {}", e);
+            return ExitCode::from(EXIT_CREATING_OCTOCRAB_INSTANCE);
+        }
+    };
+    let build =
+        |pat: String, base_uri: Option<&http::Uri>| build_octocrab(pat, base_uri, &args.headers);
+    let instance = match build(pat.clone(), base_uri.as_ref()) {
+        Ok(instance) => instance,
+        Err(e) => {
+            error!(
+                "Could not create Octocrab instance with the supplied personal access token: {}",
+                e
+            );
+            return ExitCode::from(EXIT_CREATING_OCTOCRAB_INSTANCE);
+        }
+    };
+    octocrab::initialise(instance);
+    if let Some(graphql_uri) = base_uri.as_ref().and_then(graphql_base_uri) {
+        match build(pat.clone(), Some(&graphql_uri)) {
+            Ok(instance) => {
+                GRAPHQL_INSTANCE.get_or_init(|| instance);
+            }
+            Err(e) => {
+                error!(
+                    "Could not create Octocrab instance for the GraphQL API: {}",
+                    e
+                );
+                return ExitCode::from(EXIT_CREATING_OCTOCRAB_INSTANCE);
+            }
+        }
+    }
+
+    if let Some(pool_file) = &args.token_pool_file {
+        let tokens = match token_pool_tokens(pool_file, &pat) {
+            Ok(tokens) => tokens,
+            Err(e) => {
+                error!(
+                    "Could not read the token pool from '{}': {}",
+                    pool_file.display(),
+                    e
+                );
+                return ExitCode::from(EXIT_NO_PAT);
+            }
+        };
+        let mut instances = Vec::with_capacity(tokens.len());
+        for token in tokens {
+            match build(token, base_uri.as_ref()) {
+                Ok(instance) => instances.push(instance),
+                Err(e) => {
+                    error!(
+                        "Could not create Octocrab instance for a token of the pool: {}",
+                        e
+                    );
+                    return ExitCode::from(EXIT_CREATING_OCTOCRAB_INSTANCE);
+                }
+            }
+        }
+        info!(
+            "Spreading requests over a pool of {} tokens",
+            instances.len()
+        );
+        token_pool::init(instances);
+    }
+
+    if args.starred {
+        let user = args.user.as_deref().expect("--starred requires --user");
+        return backup_starred(user, &args.destination()).await;
+    }
+
+    let owner = args.owner();
+    if args.list_events_seen {
+        return list_events_seen(owner.to_string(), args.repo().to_string()).await;
+    }
+
+    if args.check_access {
+        return check_access(owner.to_string()).await;
+    }
+
+    if args.plan {
+        return plan_backup(
+            owner.to_string(),
+            args.repo().to_string(),
+            args.destination(),
+        )
+        .await;
+    }
+
+    if args.compare_api_vs_disk {
+        return compare_api_vs_disk(
+            owner.to_string(),
+            args.repo().to_string(),
+            args.destination(),
+        )
+        .await;
+    }
+
+    if args.is_multi_repo()
+        && args
+            .destination_template
+            .as_deref()
+            .is_some_and(|t| t.contains("{repo}"))
+    {
+        error!("The {{repo}} placeholder can't be used with --all-repos or several --repo's: each repository is written to <destination>/<owner>/<repo>/ already.");
+        return ExitCode::from(EXIT_CREATING_DIRS);
+    }
+    let destination: &'static PathBuf = DESTINATION.get_or_init(|| args.destination());
+
+    for event_type in args.event_types.iter().filter(|t| !is_known_event(t)) {
+        warn!(
+            "'{}' in --event-types is not a timeline event type known to octocrab.",
+            event_type
+        );
+    }
+    if args.include_reactions {
+        warn!("--include-reactions is deprecated: the reaction totals are always recorded.");
+    }
+
+    if args.anonymize_users {
+        let salt_file = args
+            .anonymize_salt_file
+            .as_deref()
+            .expect("--anonymize-users requires --anonymize-salt-file");
+        let in_destination = match (
+            std::path::absolute(salt_file),
+            std::path::absolute(destination),
+        ) {
+            (Ok(salt_file), Ok(destination)) => salt_file.starts_with(destination),
+            _ => false,
+        };
+        if in_destination {
+            error!(
+                "The anonymization salt file {} must not be inside the destination {}.",
+                salt_file.display(),
+                destination.display()
+            );
+            return ExitCode::from(EXIT_ANONYMIZE_SALT);
+        }
+        match load_or_create_salt(salt_file) {
+            Ok(salt) => {
+                ANONYMIZE_SALT.get_or_init(|| salt);
+            }
+            Err(e) => {
+                error!(
+                    "Could not load the anonymization salt from {}: {}",
+                    salt_file.display(),
+                    e
+                );
+                return ExitCode::from(EXIT_ANONYMIZE_SALT);
+            }
+        }
+    }
+
+    // Held until main returns.
+    let _lock = match lock::BackupLock::acquire(destination, args.force) {
+        Ok(lock) => lock,
+        Err(LockError::Held(lock)) => {
+            error!(
+                "Another backup (process {}, started {}) is writing to {}. If that's not the case, remove {} or use --force.",
+                lock.pid,
+                lock.acquired_at,
+                destination.display(),
+                destination.join(lock::LOCK_FILE).display()
+            );
+            return ExitCode::from(EXIT_LOCKED);
+        }
+        Err(e) => {
+            error!("Could not lock {}: {}", destination.display(), e);
+            return ExitCode::from(EXIT_LOCKED);
+        }
+    };
+
+    if let Some(path) = &args.emit_jsonl_errors {
+        if let Err(e) = error_stream::open(path) {
+            error!(
+                "Could not open {} for --emit-jsonl-errors: {}",
+                path.display(),
+                e
+            );
+            return ExitCode::from(EXIT_WRITING);
+        }
+    }
+
+    profile::start();
+    if !args.prefetch.is_empty() {
+        return prefetch(
+            owner,
+            args.repo(),
+            destination,
+            args.prefetch.clone(),
+            false,
+        )
+        .await;
+    }
+    if args.only_metadata_refresh {
+        return refresh_metadata(owner, args.repo(), destination).await;
+    }
+    if let Some(range) = &args.issue_number_range {
+        return prefetch(
+            owner,
+            args.repo(),
+            destination,
+            range.clone().collect(),
+            true,
+        )
+        .await;
+    }
+
+    let shutdown = Arc::new(Notify::new());
+    if args.watch {
+        let shutdown = shutdown.clone();
+        task::spawn(async move {
+            shutdown_signal().await;
+            info!("Shutdown requested: exiting after the current backup.");
+            shutdown.notify_one();
+        });
+    }
+
+    // The cursors of the next incremental backups by repository.
+    let mut cursors: BTreeMap<String, DateTime<Utc>> = BTreeMap::new();
+    loop {
+        REQUESTS.store(0, Ordering::Relaxed);
+        let repos = if args.all_repos {
+            match list_repos(owner.to_string()).await {
+                Ok(repos) => repos
+                    .into_iter()
+                    .map(|repo| (owner.to_string(), repo))
+                    .collect(),
+                Err(e) => {
+                    error!("Could not list the repositories of {}: {}", owner, e);
+                    return fetch_exit_code(&e);
+                }
+            }
+        } else {
+            args.repositories()
+                .expect("the --repo's are checked on startup")
+        };
+
+        let result = backup_repos(repos, destination, &pat, &mut cursors).await;
+        info!(
+            "Sent {} requests to the GitHub API",
+            REQUESTS.load(Ordering::Relaxed)
+        );
+        if args.profile {
+            report_profile();
+        }
+        match result {
+            Ok(()) => (),
+            // Keep watching: the next backup starts from the same cursor. A
+            // destination that can't be written to stops watching.
+            Err(code) if args.watch && code == ExitCode::from(EXIT_API_ERROR) => {
+                warn!("The backup failed. Retrying with the next backup.")
+            }
+            Err(code) if args.watch && code == ExitCode::from(EXIT_REQUEST_BUDGET) => {
+                warn!("The --request-budget is used up. Resuming with the next backup.")
+            }
+            Err(code) if args.watch && code == ExitCode::from(EXIT_FAILURE_THRESHOLD) => {
+                warn!("Too many entries failed. Retrying them with the next backup.")
+            }
+            Err(code) => return code,
+        }
+
+        if !args.watch {
+            return ExitCode::SUCCESS;
+        }
+        info!("Next backup in {} seconds.", args.interval.as_secs());
+        tokio::select! {
+            _ = sleep(args.interval) => (),
+            _ = shutdown.notified() => return ExitCode::SUCCESS,
+        }
+    }
+}
+
+#[async_recursion]
+async fn get_repos_page(
+    route: String,
+    page: u32,
+    attempt: Attempt,
+) -> Result<Page<models::Repository>, FetchError> {
+    match api_get(format!("{}per_page={}&page={}", route, MAX_PER_PAGE, page)).await {
+        Ok(p) => Ok(p),
+        Err(e) => {
+            let Some(attempt) = retry_after_error(&e, attempt).await else {
+                return Err(e);
+            };
+            get_repos_page(route, page, attempt).await
+        }
+    }
+}
+
+#[async_recursion]
+async fn get_starred_page(
+    user: String,
+    page: u32,
+    attempt: Attempt,
+) -> Result<Page<serde_json::Value>, FetchError> {
+    // The star media type adds the `starred_at` of each repository.
+    let mut headers = http::HeaderMap::new();
+    headers.insert(
+        http::header::ACCEPT,
+        http::HeaderValue::from_static("application/vnd.github.star+json"),
+    );
+    let route = format!(
+        "/users/{}/starred?per_page={}&page={}",
+        user, MAX_PER_PAGE, page
+    );
+    match api_get_with_headers(route, Some(headers)).await {
+        Ok(p) => Ok(p),
+        Err(e) => {
+            let Some(attempt) = retry_after_error(&e, attempt).await else {
+                return Err(e);
+            };
+            get_starred_page(user, page, attempt).await
+        }
+    }
+}
+
+/// Writes the repositories starred by the `user` to `starred.json` in the
+/// `destination`. Only the starred repositories the token can see are listed:
+/// private repositories the token has no access to are left out by the API.
+async fn backup_starred(user: &str, destination: &Path) -> ExitCode {
+    let mut starred = Vec::new();
+    for page in START_PAGE..u32::MAX {
+        let mut starred_page = match get_starred_page(user.to_string(), page, Attempt::FIRST).await
+        {
+            Ok(starred_page) => starred_page,
+            Err(e) => {
+                error!("Could not list the repositories starred by {}: {}", user, e);
+                return fetch_exit_code(&e);
+            }
+        };
+        for star in starred_page.take_items() {
+            let Some(full_name) = star["repo"]["full_name"].as_str() else {
+                warn!("Skipping a starred repository without a name");
+                continue;
+            };
+            starred.push(StarredRepo {
+                full_name: full_name.to_string(),
+                starred_at: serde_json::from_value(star["starred_at"].clone()).ok(),
+            });
+        }
+        if starred_page.next.is_none() {
+            break;
+        }
+    }
+
+    if let Err(e) = permissions::create_dir_all(destination) {
+        error!("Could not create {}: {}", destination.display(), e);
+        return ExitCode::from(EXIT_CREATING_DIRS);
+    }
+    let path = destination.join(STARRED_FILE);
+    let result = serde_json::to_string_pretty(&starred)
+        .map_err(WriteError::from)
+        .and_then(|json| Ok(permissions::write_atomically(&path, json.as_bytes())?));
+    if let Err(e) = result {
+        error!("Could not write {}: {}", path.display(), e);
+        return ExitCode::from(EXIT_WRITING);
+    }
+    info!(
+        "Written the {} repositories starred by {} to {}",
+        starred.len(),
+        user,
+        path.display()
+    );
+    ExitCode::SUCCESS
+}
+
+/// Lists the names of all repositories of the `owner`, which is either an
+/// organization or a user.
+async fn list_repos(owner: String) -> Result<Vec<String>, FetchError> {
+    let repos: Vec<String> = list_repositories(owner.clone())
+        .await?
+        .into_iter()
+        .map(|r| r.name)
+        .collect();
+    info!("Found {} repositories of {}", repos.len(), owner);
+    Ok(repos)
+}
+
+/// Lists all repositories of the `owner` the token can see.
+async fn list_repositories(owner: String) -> Result<Vec<models::Repository>, FetchError> {
+    let user: serde_json::Value = api_get(format!("/users/{}", owner)).await?;
+    let route = if user["type"] == "Organization" {
+        format!("/orgs/{}/repos?type=all&", owner)
+    } else {
+        format!("/users/{}/repos?type=owner&", owner)
+    };
+
+    let mut repos = Vec::new();
+    for page in START_PAGE..u32::MAX {
+        let mut repos_page = get_repos_page(route.clone(), page, Attempt::FIRST).await?;
+        repos.extend(repos_page.take_items());
+        if repos_page.next.is_none() {
+            break;
+        }
+    }
+    Ok(repos)
+}
+
+/// Whether the token can read an API listing, as checked by `--check-access`.
+#[derive(Debug, PartialEq, Eq)]
+enum Access {
+    Readable,
+    /// The listing is disabled, like the issues of a repository without them.
+    Disabled,
+    Forbidden,
+    NotFound,
+}
+
+impl fmt::Display for Access {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Access::Readable => write!(f, "readable"),
+            Access::Disabled => write!(f, "disabled"),
+            Access::Forbidden => write!(f, "forbidden"),
+            Access::NotFound => write!(f, "not found"),
+        }
+    }
+}
+
+/// Requests the first item of the listing at `route` to check whether the
+/// token can read it. Errors other than a missing permission are returned.
+async fn probe_access(route: String) -> Result<Access, FetchError> {
+    match api_get::<serde_json::Value>(route).await {
+        Ok(_) => Ok(Access::Readable),
+        Err(FetchError::Octocrab(e)) => match e.as_ref() {
+            octocrab::Error::GitHub { source, .. } => match source.status_code {
+                http::StatusCode::FORBIDDEN => Ok(Access::Forbidden),
+                http::StatusCode::NOT_FOUND => Ok(Access::NotFound),
+                http::StatusCode::GONE => Ok(Access::Disabled),
+                _ => Err(FetchError::Octocrab(e)),
+            },
+            _ => Err(FetchError::Octocrab(e)),
+        },
+        Err(e) => Err(e),
+    }
+}
+
+/// Lists the repositories of the `owner` and prints for each whether the token
+/// can read its issues and pull-requests, without doing a backup. Costs two
+/// requests per repository.
+async fn check_access(owner: String) -> ExitCode {
+    let repositories = match list_repositories(owner.clone()).await {
+        Ok(repositories) => repositories,
+        Err(e) => {
+            error!("Could not list the repositories of {}: {}", owner, e);
+            return fetch_exit_code(&e);
+        }
+    };
+    let mut readable = 0;
+    for repository in repositories.iter() {
+        let listing = |kind: &str| {
+            format!(
+                "/repos/{}/{}/{}?state=all&per_page=1",
+                owner, repository.name, kind
+            )
+        };
+        let access = tokio::try_join!(
+            probe_access(listing("issues")),
+            probe_access(listing("pulls")),
+        );
+        let (issues, pulls) = match access {
+            Ok(access) => access,
+            Err(e) => {
+                error!(
+                    "Could not check the access to {}:{}: {}",
+                    owner, repository.name, e
+                );
+                return fetch_exit_code(&e);
+            }
+        };
+        let ok = |access: &Access| matches!(access, Access::Readable | Access::Disabled);
+        if ok(&issues) && ok(&pulls) {
+            readable += 1;
+        }
+        let mut notes = vec![];
+        if repository.private == Some(true) {
+            notes.push("private");
+        }
+        if repository.archived == Some(true) {
+            notes.push("archived");
+        }
+        println!(
+            "{:<40} issues: {:<10} pulls: {:<10} {}",
+            repository.name,
+            issues,
+            pulls,
+            notes.join(", ")
+        );
+    }
+    println!(
+        "The token can read the issues and pull-requests of {} of the {} repositories of {} it can see.",
+        readable,
+        repositories.len(),
+        owner
+    );
+    ExitCode::SUCCESS
+}
+
+/// Whether the `time` is further in the future than the --updated-at-slack,
+/// which means it's a clock or data anomaly.
+fn is_beyond_slack(time: DateTime<Utc>) -> bool {
+    chrono::Duration::from_std(args().updated_at_slack)
+        .ok()
+        .and_then(|slack| Utc::now().checked_add_signed(slack))
+        .is_some_and(|limit| time > limit)
+}
+
+/// Moves an incremental cursor back by the `--incremental-overlap`, so that
+/// entries updated right at the cursor are fetched again. The cursor written
+/// to the state isn't moved.
+fn with_overlap(since: Option<DateTime<Utc>>) -> Option<DateTime<Utc>> {
+    let Ok(overlap) = chrono::Duration::from_std(args().incremental_overlap) else {
+        return since;
+    };
+    since.map(|since| since.checked_sub_signed(overlap).unwrap_or(since))
+}
+
+/// Ignores a `cursor` from the `source` that is too far in the future, as it
+/// would make every following backup fetch nothing.
+fn plausible_cursor(cursor: DateTime<Utc>, source: &str) -> Option<DateTime<Utc>> {
+    if is_beyond_slack(cursor) {
+        warn!(
+            "Ignoring the cursor {} from {}, as it's in the future.",
+            cursor.to_rfc3339(),
+            source
+        );
+        return None;
+    }
+    Some(cursor)
+}
+
+/// Returns the cursor for the first incremental backup to `destination`. A
+/// cursor in the --since-file takes precedence over the one in the state file.
+/// There is none with --no-state.
+fn initial_cursor(destination: &Path) -> Option<DateTime<Utc>> {
+    if args().no_state {
+        return None;
+    }
+    args()
+        .since_file
+        .as_deref()
+        .and_then(|path| {
+            read_since_file(path)
+                .and_then(|cursor| plausible_cursor(cursor, &path.display().to_string()))
+        })
+        .or_else(|| {
+            get_last_backup_time(destination.to_path_buf())
+                .and_then(|cursor| plausible_cursor(cursor, STATE_FILE))
+        })
+}
+
+/// Backs up the `repos`, given by owner and name, up to `--parallel-repos`
+/// at the same time. With `--all-repos` or several `--repo`s, each
+/// repository is written to its own `<owner>/<repo>` directory in
+/// `destination`. A failing or panicking repository backup doesn't stop the
+/// others. Returns the exit code of the first failed repository. With
+/// several repositories, the ones completed by an interrupted run are
+/// skipped, unless `--force` is used.
+async fn backup_repos(
+    repos: Vec<(String, String)>,
+    destination: &Path,
+    pat: &str,
+    cursors: &mut BTreeMap<String, DateTime<Utc>>,
+) -> Result<(), ExitCode> {
+    let args = args();
+    // With --watch, only the first backup resumes an interrupted run.
+    let mut progress = args
+        .is_multi_repo()
+        .then(|| progress::BatchProgress::load(destination, args.force || !cursors.is_empty()));
+    let repos: Vec<(String, String)> = repos
+        .into_iter()
+        .filter(|(owner, repo)| {
+            progress
+                .as_ref()
+                .is_none_or(|progress| !progress.completed.contains(&format!("{}/{}", owner, repo)))
+        })
+        .collect();
+    let total = repos.len();
+    let slots = Arc::new(Semaphore::new(args.parallel_repos as usize));
+    let mut tasks = task::JoinSet::new();
+
+    for (owner, repo) in repos {
+        let full_name = format!("{}/{}", owner, repo);
+        let repo_destination = if args.is_multi_repo() {
+            destination.join(&owner).join(&repo)
+        } else {
+            destination.to_path_buf()
+        };
+        let since = cursors
+            .get(&full_name)
+            .copied()
+            .or_else(|| initial_cursor(&repo_destination));
+        let pat = pat.to_string();
+        let slots = slots.clone();
+        let destination = destination.to_path_buf();
+        tasks.spawn(async move {
+            let _slot = slots
+                .acquire_owned()
+                .await
+                .expect("the repository slots semaphore is never closed");
+            let started_at = chrono::Utc::now();
+            // Run the backup in its own task to isolate a panic.
+            let backup = task::spawn({
+                let (owner, repo) = (owner.clone(), repo.clone());
+                async move {
+                    let mut summary = BackupSummary::new(&owner, &repo, started_at);
+                    let result =
+                        run_backup(owner, repo, repo_destination, pat, since, &mut summary).await;
+                    (result, summary)
+                }
+            });
+            let (result, mut summary) = match backup.await {
+                Ok(backup) => backup,
+                Err(e) => {
+                    error!("The backup of {}:{} panicked: {}", owner, repo, e);
+                    let summary = BackupSummary::new(&owner, &repo, started_at);
+                    (Err(ExitCode::from(EXIT_API_ERROR)), summary)
+                }
+            };
+            summary.success = result.is_ok();
+            summary.duration_secs = (chrono::Utc::now() - started_at)
+                .to_std()
+                .unwrap_or_default()
+                .as_secs_f64();
+            notify(&summary).await;
+            if let Some(url) = &args.emit_openmetrics {
+                metrics::push(
+                    url,
+                    &args.openmetrics_job,
+                    args.openmetrics_instance.as_deref(),
+                    &summary,
+                )
+                .await;
+            }
+            if args.append_run_log {
+                let exit_code = match &result {
+                    Ok(_) => 0,
+                    Err(code) => exit_code_number(*code),
+                };
+                if let Err(e) = run_log::append(&destination, &summary, exit_code) {
+                    warn!(
+                        "Could not append to {}: {}",
+                        destination.join(run_log::RUN_LOG_FILE).display(),
+                        e
+                    );
+                }
+            }
+            (full_name, result)
+        });
+    }
+
+    let mut failed: Vec<String> = Vec::new();
+    let mut first_error: Option<ExitCode> = None;
+    while let Some(joined) = tasks.join_next().await {
+        match joined.expect("repository backup tasks don't panic") {
+            (repo, Ok(start_time)) => {
+                if let Some(progress) = &mut progress {
+                    if let Err(e) = progress.complete(&repo) {
+                        warn!(
+                            "Could not record {} as completed in {}: {}",
+                            repo,
+                            destination.join(progress::PROGRESS_FILE).display(),
+                            e
+                        );
+                    }
+                }
+                cursors.insert(repo, start_time);
+            }
+            (repo, Err(code)) => {
+                // The state of a backup stopped by the --request-budget, or
+                // above the --failure-threshold, has the cursor to resume
+                // from.
+                if code == ExitCode::from(EXIT_REQUEST_BUDGET)
+                    || code == ExitCode::from(EXIT_FAILURE_THRESHOLD)
+                {
+                    cursors.remove(&repo);
+                }
+                failed.push(repo);
+                first_error.get_or_insert(code);
+            }
+        }
+    }
+
+    if args.is_multi_repo() {
+        info!(
+            "Backed up {} of {} repositories",
+            total - failed.len(),
+            total
+        );
+        if !failed.is_empty() {
+            failed.sort();
+            warn!(
+                "The backup of the following {} repositories failed: {:?}",
+                failed.len(),
+                failed
+            );
+        }
+    }
+
+    if args.detect_schema_changes {
+        print_schema_changes();
+    }
+
+    if let (Some(progress), None) = (&progress, &first_error) {
+        if let Err(e) = progress.clear() {
+            warn!(
+                "Could not remove {}: {}",
+                destination.join(progress::PROGRESS_FILE).display(),
+                e
+            );
+        }
+    }
+
+    match first_error {
+        Some(code) => Err(code),
+        None => Ok(()),
+    }
+}
+
+/// Prints the fields of the API responses that octocrab's models dropped
+/// during the backup, recorded with `--detect-schema-changes`.
+fn print_schema_changes() {
+    let dropped = fidelity::take_recorded();
+    if dropped.values().all(BTreeMap::is_empty) {
+        println!("octocrab's models captured all fields of the API responses");
+        return;
+    }
+    println!("Fields of the API responses that octocrab's models don't capture:");
+    for (model, fields) in dropped.iter().filter(|(_, fields)| !fields.is_empty()) {
+        for (field, count) in fields {
+            println!("{:>8}  {}.{}", count, model, field);
+        }
+    }
+}
+
+/// The number of an `ExitCode`, which isn't exposed by the standard library.
+fn exit_code_number(code: ExitCode) -> u8 {
+    (1..=u8::MAX)
+        .find(|number| ExitCode::from(*number) == code)
+        .unwrap_or(EXIT_API_ERROR)
+}
+
+/// Prints the `--profile` timings so far and appends them to the
+/// `--profile-file` as a JSON line.
+fn report_profile() {
+    let profile = profile::snapshot();
+    println!("{}", profile);
+    if let Some(path) = &args().profile_file {
+        let result = serde_json::to_string(&profile)
+            .map_err(WriteError::from)
+            .and_then(|line| {
+                let mut file = fs::OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(path)?;
+                writeln!(file, "{}", line)?;
+                Ok(())
+            });
+        if let Err(e) = result {
+            warn!("Could not append the profile to {}: {}", path.display(), e);
+        }
+    }
+}
+
+/// POSTs the `summary` to the `--notify-url`, if the `--notify-on` condition is
+/// met. A failed notification is only logged and doesn't fail the backup.
+async fn notify(summary: &BackupSummary) {
+    let args = args();
+    let url = match &args.notify_url {
+        Some(url) => url,
+        None => return,
+    };
+    if args.notify_on == NotifyOn::Failure && summary.success {
+        return;
+    }
+    let client = match reqwest::Client::builder().timeout(NOTIFY_TIMEOUT).build() {
+        Ok(client) => client,
+        Err(e) => {
+            warn!("Could not create the HTTP client to notify {}: {}", url, e);
+            return;
+        }
+    };
+    match client
+        .post(url)
+        .json(summary)
+        .send()
+        .await
+        .and_then(|response| response.error_for_status())
+    {
+        Ok(_) => info!(
+            "Notified {} about the backup of {}:{}",
+            url, summary.owner, summary.repo
+        ),
+        Err(e) => warn!(
+            "Could not notify {} about the backup of {}:{}: {}",
+            url, summary.owner, summary.repo, e
+        ),
+    }
+}
+
+/// Runs one backup of the issues and pull-requests updated since `since`.
+/// Returns the time the backup started, which is the cursor for the next
+/// incremental backup. The written and failed entries are counted in the
+/// `summary`.
+async fn run_backup(
+    owner: String,
+    repo: String,
+    destination: PathBuf,
+    pat: String,
+    since: Option<DateTime<Utc>>,
+    summary: &mut BackupSummary,
+) -> Result<DateTime<Utc>, ExitCode> {
+    let args = args();
+    let start_time = chrono::Utc::now();
+    let destination: &Path = &destination;
+    let state = match args.no_state {
+        true => None,
+        false => match load_backup_state(destination) {
+            Ok(state) => state,
+            Err(version) => {
+                error!(
+                    "The {} in {} has version {}, but this version of github-metadata-backup only supports version {}. It was likely written by a newer version, which the backup would overwrite. Upgrade github-metadata-backup or pass --ignore-state-version-mismatch to do a full backup.",
+                    STATE_FILE,
+                    destination.display(),
+                    version,
+                    STATE_VERSION
+                );
+                return Err(ExitCode::from(EXIT_STATE_VERSION));
+            }
+        },
+    };
+    // With --retry-failed-only, the issue pages aren't scanned, so the cursor
+    // stays where the previous backup left it.
+    let cursor = match (&state, args.retry_failed_only) {
+        (Some(state), true) => state.last_backup,
+        _ => start_time,
+    };
+    // The cursors of the issues and of the pull-requests. They differ after an
+    // --only-issues or --only-pulls backup. A `since` that isn't the one of
+    // the state, e.g. from an edited --since-file, applies to both.
+    let type_since = match &state {
+        Some(state) if Some(state.last_backup) == since => {
+            (state.last_backup_issues, state.last_backup_pulls)
+        }
+        _ => (since, since),
+    };
+    // The id and path of the repository are kept in the state, so that a
+    // later --follow-renames can find it again.
+    let mut followed = state
+        .as_ref()
+        .and_then(|state| Some((state.repository_id?, state.repository.clone()?)));
+    let (owner, repo) = match args.follow_renames {
+        true => {
+            let stored_id = followed.as_ref().map(|(id, _)| *id);
+            match follow_repository(owner.clone(), repo.clone(), stored_id).await {
+                Ok((id, repository)) => {
+                    followed = Some((id, repository.clone()));
+                    (repository.owner, repository.name)
+                }
+                Err(e) => {
+                    error!("Could not resolve the repository {}:{}: {}", owner, repo, e);
+                    return Err(fetch_exit_code(&e));
+                }
+            }
+        }
+        false => (owner, repo),
+    };
+    if args.retry_failed_only && state.as_ref().is_none_or(|state| state.failed.is_empty()) {
+        info!(
+            "No issues or pull-requests of {}:{} failed in the previous backup. Nothing to retry.",
+            owner, repo
+        );
+        return Ok(cursor);
+    }
+    info!(
+        "Starting backup of {}:{} on GitHub to '{}'",
+        owner,
+        repo,
+        destination.display()
     );
-    for page_num in START_PAGE..u32::MAX {
-        let page = match get_issue_page(page_num, since, owner.clone(), repo.clone(), 0).await {
-            Ok(page) => page,
+
+    let issues_dir = destination.join(&args.issues_dir_name);
+    let pulls_dir = destination.join(&args.pulls_dir_name);
+    let sink_kinds = args.sinks();
+    if !sink_kinds.contains(&SinkKind::Files) {
+        if let Err(e) = permissions::create_dir_all(destination) {
+            error!(
+                "Could not create the destination {}: {}",
+                destination.display(),
+                e
+            );
+            return Err(ExitCode::from(EXIT_CREATING_DIRS));
+        }
+    } else {
+        info!(
+            "If not existing yet, creating the issues and pulls directory as {} and {}",
+            issues_dir.display(),
+            pulls_dir.display()
+        );
+        if let Err(e) = permissions::create_dir_all(issues_dir.clone()) {
+            error!(
+                "Could not create the issues directory in {}: {}",
+                issues_dir.display(),
+                e
+            );
+            return Err(ExitCode::from(EXIT_CREATING_DIRS));
+        }
+        if let Err(e) = permissions::create_dir_all(pulls_dir.clone()) {
+            error!(
+                "Could not create the pulls directory in {}: {}",
+                pulls_dir.display(),
+                e
+            );
+            return Err(ExitCode::from(EXIT_CREATING_DIRS));
+        }
+        for dir in [&issues_dir, &pulls_dir] {
+            if let Err(e) = remove_partial_files(dir) {
+                warn!(
+                    "Could not remove the partial files in {}: {}",
+                    dir.display(),
+                    e
+                );
+            }
+        }
+    }
+
+    if args.include_repo_metadata && !args.retry_failed_only {
+        backup_repo_metadata(
+            owner.to_string(),
+            repo.to_string(),
+            destination.to_path_buf(),
+        )
+        .await;
+    }
+    let attachments = match args.download_attachments {
+        true => match attachments::AttachmentStore::open(destination, &pat, args.max_retries) {
+            Ok(store) => Some(store),
             Err(e) => {
                 error!(
-                    "Could not load issue page {} for {}:{} from GitHub: {}",
-                    page_num, owner, repo, e
+                    "Could not open the attachments directory in {}: {}",
+                    destination.display(),
+                    e
+                );
+                return Err(ExitCode::from(EXIT_CREATING_DIRS));
+            }
+        },
+        false => None,
+    };
+    let milestone_numbers =
+        backup_milestones(owner.to_string(), repo.to_string(), destination).await;
+    backup_labels(owner.to_string(), repo.to_string(), destination).await;
+    if args.include_discussions {
+        backup_discussions(owner.to_string(), repo.to_string(), destination, since).await;
+    }
+    let users = match args.export_users {
+        true => match users::UserDirectory::load(destination) {
+            Ok(users) => Some(Arc::new(users)),
+            Err(e) => {
+                error!(
+                    "Could not load {} from {}: {}",
+                    users::USERS_FILE,
+                    destination.display(),
+                    e
+                );
+                return Err(ExitCode::from(EXIT_WRITING));
+            }
+        },
+        false => None,
+    };
+    let mut sitemap = match &args.emit_sitemap {
+        Some(path) => match sitemap::Sitemap::load(path) {
+            Ok(sitemap) => Some(sitemap),
+            Err(e) => {
+                error!("Could not load the sitemap {}: {}", path.display(), e);
+                return Err(ExitCode::from(EXIT_WRITING));
+            }
+        },
+        None => None,
+    };
+    if args.download_release_assets && !args.retry_failed_only {
+        backup_releases(owner.to_string(), repo.to_string(), destination, &pat).await;
+    }
+    if args.include_wiki && !args.retry_failed_only {
+        backup_wiki(
+            owner.to_string(),
+            repo.to_string(),
+            destination.to_path_buf(),
+            pat,
+        )
+        .await;
+    }
+
+    // Fetched issues and PRs are send into this mpsc channel and received by
+    // the writer which persist them to the disk.
+    let (sender, mut receiver) = mpsc::channel(100);
+    // Bounds the number of events and comments held by entries waiting in
+    // the channel, as a few huge entries can take up a lot of memory.
+    let buffer = Arc::new(Semaphore::new(args.max_buffered_items as usize));
+    let writer_buffer = buffer.clone();
+    let mut index = index::Index::load(destination, &args.issues_dir_name, &args.pulls_dir_name);
+    // The first backup has no previous one to compare with.
+    let mut changelog = match index.issues.is_empty() && index.pulls.is_empty() {
+        false if args.emit_changelog => Some(changelog::Changelog::default()),
+        true if args.emit_changelog => {
+            info!("Not writing a changelog, as there is no previous backup to compare with");
+            None
+        }
+        _ => None,
+    };
+    // The content hashes of the previous backup, keyed by whether the entry
+    // is a pull-request and its number.
+    let previous_hashes: BTreeMap<(bool, u64), String> = match args.diff_only_state {
+        true => [(false, &index.issues), (true, &index.pulls)]
+            .into_iter()
+            .flat_map(|(is_pull, entries)| {
+                entries.iter().filter_map(move |(number, entry)| {
+                    Some(((is_pull, *number), entry.content_hash.clone()?))
+                })
+            })
+            .collect(),
+        false => BTreeMap::new(),
+    };
+    if args.detect_deletions && !args.retry_failed_only {
+        let removals = match detect_removals(&owner, &repo, since).await {
+            Ok(removals) => removals,
+            Err(e) => {
+                error!(
+                    "Could not detect deleted and transferred issues of {}:{}: {}",
+                    owner, repo, e
+                );
+                return Err(fetch_exit_code(&e));
+            }
+        };
+        if !removals.is_empty() {
+            info!(
+                "Found {} deleted or transferred issues and pull-requests",
+                removals.len()
+            );
+        }
+        if let Err(e) = reconcile_removals(removals, destination, &mut index) {
+            error!(
+                "Could not update the files of deleted and transferred issues in {}: {}",
+                destination.display(),
+                e
+            );
+            return Err(ExitCode::from(EXIT_WRITING));
+        }
+    }
+
+    let raw_dir = args
+        .dump_raw_dir
+        .as_ref()
+        .map(|dir| match args.is_multi_repo() {
+            true => dir.join(&owner).join(&repo),
+            false => dir.clone(),
+        });
+
+    // Every entry is written to each of the --sink's.
+    let mut sinks: Vec<Box<dyn Sink>> = vec![];
+    for kind in sink_kinds {
+        let sink: Box<dyn Sink> = match kind {
+            SinkKind::Files => Box::new(FilesSink {
+                destination: destination.to_path_buf(),
+                layout: args.layout(),
+                compress: args.compress,
+            }),
+            SinkKind::SingleFile => match single_file::SingleFileSink::load(destination) {
+                Ok(sink) => Box::new(sink),
+                Err(e) => {
+                    error!(
+                        "Could not load {} from {}: {}",
+                        single_file::SINGLE_FILE,
+                        destination.display(),
+                        e
+                    );
+                    return Err(ExitCode::from(EXIT_WRITING));
+                }
+            },
+            SinkKind::Http => {
+                let url = match &args.http_sink_url {
+                    Some(url) => url,
+                    None => {
+                        error!("The http sink needs an --http-sink-url");
+                        return Err(ExitCode::from(EXIT_WRITING));
+                    }
+                };
+                match sink::HttpSink::new(url, &args.http_sink_header, args.http_sink_batch_size) {
+                    Ok(sink) => Box::new(sink),
+                    Err(e) => {
+                        error!("Could not create the http sink for {}: {}", url, e);
+                        return Err(ExitCode::from(EXIT_WRITING));
+                    }
+                }
+            }
+            SinkKind::Ndjson => match sink::NdjsonSink::open(destination) {
+                Ok(sink) => Box::new(sink),
+                Err(e) => {
+                    error!(
+                        "Could not open {} in {}: {}",
+                        sink::NDJSON_FILE,
+                        destination.display(),
+                        e
+                    );
+                    return Err(ExitCode::from(EXIT_WRITING));
+                }
+            },
+        };
+        sinks.push(sink);
+    }
+    let sinks = Arc::new(sinks);
+    let sink_count = sinks.len();
+
+    // Kept as failed by the checkpoints until the final state is written.
+    let previous_failed: Vec<FailedEntry> = state
+        .as_ref()
+        .map(|state| state.failed.clone())
+        .unwrap_or_default();
+    let retries = state
+        .map(|state| read_checkpoints(destination, state.failed))
+        .unwrap_or_default();
+    let repository = format!("{}/{}", owner, repo);
+
+    let entries_destination = destination.to_path_buf();
+    let watermark: Arc<std::sync::Mutex<Watermark>> = Arc::default();
+    let fetch_watermark = watermark.clone();
+    let previous_etags = Arc::new(match args.use_etags {
+        true => load_etags(destination),
+        false => BTreeMap::new(),
+    });
+    // The ETags of the entries written by this backup replace the previous
+    // ones.
+    let mut etags = args.use_etags.then(|| (*previous_etags).clone());
+    let task = task::spawn(async move {
+        match get_issues_and_pulls(
+            sender,
+            buffer,
+            with_overlap(since),
+            (with_overlap(type_since.0), with_overlap(type_since.1)),
+            owner.to_string(),
+            repo.to_string(),
+            retries,
+            entries_destination,
+            fetch_watermark,
+            previous_etags,
+        )
+        .await
+        {
+            Ok(failed) => failed,
+            Err(e) => panic!("Error loading issues and pulls: {}", e),
+        }
+    });
+
+    // Entries that couldn't be serialized or written to all sinks. They are
+    // retried by the next backup.
+    let mut unwritten: Vec<Failure> = vec![];
+    // Entries --diff-only-state didn't rewrite, with their new ETags.
+    let mut unchanged: Vec<(u64, Option<EntryEtags>)> = vec![];
+    // Handles the result of writing an entry to the sinks and its raw
+    // responses.
+    let mut finish_write = |data: EntryWithMetadata,
+                            result: Result<(), WriteError>,
+                            sink_errors: Vec<(&'static str, WriteError)>,
+                            raw_result: Option<Result<(), WriteError>>,
+                            content_hash: Option<String>|
+     -> Result<(), ExitCode> {
+        let emit = |phase: &str, e: &WriteError| {
+            error_stream::emit(&repository, Some(data.number()), data.type_name(), phase, e)
+        };
+        match result {
+            Ok(()) => (),
+            Err(e @ (WriteError::JsonSerdeError(_) | WriteError::InvalidOutput(_)))
+                if !args.fail_fast =>
+            {
+                error!(
+                    "Could not serialize {}: {}. Skipping it until the next backup.",
+                    data, e
+                );
+                emit("serialize", &e);
+                let checkpoint = Checkpoint::default();
+                let entry = FailedEntry::new(data.number(), data.type_name(), &checkpoint);
+                watermark.lock().unwrap().fail(&entry);
+                unwritten.push((entry, checkpoint));
+                writer_buffer.add_permits(buffer_weight(&data) as usize);
+                return Ok(());
+            }
+            Err(e) => {
+                error!(
+                    "Could not write {} to {}: {}",
+                    data,
+                    destination.display(),
+                    e
+                );
+                match e {
+                    WriteError::JsonSerdeError(_) | WriteError::InvalidOutput(_) => {
+                        emit("serialize", &e)
+                    }
+                    _ => emit("write", &e),
+                }
+                return Err(ExitCode::from(EXIT_WRITING));
+            }
+        }
+        if !sink_errors.is_empty() {
+            for (name, e) in sink_errors.iter() {
+                error!(
+                    "Could not write {} to the {} sink in {}: {}",
+                    data,
+                    name,
+                    destination.display(),
+                    e
+                );
+                emit("write", e);
+            }
+            if args.on_sink_error == OnSinkError::Abort {
+                return Err(ExitCode::from(EXIT_WRITING));
+            }
+            warn!("Retrying {} with the next backup", data);
+            let checkpoint = Checkpoint::default();
+            let entry = FailedEntry::new(data.number(), data.type_name(), &checkpoint);
+            watermark.lock().unwrap().fail(&entry);
+            unwritten.push((entry, checkpoint));
+            if sink_errors.len() == sink_count {
+                writer_buffer.add_permits(buffer_weight(&data) as usize);
+                return Ok(());
+            }
+        }
+        if let (Some(Err(e)), Some(raw_dir)) = (raw_result, &raw_dir) {
+            error!(
+                "Could not write the raw responses of {} to {}: {}",
+                data,
+                raw_dir.display(),
+                e
+            );
+            emit("write", &e);
+            return Err(ExitCode::from(EXIT_WRITING));
+        }
+        if let Some(changelog) = &mut changelog {
+            changelog.record(&index, &data);
+        }
+        if let Some(etags) = &mut etags {
+            match data.etags() {
+                Some(entry_etags) => etags.insert(data.number(), entry_etags.clone()),
+                None => etags.remove(&data.number()),
+            };
+        }
+        index.insert(&data, index_path(destination, &data));
+        if let Some(hash) = content_hash {
+            index.set_content_hash(&data, hash);
+        }
+        if let Some(sitemap) = &mut sitemap {
+            sitemap.insert(&repository, &data, entry_path(destination, &data));
+        }
+        match data {
+            EntryWithMetadata::Issue(_) => summary.issues += 1,
+            EntryWithMetadata::Pull(_) => summary.pulls += 1,
+        }
+        if data.is_truncated() {
+            summary.truncated.push(data.number());
+        }
+        let mut watermark = watermark.lock().unwrap();
+        watermark.pending.remove(&data.number());
+        watermark.written += 1;
+        writer_buffer.add_permits(buffer_weight(&data) as usize);
+        Ok(())
+    };
+
+    // The state is checkpointed while the issues are listed in the order of
+    // their last update, as only then the backup can resume from a cursor.
+    let checkpoints = args.checkpoint_interval > 0
+        && !args.no_state
+        && args.resume_token.is_none()
+        && !args.retry_failed_only
+        && is_sorted_by_update(since);
+    let mut checkpointed = 0;
+
+    // The entries are written on the blocking thread pool, up to
+    // --max-concurrent-writes at the same time.
+    let mut writes = task::JoinSet::new();
+    let max_writes = args.max_concurrent_writes as usize;
+    let mut receiving = true;
+    loop {
+        tokio::select! {
+            data = receiver.recv(), if receiving && writes.len() < max_writes => {
+                let mut data = match data {
+                    Some(data) => data,
+                    None => {
+                        receiving = false;
+                        continue;
+                    }
+                };
+                if !enough_disk_space(destination) {
+                    error!(
+                        "Aborting the backup before running out of disk space. The {} file is left as is.",
+                        STATE_FILE
+                    );
+                    receiver.close();
+                    return Err(ExitCode::from(EXIT_LOW_DISK_SPACE));
+                }
+                if let Some(numbers) = &milestone_numbers {
+                    data.link_milestones(numbers);
+                }
+                if let Some(filter) = &args.entry_filter_expr {
+                    if !filter.matches(&data) {
+                        debug!("Skipped {} as it doesn't match the --entry-filter-expr", data);
+                        summary.filtered += 1;
+                        watermark.lock().unwrap().pending.remove(&data.number());
+                        writer_buffer.add_permits(buffer_weight(&data) as usize);
+                        continue;
+                    }
+                }
+                // Serialization errors are left to the write.
+                let content_hash = match args.diff_only_state {
+                    true => content_hash(&data).ok(),
+                    false => None,
+                };
+                let is_pull = matches!(data, EntryWithMetadata::Pull(_));
+                if content_hash.is_some()
+                    && content_hash.as_ref() == previous_hashes.get(&(is_pull, data.number()))
+                    && is_on_disk(destination, is_pull, data.number())
+                {
+                    debug!("Skipped {} as only its updated_at changed", data);
+                    unchanged.push((data.number(), data.etags().cloned()));
+                    watermark.lock().unwrap().pending.remove(&data.number());
+                    writer_buffer.add_permits(buffer_weight(&data) as usize);
+                    continue;
+                }
+                let files = match (&attachments, serialize(&data)) {
+                    (Some(store), Ok(value)) => Some(store.download_entry(&value).await),
+                    _ => None,
+                };
+                let destination = destination.to_path_buf();
+                let raw_dir = raw_dir.clone();
+                let sinks = sinks.clone();
+                let users = users.clone();
+                writes.spawn_blocking(move || {
+                    let started = Instant::now();
+                    let mut sink_errors = vec![];
+                    let result = serialize(&data).map(|mut value| {
+                        if let Some(users) = &users {
+                            users.collect(&mut value, args.user_refs);
+                        }
+                        for sink in sinks.iter() {
+                            if let Err(e) = sink.write(&data, &value) {
+                                sink_errors.push((sink.name(), e));
+                            }
+                        }
+                    });
+                    let result = match (result, files) {
+                        (Ok(()), Some(files)) if args.rewrite_attachment_urls => {
+                            write_rewritten(&data, &destination, &files)
+                        }
+                        (result, _) => result,
+                    };
+                    let raw_result = match (&result, &raw_dir) {
+                        (Ok(()), Some(raw_dir)) => Some(write_raw(&data, raw_dir)),
+                        _ => None,
+                    };
+                    profile::record(profile::Phase::Write, started.elapsed());
+                    (data, result, sink_errors, raw_result, content_hash)
+                });
+            }
+            Some(written) = writes.join_next(), if !writes.is_empty() => {
+                let result = match written {
+                    Ok((data, result, sink_errors, raw_result, content_hash)) => {
+                        finish_write(data, result, sink_errors, raw_result, content_hash)
+                    }
+                    Err(e) => {
+                        error!("Writing to {} failed: {}", destination.display(), e);
+                        Err(ExitCode::from(EXIT_WRITING))
+                    }
+                };
+                if let Err(code) = result {
+                    receiver.close();
+                    return Err(code);
+                }
+                let written = watermark.lock().unwrap().written;
+                if checkpoints && written >= checkpointed + args.checkpoint_interval {
+                    checkpointed = written;
+                    let state = checkpoint_state(
+                        &watermark.lock().unwrap(),
+                        &previous_failed,
+                        type_since,
+                        &followed,
+                    );
+                    if let Some(state) = state {
+                        if let Err(e) = write_backup_state(&state, destination.to_path_buf()) {
+                            error!(
+                                "Failed to checkpoint {} to {}: {}",
+                                STATE_FILE,
+                                destination.display(),
+                                e
+                            );
+                            return Err(ExitCode::from(EXIT_WRITING));
+                        }
+                        info!(
+                            "Checkpointed the state after {} written issues and pull-requests: an interrupted backup resumes from {}",
+                            written,
+                            state.last_backup.to_rfc3339()
+                        );
+                    }
+                }
+            }
+            else => break,
+        }
+    }
+    let loaded = task.await;
+    summary.unchanged = unchanged.len() as u64;
+    if summary.unchanged > 0 {
+        info!(
+            "Didn't rewrite {} issues and pulls whose content didn't change (--diff-only-state)",
+            summary.unchanged
+        );
+    }
+    if let Some(etags) = &mut etags {
+        for (number, entry_etags) in unchanged {
+            match entry_etags {
+                Some(entry_etags) => etags.insert(number, entry_etags),
+                None => etags.remove(&number),
+            };
+        }
+    }
+
+    if args.prune {
+        match &loaded {
+            Ok(Loaded {
+                listed: Some(listed),
+                ..
+            }) => match prune_orphans(listed, destination, &mut index) {
+                Ok(pruned) => {
+                    if pruned > 0 {
+                        info!(
+                            "Pruned {} issues and pull-requests that are no longer in {}:{}",
+                            pruned, summary.owner, summary.repo
+                        );
+                    }
+                    summary.pruned = pruned;
+                }
+                Err(e) => {
+                    error!(
+                        "Could not prune the files of removed issues in {}: {}",
+                        destination.display(),
+                        e
+                    );
+                    return Err(ExitCode::from(EXIT_WRITING));
+                }
+            },
+            _ => info!(
+                "Not pruning {}:{}, as this backup didn't list all of its issues and pull-requests",
+                summary.owner, summary.repo
+            ),
+        }
+    }
+
+    if let Some(store) = &attachments {
+        if let Err(e) = store.write_urls() {
+            error!(
+                "Failed to write the downloaded attachments to {}: {}",
+                destination.join(attachments::ATTACHMENTS_DIR).display(),
+                e
+            );
+            return Err(ExitCode::from(EXIT_WRITING));
+        }
+    }
+
+    if let Some(users) = &users {
+        if let Err(e) = users.write() {
+            error!(
+                "Failed to write {} to {}: {}",
+                users::USERS_FILE,
+                destination.display(),
+                e
+            );
+            return Err(ExitCode::from(EXIT_WRITING));
+        }
+    }
+
+    if let Err(e) = index.write(destination) {
+        error!(
+            "Failed to write {} to {}: {}",
+            index::INDEX_FILE,
+            destination.display(),
+            e
+        );
+        return Err(ExitCode::from(EXIT_WRITING));
+    }
+
+    if let Some(etags) = &etags {
+        if let Err(e) = write_etags(etags, destination) {
+            error!(
+                "Failed to write {} to {}: {}",
+                ETAGS_FILE,
+                destination.display(),
+                e
+            );
+            return Err(ExitCode::from(EXIT_WRITING));
+        }
+    }
+
+    if let Some(changelog) = &changelog {
+        if let Err(e) = changelog.write(&repository, since, args.changelog_group_by, destination) {
+            error!(
+                "Failed to write {} to {}: {}",
+                changelog::CHANGELOG_FILE,
+                destination.display(),
+                e
+            );
+            return Err(ExitCode::from(EXIT_WRITING));
+        }
+    }
+
+    if let Some(sitemap) = &mut sitemap {
+        sitemap.retain(&repository, &index);
+        if let Err(e) = sitemap.write() {
+            error!("Failed to write the sitemap: {}", e);
+            return Err(ExitCode::from(EXIT_WRITING));
+        }
+    }
+
+    if let Ok(Loaded {
+        mut failed,
+        exhausted,
+        ..
+    }) = loaded
+    {
+        failed.append(&mut unwritten);
+        // The next backup lists the issues again from where the used up
+        // --request-budget stopped this one, or from the previous cursor.
+        let cursor = match exhausted {
+            Some(resume_from) => {
+                let cursor = resume_from.or(since).unwrap_or(DateTime::UNIX_EPOCH);
+                error!(
+                    "The --request-budget of {} requests is used up: stopping the backup of {}:{}. {} issues and pull-requests are retried and the next backup resumes from {}.",
+                    args.request_budget.unwrap_or_default(),
+                    summary.owner,
+                    summary.repo,
+                    failed.len(),
+                    cursor.to_rfc3339()
+                );
+                cursor
+            }
+            None => cursor,
+        };
+        // Only the cursors of the types that were listed advance. The issue
+        // pages are listed from the older one.
+        let advance =
+            |is_pull: bool, previous: Option<DateTime<Utc>>| match is_skipped_type(is_pull)
+                || args.retry_failed_only
+            {
+                true => previous,
+                false => Some(cursor),
+            };
+        let last_backup_issues = advance(false, type_since.0);
+        let last_backup_pulls = advance(true, type_since.1);
+        let cursor = match (last_backup_issues, last_backup_pulls) {
+            (Some(issues), Some(pulls)) => issues.min(pulls),
+            _ => DateTime::UNIX_EPOCH,
+        };
+        let backup_state = BackupState {
+            version: STATE_VERSION,
+            last_backup: cursor,
+            last_backup_issues,
+            last_backup_pulls,
+            failed: failed.iter().map(|(entry, _)| entry.clone()).collect(),
+            repository_id: followed.as_ref().map(|(id, _)| *id),
+            repository: followed.map(|(_, repository)| repository),
+        };
+        let state = match args.no_state {
+            true => serde_json::Value::Null,
+            false => serde_json::json!(backup_state),
+        };
+        for sink in sinks.iter() {
+            if let Err(e) = sink.finish(&state) {
+                error!(
+                    "Failed to finish the {} sink in {}: {}",
+                    sink.name(),
+                    destination.display(),
+                    e
+                );
+                if args.on_sink_error == OnSinkError::Abort {
+                    return Err(ExitCode::from(EXIT_WRITING));
+                }
+            }
+        }
+        if args.no_state {
+            for (entry, _) in failed.iter() {
+                warn!(
+                    "The {} #{} of {}:{} is missing from this backup",
+                    entry.r#type, entry.number, summary.owner, summary.repo
                 );
-                return Err(e);
+                match entry.r#type.as_str() {
+                    "pull" => summary.failed_pulls.push(entry.number),
+                    _ => summary.failed_issues.push(entry.number),
+                }
             }
-        };
-
-        for entry in page.items {
-            if entry.pull_request.is_none() {
-                match get_issue(entry.clone(), entry.number, owner.clone(), repo.clone()).await {
-                    Ok(issue) => {
-                        sender.send(issue).await.unwrap();
-                        loaded_issues += 1;
-                    }
-                    Err(e) => {
-                        error!("Could not get issue #{}: {}", entry.number, e);
-                        failed_issues.push(entry.number);
-                    }
+            if exhausted.is_some() {
+                return Err(ExitCode::from(EXIT_REQUEST_BUDGET));
+            }
+            if exceeds_failure_threshold(summary) {
+                return Err(ExitCode::from(EXIT_FAILURE_THRESHOLD));
+            }
+            return Ok(start_time);
+        }
+        if let Err(e) = write_checkpoints(destination, &failed) {
+            error!(
+                "Failed to write the checkpoints to {}: {}",
+                destination.join(PARTIAL_DIR).display(),
+                e
+            );
+            return Err(ExitCode::from(EXIT_WRITING));
+        }
+        for entry in backup_state.failed.iter() {
+            match entry.r#type.as_str() {
+                "pull" => summary.failed_pulls.push(entry.number),
+                _ => summary.failed_issues.push(entry.number),
+            }
+        }
+        if args.resume_token.is_some() {
+            match resume_token::encode(&backup_state) {
+                Ok(token) => {
+                    info!("The resume token of the next backup is printed to stdout");
+                    println!("{}", token);
                 }
-            } else {
-                match get_pull(entry.number, owner.clone(), repo.clone()).await {
-                    Ok(pull) => {
-                        sender.send(pull).await.unwrap();
-                        loaded_pulls += 1;
-                    }
-                    Err(e) => {
-                        error!("Could not get pull-request #{}: {}", entry.number, e);
-                        failed_pulls.push(entry.number);
-                    }
+                Err(e) => {
+                    error!("Failed to encode the resume token: {}", e);
+                    return Err(ExitCode::from(EXIT_WRITING));
                 }
             }
+        } else if let Err(e) = write_backup_state(&backup_state, destination.to_path_buf()) {
+            error!(
+                "Failed to write {} to {}: {}",
+                STATE_FILE,
+                destination.display(),
+                e
+            );
+            return Err(ExitCode::from(EXIT_WRITING));
         }
-
-        if page.next.is_none() {
-            break;
+        if let Some(since_file) = &args.since_file {
+            if let Err(e) = write_since_file(cursor, since_file) {
+                error!(
+                    "Failed to write the since-file {}: {}",
+                    since_file.display(),
+                    e
+                );
+                return Err(ExitCode::from(EXIT_WRITING));
+            }
         }
+        if exhausted.is_some() {
+            return Err(ExitCode::from(EXIT_REQUEST_BUDGET));
+        }
+        if exceeds_failure_threshold(summary) {
+            return Err(ExitCode::from(EXIT_FAILURE_THRESHOLD));
+        }
+        Ok(cursor)
+    } else {
+        Err(ExitCode::from(EXIT_API_ERROR))
+    }
+}
+
+/// Whether more than the `--failure-threshold` percent of the issues and
+/// pull-requests of a backup failed to load or write. Without it, failed
+/// entries don't fail the backup.
+fn exceeds_failure_threshold(summary: &BackupSummary) -> bool {
+    let Some(threshold) = args().failure_threshold else {
+        return false;
+    };
+    let failed = summary.failed_issues.len() + summary.failed_pulls.len();
+    let total = failed as u64 + summary.issues + summary.pulls;
+    if total == 0 {
+        return false;
+    }
+    let rate = failed as f64 * 100.0 / total as f64;
+    if rate <= threshold {
+        return false;
     }
+    error!(
+        "{} of {} issues and pull-requests of {}:{} failed ({:.1}%), more than the --failure-threshold of {}%. They are retried with the next backup.",
+        failed, total, summary.owner, summary.repo, rate, threshold
+    );
+    true
+}
+
+/// Fetches and writes the issues and pull-requests with the given `numbers`,
+/// `--prefetch-concurrency` at a time. The incremental state isn't read or
+/// written, but the index is updated. With `skip_missing`, numbers without an
+/// issue or pull-request, like the gaps of an `--issue-number-range`, are
+/// skipped instead of failing.
+async fn prefetch(
+    owner: &str,
+    repo: &str,
+    destination: &Path,
+    numbers: Vec<u64>,
+    skip_missing: bool,
+) -> ExitCode {
+    let args = args();
     info!(
-        "Loaded {} issues and {} pulls from {}:{}",
-        loaded_issues, loaded_pulls, owner, repo
+        "Prefetching {} issues and pull-requests of {}:{} into {}",
+        numbers.len(),
+        owner,
+        repo,
+        destination.display()
     );
-    if !failed_issues.is_empty() {
-        warn!(
-            "The following {} issues failed to load: {:?}",
-            failed_issues.len(),
-            failed_issues
-        );
+    let slots = Arc::new(Semaphore::new(args.prefetch_concurrency as usize));
+    let mut tasks = task::JoinSet::new();
+    for number in numbers {
+        let (owner, repo) = (owner.to_string(), repo.to_string());
+        let slots = slots.clone();
+        tasks.spawn(async move {
+            let _slot = slots
+                .acquire_owned()
+                .await
+                .expect("the prefetch slots semaphore is never closed");
+            let raw = get_issue_by_number(number, owner.clone(), repo.clone(), Attempt::FIRST)
+                .await
+                .map_err(|e| (number, e))?;
+            fetch_entry(raw, &owner, &repo, Checkpoint::default())
+                .await
+                .map_err(|(e, _)| (number, e))
+        });
     }
-    if !failed_issues.is_empty() {
-        warn!(
-            "The following {} pulls failed to load: {:?}",
-            failed_pulls.len(),
-            failed_pulls
+
+    let mut index = index::Index::load(destination, &args.issues_dir_name, &args.pulls_dir_name);
+    let (mut written, mut missing, mut failed) = (0, 0, 0);
+    while let Some(joined) = tasks.join_next().await {
+        match joined {
+            Ok(Ok(entry)) => {
+                if let Err(e) = write(entry.clone(), destination.to_path_buf()) {
+                    error!(
+                        "Could not write {} to {}: {}",
+                        entry,
+                        destination.display(),
+                        e
+                    );
+                    return ExitCode::from(EXIT_WRITING);
+                }
+                index.insert(&entry, index_path(destination, &entry));
+                written += 1;
+            }
+            Ok(Err((number, e))) if skip_missing && is_removed(&e) => {
+                debug!("Skipping #{}: {}", number, e);
+                missing += 1;
+            }
+            Ok(Err((number, e))) => {
+                error!("Could not prefetch #{}: {}", number, e);
+                failed += 1;
+            }
+            Err(e) => {
+                error!("Prefetching an issue or pull-request panicked: {}", e);
+                failed += 1;
+            }
+        }
+    }
+    if let Err(e) = index.write(destination) {
+        error!(
+            "Failed to write {} to {}: {}",
+            index::INDEX_FILE,
+            destination.display(),
+            e
         );
+        return ExitCode::from(EXIT_WRITING);
     }
 
-    Ok(())
+    info!(
+        "Prefetched {} issues and pull-requests, {} failed and {} don't exist",
+        written, failed, missing
+    );
+    if failed > 0 {
+        ExitCode::from(EXIT_API_ERROR)
+    } else {
+        ExitCode::SUCCESS
+    }
 }
 
-fn write(x: EntryWithMetadata, destination: PathBuf) -> Result<(), WriteError> {
-    let mut path = destination;
-    let json: String = match x {
-        EntryWithMetadata::Issue(i) => {
-            path.push("issues");
-            path.push(format!("{}.json", i.issue.number));
-            serde_json::to_string_pretty(&i)?
-        }
-        EntryWithMetadata::Pull(p) => {
-            path.push("pulls");
-            path.push(format!("{}.json", p.pull.number));
-            serde_json::to_string_pretty(&p)?
+/// Resolves once the process is asked to shut down with SIGINT or SIGTERM.
+async fn shutdown_signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+        match signal(SignalKind::terminate()) {
+            Ok(mut terminate) => {
+                tokio::select! {
+                    _ = tokio::signal::ctrl_c() => (),
+                    _ = terminate.recv() => (),
+                }
+            }
+            Err(e) => {
+                warn!("Could not listen for SIGTERM: {}", e);
+                let _ = tokio::signal::ctrl_c().await;
+            }
         }
-    };
-    let mut file = File::create(path.clone())?;
-    file.write_all(json.as_bytes())?;
-    info!("Written {}", path.display());
-    Ok(())
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = tokio::signal::ctrl_c().await;
+    }
 }
 
-fn write_backup_state(
-    start_time: DateTime<Utc>,
-    mut destination: PathBuf,
-) -> Result<(), WriteError> {
-    let state = BackupState {
-        version: STATE_VERSION,
-        last_backup: start_time,
-    };
-    destination.push(STATE_FILE);
-    let json = serde_json::to_string_pretty(&state)?;
-    let mut file = File::create(destination.clone())?;
-    file.write_all(json.as_bytes())?;
-    info!("Written backup state to {}", destination.display());
-    Ok(())
-}
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-fn get_last_backup_time(destination: PathBuf) -> Option<DateTime<Utc>> {
-    let mut path = destination;
-    path.push(STATE_FILE);
-    info!("Trying to read {} file", path.display());
-    match fs::read_to_string(path.clone()) {
-        Ok(contents) => {
-            info!("Trying deserialize {} file", path.display());
-            match serde_json::from_str::<BackupState>(&contents) {
-                Ok(state) => match state.version {
-                    STATE_VERSION => {
-                        info!(
-                            "Doing an incremental GitHub backup starting from {}.",
-                            state.last_backup
-                        );
-                        Some(state.last_backup)
-                    }
-                    _ => {
-                        warn!("BackupState version {} is unknown.", state.version);
-                        None
-                    }
-                },
-                Err(e) => {
-                    warn!(
-                        "BackupState file {} could not be deserialized: {}",
-                        path.display(),
-                        e
-                    );
-                    None
+    /// Parses the default arguments once, for the functions that read them.
+    fn init_args() {
+        ARGS.get_or_init(|| {
+            Args::parse_from([
+                "github-metadata-backup",
+                "--owner",
+                "octo-org",
+                "--repo",
+                "octo-repo",
+                "--destination",
+                "backup",
+            ])
+        });
+    }
+
+    /// An empty directory for the test `name` below the temporary directory.
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "github-metadata-backup-{}-{}",
+            name,
+            std::process::id()
+        ));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    /// The error octocrab returns for a response with the `status` and the
+    /// error `message`.
+    async fn github_error(status: u16, message: &str) -> FetchError {
+        let body = serde_json::json!({ "message": message }).to_string();
+        let response = http::Response::builder()
+            .status(status)
+            .body(
+                Full::new(Bytes::from(body))
+                    .map_err(|never| match never {})
+                    .boxed(),
+            )
+            .unwrap();
+        match octocrab::map_github_error(response).await {
+            Ok(_) => panic!("a {} response is an error", status),
+            Err(e) => e.into(),
+        }
+    }
+
+    /// A response with the `status` and `headers`, and no body.
+    fn response(status: u16, headers: &[(&str, &str)]) -> http::Response<Full<Bytes>> {
+        let mut response = http::Response::builder().status(status);
+        for (name, value) in headers {
+            response = response.header(*name, *value);
+        }
+        response.body(Full::new(Bytes::new())).unwrap()
+    }
+
+    fn issue() -> IssueWithMetadata {
+        let issue = serde_json::from_str(include_str!("../tests/fixtures/issue.json")).unwrap();
+        IssueWithMetadata::new(
+            RepositoryRef {
+                owner: "octo-org".to_string(),
+                name: "octo-repo".to_string(),
+            },
+            issue,
+            vec![],
+        )
+    }
+
+    #[test]
+    fn content_hash_ignores_timestamps() {
+        init_args();
+        let first = issue();
+        let mut second = first.clone();
+        second.generated_by.generated_at = DateTime::<Utc>::UNIX_EPOCH;
+        second.issue.updated_at = Utc::now();
+        assert_eq!(
+            content_hash(&EntryWithMetadata::Issue(Box::new(first.clone()))).unwrap(),
+            content_hash(&EntryWithMetadata::Issue(Box::new(second))).unwrap()
+        );
+
+        let mut changed = first.clone();
+        changed.issue.title = "Found two bugs".to_string();
+        assert_ne!(
+            content_hash(&EntryWithMetadata::Issue(Box::new(first))).unwrap(),
+            content_hash(&EntryWithMetadata::Issue(Box::new(changed))).unwrap()
+        );
+    }
+
+    #[test]
+    fn annotate_removal_keeps_the_format() {
+        init_args();
+        let dir = temp_dir("annotate-removal");
+        let value: serde_json::Value =
+            serde_json::from_str(include_str!("../tests/fixtures/issue.json")).unwrap();
+        let removal = Removal {
+            reason: "deleted".to_string(),
+            detected_at: DateTime::<Utc>::UNIX_EPOCH,
+            new_url: None,
+        };
+        for format in Serialization::ALL {
+            for gz in ["", ".gz"] {
+                let path = dir.join(format!("1347.{}{}", format.extension(), gz));
+                let mut bytes = sink::encode(&value, format).unwrap();
+                if !gz.is_empty() {
+                    bytes = gzip(&bytes).unwrap();
                 }
+                fs::write(&path, bytes).unwrap();
+
+                assert!(annotate_removal(&path, &removal).unwrap());
+                assert!(!annotate_removal(&path, &removal).unwrap());
+                let contents = fs::read(&path).unwrap();
+                assert_eq!(contents.starts_with(&GZIP_MAGIC), !gz.is_empty());
+                let mut annotated =
+                    sink::decode(&read_maybe_compressed(&path).unwrap(), format).unwrap();
+                assert_eq!(annotated["removed"]["reason"], "deleted", "{:?}", path);
+                annotated.as_object_mut().unwrap().remove("removed");
+                assert_eq!(annotated, value, "{:?}", path);
             }
         }
-        Err(e) => {
-            info!(
-                "BackupState file {} could not be found: {}",
-                path.display(),
-                e
-            );
-            None
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn transient_errors() {
+        init_args();
+        for status in [502, 503, 504] {
+            let e = inspect_response("/", &response(status, &[])).unwrap_err();
+            assert!(is_transient(&e), "{}", status);
         }
+        assert!(is_transient(&github_error(500, "Server Error").await));
+        for status in [403, 404, 422, 429] {
+            let e = github_error(status, "Not a transient error").await;
+            assert!(!is_transient(&e), "{}", status);
+        }
+        let e = inspect_response("/", &response(403, &[("retry-after", "30")])).unwrap_err();
+        assert!(!is_transient(&e));
     }
-}
 
-fn personal_access_token(args: Args) -> Option<String> {
-    if let Some(pat) = args.personal_access_token {
-        info!("Using the GitHub personal access token specified on the command line");
-        return Some(pat);
-    } else if let Some(pat_file) = args.personal_access_token_file {
-        info!(
-            "Reading the GitHub personal access token from '{}'",
-            pat_file.display()
+    #[tokio::test]
+    async fn secondary_rate_limits() {
+        init_args();
+        let message = "You have exceeded a secondary rate limit. Please wait a few minutes before you try again.";
+        assert!(is_secondary_rate_limit(&github_error(403, message).await));
+        assert!(is_secondary_rate_limit(&github_error(429, message).await));
+        assert!(!is_secondary_rate_limit(
+            &github_error(403, "Resource not accessible by integration").await
+        ));
+        assert!(!is_secondary_rate_limit(&github_error(500, message).await));
+        // With a Retry-After header, the wait is known before the message.
+        let e = inspect_response("/", &response(403, &[("retry-after", "30")])).unwrap_err();
+        assert!(matches!(e, FetchError::RateLimited(wait) if wait == Duration::from_secs(30)));
+        assert!(!is_secondary_rate_limit(&e));
+    }
+
+    #[tokio::test]
+    async fn rate_limit_retry_backs_off() {
+        init_args();
+        let e = github_error(429, "API rate limit exceeded").await;
+        assert_eq!(
+            retry_wait(&e, Attempt::FIRST, 0.5),
+            Some(RetryWait::RateLimitReset(SECONDARY_RATE_LIMIT_BACKOFF))
         );
-        match fs::read_to_string(pat_file.clone()) {
-            Ok(pat) => {
-                return Some(pat.trim().to_string());
-            }
-            Err(e) => {
-                error!(
-                    "Could not read GitHub personal access token from '{}': {}",
-                    pat_file.display(),
-                    e
+        assert_eq!(retry_wait(&e, Attempt::FIRST.retry(None), 0.5), None);
+    }
+
+    #[tokio::test]
+    async fn forbidden_retries() {
+        init_args();
+        let e = inspect_response("/", &response(403, &[("retry-after", "30")])).unwrap_err();
+        assert_eq!(
+            retry_wait(&e, Attempt::FIRST, 0.5),
+            Some(RetryWait::RateLimited(Duration::from_secs(30)))
+        );
+
+        let e = github_error(
+            403,
+            "You have exceeded a secondary rate limit. Please wait a few minutes before you try again.",
+        )
+        .await;
+        assert_eq!(
+            retry_wait(&e, Attempt::FIRST, 0.5),
+            Some(RetryWait::SecondaryRateLimit(SECONDARY_RATE_LIMIT_BACKOFF))
+        );
+        let last = Attempt {
+            retries: args().max_retries,
+            previous_delay: None,
+        };
+        assert_eq!(retry_wait(&e, last, 0.5), None);
+
+        let e = github_error(403, "Resource not accessible by integration").await;
+        assert_eq!(retry_wait(&e, Attempt::FIRST, 0.5), None);
+    }
+
+    #[test]
+    fn gateway_errors_are_retried() {
+        init_args();
+        for status in [502, 503, 504] {
+            let e = inspect_response("/", &response(status, &[])).unwrap_err();
+            let mut attempt = Attempt::FIRST;
+            for delay in [1, 2, 4] {
+                let wait = retry_wait(&e, attempt, 0.5);
+                assert_eq!(
+                    wait,
+                    Some(RetryWait::Transient(Duration::from_secs(delay))),
+                    "{}",
+                    status
                 );
-                return None;
+                attempt = attempt.retry(Some(Duration::from_secs(delay)));
             }
+            assert_eq!(retry_wait(&e, attempt, 0.5), None, "{}", status);
         }
     }
-    None
-}
 
-#[tokio::main]
-async fn main() -> ExitCode {
-    env_logger::Builder::from_env(Env::default().default_filter_or("info")).init();
+    #[test]
+    fn rate_limit_waits() {
+        let headers = |pairs: &[(&str, &str)]| {
+            let mut headers = http::HeaderMap::new();
+            for (name, value) in pairs {
+                headers.insert(
+                    http::HeaderName::from_bytes(name.as_bytes()).unwrap(),
+                    value.parse().unwrap(),
+                );
+            }
+            headers
+        };
+        assert_eq!(
+            rate_limit_wait(&headers(&[("retry-after", "120")])),
+            Some(Duration::from_secs(120))
+        );
+        let reset = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap()
+            .as_secs()
+            + 30;
+        let wait = rate_limit_wait(&headers(&[
+            ("x-ratelimit-remaining", "0"),
+            ("x-ratelimit-resource", "search"),
+            ("x-ratelimit-reset", &reset.to_string()),
+        ]))
+        .unwrap();
+        assert!(wait > Duration::from_secs(25) && wait <= Duration::from_secs(31));
+        // The core rate-limit is left to wait_on_ratelimit().
+        assert_eq!(
+            rate_limit_wait(&headers(&[
+                ("x-ratelimit-remaining", "0"),
+                ("x-ratelimit-resource", "core"),
+                ("x-ratelimit-reset", &reset.to_string()),
+            ])),
+            None
+        );
+        assert_eq!(
+            rate_limit_wait(&headers(&[("x-ratelimit-remaining", "42")])),
+            None
+        );
+    }
 
-    let args: Args = Args::parse();
-    info!(
-        "Starting backup of {}:{} on GitHub to '{}'",
-        args.owner,
-        args.repo,
-        args.destination.display()
-    );
+    #[test]
+    fn write_recreates_a_removed_directory() {
+        init_args();
+        let dir = temp_dir("files-sink");
+        let sink = FilesSink {
+            destination: dir.clone(),
+            layout: Layout::PerNumber,
+            compress: false,
+        };
+        let x = EntryWithMetadata::Issue(Box::new(issue()));
+        let value = serialize(&x).unwrap();
+        let path = entry_file(&dir, &x);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        // Removed after startup, e.g. by an external cleaner.
+        fs::remove_dir_all(&dir).unwrap();
 
-    let pat = match personal_access_token(args.clone()) {
-        Some(pat) => pat,
-        None => {
-            error!("No GitHub personal access token present - exiting.");
-            return ExitCode::from(EXIT_NO_PAT);
-        }
-    };
+        sink.write(&x, &value).unwrap();
+        let written: serde_json::Value = serde_json::from_slice(&fs::read(&path).unwrap()).unwrap();
+        assert_eq!(written, value);
+        fs::remove_dir_all(&dir).unwrap();
+    }
 
-    let issues_dir = args.destination.join("issues");
-    let pulls_dir = args.destination.join("pulls");
-    info!(
-        "If not existing yet, creating 'issues' and 'pulls' directory as {} and {}",
-        issues_dir.display(),
-        pulls_dir.display()
-    );
-    if let Err(e) = fs::create_dir_all(issues_dir.clone()) {
-        error!(
-            "Could not create 'issues' directory in {}: {}",
-            issues_dir.display(),
-            e
+    #[tokio::test]
+    async fn custom_headers_are_sent() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let base_uri: http::Uri = format!("http://{}", listener.local_addr().unwrap())
+            .parse()
+            .unwrap();
+        let server = std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = vec![];
+            let mut buf = [0u8; 1024];
+            while !request.ends_with(b"\r\n\r\n") {
+                let n = stream.read(&mut buf).unwrap();
+                assert!(n > 0, "the request ended early");
+                request.extend_from_slice(&buf[..n]);
+            }
+            stream
+                .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\n{}")
+                .unwrap();
+            String::from_utf8(request).unwrap().to_lowercase()
+        });
+        let headers = vec![Header {
+            name: http::HeaderName::from_static("x-api-key"),
+            value: "secret".to_string(),
+        }];
+        let instance = build_octocrab("token".to_string(), Some(&base_uri), &headers).unwrap();
+        instance._get("/rate_limit").await.unwrap();
+
+        let request = server.join().unwrap();
+        assert!(request.contains("\r\nx-api-key: secret\r\n"), "{}", request);
+        assert!(
+            request.contains("\r\nauthorization: bearer token\r\n"),
+            "{}",
+            request
         );
-        return ExitCode::from(EXIT_CREATING_DIRS);
     }
-    if let Err(e) = fs::create_dir_all(pulls_dir.clone()) {
-        error!(
-            "Could not create 'pulls' directory in {}: {}",
-            pulls_dir.display(),
-            e
-        );
-        return ExitCode::from(EXIT_CREATING_DIRS);
+
+    #[test]
+    fn dedupe_comments_across_pages() {
+        let pages: Vec<Vec<serde_json::Value>> = serde_json::from_str(include_str!(
+            "../tests/fixtures/review_comments_duplicated.json"
+        ))
+        .unwrap();
+        let mut comments: Vec<serde_json::Value> = pages.into_iter().flatten().collect();
+        let dropped = dedupe_items(&mut comments, |comment| {
+            comment["id"].as_u64().map(|id| id.to_string())
+        });
+        assert_eq!(dropped, 1);
+        let comments: Vec<models::pulls::Comment> = comments
+            .into_iter()
+            .map(|comment| serde_json::from_value(comment).unwrap())
+            .collect();
+        let ids: Vec<u64> = comments.iter().map(|comment| *comment.id).collect();
+        assert_eq!(ids, [10, 11, 12]);
+        // The latest version by updated_at is kept.
+        assert_eq!(comments[1].body, "Second, edited");
     }
 
-    let start_time = chrono::Utc::now();
-    let last_backup_time: Option<DateTime<Utc>> = get_last_backup_time(args.destination.clone());
+    #[test]
+    fn migrate_state_from_version_1() {
+        let state = serde_json::json!({
+            "version": 1,
+            "last_backup": "2024-03-01T12:00:00Z",
+        });
+        let migrated = migrate_state(state, 1).unwrap();
+        assert_eq!(migrated["version"], STATE_VERSION);
+        assert_eq!(migrated["last_backup_issues"], "2024-03-01T12:00:00Z");
+        assert_eq!(migrated["last_backup_pulls"], "2024-03-01T12:00:00Z");
+        // There is no step from version 0.
+        assert_eq!(migrate_state(serde_json::json!({}), 0), None);
+    }
 
-    let instance = match octocrab::OctocrabBuilder::default()
-        .personal_token(pat)
-        .build()
-    {
-        Ok(instance) => instance,
-        Err(e) => {
-            error!(
-                "Could not create Octocrab instance with the supplied personal access token: {}",
-                e
-            );
-            return ExitCode::from(EXIT_CREATING_OCTOCRAB_INSTANCE);
+    #[test]
+    fn reactions_summary_is_kept() {
+        let mut raw: serde_json::Value =
+            serde_json::from_str(include_str!("../tests/fixtures/issue.json")).unwrap();
+        let reactions = take_field(&mut raw, "reactions");
+        let mut entry = issue();
+        entry.issue = serde_json::from_value(raw.clone()).unwrap();
+        entry.reactions = reactions;
+        let value = serde_json::to_value(&entry).unwrap();
+        assert_eq!(value["reactions"]["total_count"], 3);
+        assert_eq!(value["reactions"]["+1"], 2);
+        assert_eq!(value["reactions"]["heart"], 1);
+
+        // An issue the API returned without reactions has no summary.
+        raw["reactions"] = serde_json::Value::Null;
+        entry.reactions = take_field(&mut raw, "reactions");
+        let value = serde_json::to_value(&entry).unwrap();
+        assert!(value.get("reactions").is_none());
+    }
+
+    #[test]
+    fn dedupe_events_across_pages() {
+        let pages: Vec<Vec<serde_json::Value>> =
+            serde_json::from_str(include_str!("../tests/fixtures/timeline_duplicated.json"))
+                .unwrap();
+        let mut events: Vec<serde_json::Value> = pages.into_iter().flatten().collect();
+        let dropped = dedupe_items(&mut events, event_key);
+        assert_eq!(dropped, 3);
+        let keys: Vec<String> = events.iter().filter_map(event_key).collect();
+        assert_eq!(
+            keys,
+            [
+                "EV_lADOA201",
+                "IC_kwDOA202",
+                "committed:6dcb09b5b57875f334f61aebed695e2e4193db5e",
+                "EV_lADOA203"
+            ]
+        );
+        // The latest version of the comment by updated_at is kept.
+        assert_eq!(events[1]["body"], "First, edited");
+        for event in events {
+            serde_json::from_value::<models::timelines::TimelineEvent>(event).unwrap();
         }
-    };
-    octocrab::initialise(instance);
+    }
 
-    // Fetched issues and PRs are send into this mpsc channel and received by
-    // the writer which persist them to the disk.
-    let (sender, mut receiver) = mpsc::channel(100);
+    #[test]
+    fn reopened_issue_moves_between_state_dirs() {
+        init_args();
+        let dir = temp_dir("split-by-state");
+        let sink = FilesSink {
+            destination: dir.clone(),
+            layout: Layout::SplitByState,
+            compress: false,
+        };
+        let issues = dir.join(&args().issues_dir_name);
+        let state = |path: PathBuf| -> serde_json::Value {
+            serde_json::from_slice::<serde_json::Value>(&fs::read(path).unwrap()).unwrap()["issue"]
+                ["state"]
+                .clone()
+        };
 
-    let task = task::spawn(async move {
-        if let Err(e) = get_issues_and_pulls(sender, last_backup_time, args.owner, args.repo).await
-        {
-            panic!("Error loading issues and pulls: {}", e);
+        // The first run backs up the closed issue, the second the reopened
+        // one, and a third closes it again.
+        let mut x = issue();
+        let runs = [
+            (true, "closed", "open"),
+            (false, "open", "closed"),
+            (true, "closed", "open"),
+        ];
+        for (closed, current, stale) in runs {
+            x.issue.state = match closed {
+                true => models::IssueState::Closed,
+                false => models::IssueState::Open,
+            };
+            let entry = EntryWithMetadata::Issue(Box::new(x.clone()));
+            sink.write(&entry, &serialize(&entry).unwrap()).unwrap();
+            assert_eq!(state(issues.join(current).join("1347.json")), current);
+            assert!(!issues.join(stale).join("1347.json").exists());
         }
-    });
 
-    while let Some(data) = receiver.recv().await {
-        if let Err(e) = write(data.clone(), args.destination.clone()) {
-            error!(
-                "Could not write {} to {}: {}",
-                data,
-                args.destination.clone().display(),
-                e
-            );
-            receiver.close();
-            return ExitCode::from(EXIT_WRITING);
+        // If the file of the reopened issue can't be written, the closed one
+        // is kept.
+        fs::create_dir_all(issues.join("open").join("1347.json.partial")).unwrap();
+        x.issue.state = models::IssueState::Open;
+        let entry = EntryWithMetadata::Issue(Box::new(x));
+        assert!(sink.write(&entry, &serialize(&entry).unwrap()).is_err());
+        assert_eq!(state(issues.join("closed").join("1347.json")), "closed");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn compressed_write_round_trips() {
+        init_args();
+        let dir = temp_dir("compress");
+        let sink = FilesSink {
+            destination: dir.clone(),
+            layout: Layout::PerNumber,
+            compress: true,
+        };
+        let x = EntryWithMetadata::Issue(Box::new(issue()));
+        let value = serialize(&x).unwrap();
+        sink.write(&x, &value).unwrap();
+
+        let path = dir.join(&args().issues_dir_name).join("1347.json.gz");
+        assert_eq!(
+            parse_entry_file_name(&path),
+            Some((1347, Serialization::Json))
+        );
+        assert!(fs::read(&path).unwrap().starts_with(&GZIP_MAGIC));
+        let read: serde_json::Value =
+            serde_json::from_slice(&read_maybe_compressed(&path).unwrap()).unwrap();
+        assert_eq!(read, value);
+        serde_json::from_value::<issues::Issue>(read["issue"].clone()).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn nested_discussion_thread() {
+        let mut data: serde_json::Value =
+            serde_json::from_str(include_str!("../tests/fixtures/discussions_page.json")).unwrap();
+        let mut page = discussions::take_connection(&mut data["repository"]["discussions"]);
+        assert_eq!(page.next, None);
+        let discussion = &mut page.nodes[0];
+        // All comments and replies came with the page, so nothing is fetched.
+        complete_discussion(discussion, "octo-org", "octo-repo")
+            .await
+            .unwrap();
+
+        assert_eq!(discussion["labels"], serde_json::json!(["question"]));
+        assert_eq!(discussion["reactions"]["+1"], 3);
+        let comments = discussion["comments"].as_array().unwrap();
+        assert_eq!(comments.len(), 2);
+        assert_eq!(comments[0]["authorAssociation"], "MEMBER");
+        assert_eq!(
+            comments[0]["reactions"],
+            serde_json::json!({
+                "+1": 2, "-1": 0, "laugh": 0, "hooray": 0, "confused": 0,
+                "heart": 1, "rocket": 0, "eyes": 0, "total_count": 3
+            })
+        );
+        assert!(comments[0].get("reactionGroups").is_none());
+        // The reply to the reply is listed with the comment, and refers to the
+        // reply it answers.
+        let replies = comments[0]["replies"].as_array().unwrap();
+        assert_eq!(replies.len(), 2);
+        assert_eq!(replies[0]["replyTo"]["id"], comments[0]["id"]);
+        assert_eq!(replies[1]["replyTo"]["id"], replies[0]["id"]);
+        assert_eq!(replies[1]["authorAssociation"], "MEMBER");
+        assert_eq!(replies[1]["reactions"]["hooray"], 1);
+        assert_eq!(comments[1]["replies"], serde_json::json!([]));
+    }
+
+    #[test]
+    fn closed_by_the_latest_closed_event() {
+        let events: Vec<serde_json::Value> =
+            serde_json::from_str(include_str!("../tests/fixtures/timeline_closed.json")).unwrap();
+        for event in &events {
+            serde_json::from_value::<models::timelines::TimelineEvent>(event.clone()).unwrap();
         }
+        let login = |user: Option<serde_json::Value>| user.map(|user| user["login"].clone());
+
+        // Closed, reopened and closed again by someone else.
+        assert_eq!(
+            login(closed_by(1347, true, None, &events)),
+            Some("monalisa".into())
+        );
+        // The timeline is preferred over a mismatching closed_by of the API.
+        let reported = events[0]["actor"].clone();
+        assert_eq!(
+            login(closed_by(1347, true, Some(reported.clone()), &events)),
+            Some("monalisa".into())
+        );
+        // Without a closed event, the closed_by of the API is used.
+        assert_eq!(
+            login(closed_by(1347, true, Some(reported), &events[1..2])),
+            Some("octocat".into())
+        );
+        // Open entries have no closed_by.
+        assert_eq!(closed_by(1347, false, None, &events), None);
     }
 
-    if task.await.is_ok() {
-        if let Err(e) = write_backup_state(start_time, args.destination.clone()) {
-            error!(
-                "Failed to write {} to {}: {}",
-                STATE_FILE,
-                args.destination.clone().display(),
-                e
-            );
-            return ExitCode::from(EXIT_WRITING);
+    #[test]
+    fn unknown_events_are_kept() {
+        init_args();
+        let items: Vec<serde_json::Value> =
+            serde_json::from_str(include_str!("../tests/fixtures/timeline_unknown.json")).unwrap();
+        assert!(is_known_event("labeled"));
+        assert!(!is_known_event("quantum_entangled"));
+
+        // The unknown event doesn't fail the timeline.
+        let mut entry = issue();
+        entry.events = timeline_events(1347, &items).unwrap();
+        entry.unknown_events = unknown_events(&items);
+        assert_eq!(entry.events.len(), 1);
+
+        // Both are in the backup, the unknown one as returned by the API.
+        let value = serde_json::to_value(&entry).unwrap();
+        assert_eq!(value["events"][0]["event"], "labeled");
+        assert_eq!(value["events"][0]["label"]["name"], "bug");
+        assert_eq!(value["unknown_events"], serde_json::json!([items[1]]));
+    }
+
+    #[test]
+    fn graphql_limit_errors() {
+        for code in ["MAX_NODE_LIMIT_EXCEEDED", "RESOURCE_LIMITS_EXCEEDED"] {
+            let e = FetchError::GraphQL(format!(r#"[{{"type":"{}","message":"..."}}]"#, code));
+            assert!(is_graphql_limit(&e), "{}", code);
         }
-    } else {
-        return ExitCode::from(EXIT_API_ERROR);
+        assert!(!is_graphql_limit(&FetchError::GraphQL(
+            r#"[{"type":"NOT_FOUND","message":"..."}]"#.to_string()
+        )));
     }
 
-    ExitCode::SUCCESS
+    #[test]
+    fn exit_codes_are_distinct() {
+        let codes = [
+            EXIT_CREATING_DIRS,
+            EXIT_CREATING_OCTOCRAB_INSTANCE,
+            EXIT_API_ERROR,
+            EXIT_WRITING,
+            EXIT_NO_PAT,
+            EXIT_LOW_DISK_SPACE,
+            EXIT_ANONYMIZE_SALT,
+            EXIT_LOCKED,
+            EXIT_DRIFT,
+            EXIT_STATE_VERSION,
+            EXIT_REQUEST_BUDGET,
+            EXIT_FAILURE_THRESHOLD,
+        ];
+        let distinct: std::collections::BTreeSet<u8> = codes.into_iter().collect();
+        // --watch tells an API error, after which it keeps going, from a
+        // failed write by the exit code.
+        assert_eq!(distinct.len(), codes.len());
+    }
+
+    #[tokio::test]
+    async fn prefetched_response_is_used_once() {
+        init_args();
+        let route = pull_route("octo-org", "octo-repo", 1347);
+        let response = http::Response::builder()
+            .header(http::header::ETAG, "W/\"changed\"")
+            .body(Bytes::from_static(br#"{"number":1347,"state":"open"}"#))
+            .unwrap();
+        PREFETCHED.lock().unwrap().insert(route.clone(), response);
+
+        let (pull, etag): (serde_json::Value, _) =
+            api_get_tagged(route.clone(), None).await.unwrap();
+        assert_eq!(pull["number"], 1347);
+        assert_eq!(etag.as_deref(), Some("W/\"changed\""));
+        assert!(!PREFETCHED.lock().unwrap().contains_key(&route));
+    }
 }
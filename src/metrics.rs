@@ -0,0 +1,140 @@
+use base64::engine::general_purpose::URL_SAFE;
+use base64::Engine;
+use log::{info, warn};
+use std::fmt::Write as _;
+use std::time::Duration;
+
+use crate::types::BackupSummary;
+
+/// How long to wait for the Pushgateway to accept the metrics.
+const PUSH_TIMEOUT: Duration = Duration::from_secs(10);
+/// The prefix of the names of all pushed metrics.
+const PREFIX: &str = "github_metadata_backup";
+
+/// Renders the `summary` of a backup as gauges in the Prometheus text format.
+/// The time of the last success is only included if the backup succeeded, so
+/// a failed backup doesn't replace the one pushed before.
+fn render(summary: &BackupSummary) -> String {
+    let mut gauges: Vec<(&str, &str, f64)> = vec![
+        (
+            "success",
+            "Whether the last backup succeeded.",
+            summary.success as u8 as f64,
+        ),
+        (
+            "last_run_timestamp_seconds",
+            "When the last backup started, in seconds since the Unix epoch.",
+            summary.started_at.timestamp() as f64,
+        ),
+        (
+            "duration_seconds",
+            "How long the last backup took.",
+            summary.duration_secs,
+        ),
+        (
+            "issues_written",
+            "Number of issues written by the last backup.",
+            summary.issues as f64,
+        ),
+        (
+            "pulls_written",
+            "Number of pull-requests written by the last backup.",
+            summary.pulls as f64,
+        ),
+        (
+            "filtered_entries",
+            "Number of issues and pull-requests skipped by the --entry-filter-expr.",
+            summary.filtered as f64,
+        ),
+        (
+            "pruned_files",
+            "Number of files removed by --prune.",
+            summary.pruned as f64,
+        ),
+        (
+            "truncated_entries",
+            "Number of issues and pull-requests cut off by --max-comment-pages or --max-event-pages.",
+            summary.truncated.len() as f64,
+        ),
+        (
+            "unchanged_entries",
+            "Number of issues and pull-requests --diff-only-state didn't rewrite.",
+            summary.unchanged as f64,
+        ),
+        (
+            "failed_issues",
+            "Number of issues that failed to load.",
+            summary.failed_issues.len() as f64,
+        ),
+        (
+            "failed_pulls",
+            "Number of pull-requests that failed to load.",
+            summary.failed_pulls.len() as f64,
+        ),
+    ];
+    if summary.success {
+        gauges.push((
+            "last_success_timestamp_seconds",
+            "When the last successful backup started, in seconds since the Unix epoch.",
+            summary.started_at.timestamp() as f64,
+        ));
+    }
+    let mut text = String::new();
+    for (name, help, value) in gauges {
+        let _ = writeln!(text, "# HELP {}_{} {}", PREFIX, name, help);
+        let _ = writeln!(text, "# TYPE {}_{} gauge", PREFIX, name);
+        let _ = writeln!(text, "{}_{} {}", PREFIX, name, value);
+    }
+    text
+}
+
+/// Appends a label of the Pushgateway grouping key to the `path`. Values with
+/// a `/`, or empty ones, are base64url-encoded, as the Pushgateway expects.
+fn push_label(path: &mut String, name: &str, value: &str) {
+    if value.is_empty() || value.contains('/') {
+        let _ = write!(path, "/{}@base64/{}", name, URL_SAFE.encode(value));
+    } else {
+        let _ = write!(path, "/{}/{}", name, value);
+    }
+}
+
+/// Pushes the metrics of a backup's `summary` to the Pushgateway at `url`,
+/// grouped by the `job`, the `instance` if any, and the owner and repo. The
+/// metrics are POSTed, so they only replace the ones of the same name in the
+/// group. A failed push is only logged and doesn't fail the backup.
+pub async fn push(url: &str, job: &str, instance: Option<&str>, summary: &BackupSummary) {
+    let mut endpoint = format!("{}/metrics", url.trim_end_matches('/'));
+    push_label(&mut endpoint, "job", job);
+    if let Some(instance) = instance {
+        push_label(&mut endpoint, "instance", instance);
+    }
+    push_label(&mut endpoint, "owner", &summary.owner);
+    push_label(&mut endpoint, "repo", &summary.repo);
+    let client = match reqwest::Client::builder().timeout(PUSH_TIMEOUT).build() {
+        Ok(client) => client,
+        Err(e) => {
+            warn!(
+                "Could not create the HTTP client to push the metrics: {}",
+                e
+            );
+            return;
+        }
+    };
+    match client
+        .post(&endpoint)
+        .header(reqwest::header::CONTENT_TYPE, "text/plain; version=0.0.4")
+        .body(render(summary))
+        .send()
+        .await
+        .and_then(|response| response.error_for_status())
+    {
+        Ok(_) => info!(
+            "Pushed the metrics of the backup of {}:{} to {}",
+            summary.owner, summary.repo, endpoint
+        ),
+        Err(e) => warn!(
+            "Could not push the metrics of the backup of {}:{} to {}: {}",
+            summary.owner, summary.repo, endpoint, e
+        ),
+    }
+}
@@ -0,0 +1,314 @@
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::permissions;
+use crate::types::WriteError;
+
+/// Version written to the `schema.json` of the archive.
+const MIGRATION_SCHEMA_VERSION: &str = "1.0.1";
+const GITHUB_URL: &str = "https://github.com";
+
+/// The issues, pull-requests and related records of a backup, reshaped into
+/// the JSON arrays of a GitHub migration archive. Records reference each
+/// other by their URL, and users by their profile URL.
+#[derive(Debug, Default)]
+pub struct MigrationArchive {
+    users: BTreeMap<String, Value>,
+    issues: Vec<Value>,
+    pull_requests: Vec<Value>,
+    issue_comments: Vec<Value>,
+    issue_events: Vec<Value>,
+    pull_request_reviews: Vec<Value>,
+    pull_request_review_comments: Vec<Value>,
+}
+
+/// Timeline event types the migration format has issue events for.
+const MIGRATED_EVENTS: [&str; 13] = [
+    "closed",
+    "reopened",
+    "labeled",
+    "unlabeled",
+    "assigned",
+    "unassigned",
+    "milestoned",
+    "demilestoned",
+    "renamed",
+    "locked",
+    "unlocked",
+    "merged",
+    "referenced",
+];
+
+impl MigrationArchive {
+    /// Adds the user to the `users` and returns their profile URL.
+    fn user(&mut self, user: &Value) -> Value {
+        let login = match user["login"].as_str() {
+            Some(login) => login,
+            None => return Value::Null,
+        };
+        let url = format!("{}/{}", GITHUB_URL, login);
+        self.users.entry(login.to_string()).or_insert_with(|| {
+            json!({
+                "type": "user",
+                "url": url,
+                "login": login,
+                "name": user["name"],
+                "company": null,
+                "website": null,
+                "location": null,
+                "emails": [],
+                "created_at": null,
+            })
+        });
+        Value::String(url)
+    }
+
+    /// Adds a written issue file.
+    pub fn add_issue(&mut self, file: &Value) {
+        let issue = &file["issue"];
+        let url = issue["html_url"].clone();
+        let repository = repository_url(&url);
+        let record = json!({
+            "type": "issue",
+            "url": url,
+            "repository": repository,
+            "user": self.user(&issue["user"]),
+            "title": issue["title"],
+            "body": issue["body"],
+            "assignee": self.user(&issue["assignee"]),
+            "milestone": milestone(&repository, &issue["milestone"]),
+            "labels": labels(&repository, &issue["labels"]),
+            "state": issue["state"],
+            "closed_at": issue["closed_at"],
+            "created_at": issue["created_at"],
+            "updated_at": issue["updated_at"],
+        });
+        self.issues.push(record);
+        self.add_events("issue", &url, &file["events"]);
+    }
+
+    /// Adds a written pull-request file.
+    pub fn add_pull(&mut self, file: &Value) {
+        let pull = &file["pull"];
+        let url = pull["html_url"].clone();
+        let repository = repository_url(&url);
+        let record = json!({
+            "type": "pull_request",
+            "url": url,
+            "repository": repository,
+            "user": self.user(&pull["user"]),
+            "title": pull["title"],
+            "body": pull["body"],
+            "base": self.pull_ref(&pull["base"]),
+            "head": self.pull_ref(&pull["head"]),
+            "assignee": self.user(&pull["assignee"]),
+            "milestone": milestone(&repository, &pull["milestone"]),
+            "labels": labels(&repository, &pull["labels"]),
+            "merged_at": pull["merged_at"],
+            "closed_at": pull["closed_at"],
+            "created_at": pull["created_at"],
+        });
+        self.pull_requests.push(record);
+        self.add_events("pull_request", &url, &file["events"]);
+
+        for comment in file["comments"].as_array().into_iter().flatten() {
+            let record = json!({
+                "type": "pull_request_review_comment",
+                "url": comment["html_url"],
+                "pull_request": url,
+                "pull_request_review": comment["pull_request_review_id"]
+                    .as_u64()
+                    .map(|id| format!("{}#pullrequestreview-{}", url_str(&url), id)),
+                "user": self.user(&comment["user"]),
+                "body": comment["body"],
+                "formatter": "markdown",
+                "diff_hunk": comment["diff_hunk"],
+                "path": comment["path"],
+                "position": comment["position"],
+                "original_position": comment["original_position"],
+                "commit_id": comment["commit_id"],
+                "original_commit_id": comment["original_commit_id"],
+                "created_at": comment["created_at"],
+            });
+            self.pull_request_review_comments.push(record);
+        }
+    }
+
+    fn pull_ref(&mut self, pull_ref: &Value) -> Value {
+        json!({
+            "ref": pull_ref["ref"],
+            "sha": pull_ref["sha"],
+            "user": self.user(&pull_ref["user"]),
+            "repo": pull_ref["repo"]["html_url"],
+        })
+    }
+
+    /// Reshapes the timeline `events` of the issue or pull-request at `url`
+    /// into comments, reviews and issue events. Other event types are left
+    /// out.
+    fn add_events(&mut self, kind: &str, url: &Value, events: &Value) {
+        for event in events.as_array().into_iter().flatten() {
+            let name = event["event"].as_str().unwrap_or_default();
+            match name {
+                "commented" => {
+                    let record = json!({
+                        "type": "issue_comment",
+                        "url": event["html_url"],
+                        kind: url,
+                        "user": self.user(&event["user"]),
+                        "body": event["body"],
+                        "formatter": "markdown",
+                        "created_at": event["created_at"],
+                    });
+                    self.issue_comments.push(record);
+                }
+                "reviewed" if kind == "pull_request" => {
+                    let record = json!({
+                        "type": "pull_request_review",
+                        "url": event["html_url"],
+                        "pull_request": url,
+                        "user": self.user(&event["user"]),
+                        "body": event["body"],
+                        "head_sha": event["commit_id"],
+                        "formatter": "markdown",
+                        "state": event["state"],
+                        "created_at": event["submitted_at"],
+                    });
+                    self.pull_request_reviews.push(record);
+                }
+                name if MIGRATED_EVENTS.contains(&name) => {
+                    let record = json!({
+                        "type": "issue_event",
+                        "url": format!("{}#event-{}", url_str(url), event["id"]),
+                        kind: url,
+                        "actor": self.user(&event["actor"]),
+                        "event": name,
+                        "commit_id": event["commit_id"],
+                        "label_name": event["label"]["name"],
+                        "label_color": event["label"]["color"],
+                        "milestone_title": event["milestone"]["title"],
+                        "title_was": event["rename"]["from"],
+                        "title_is": event["rename"]["to"],
+                        "subject": self.user(&event["assignee"]),
+                        "created_at": event["created_at"],
+                    });
+                    self.issue_events.push(record);
+                }
+                _ => (),
+            }
+        }
+    }
+
+    /// Writes the archive's JSON files to `output`.
+    pub fn write(self, output: &Path) -> Result<Vec<PathBuf>, WriteError> {
+        permissions::create_dir_all(output)?;
+        let mut written = vec![];
+        let mut write_json = |name: &str, value: &Value| -> Result<(), WriteError> {
+            let path = output.join(name);
+            permissions::write_atomically(&path, serde_json::to_string_pretty(value)?.as_bytes())?;
+            written.push(path);
+            Ok(())
+        };
+        write_json(
+            "schema.json",
+            &json!({ "version": MIGRATION_SCHEMA_VERSION }),
+        )?;
+        let users: Vec<Value> = self.users.into_values().collect();
+        for (name, records) in [
+            ("users", users),
+            ("issues", self.issues),
+            ("pull_requests", self.pull_requests),
+            ("issue_comments", self.issue_comments),
+            ("issue_events", self.issue_events),
+            ("pull_request_reviews", self.pull_request_reviews),
+            (
+                "pull_request_review_comments",
+                self.pull_request_review_comments,
+            ),
+        ] {
+            if !records.is_empty() {
+                write_json(&format!("{}_000001.json", name), &Value::Array(records))?;
+            }
+        }
+        Ok(written)
+    }
+}
+
+fn labels(repository: &str, labels: &Value) -> Vec<Value> {
+    labels
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|label| label["name"].as_str())
+        .map(|name| Value::String(format!("{}/labels/{}", repository, name)))
+        .collect()
+}
+
+fn milestone(repository: &str, milestone: &Value) -> Value {
+    match milestone["number"].as_u64() {
+        Some(number) => Value::String(format!("{}/milestones/{}", repository, number)),
+        None => Value::Null,
+    }
+}
+
+fn url_str(url: &Value) -> &str {
+    url.as_str().unwrap_or_default()
+}
+
+/// Derives the repository URL from the URL of an issue or pull-request, e.g.
+/// `https://github.com/owner/repo` from `https://github.com/owner/repo/issues/1`.
+fn repository_url(url: &Value) -> String {
+    let url = url_str(url);
+    match url.rsplitn(3, '/').nth(2) {
+        Some(repository) => repository.to_string(),
+        None => url.to_string(),
+    }
+}
+
+/// Reads the issue and pull-request files in the `issues_dir` and `pulls_dir`
+/// of the backup in `directory` into a [MigrationArchive]. Files that can't
+/// be read are skipped and returned.
+pub fn from_backup(
+    directory: &Path,
+    issues_dir: &str,
+    pulls_dir: &str,
+) -> std::io::Result<(MigrationArchive, Vec<PathBuf>)> {
+    let mut archive = MigrationArchive::default();
+    let mut skipped = vec![];
+    for dir in [issues_dir, pulls_dir] {
+        let dir = directory.join(dir);
+        if !dir.exists() {
+            continue;
+        }
+        let mut paths: Vec<PathBuf> = fs::read_dir(&dir)?
+            .map(|entry| entry.map(|e| e.path()))
+            .collect::<std::io::Result<_>>()?;
+        paths.retain(|path| path.extension().is_some_and(|ext| ext == "json"));
+        // Sort by number, so the records are in the order they were created.
+        paths.sort_by_key(|path| {
+            path.file_stem()
+                .and_then(|stem| stem.to_str())
+                .and_then(|stem| stem.parse::<u64>().ok())
+        });
+        for path in paths {
+            let file: Value = match fs::read_to_string(&path)
+                .ok()
+                .and_then(|contents| serde_json::from_str(&contents).ok())
+            {
+                Some(file) => file,
+                None => {
+                    skipped.push(path);
+                    continue;
+                }
+            };
+            match file["type"].as_str() {
+                Some("issue") => archive.add_issue(&file),
+                Some("pull") => archive.add_pull(&file),
+                _ => skipped.push(path),
+            }
+        }
+    }
+    Ok((archive, skipped))
+}
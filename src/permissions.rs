@@ -0,0 +1,122 @@
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::io::prelude::*;
+use std::io::BufWriter;
+use std::path::Path;
+use std::sync::OnceLock;
+
+/// The `--output-file-mode` and `--output-dir-mode`. Not set without them, in
+/// which case the modes follow the umask of the process.
+static MODES: OnceLock<Modes> = OnceLock::new();
+
+#[derive(Debug, Default)]
+struct Modes {
+    file: Option<u32>,
+    dir: Option<u32>,
+}
+
+/// Sets the modes of the created files and directories. Only supported on
+/// unix, returns false elsewhere.
+pub fn init(file: Option<u32>, dir: Option<u32>) -> bool {
+    MODES.get_or_init(|| Modes { file, dir });
+    cfg!(unix)
+}
+
+#[cfg(unix)]
+fn set_mode(path: &Path, mode: Option<u32>) -> io::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+
+    match mode {
+        Some(mode) => fs::set_permissions(path, fs::Permissions::from_mode(mode)),
+        None => Ok(()),
+    }
+}
+
+#[cfg(not(unix))]
+fn set_mode(_path: &Path, _mode: Option<u32>) -> io::Result<()> {
+    Ok(())
+}
+
+/// Creates (or truncates) the file at `path` with the `--output-file-mode`.
+/// The mode is kept when the file is renamed afterwards.
+pub fn create_file<P: AsRef<Path>>(path: P) -> io::Result<File> {
+    let path = path.as_ref();
+    let file = File::create(path)?;
+    set_mode(path, MODES.get().and_then(|modes| modes.file))?;
+    Ok(file)
+}
+
+/// Writes the `bytes` to a `.partial` file next to `path` and renames it to
+/// `path`, so an interrupted write can't leave a truncated file behind.
+pub fn write_atomically(path: &Path, bytes: &[u8]) -> io::Result<()> {
+    write_atomically_with(path, |file| file.write_all(bytes))
+}
+
+/// Like [write_atomically], but `write` writes the contents to the buffered
+/// `.partial` file, so they don't have to be held in memory.
+pub fn write_atomically_with<E: From<io::Error>>(
+    path: &Path,
+    write: impl FnOnce(&mut BufWriter<File>) -> Result<(), E>,
+) -> Result<(), E> {
+    let mut partial = path.as_os_str().to_owned();
+    partial.push(".partial");
+    let mut file = BufWriter::new(create_file(&partial)?);
+    write(&mut file)?;
+    file.into_inner().map_err(io::Error::from)?;
+    Ok(fs::rename(&partial, path)?)
+}
+
+/// Opens the file at `path` for appending, creating it with the
+/// `--output-file-mode` if it doesn't exist.
+pub fn append_file(path: &Path) -> io::Result<File> {
+    let exists = path.exists();
+    let file = fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)?;
+    if !exists {
+        set_mode(path, MODES.get().and_then(|modes| modes.file))?;
+    }
+    Ok(file)
+}
+
+/// Creates the directory at `path` and its missing parents, like
+/// [fs::create_dir_all]. The directories created here get the
+/// `--output-dir-mode`, existing ones are left as they are.
+pub fn create_dir_all<P: AsRef<Path>>(path: P) -> io::Result<()> {
+    let path = path.as_ref();
+    let mode = MODES.get().and_then(|modes| modes.dir);
+    if mode.is_none() || path.is_dir() {
+        return fs::create_dir_all(path);
+    }
+    let missing: Vec<&Path> = path
+        .ancestors()
+        .take_while(|ancestor| !ancestor.as_os_str().is_empty() && !ancestor.exists())
+        .collect();
+    fs::create_dir_all(path)?;
+    for dir in missing {
+        set_mode(dir, mode)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn write_atomically_replaces_the_file() {
+        let dir = std::env::temp_dir().join(format!(
+            "github-metadata-backup-permissions-{}",
+            std::process::id()
+        ));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("index.json");
+        write_atomically(&path, b"old").unwrap();
+        write_atomically(&path, b"new").unwrap();
+        assert_eq!(fs::read(&path).unwrap(), b"new");
+        assert!(!dir.join("index.json.partial").exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
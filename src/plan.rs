@@ -0,0 +1,122 @@
+use std::time::Duration;
+
+use crate::types::Args;
+
+/// Items per page of the REST API listings.
+const PER_PAGE: u64 = 100;
+
+/// A phase of a backup with the number of requests it's estimated to take.
+#[derive(Debug)]
+pub struct Phase {
+    pub name: &'static str,
+    pub requests: u64,
+    /// Set if the phase takes more requests for entries with many events,
+    /// comments or references, which can't be known without fetching them.
+    pub at_least: bool,
+    /// Set if the phase uses the GraphQL API, which has a separate
+    /// rate-limit.
+    pub graphql: bool,
+}
+
+impl Phase {
+    fn rest(name: &'static str, requests: u64, at_least: bool) -> Self {
+        Phase {
+            name,
+            requests,
+            at_least,
+            graphql: false,
+        }
+    }
+
+    fn graphql(name: &'static str, requests: u64, at_least: bool) -> Self {
+        Phase {
+            name,
+            requests,
+            at_least,
+            graphql: true,
+        }
+    }
+}
+
+/// Estimates the requests of a backup of `issues` issues and `pulls`
+/// pull-requests with the enabled flags. Each timeline, review comment list
+/// and GraphQL connection is counted as a single page. Phases that depend on
+/// the contents of the entries, like the reaction users, are counted with
+/// zero requests and marked as a lower bound.
+pub fn estimate(args: &Args, issues: u64, pulls: u64) -> Vec<Phase> {
+    let issues = if args.only_pulls { 0 } else { issues };
+    let pulls = if args.only_issues { 0 } else { pulls };
+    let entries = issues + pulls;
+    let mut phases = vec![
+        Phase::rest(
+            "listing issue pages",
+            entries.div_ceil(PER_PAGE).max(1),
+            false,
+        ),
+        Phase::rest("issue timelines", issues, true),
+        Phase::rest("pull-request bodies", pulls, false),
+        Phase::rest("pull-request timelines", pulls, true),
+        Phase::rest("review comments", pulls, true),
+        Phase::rest("reviews", pulls, true),
+    ];
+    if args.follow_renames {
+        phases.push(Phase::rest("repository lookup", 1, false));
+    }
+    if args.include_repo_metadata {
+        phases.push(Phase::rest("repository metadata", 3, true));
+    }
+    phases.push(Phase::rest("milestones", 1, true));
+    phases.push(Phase::rest("labels", 1, true));
+    if args.include_discussions {
+        phases.push(Phase::graphql("discussions", 1, true));
+    }
+    if args.detect_deletions {
+        phases.push(Phase::rest("deleted and transferred issues", 1, true));
+    }
+    if args.include_checks {
+        phases.push(Phase::rest("checks", pulls * 2, true));
+    }
+    if args.render_html {
+        phases.push(Phase::rest("rendered markdown", entries, true));
+    }
+    if args.include_reaction_users || args.with_reactions {
+        phases.push(Phase::rest("reaction users", 0, true));
+    }
+    if args.include_sub_issues {
+        phases.push(Phase::rest("sub-issues", 0, true));
+    }
+    if args.resolve_referencing_commits {
+        phases.push(Phase::rest("referencing commits", 0, true));
+    }
+    if args.resolve_cross_refs {
+        phases.push(Phase::graphql("cross-references", entries, true));
+    }
+    if args.include_links {
+        phases.push(Phase::graphql("links", entries, false));
+    }
+    if args.include_comment_moderation {
+        phases.push(Phase::graphql(
+            "comment moderation",
+            issues + pulls * 2,
+            true,
+        ));
+    }
+    phases
+}
+
+/// Estimates how long the rate-limit makes a backup of `requests` REST
+/// requests take, given the `remaining` requests of the current rate-limit
+/// window, which resets in `reset_in`, and the `limit` per hour. Ignores how
+/// long the requests themselves take.
+pub fn rate_limited_duration(
+    requests: u64,
+    remaining: u64,
+    limit: u64,
+    reset_in: Duration,
+) -> Duration {
+    if requests <= remaining || limit == 0 {
+        return Duration::ZERO;
+    }
+    let windows = (requests - remaining).div_ceil(limit);
+    reset_in + Duration::from_secs(3600 * (windows - 1))
+}
@@ -0,0 +1,149 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::OnceLock;
+use std::time::{Duration, Instant};
+
+/// The phases of a backup timed for `--profile`.
+#[derive(Debug, Clone, Copy)]
+pub enum Phase {
+    /// Listing the issues and pull-requests.
+    Listing,
+    /// Fetching the body of a single issue or pull-request.
+    Body,
+    Timeline,
+    /// Fetching the review comments of pull-requests.
+    Comments,
+    /// Any other requests, e.g. for the checks, links or rendered HTML.
+    Other,
+    /// Writing the files.
+    Write,
+}
+
+const PHASES: [Phase; 6] = [
+    Phase::Listing,
+    Phase::Body,
+    Phase::Timeline,
+    Phase::Comments,
+    Phase::Other,
+    Phase::Write,
+];
+
+impl Phase {
+    /// Returns the phase a REST API `route` is requested in.
+    pub fn of_route(route: &str) -> Phase {
+        let path = route.split('?').next().unwrap_or(route);
+        let segments: Vec<&str> = path.trim_end_matches('/').rsplit('/').take(3).collect();
+        match segments.as_slice() {
+            ["timeline", ..] => Phase::Timeline,
+            ["comments", _, "pulls"] => Phase::Comments,
+            ["issues", ..] => Phase::Listing,
+            [number, "issues" | "pulls", ..] if number.parse::<u64>().is_ok() => Phase::Body,
+            _ => Phase::Other,
+        }
+    }
+}
+
+impl fmt::Display for Phase {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Phase::Listing => "listing",
+            Phase::Body => "body",
+            Phase::Timeline => "timeline",
+            Phase::Comments => "comments",
+            Phase::Other => "other",
+            Phase::Write => "write",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Cumulative wall time and number of requests (or writes) of each phase.
+struct Counters {
+    started: Instant,
+    nanos: [AtomicU64; PHASES.len()],
+    counts: [AtomicU64; PHASES.len()],
+}
+
+static COUNTERS: OnceLock<Counters> = OnceLock::new();
+
+fn counters() -> &'static Counters {
+    COUNTERS.get_or_init(|| Counters {
+        started: Instant::now(),
+        nanos: Default::default(),
+        counts: Default::default(),
+    })
+}
+
+/// Starts the wall clock of the profile.
+pub fn start() {
+    counters();
+}
+
+/// Adds a request or write that took `elapsed` to the `phase`.
+pub fn record(phase: Phase, elapsed: Duration) {
+    let counters = counters();
+    counters.nanos[phase as usize].fetch_add(elapsed.as_nanos() as u64, Ordering::Relaxed);
+    counters.counts[phase as usize].fetch_add(1, Ordering::Relaxed);
+}
+
+#[derive(Serialize, Debug)]
+pub struct PhaseTiming {
+    /// Number of requests, or of files for the write phase.
+    pub count: u64,
+    /// Cumulative wall time. Concurrent requests are all counted.
+    pub secs: f64,
+}
+
+/// The timings of the phases so far.
+#[derive(Serialize, Debug)]
+pub struct Profile {
+    pub finished_at: DateTime<Utc>,
+    pub wall_secs: f64,
+    pub phases: BTreeMap<String, PhaseTiming>,
+}
+
+/// Returns the timings recorded since [start].
+pub fn snapshot() -> Profile {
+    let counters = counters();
+    let phases = PHASES
+        .iter()
+        .map(|phase| {
+            let timing = PhaseTiming {
+                count: counters.counts[*phase as usize].load(Ordering::Relaxed),
+                secs: counters.nanos[*phase as usize].load(Ordering::Relaxed) as f64 / 1e9,
+            };
+            (phase.to_string(), timing)
+        })
+        .collect();
+    Profile {
+        finished_at: Utc::now(),
+        wall_secs: counters.started.elapsed().as_secs_f64(),
+        phases,
+    }
+}
+
+impl fmt::Display for Profile {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "{:<10} {:>9} {:>10} {:>9}",
+            "phase", "requests", "time (s)", "avg (ms)"
+        )?;
+        for phase in PHASES {
+            let timing = &self.phases[&phase.to_string()];
+            let avg = match timing.count {
+                0 => 0.0,
+                count => timing.secs * 1000.0 / count as f64,
+            };
+            writeln!(
+                f,
+                "{:<10} {:>9} {:>10.1} {:>9.1}",
+                phase, timing.count, timing.secs, avg
+            )?;
+        }
+        write!(f, "total wall time: {:.1}s", self.wall_secs)
+    }
+}
@@ -0,0 +1,90 @@
+use chrono::{DateTime, Utc};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::fs;
+use std::io;
+use std::io::prelude::*;
+use std::path::{Path, PathBuf};
+
+use crate::permissions;
+use crate::types::WriteError;
+
+pub const PROGRESS_FILE: &str = "progress.json";
+
+/// The repositories completed so far by an `--all-repos` run, or one with
+/// several `--repo`s, as `owner/repo`. Written after
+/// each repository, so a run that is killed can be resumed, and removed once
+/// all repositories are backed up.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct BatchProgress {
+    /// UTC timestamp when the interrupted run started.
+    pub started_at: DateTime<Utc>,
+    pub completed: BTreeSet<String>,
+    #[serde(skip)]
+    path: PathBuf,
+}
+
+impl BatchProgress {
+    /// Loads the progress of an interrupted run from `destination`, or starts
+    /// a new batch. With `fresh`, the progress of an interrupted run is
+    /// discarded.
+    pub fn load(destination: &Path, fresh: bool) -> Self {
+        let path = destination.join(PROGRESS_FILE);
+        let loaded = match fs::read_to_string(&path) {
+            Ok(contents) => match serde_json::from_str::<BatchProgress>(&contents) {
+                Ok(progress) => Some(progress),
+                Err(e) => {
+                    warn!("Ignoring the unreadable {}: {}", path.display(), e);
+                    None
+                }
+            },
+            Err(e) if e.kind() == io::ErrorKind::NotFound => None,
+            Err(e) => {
+                warn!("Could not read {}: {}", path.display(), e);
+                None
+            }
+        };
+        match loaded {
+            Some(progress) if fresh => {
+                info!("Not resuming the run started {}.", progress.started_at);
+                Self::new(path)
+            }
+            Some(progress) => {
+                info!(
+                    "Resuming the run started {}: skipping {} completed repositories.",
+                    progress.started_at,
+                    progress.completed.len()
+                );
+                Self { path, ..progress }
+            }
+            None => Self::new(path),
+        }
+    }
+
+    fn new(path: PathBuf) -> Self {
+        Self {
+            started_at: Utc::now(),
+            completed: BTreeSet::new(),
+            path,
+        }
+    }
+
+    /// Records the completed `repo`.
+    pub fn complete(&mut self, repo: &str) -> Result<(), WriteError> {
+        self.completed.insert(repo.to_string());
+        let partial = self.path.with_extension("json.partial");
+        permissions::create_file(&partial)?
+            .write_all(serde_json::to_string_pretty(self)?.as_bytes())?;
+        fs::rename(&partial, &self.path)?;
+        Ok(())
+    }
+
+    /// Removes the progress file once the whole batch is backed up.
+    pub fn clear(&self) -> Result<(), WriteError> {
+        match fs::remove_file(&self.path) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        }
+    }
+}
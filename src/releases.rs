@@ -0,0 +1,176 @@
+use log::{debug, info, warn};
+use serde_json::Value;
+use std::fs;
+use std::io;
+use std::io::prelude::*;
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+use tokio::time::sleep;
+
+use crate::attachments::DownloadError;
+use crate::permissions;
+use crate::types::WriteError;
+
+pub const RELEASES_DIR: &str = "releases";
+pub const RELEASE_FILE: &str = "release.json";
+const ASSETS_DIR: &str = "assets";
+/// How long to wait for the connection and for each chunk of an asset. The
+/// download as a whole has no timeout, as assets can be large.
+const DOWNLOAD_TIMEOUT: Duration = Duration::from_secs(60);
+/// Backoff when a download is rate-limited without a `Retry-After`.
+const RATE_LIMIT_BACKOFF: Duration = Duration::from_secs(60);
+
+/// The bytes of release assets downloaded by all repositories of this run,
+/// counted against `--release-assets-max-bytes`.
+static DOWNLOADED_BYTES: AtomicU64 = AtomicU64::new(0);
+
+/// Downloads the assets of releases. Interrupted downloads are kept in a
+/// `.partial` file and continued with a range request by the next backup.
+pub struct AssetDownloader {
+    client: reqwest::Client,
+    token: String,
+    max_retries: u8,
+    max_bytes: Option<u64>,
+}
+
+impl AssetDownloader {
+    /// Creates a downloader sending the `token`, which the API requires for
+    /// the assets of private repositories. At most `max_bytes` are
+    /// downloaded over the whole run.
+    pub fn new(token: &str, max_retries: u8, max_bytes: Option<u64>) -> Result<Self, WriteError> {
+        let client = reqwest::Client::builder()
+            .connect_timeout(DOWNLOAD_TIMEOUT)
+            .read_timeout(DOWNLOAD_TIMEOUT)
+            .user_agent("github-metadata-backup")
+            .build()
+            .map_err(|e| io::Error::other(e.to_string()))?;
+        Ok(Self {
+            client,
+            token: token.to_string(),
+            max_retries,
+            max_bytes,
+        })
+    }
+
+    /// Downloads the assets of a `release` into the `assets/` directory in
+    /// `dir`, skipping the ones that are already complete. A failed download
+    /// is logged and continued with the next backup.
+    pub async fn download_release(&self, release: &Value, dir: &Path) -> Result<(), WriteError> {
+        let Some(assets) = release["assets"].as_array() else {
+            return Ok(());
+        };
+        let dir = dir.join(ASSETS_DIR);
+        permissions::create_dir_all(&dir)?;
+        for asset in assets {
+            let (Some(url), Some(name), Some(size)) = (
+                asset["url"].as_str(),
+                asset["name"].as_str(),
+                asset["size"].as_u64(),
+            ) else {
+                warn!("Skipping a release asset without url, name or size");
+                continue;
+            };
+            let path = dir.join(name.replace(['/', '\\'], "_"));
+            if fs::metadata(&path).is_ok_and(|m| m.len() == size) {
+                continue;
+            }
+            match self.download(url, &path, size).await {
+                Ok(true) => debug!("Downloaded {} to {}", url, path.display()),
+                Ok(false) => info!(
+                    "Skipping the release asset {}: --release-assets-max-bytes is reached",
+                    url
+                ),
+                Err(e) => warn!("Could not download the release asset {}: {}", url, e),
+            }
+        }
+        Ok(())
+    }
+
+    /// Downloads the asset at `url` into `path`, continuing a previous
+    /// `.partial` download. Returns `Ok(false)` if the asset doesn't fit into
+    /// the remaining `--release-assets-max-bytes`. A download that doesn't
+    /// end up with the expected `size` is started over once.
+    async fn download(&self, url: &str, path: &Path, size: u64) -> Result<bool, DownloadError> {
+        let mut partial_name = path.as_os_str().to_owned();
+        partial_name.push(".partial");
+        let partial = Path::new(&partial_name);
+        for restart in [false, true] {
+            let offset = match fs::metadata(partial) {
+                Ok(metadata) if !restart && metadata.len() <= size => metadata.len(),
+                Ok(_) => {
+                    fs::remove_file(partial)?;
+                    0
+                }
+                Err(_) => 0,
+            };
+            if let Some(max_bytes) = self.max_bytes {
+                if DOWNLOADED_BYTES.load(Ordering::Relaxed) + (size - offset) > max_bytes {
+                    return Ok(false);
+                }
+            }
+            let mut response = self.request(url, offset).await?;
+            let mut file = match response.status() {
+                reqwest::StatusCode::PARTIAL_CONTENT => permissions::append_file(partial)?,
+                reqwest::StatusCode::RANGE_NOT_SATISFIABLE => continue,
+                _ => permissions::create_file(partial)?,
+            };
+            while let Some(chunk) = response.chunk().await? {
+                file.write_all(&chunk)?;
+                DOWNLOADED_BYTES.fetch_add(chunk.len() as u64, Ordering::Relaxed);
+            }
+            drop(file);
+            let downloaded = fs::metadata(partial)?.len();
+            if downloaded == size {
+                fs::rename(partial, path)?;
+                return Ok(true);
+            }
+            warn!(
+                "Downloaded {} bytes of {}, but expected {} bytes",
+                downloaded, url, size
+            );
+        }
+        let _ = fs::remove_file(partial);
+        Err(io::Error::other("the downloaded size doesn't match the asset's size").into())
+    }
+
+    /// Requests the asset at `url` from the byte `offset` on. Rate-limited
+    /// requests are retried after the `Retry-After` time.
+    async fn request(&self, url: &str, offset: u64) -> Result<reqwest::Response, DownloadError> {
+        let mut attempt = 0;
+        loop {
+            let mut request = self
+                .client
+                .get(url)
+                .bearer_auth(&self.token)
+                .header(reqwest::header::ACCEPT, "application/octet-stream");
+            if offset > 0 {
+                request = request.header(reqwest::header::RANGE, format!("bytes={}-", offset));
+            }
+            let response = request.send().await?;
+            let status = response.status();
+            if status == reqwest::StatusCode::RANGE_NOT_SATISFIABLE {
+                return Ok(response);
+            }
+            let rate_limited = status == reqwest::StatusCode::TOO_MANY_REQUESTS
+                || (status == reqwest::StatusCode::FORBIDDEN
+                    && response.headers().contains_key("retry-after"));
+            if !rate_limited || attempt >= self.max_retries {
+                return Ok(response.error_for_status()?);
+            }
+            let wait = response
+                .headers()
+                .get("retry-after")
+                .and_then(|value| value.to_str().ok())
+                .and_then(|value| value.parse().ok())
+                .map(Duration::from_secs)
+                .unwrap_or(RATE_LIMIT_BACKOFF);
+            info!(
+                "Downloading release assets is rate-limited. Waiting {} seconds.",
+                wait.as_secs()
+            );
+            sleep(wait).await;
+            attempt += 1;
+        }
+    }
+}
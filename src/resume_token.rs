@@ -0,0 +1,34 @@
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use std::io::prelude::*;
+
+use crate::types::{BackupState, WriteError};
+
+/// Encodes the `state` into an opaque `--resume-token`: the gzip-compressed
+/// JSON of the state, base64url-encoded without padding. The format isn't
+/// stable and can change with any version.
+pub fn encode(state: &BackupState) -> Result<String, WriteError> {
+    let mut encoder = GzEncoder::new(vec![], Compression::best());
+    encoder.write_all(&serde_json::to_vec(state)?)?;
+    Ok(URL_SAFE_NO_PAD.encode(encoder.finish()?))
+}
+
+/// Decodes a `--resume-token` into the JSON of the state it holds. An empty
+/// token holds no state and starts a full backup.
+pub fn decode(token: &str) -> Result<Option<String>, String> {
+    let token = token.trim();
+    if token.is_empty() {
+        return Ok(None);
+    }
+    let compressed = URL_SAFE_NO_PAD
+        .decode(token)
+        .map_err(|e| format!("not a resume token: {}", e))?;
+    let mut json = String::new();
+    GzDecoder::new(compressed.as_slice())
+        .read_to_string(&mut json)
+        .map_err(|e| format!("not a resume token: {}", e))?;
+    Ok(Some(json))
+}
@@ -0,0 +1,60 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::fs::File;
+use std::io;
+use std::io::prelude::*;
+use std::path::Path;
+
+use crate::permissions;
+use crate::types::BackupSummary;
+
+pub const RUN_LOG_FILE: &str = "runs.jsonl";
+
+/// A completed backup of a repository as appended to the [RUN_LOG_FILE], one
+/// per line.
+#[derive(Serialize, Debug)]
+struct RunRecord<'a> {
+    /// Version of github-metadata-backup.
+    tool_version: &'static str,
+    finished_at: DateTime<Utc>,
+    /// The exit code of the backup, 0 if it succeeded.
+    exit_code: u8,
+    #[serde(flatten)]
+    summary: &'a BackupSummary,
+}
+
+/// Appends the `summary` of a completed backup to the [RUN_LOG_FILE] in
+/// `destination`. The line is written with a single write while holding an
+/// exclusive lock on the file, so that the backups of `--parallel-repos` and
+/// of other processes don't interleave their lines.
+pub fn append(destination: &Path, summary: &BackupSummary, exit_code: u8) -> io::Result<()> {
+    let record = RunRecord {
+        tool_version: env!("CARGO_PKG_VERSION"),
+        finished_at: Utc::now(),
+        exit_code,
+        summary,
+    };
+    let mut line = serde_json::to_vec(&record)?;
+    line.push(b'\n');
+    permissions::create_dir_all(destination)?;
+    let mut file = permissions::append_file(&destination.join(RUN_LOG_FILE))?;
+    lock(&file)?;
+    // The lock is released when the file is closed.
+    file.write_all(&line)
+}
+
+#[cfg(unix)]
+fn lock(file: &File) -> io::Result<()> {
+    use std::os::unix::io::AsRawFd;
+
+    if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// Without `flock`, only the appending single write keeps the lines apart.
+#[cfg(not(unix))]
+fn lock(_file: &File) -> io::Result<()> {
+    Ok(())
+}
@@ -0,0 +1,112 @@
+use octocrab::models::{issues, pulls, timelines};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::Value;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::fidelity;
+
+/// Result of re-loading the files of a backup into the current models.
+#[derive(Debug, Default)]
+pub struct Report {
+    /// Number of issue and pull-request files checked.
+    pub checked: usize,
+    /// The files that can't be loaded, with the problems found in each.
+    pub incompatible: Vec<(PathBuf, Vec<String>)>,
+}
+
+/// Re-loads every issue and pull-request file in the `issues_dir` and
+/// `pulls_dir` directories below `directory` into the current octocrab
+/// models. A file is incompatible if it can't be deserialized or if fields
+/// would be dropped, as with `--strict`. Files without an issue or pull-request `type`, e.g. those
+/// of `--dump-raw-dir`, are skipped.
+pub fn self_test(directory: &Path, issues_dir: &str, pulls_dir: &str) -> io::Result<Report> {
+    let mut report = Report::default();
+    let mut dirs = vec![directory.to_path_buf()];
+    while let Some(dir) = dirs.pop() {
+        let is_entry_dir = dir
+            .file_name()
+            .is_some_and(|name| name == issues_dir || name == pulls_dir);
+        let mut entries: Vec<PathBuf> = fs::read_dir(&dir)?
+            .map(|entry| entry.map(|e| e.path()))
+            .collect::<io::Result<_>>()?;
+        entries.sort();
+        for path in entries {
+            if path.is_dir() {
+                dirs.push(path);
+            } else if is_entry_dir && path.extension().is_some_and(|ext| ext == "json") {
+                if let Some(problems) = check_file(&path) {
+                    report.checked += 1;
+                    if !problems.is_empty() {
+                        report.incompatible.push((path, problems));
+                    }
+                }
+            }
+        }
+    }
+    report.incompatible.sort();
+    Ok(report)
+}
+
+/// Returns the problems found in an issue or pull-request file, or None if it
+/// isn't one.
+fn check_file(path: &Path) -> Option<Vec<String>> {
+    let file: Value = match fs::read_to_string(path)
+        .map_err(|e| e.to_string())
+        .and_then(|contents| serde_json::from_str(&contents).map_err(|e| e.to_string()))
+    {
+        Ok(file) => file,
+        Err(e) => return Some(vec![format!("could not be read: {}", e)]),
+    };
+
+    let mut problems = Vec::new();
+    match file["type"].as_str() {
+        Some("issue") => {
+            check::<issues::Issue>("issue", &file["issue"], &mut problems);
+        }
+        Some("pull") => {
+            check::<pulls::PullRequest>("pull", &file["pull"], &mut problems);
+            check_all::<pulls::Comment>("comments", &file["comments"], &mut problems);
+        }
+        _ => return None,
+    }
+    check_all::<timelines::TimelineEvent>("events", &file["events"], &mut problems);
+    Some(problems)
+}
+
+fn check<T: DeserializeOwned + Serialize>(what: &str, value: &Value, problems: &mut Vec<String>) {
+    let typed: T = match serde_json::from_value(value.clone()) {
+        Ok(typed) => typed,
+        Err(e) => {
+            problems.push(format!("{} could not be deserialized: {}", what, e));
+            return;
+        }
+    };
+    match serde_json::to_value(&typed) {
+        Ok(reserialized) => {
+            let dropped = fidelity::dropped_fields(value, &reserialized);
+            if !dropped.is_empty() {
+                problems.push(format!("{} would lose: {}", what, dropped.join(", ")));
+            }
+        }
+        Err(e) => problems.push(format!("{} could not be serialized: {}", what, e)),
+    }
+}
+
+fn check_all<T: DeserializeOwned + Serialize>(
+    what: &str,
+    values: &Value,
+    problems: &mut Vec<String>,
+) {
+    match values {
+        Value::Array(values) => {
+            for (i, value) in values.iter().enumerate() {
+                check::<T>(&format!("{}[{}]", what, i), value, problems);
+            }
+        }
+        Value::Null => (),
+        _ => problems.push(format!("{} is not a list", what)),
+    }
+}
@@ -0,0 +1,137 @@
+use log::{info, warn};
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::io::prelude::*;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use crate::permissions;
+use crate::sink::Sink;
+use crate::transform;
+use crate::types::{EntryWithMetadata, WriteError};
+
+pub const SINGLE_FILE: &str = "backup.json";
+/// Number of entries above which holding them all in memory is warned about.
+const WARN_ENTRIES: usize = 5_000;
+
+#[derive(Debug, Default)]
+struct Entries {
+    issues: BTreeMap<u64, Value>,
+    pulls: BTreeMap<u64, Value>,
+}
+
+impl Entries {
+    /// Loads the entries of the `backup.json` of a previous backup from
+    /// `destination`, so that an incremental backup only replaces the updated
+    /// entries. Starts empty if there is none.
+    fn load(destination: &Path) -> io::Result<Self> {
+        let path = destination.join(SINGLE_FILE);
+        let contents = match fs::read_to_string(&path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(e) => return Err(e),
+        };
+        let mut file: Value = serde_json::from_str(&contents)?;
+        let mut loaded = Self::default();
+        for (key, model, entries) in [
+            ("issues", "issue", &mut loaded.issues),
+            ("pulls", "pull", &mut loaded.pulls),
+        ] {
+            if let Value::Array(values) = file[key].take() {
+                for value in values {
+                    if let Some(number) = transform::entry_number(&value, model) {
+                        entries.insert(number, value);
+                    }
+                }
+            }
+        }
+        info!(
+            "Loaded {} issues and {} pull-requests from {}",
+            loaded.issues.len(),
+            loaded.pulls.len(),
+            path.display()
+        );
+        Ok(loaded)
+    }
+
+    /// Adds or replaces the serialized entry with the `number`. The `type` is
+    /// either "issue" or "pull".
+    fn insert(&mut self, r#type: &str, number: u64, value: Value) {
+        let entries = match r#type {
+            "pull" => &mut self.pulls,
+            _ => &mut self.issues,
+        };
+        entries.insert(number, value);
+        if self.issues.len() + self.pulls.len() == WARN_ENTRIES {
+            warn!(
+                "Holding more than {} issues and pull-requests in memory for --output-single-file. Consider writing a file per entry for large repositories.",
+                WARN_ENTRIES
+            );
+        }
+    }
+
+    /// Writes all entries and the `state` to `backup.json` in `destination`.
+    /// The file is replaced only once it's completely written.
+    fn write(&self, destination: &Path, state: &Value) -> Result<(), WriteError> {
+        let document = json!({
+            "issues": self.issues.values().collect::<Vec<_>>(),
+            "pulls": self.pulls.values().collect::<Vec<_>>(),
+            "state": state,
+        });
+        let path = destination.join(SINGLE_FILE);
+        let partial = destination.join(format!("{}.partial", SINGLE_FILE));
+        let mut file = permissions::create_file(&partial)?;
+        file.write_all(serde_json::to_string_pretty(&document)?.as_bytes())?;
+        fs::rename(&partial, &path)?;
+        info!(
+            "Written {} issues and {} pull-requests to {}",
+            self.issues.len(),
+            self.pulls.len(),
+            path.display()
+        );
+        Ok(())
+    }
+}
+
+/// Collects the serialized issues and pull-requests of a backup in memory, to
+/// write them as a single `backup.json` once the backup is done.
+pub struct SingleFileSink {
+    destination: PathBuf,
+    entries: Mutex<Entries>,
+}
+
+impl SingleFileSink {
+    /// Loads the entries of the `backup.json` of a previous backup from
+    /// `destination`, so that an incremental backup only replaces the updated
+    /// entries.
+    pub fn load(destination: &Path) -> io::Result<Self> {
+        Ok(Self {
+            destination: destination.to_path_buf(),
+            entries: Mutex::new(Entries::load(destination)?),
+        })
+    }
+
+    fn entries(&self) -> std::sync::MutexGuard<'_, Entries> {
+        self.entries
+            .lock()
+            .expect("the single file entries are never poisoned")
+    }
+}
+
+impl Sink for SingleFileSink {
+    fn name(&self) -> &'static str {
+        "single-file"
+    }
+
+    fn write(&self, entry: &EntryWithMetadata, value: &Value) -> Result<(), WriteError> {
+        self.entries()
+            .insert(entry.type_name(), entry.number(), value.clone());
+        Ok(())
+    }
+
+    fn finish(&self, state: &Value) -> Result<(), WriteError> {
+        self.entries().write(&self.destination, state)
+    }
+}
@@ -0,0 +1,254 @@
+use log::{debug, warn};
+use serde_json::Value;
+use std::fs::File;
+use std::io::prelude::*;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use crate::permissions;
+use crate::types::{EntryWithMetadata, Header, Serialization, WriteError};
+
+pub const NDJSON_FILE: &str = "entries.ndjson";
+
+/// How many issue or pull-request numbers each subdirectory of
+/// `--layout sharded` holds.
+pub const SHARD_SIZE: u64 = 1000;
+
+/// The subdirectory of `--layout sharded` holding the file of entry `number`,
+/// e.g. `1000-1999`.
+pub fn shard_dir(number: u64) -> String {
+    let start = number / SHARD_SIZE * SHARD_SIZE;
+    format!("{:04}-{:04}", start, start + SHARD_SIZE - 1)
+}
+
+/// Encodes a serialized entry in the `serialization` format. JSON is
+/// pretty-printed, like the other files of a backup.
+pub fn encode(value: &Value, serialization: Serialization) -> Result<Vec<u8>, WriteError> {
+    let mut bytes = vec![];
+    encode_into(value, serialization, &mut bytes)?;
+    Ok(bytes)
+}
+
+/// Encodes a serialized entry in the `serialization` format into the
+/// `writer`, without holding the encoded entry in memory.
+pub fn encode_into<W: Write>(
+    value: &Value,
+    serialization: Serialization,
+    mut writer: W,
+) -> Result<(), WriteError> {
+    match serialization {
+        Serialization::Json => Ok(serde_json::to_writer_pretty(writer, value)?),
+        Serialization::Messagepack => rmp_serde::encode::write_named(&mut writer, value)
+            .map_err(|e| WriteError::EncodingError(e.to_string())),
+        Serialization::Cbor => ciborium::into_writer(value, writer)
+            .map_err(|e| WriteError::EncodingError(e.to_string())),
+    }
+}
+
+/// Decodes an entry file in the `serialization` format.
+pub fn decode(bytes: &[u8], serialization: Serialization) -> Result<Value, WriteError> {
+    match serialization {
+        Serialization::Json => Ok(serde_json::from_slice(bytes)?),
+        Serialization::Messagepack => {
+            rmp_serde::from_slice(bytes).map_err(|e| WriteError::EncodingError(e.to_string()))
+        }
+        Serialization::Cbor => {
+            ciborium::from_reader(bytes).map_err(|e| WriteError::EncodingError(e.to_string()))
+        }
+    }
+}
+
+/// A destination the writer writes the serialized issues and pull-requests
+/// to. Entries can be written from multiple threads at the same time, see
+/// `--max-concurrent-writes`.
+pub trait Sink: Send + Sync {
+    /// Name of the sink in the logs, as selected with `--sink`.
+    fn name(&self) -> &'static str;
+
+    /// Writes the serialized `value` of the `entry`.
+    fn write(&self, entry: &EntryWithMetadata, value: &Value) -> Result<(), WriteError>;
+
+    /// Called once all entries of a backup are written, with the `state` of
+    /// the backup. `null` with `--no-state`.
+    fn finish(&self, _state: &Value) -> Result<(), WriteError> {
+        Ok(())
+    }
+}
+
+/// Appends each entry as a line to `entries.ndjson`.
+pub struct NdjsonSink {
+    path: PathBuf,
+    file: Mutex<File>,
+}
+
+impl NdjsonSink {
+    pub fn open(destination: &Path) -> Result<Self, WriteError> {
+        let path = destination.join(NDJSON_FILE);
+        let file = permissions::append_file(&path)?;
+        Ok(Self {
+            path,
+            file: Mutex::new(file),
+        })
+    }
+}
+
+impl Sink for NdjsonSink {
+    fn name(&self) -> &'static str {
+        "ndjson"
+    }
+
+    fn write(&self, entry: &EntryWithMetadata, value: &Value) -> Result<(), WriteError> {
+        let mut line = serde_json::to_string(value)?;
+        line.push('\n');
+        // A single write per line, so lines of concurrent writes don't mix.
+        self.file
+            .lock()
+            .expect("the ndjson file is never poisoned")
+            .write_all(line.as_bytes())?;
+        debug!("Appended {} to {}", entry, self.path.display());
+        Ok(())
+    }
+
+    fn finish(&self, _state: &Value) -> Result<(), WriteError> {
+        self.file
+            .lock()
+            .expect("the ndjson file is never poisoned")
+            .sync_all()?;
+        Ok(())
+    }
+}
+
+/// How often a batch is POSTed to the `--http-sink-url` before giving up.
+const HTTP_SINK_ATTEMPTS: u32 = 3;
+/// How long a POST of a batch may take.
+const HTTP_SINK_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// POSTs the entries in batches of `--http-sink-batch-size` as a JSON array
+/// to the `--http-sink-url`. A batch is sent by the write that fills it, which
+/// blocks that writer until the endpoint accepted it.
+pub struct HttpSink {
+    url: String,
+    headers: Vec<Header>,
+    batch_size: usize,
+    client: reqwest::Client,
+    batch: Mutex<Vec<Value>>,
+}
+
+impl HttpSink {
+    pub fn new(url: &str, headers: &[Header], batch_size: u64) -> Result<Self, WriteError> {
+        let client = reqwest::Client::builder()
+            .timeout(HTTP_SINK_TIMEOUT)
+            .build()
+            .map_err(|e| WriteError::HttpError(e.to_string()))?;
+        Ok(Self {
+            url: url.to_string(),
+            headers: headers.to_vec(),
+            batch_size: batch_size as usize,
+            client,
+            batch: Mutex::new(vec![]),
+        })
+    }
+
+    /// POSTs the `entries`, retrying failed attempts after 1s and 2s. Blocks
+    /// the calling thread, which is either a blocking writer thread or, when
+    /// finishing, a worker of the runtime.
+    fn post(&self, entries: &[Value]) -> Result<(), WriteError> {
+        let handle = tokio::runtime::Handle::current();
+        tokio::task::block_in_place(|| {
+            handle.block_on(async {
+                let mut attempt = 1;
+                loop {
+                    let mut request = self.client.post(&self.url).json(entries);
+                    for header in self.headers.iter() {
+                        request = request.header(header.name.clone(), &header.value);
+                    }
+                    let result = match request.send().await {
+                        Ok(response) if response.status().is_success() => return Ok(()),
+                        Ok(response) => {
+                            format!("the endpoint responded with {}", response.status())
+                        }
+                        Err(e) => e.to_string(),
+                    };
+                    if attempt == HTTP_SINK_ATTEMPTS {
+                        return Err(WriteError::HttpError(result));
+                    }
+                    warn!(
+                        "Could not POST {} entries to {} (attempt {} of {}): {}",
+                        entries.len(),
+                        self.url,
+                        attempt,
+                        HTTP_SINK_ATTEMPTS,
+                        result
+                    );
+                    tokio::time::sleep(Duration::from_secs(1 << (attempt - 1))).await;
+                    attempt += 1;
+                }
+            })
+        })
+    }
+
+    /// POSTs the entries collected so far. If the endpoint doesn't accept
+    /// them, they are kept to be sent again with the next batch.
+    fn flush(&self, min_size: usize) -> Result<(), WriteError> {
+        let entries = {
+            let mut batch = self.batch.lock().expect("the http batch is never poisoned");
+            if batch.is_empty() || batch.len() < min_size {
+                return Ok(());
+            }
+            std::mem::take(&mut *batch)
+        };
+        match self.post(&entries) {
+            Ok(()) => {
+                debug!("POSTed {} entries to {}", entries.len(), self.url);
+                Ok(())
+            }
+            Err(e) => {
+                let mut batch = self.batch.lock().expect("the http batch is never poisoned");
+                let newer = std::mem::replace(&mut *batch, entries);
+                batch.extend(newer);
+                Err(e)
+            }
+        }
+    }
+}
+
+impl Sink for HttpSink {
+    fn name(&self) -> &'static str {
+        "http"
+    }
+
+    fn write(&self, _entry: &EntryWithMetadata, value: &Value) -> Result<(), WriteError> {
+        self.batch
+            .lock()
+            .expect("the http batch is never poisoned")
+            .push(value.clone());
+        self.flush(self.batch_size)
+    }
+
+    fn finish(&self, _state: &Value) -> Result<(), WriteError> {
+        self.flush(1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn formats_round_trip() {
+        let value: Value =
+            serde_json::from_str(include_str!("../tests/fixtures/issue.json")).unwrap();
+        for format in Serialization::ALL {
+            let bytes = encode(&value, format).unwrap();
+            assert_eq!(decode(&bytes, format).unwrap(), value, "{:?}", format);
+        }
+    }
+
+    #[test]
+    fn shard_dirs() {
+        assert_eq!(shard_dir(0), "0000-0999");
+        assert_eq!(shard_dir(1234), "1000-1999");
+        assert_eq!(shard_dir(12345), "12000-12999");
+    }
+}
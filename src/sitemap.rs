@@ -0,0 +1,127 @@
+use chrono::{DateTime, Utc};
+use log::info;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::io::prelude::*;
+use std::path::{Path, PathBuf};
+
+use crate::index::Index;
+use crate::permissions;
+use crate::types::{EntryWithMetadata, WriteError};
+
+/// An issue or pull-request in the `--emit-sitemap` file.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SitemapEntry {
+    /// The repository as `owner/repo`.
+    pub repository: String,
+    /// Either "issue" or "pull".
+    pub kind: String,
+    pub number: u64,
+    pub title: String,
+    /// The original page on GitHub.
+    pub html_url: Option<String>,
+    /// The file the entry was written to.
+    pub path: PathBuf,
+    pub created_at: Option<DateTime<Utc>>,
+    pub updated_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct SitemapFile {
+    generated_at: DateTime<Utc>,
+    entries: Vec<SitemapEntry>,
+}
+
+/// All entries written to the backups, merged with the `--emit-sitemap` file
+/// of previous backups so that an incremental backup keeps the entries that
+/// weren't updated.
+pub struct Sitemap {
+    path: PathBuf,
+    entries: BTreeMap<(String, String, u64), SitemapEntry>,
+}
+
+impl Sitemap {
+    /// Loads the sitemap at `path`. Starts empty if there is none.
+    pub fn load(path: &Path) -> Result<Self, WriteError> {
+        let entries = match fs::read_to_string(path) {
+            Ok(contents) => serde_json::from_str::<SitemapFile>(&contents)?
+                .entries
+                .into_iter()
+                .map(|entry| {
+                    let key = (entry.repository.clone(), entry.kind.clone(), entry.number);
+                    (key, entry)
+                })
+                .collect(),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => BTreeMap::new(),
+            Err(e) => return Err(e.into()),
+        };
+        Ok(Self {
+            path: path.to_path_buf(),
+            entries,
+        })
+    }
+
+    /// Adds the entry of `repository` written to the file at `path`,
+    /// replacing an older version of it.
+    pub fn insert(&mut self, repository: &str, x: &EntryWithMetadata, path: PathBuf) {
+        let entry = match x {
+            EntryWithMetadata::Issue(i) => SitemapEntry {
+                repository: repository.to_string(),
+                kind: x.type_name().to_string(),
+                number: i.issue.number,
+                title: i.issue.title.clone(),
+                html_url: Some(i.issue.html_url.to_string()),
+                path,
+                created_at: Some(i.issue.created_at),
+                updated_at: Some(i.issue.updated_at),
+            },
+            EntryWithMetadata::Pull(p) => SitemapEntry {
+                repository: repository.to_string(),
+                kind: x.type_name().to_string(),
+                number: p.pull.number,
+                title: p.pull.title.clone().unwrap_or_default(),
+                html_url: p.pull.html_url.as_ref().map(|url| url.to_string()),
+                path,
+                created_at: p.pull.created_at,
+                updated_at: p.pull.updated_at,
+            },
+        };
+        let key = (entry.repository.clone(), entry.kind.clone(), entry.number);
+        self.entries.insert(key, entry);
+    }
+
+    /// Drops the entries of `repository` that are no longer in its index,
+    /// e.g. because they were deleted or transferred.
+    pub fn retain(&mut self, repository: &str, index: &Index) {
+        self.entries.retain(|(repo, kind, number), _| {
+            repo != repository
+                || match kind.as_str() {
+                    "pull" => index.pulls.contains_key(number),
+                    _ => index.issues.contains_key(number),
+                }
+        });
+    }
+
+    /// Writes the entries sorted by repository, kind and number.
+    pub fn write(&self) -> Result<(), WriteError> {
+        let file = SitemapFile {
+            generated_at: Utc::now(),
+            entries: self.entries.values().cloned().collect(),
+        };
+        if let Some(parent) = self.path.parent() {
+            permissions::create_dir_all(parent)?;
+        }
+        let partial = self.path.with_extension("partial");
+        permissions::create_file(&partial)?
+            .write_all(serde_json::to_string_pretty(&file)?.as_bytes())?;
+        fs::rename(&partial, &self.path)?;
+        info!(
+            "Written {} entries to the sitemap {}",
+            self.entries.len(),
+            self.path.display()
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,147 @@
+use log::info;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tokio::time::sleep;
+
+/// How long the rate has to stay free of secondary rate-limits before it's
+/// raised again. Also the window the request rate is measured over.
+const QUIET_PERIOD: Duration = Duration::from_secs(60);
+/// Requests per second added to the rate after each quiet period.
+const RATE_INCREASE: f64 = 1.0;
+/// The rate is never lowered below this many requests per second.
+const MIN_RATE: f64 = 0.2;
+/// The assumed request rate before the first secondary rate-limit, if it
+/// came too early to measure it.
+const UNMEASURED_RATE: f64 = 10.0;
+/// Secondary rate-limits within this time of the last decrease are hit by
+/// requests sent before it, so they don't lower the rate again.
+const DECREASE_COOLDOWN: Duration = Duration::from_secs(10);
+
+/// Adapts the request rate to GitHub's secondary rate-limits for
+/// `--throttle-on-abuse-detection`: each secondary rate-limit halves it, and
+/// every quiet period without one raises it by a fixed step, until it's back
+/// at the rate the requests were sent at before.
+struct Controller {
+    /// The requests per second, or None while not throttling.
+    rate: Option<f64>,
+    /// The measured rate before the first secondary rate-limit. Throttling
+    /// stops once the rate is raised back to it.
+    ceiling: f64,
+    /// When the next request may be sent.
+    next_slot: Option<Instant>,
+    /// When the rate was last changed.
+    changed_at: Option<Instant>,
+    /// Start of the window the unthrottled request rate is measured over.
+    window_start: Option<Instant>,
+    /// Number of requests sent since `window_start`.
+    window_requests: u32,
+}
+
+static CONTROLLER: Mutex<Controller> = Mutex::new(Controller {
+    rate: None,
+    ceiling: 0.0,
+    next_slot: None,
+    changed_at: None,
+    window_start: None,
+    window_requests: 0,
+});
+
+impl Controller {
+    /// The unthrottled request rate since the start of the window.
+    fn measured_rate(&self, now: Instant) -> f64 {
+        let elapsed = self
+            .window_start
+            .map_or(Duration::ZERO, |start| now - start);
+        match elapsed >= Duration::from_secs(1) {
+            true => self.window_requests as f64 / elapsed.as_secs_f64(),
+            false => UNMEASURED_RATE,
+        }
+    }
+
+    /// Raises the rate by a step if there was no secondary rate-limit for a
+    /// quiet period, and stops throttling once it's back at the ceiling.
+    fn raise_if_quiet(&mut self, now: Instant) {
+        let (Some(rate), Some(changed_at)) = (self.rate, self.changed_at) else {
+            return;
+        };
+        if now - changed_at < QUIET_PERIOD {
+            return;
+        }
+        let raised = rate + RATE_INCREASE;
+        self.changed_at = Some(now);
+        if raised >= self.ceiling {
+            self.rate = None;
+            self.window_start = None;
+            info!(
+                "No secondary rate-limit for {} seconds: no longer throttling the requests",
+                QUIET_PERIOD.as_secs()
+            );
+        } else {
+            self.rate = Some(raised);
+            info!(
+                "No secondary rate-limit for {} seconds: raising the request rate to {:.1} per second",
+                QUIET_PERIOD.as_secs(),
+                raised
+            );
+        }
+    }
+}
+
+/// Waits until the next request may be sent at the current rate. Doesn't
+/// wait while not throttling.
+pub async fn pace() {
+    let wait = {
+        let mut controller = CONTROLLER.lock().unwrap();
+        let now = Instant::now();
+        controller.raise_if_quiet(now);
+        match controller.rate {
+            None => {
+                if controller
+                    .window_start
+                    .is_none_or(|start| now - start > QUIET_PERIOD)
+                {
+                    controller.window_start = Some(now);
+                    controller.window_requests = 0;
+                }
+                controller.window_requests += 1;
+                None
+            }
+            Some(rate) => {
+                let slot = controller.next_slot.map_or(now, |next| next.max(now));
+                controller.next_slot = Some(slot + Duration::from_secs_f64(1.0 / rate));
+                Some(slot - now)
+            }
+        }
+    };
+    if let Some(wait) = wait.filter(|wait| !wait.is_zero()) {
+        sleep(wait).await;
+    }
+}
+
+/// Halves the request rate after a secondary rate-limit. The first one
+/// starts throttling at half the rate measured before.
+pub fn on_secondary_rate_limit() {
+    let mut controller = CONTROLLER.lock().unwrap();
+    let now = Instant::now();
+    if controller.rate.is_some()
+        && controller
+            .changed_at
+            .is_some_and(|changed_at| now - changed_at < DECREASE_COOLDOWN)
+    {
+        return;
+    }
+    let rate = match controller.rate {
+        Some(rate) => rate / 2.0,
+        None => {
+            controller.ceiling = controller.measured_rate(now);
+            controller.ceiling / 2.0
+        }
+    }
+    .max(MIN_RATE);
+    controller.rate = Some(rate);
+    controller.changed_at = Some(now);
+    info!(
+        "Secondary rate-limit hit: lowering the request rate to {:.1} per second",
+        rate
+    );
+}
@@ -0,0 +1,132 @@
+use log::info;
+use octocrab::Octocrab;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, OnceLock};
+use std::time::{Duration, SystemTime};
+
+/// The tokens of `--token-pool-file`. Not set without it, in which case the
+/// global octocrab instance is used.
+static POOL: OnceLock<Vec<PooledToken>> = OnceLock::new();
+
+/// A token of the pool with the last rate-limit quota GitHub reported for it.
+struct PooledToken {
+    instance: Arc<Octocrab>,
+    /// Requests left in the core rate-limit. Unknown until the first
+    /// response, which counts as a full quota.
+    remaining: AtomicU64,
+    /// Unix time when the core rate-limit resets.
+    reset: AtomicU64,
+}
+
+/// The octocrab instance a request is sent with.
+pub struct Client {
+    /// Index of the token in the pool. None without a pool.
+    token: Option<usize>,
+    pub instance: Arc<Octocrab>,
+}
+
+impl Client {
+    /// Records the rate-limit quota of the token from the headers of a
+    /// response. Only the core rate-limit of the REST API is tracked.
+    pub fn record(&self, response: &http::Response<impl http_body::Body>) {
+        let (Some(token), Some(pool)) = (self.token, POOL.get()) else {
+            return;
+        };
+        let header = |name: &str| {
+            response
+                .headers()
+                .get(name)
+                .and_then(|v| v.to_str().ok())
+                .map(str::to_string)
+        };
+        if header("x-ratelimit-resource").is_some_and(|resource| resource != "core") {
+            return;
+        }
+        let number = |name: &str| header(name).and_then(|v| v.parse::<u64>().ok());
+        if let (Some(remaining), Some(reset)) =
+            (number("x-ratelimit-remaining"), number("x-ratelimit-reset"))
+        {
+            pool[token].remaining.store(remaining, Ordering::Relaxed);
+            pool[token].reset.store(reset, Ordering::Relaxed);
+        }
+    }
+}
+
+fn unix_time() -> u64 {
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .expect("SystemTime before UNIX EPOCH!")
+        .as_secs()
+}
+
+/// Sets up the pool with an octocrab instance per token.
+pub fn init(instances: Vec<Octocrab>) {
+    let tokens = instances
+        .into_iter()
+        .map(|instance| PooledToken {
+            instance: Arc::new(instance),
+            remaining: AtomicU64::new(u64::MAX),
+            reset: AtomicU64::new(0),
+        })
+        .collect();
+    POOL.get_or_init(|| tokens);
+}
+
+/// Whether requests are spread over a `--token-pool-file`.
+pub fn is_active() -> bool {
+    POOL.get().is_some()
+}
+
+/// Picks the token with the most remaining quota for the next request. A
+/// token whose rate-limit has reset since its last response counts as full.
+pub fn pick() -> Client {
+    let Some(pool) = POOL.get() else {
+        return Client {
+            token: None,
+            instance: octocrab::instance(),
+        };
+    };
+    let now = unix_time();
+    let (token, _) = pool
+        .iter()
+        .enumerate()
+        .map(|(i, token)| match token.reset.load(Ordering::Relaxed) {
+            reset if reset != 0 && reset <= now => (i, u64::MAX),
+            _ => (i, token.remaining.load(Ordering::Relaxed)),
+        })
+        .max_by_key(|(_, remaining)| *remaining)
+        .expect("the token pool is never empty");
+    Client {
+        token: Some(token),
+        instance: pool[token].instance.clone(),
+    }
+}
+
+/// Waits until at least one token of the pool has `floor` or more requests
+/// left. If all are below it, this waits for the soonest reset.
+pub async fn wait_for_quota(floor: u64) {
+    let Some(pool) = POOL.get() else {
+        return;
+    };
+    let now = unix_time();
+    let exhausted = pool.iter().all(|token| {
+        token.remaining.load(Ordering::Relaxed) < floor.max(1)
+            && token.reset.load(Ordering::Relaxed) > now
+    });
+    if !exhausted {
+        return;
+    }
+    let soonest = pool
+        .iter()
+        .map(|token| token.reset.load(Ordering::Relaxed))
+        .min()
+        .unwrap_or(now);
+    let reset_in = soonest.saturating_sub(now) + 2;
+    info!(
+        "All {} tokens of the pool hit the GitHub rate-limit: the first resets in {} seconds (at {}).",
+        pool.len(),
+        reset_in,
+        soonest
+    );
+    tokio::time::sleep(Duration::from_secs(reset_in)).await;
+}
@@ -0,0 +1,336 @@
+use chrono::{DateTime, SecondsFormat, Utc};
+use serde_json::{Map, Value};
+use sha2::{Digest, Sha256};
+
+use crate::types::FlattenArrays;
+
+/// Top-level fields of an entry that are always kept by [project_fields].
+const ALWAYS_KEPT: [&str; 5] = [
+    "schema_version",
+    "type",
+    "repository",
+    "number",
+    "generated_by",
+];
+/// Fields of the issue or pull-request object always kept by [project_fields].
+const ALWAYS_KEPT_MODEL: [&str; 1] = ["number"];
+
+/// Projects a serialized entry down to the selected `fields`. The fields name
+/// either fields of the `issue`/`pull` object (e.g. `title` or `labels`) or the
+/// `events` and `comments` lists of the entry. The `schema_version`, `type`,
+/// `repository`, `number` and `generated_by` fields of the entry and the
+/// `number` of the issue or pull-request are always kept.
+pub fn project_fields(entry: &mut Value, fields: &[String]) {
+    let selected = |key: &str| fields.iter().any(|f| f == key);
+
+    if let Value::Object(map) = entry {
+        map.retain(|key, value| {
+            if ALWAYS_KEPT.contains(&key.as_str()) {
+                return true;
+            }
+            if key == "issue" || key == "pull" {
+                if let Value::Object(model) = value {
+                    model.retain(|k, _| ALWAYS_KEPT_MODEL.contains(&k.as_str()) || selected(k));
+                }
+                return true;
+            }
+            selected(key)
+        });
+    }
+}
+
+/// A `+` (keep) or `-` (drop) rule of a [FieldsSpec].
+#[derive(Debug, Clone)]
+struct FieldRule {
+    keep: bool,
+    /// The path segments. `*` matches any key, `**` any number of keys.
+    pattern: Vec<String>,
+}
+
+/// Keeps or drops nested fields of a serialized entry by their path, as read
+/// from a `--fields-file`. Each line is a rule: `+` or `-`, followed by a
+/// dot-separated path like `issue.user.avatar_url`, with `*` matching any key
+/// and `**` any number of keys. Lists are transparent, so `events.actor`
+/// matches the `actor` of every event. The last matching rule decides whether
+/// a field is kept, and unmatched fields are kept. Dropped fields are dropped
+/// with everything in them. Empty lines and lines starting with `#` are
+/// ignored.
+#[derive(Debug, Clone)]
+pub struct FieldsSpec {
+    rules: Vec<FieldRule>,
+}
+
+impl FieldsSpec {
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        let mut rules = vec![];
+        for (i, line) in spec.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (keep, path) = match (line.strip_prefix('+'), line.strip_prefix('-')) {
+                (Some(path), _) => (true, path.trim()),
+                (_, Some(path)) => (false, path.trim()),
+                _ => {
+                    return Err(format!(
+                        "line {}: '{}' doesn't start with + or -",
+                        i + 1,
+                        line
+                    ))
+                }
+            };
+            let pattern: Vec<String> = path.split('.').map(str::to_string).collect();
+            for segment in pattern.iter() {
+                let wildcard = segment == "*" || segment == "**";
+                if segment.is_empty()
+                    || (segment.contains('*') && !wildcard)
+                    || segment.contains(char::is_whitespace)
+                {
+                    return Err(format!(
+                        "line {}: '{}' is not a path like issue.user.avatar_url",
+                        i + 1,
+                        path
+                    ));
+                }
+            }
+            rules.push(FieldRule { keep, pattern });
+        }
+        if rules.is_empty() {
+            return Err("the fields file has no rules".to_string());
+        }
+        Ok(Self { rules })
+    }
+
+    /// Drops the fields of a serialized entry according to the rules. The
+    /// fields always kept by [project_fields] are kept here too.
+    pub fn apply(&self, entry: &mut Value) {
+        self.apply_at(entry, &mut vec![]);
+    }
+
+    fn apply_at(&self, value: &mut Value, path: &mut Vec<String>) {
+        match value {
+            Value::Object(map) => map.retain(|key, field| {
+                path.push(key.clone());
+                let keep = self.keeps(path);
+                if keep {
+                    self.apply_at(field, path);
+                }
+                path.pop();
+                keep
+            }),
+            Value::Array(items) => items.iter_mut().for_each(|item| self.apply_at(item, path)),
+            _ => (),
+        }
+    }
+
+    fn keeps(&self, path: &[String]) -> bool {
+        let always_kept = match path {
+            [key] => ALWAYS_KEPT.contains(&key.as_str()),
+            [model, key] => {
+                (model == "issue" || model == "pull") && ALWAYS_KEPT_MODEL.contains(&key.as_str())
+            }
+            _ => false,
+        };
+        always_kept
+            || self
+                .rules
+                .iter()
+                .rev()
+                .find(|rule| matches_path(&rule.pattern, path))
+                .is_none_or(|rule| rule.keep)
+    }
+}
+
+fn matches_path(pattern: &[String], path: &[String]) -> bool {
+    match (pattern.split_first(), path.split_first()) {
+        (None, _) => path.is_empty(),
+        (Some((first, rest)), _) if first == "**" => {
+            (0..=path.len()).any(|i| matches_path(rest, &path[i..]))
+        }
+        (Some((first, rest)), Some((key, path))) => {
+            (first == "*" || first == key) && matches_path(rest, path)
+        }
+        (Some(_), None) => false,
+    }
+}
+
+/// Replaces every GitHub user (any object with a `login` and an `avatar_url`)
+/// in a serialized entry with a pseudonym. The pseudonym is derived by hashing
+/// the login with the `salt`, so the same user gets the same pseudonym across
+/// entries and backups using the same salt. The `id` is replaced by a number
+/// derived from the same hash and the `login` in URLs by the pseudonym. The
+/// `avatar_url`, `gravatar_id`, `node_id`, `name` and `email` are cleared.
+pub fn anonymize_users(value: &mut Value, salt: &[u8]) {
+    match value {
+        Value::Object(map) => {
+            let login = match (map.get("login"), map.get("avatar_url")) {
+                (Some(Value::String(login)), Some(_)) => Some(login.clone()),
+                _ => None,
+            };
+            if let Some(login) = login {
+                let (pseudonym, id) = pseudonym(&login, salt);
+                for (key, field) in map.iter_mut() {
+                    match key.as_str() {
+                        "login" => *field = Value::String(pseudonym.clone()),
+                        "id" => *field = Value::from(id),
+                        "avatar_url" | "gravatar_id" | "node_id" => {
+                            *field = Value::String(String::new())
+                        }
+                        "name" | "email" => *field = Value::Null,
+                        k if k == "url" || k.ends_with("_url") => {
+                            if let Value::String(url) = field {
+                                *url =
+                                    url.replace(&format!("/{}", login), &format!("/{}", pseudonym));
+                            }
+                        }
+                        _ => anonymize_users(field, salt),
+                    }
+                }
+            } else {
+                map.values_mut()
+                    .for_each(|field| anonymize_users(field, salt));
+            }
+        }
+        Value::Array(items) => items
+            .iter_mut()
+            .for_each(|item| anonymize_users(item, salt)),
+        _ => (),
+    }
+}
+
+/// Rewrites the timestamps of a serialized entry into one canonical RFC 3339
+/// form: UTC with a `Z` suffix, and fractional seconds only if they aren't
+/// zero, as 3, 6 or 9 digits, e.g. `2024-01-31T12:00:00Z`. Only strings in
+/// fields whose name ends with `_at` or `_on`, or is `date`, are rewritten, so
+/// a title or body that happens to be a timestamp is kept as it is.
+pub fn normalize_timestamps(value: &mut Value) {
+    match value {
+        Value::Object(map) => {
+            for (key, field) in map.iter_mut() {
+                let is_timestamp = key.ends_with("_at") || key.ends_with("_on") || key == "date";
+                match field {
+                    Value::String(s) if is_timestamp => {
+                        if let Ok(time) = DateTime::parse_from_rfc3339(s) {
+                            *s = time
+                                .with_timezone(&Utc)
+                                .to_rfc3339_opts(SecondsFormat::AutoSi, true);
+                        }
+                    }
+                    field => normalize_timestamps(field),
+                }
+            }
+        }
+        Value::Array(items) => items.iter_mut().for_each(normalize_timestamps),
+        _ => (),
+    }
+}
+
+/// Rewrites the URLs of the GitHub API at `api`, e.g.
+/// `https://api.github.com`, in a serialized entry to start with `base`
+/// instead. An empty `base` makes them relative, e.g.
+/// `/repos/owner/repo/issues/1`. Only strings in fields named `url`, `href`
+/// or ending with `_url` are rewritten, and only if they start with `api`, so
+/// the `html_url` on github.com, the `avatar_url` and URLs in bodies are kept.
+pub fn rewrite_api_urls(value: &mut Value, api: &str, base: &str) {
+    match value {
+        Value::Object(map) => {
+            for (key, field) in map.iter_mut() {
+                let is_url = key == "url" || key == "href" || key.ends_with("_url");
+                match field {
+                    Value::String(url) if is_url => {
+                        if let Some(rest) = url.strip_prefix(api) {
+                            if rest.is_empty() || rest.starts_with(['/', '{', '?']) {
+                                *url = format!("{}{}", base, rest);
+                            }
+                        }
+                    }
+                    field => rewrite_api_urls(field, api, base),
+                }
+            }
+        }
+        Value::Array(items) => items
+            .iter_mut()
+            .for_each(|item| rewrite_api_urls(item, api, base)),
+        _ => (),
+    }
+}
+
+/// Flattens a serialized entry into a single object without nested objects,
+/// as read by tools that only handle flat records. The keys of nested fields
+/// are joined with the `separator`, so `{"issue": {"user": {"login": ..}}}`
+/// becomes `{"issue_user_login": ..}`. Arrays are handled as set by `arrays`.
+/// Empty objects, and empty arrays flattened by index, become `null` so the
+/// field doesn't disappear.
+pub fn flatten(value: &mut Value, separator: &str, arrays: FlattenArrays) {
+    let mut flat = Map::new();
+    flatten_into(&mut flat, None, value.take(), separator, arrays);
+    *value = Value::Object(flat);
+}
+
+fn flatten_into(
+    flat: &mut Map<String, Value>,
+    prefix: Option<String>,
+    value: Value,
+    separator: &str,
+    arrays: FlattenArrays,
+) {
+    let key = |name: &str| match &prefix {
+        Some(prefix) => format!("{}{}{}", prefix, separator, name),
+        None => name.to_string(),
+    };
+    match value {
+        Value::Object(map) if !map.is_empty() => {
+            for (name, field) in map {
+                flatten_into(flat, Some(key(&name)), field, separator, arrays);
+            }
+        }
+        Value::Array(items) if arrays == FlattenArrays::Index && !items.is_empty() => {
+            for (i, item) in items.into_iter().enumerate() {
+                flatten_into(flat, Some(key(&i.to_string())), item, separator, arrays);
+            }
+        }
+        value => {
+            let value = match value {
+                Value::Object(_) => Value::Null,
+                Value::Array(items) if items.is_empty() && arrays == FlattenArrays::Index => {
+                    Value::Null
+                }
+                Value::Array(items) if arrays == FlattenArrays::Json => {
+                    Value::String(Value::Array(items).to_string())
+                }
+                value => value,
+            };
+            flat.insert(prefix.unwrap_or_default(), value);
+        }
+    }
+}
+
+/// Returns the `number` of the `model` ("issue" or "pull") of a serialized
+/// entry, also if it was flattened with `--flatten-nested-json`. The
+/// separator of a flattened entry isn't known, so the key is any
+/// `<model><separator>number` without letters or digits in the separator.
+pub fn entry_number(value: &Value, model: &str) -> Option<u64> {
+    if let Some(number) = value[model]["number"].as_u64() {
+        return Some(number);
+    }
+    value.as_object()?.iter().find_map(|(key, field)| {
+        let separator = key.strip_prefix(model)?.strip_suffix("number")?;
+        let flattened = !separator.is_empty() && !separator.chars().any(char::is_alphanumeric);
+        flattened.then(|| field.as_u64()).flatten()
+    })
+}
+
+/// Derives the pseudonymous login and id of a user.
+fn pseudonym(login: &str, salt: &[u8]) -> (String, u64) {
+    let mut hasher = Sha256::new();
+    hasher.update(salt);
+    // GitHub logins are case-insensitive.
+    hasher.update(login.to_lowercase().as_bytes());
+    let hash = hasher.finalize();
+    let hex: String = hash[..6].iter().map(|b| format!("{:02x}", b)).collect();
+    let mut id_bytes = [0u8; 8];
+    id_bytes.copy_from_slice(&hash[6..14]);
+    // Keep ids below 2^53 so they are exact in JavaScript numbers too.
+    let id = u64::from_be_bytes(id_bytes) >> 11;
+    (format!("user-{}", hex), id)
+}
@@ -1,16 +1,32 @@
 use chrono::{DateTime, Utc};
-use clap::Parser;
+use clap::{Parser, Subcommand, ValueEnum};
 use octocrab::models::{issues, pulls, timelines};
+use schemars::gen::SchemaGenerator;
+use schemars::schema::{InstanceType, Schema, SchemaObject};
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::error;
 use std::fmt;
 use std::io;
+use std::ops::Range;
 use std::path::PathBuf;
+use std::time::Duration;
+
+use crate::filter::EntryFilter;
+use crate::transform::FieldsSpec;
 
 #[derive(Debug)]
 pub enum WriteError {
     IoError(io::Error),
     JsonSerdeError(serde_json::Error),
+    /// The serialized entry doesn't match the JSON Schema of the written
+    /// files. Only checked with `--validate-output`.
+    InvalidOutput(Vec<String>),
+    /// The `--http-sink-url` didn't accept a batch of entries.
+    HttpError(String),
+    /// An entry couldn't be encoded in the `--serialization` format.
+    EncodingError(String),
 }
 
 impl From<io::Error> for WriteError {
@@ -32,19 +48,116 @@ impl fmt::Display for WriteError {
         match self {
             WriteError::IoError(e) => write!(f, "WriteError::IoError: {}", e),
             WriteError::JsonSerdeError(e) => write!(f, "WriteError::JsonSerdeError: {}", e),
+            WriteError::InvalidOutput(errors) => {
+                write!(f, "WriteError::InvalidOutput: {}", errors.join("; "))
+            }
+            WriteError::HttpError(e) => write!(f, "WriteError::HttpError: {}", e),
+            WriteError::EncodingError(e) => write!(f, "WriteError::EncodingError: {}", e),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum LockError {
+    /// The lock is held by another running backup.
+    Held(crate::lock::LockInfo),
+    IoError(io::Error),
+    JsonSerdeError(serde_json::Error),
+}
+
+impl From<io::Error> for LockError {
+    fn from(err: io::Error) -> Self {
+        LockError::IoError(err)
+    }
+}
+
+impl From<serde_json::Error> for LockError {
+    fn from(err: serde_json::Error) -> Self {
+        LockError::JsonSerdeError(err)
+    }
+}
+
+impl error::Error for LockError {}
+
+impl fmt::Display for LockError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LockError::Held(lock) => write!(
+                f,
+                "LockError::Held: locked by the running process {} since {}",
+                lock.pid, lock.acquired_at
+            ),
+            LockError::IoError(e) => write!(f, "LockError::IoError: {}", e),
+            LockError::JsonSerdeError(e) => write!(f, "LockError::JsonSerdeError: {}", e),
+        }
+    }
+}
+
+/// Version of the format of the written issue and pull-request files.
+pub const SCHEMA_VERSION: u32 = 3;
+
+#[derive(Debug)]
+pub enum FetchError {
+    /// An error returned by octocrab, including errors responses from the GitHub API.
+    Octocrab(Box<octocrab::Error>),
+    /// The GitHub API responded with a 502, 503 or 504 gateway error.
+    Gateway(http::StatusCode),
+    /// The GitHub API responded with a rate-limit and asked to wait this long,
+    /// e.g. with a `Retry-After` header.
+    RateLimited(Duration),
+    /// The GitHub GraphQL API responded with errors.
+    GraphQL(String),
+    /// A response couldn't be deserialized.
+    Json(serde_json::Error),
+    /// With `--strict`, deserializing a response would drop fields.
+    DroppedFields(String),
+    /// The `--request-budget` of this many requests is used up.
+    RequestBudget(u64),
+}
+
+impl From<serde_json::Error> for FetchError {
+    fn from(err: serde_json::Error) -> Self {
+        FetchError::Json(err)
+    }
+}
+
+impl From<octocrab::Error> for FetchError {
+    fn from(err: octocrab::Error) -> Self {
+        FetchError::Octocrab(Box::new(err))
+    }
+}
+
+impl error::Error for FetchError {}
+
+impl fmt::Display for FetchError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FetchError::Octocrab(e) => write!(f, "FetchError::Octocrab: {}", e),
+            FetchError::Gateway(status) => write!(f, "FetchError::Gateway: {}", status),
+            FetchError::RateLimited(wait) => {
+                write!(f, "FetchError::RateLimited: retry in {}s", wait.as_secs())
+            }
+            FetchError::GraphQL(e) => write!(f, "FetchError::GraphQL: {}", e),
+            FetchError::Json(e) => write!(f, "FetchError::Json: {}", e),
+            FetchError::DroppedFields(e) => write!(f, "FetchError::DroppedFields: {}", e),
+            FetchError::RequestBudget(budget) => {
+                write!(f, "FetchError::RequestBudget: {} requests made", budget)
+            }
         }
     }
 }
 
 #[derive(Parser, Debug, Clone)]
-#[command(author, version, about, long_about = None)]
+#[command(author, version, about, long_about = None, subcommand_negates_reqs = true)]
 pub struct Args {
-    /// Owner of the repository to backup
-    #[arg(short, long)]
-    pub owner: String,
-    /// Name of the repository to backup
-    #[arg(short, long)]
-    pub repo: String,
+    #[command(subcommand)]
+    pub command: Option<Command>,
+    /// Owner of the repository to backup. Not needed if all --repo's are given as owner/repo
+    #[arg(short, long, required_unless_present_any = ["starred", "repo"])]
+    pub owner: Option<String>,
+    /// Name of the repository to backup, or owner/repo for a repository of another owner. Several can be given comma-separated or by repeating it
+    #[arg(short, long, value_name = "REPO", value_delimiter = ',', required_unless_present_any = ["all_repos", "check_access", "starred"])]
+    pub repo: Vec<String>,
     /// Personal Access Token to the GitHub API supplied via the command line
     #[arg(short, long, group = "pat")]
     pub personal_access_token: Option<String>,
@@ -52,14 +165,1016 @@ pub struct Args {
     #[arg(short = 'f', long, value_name = "PATH", group = "pat")]
     pub personal_access_token_file: Option<PathBuf>,
     /// Destination where the backup should be written to
-    #[arg(short, long, value_name = "PATH")]
-    pub destination: PathBuf,
+    #[arg(
+        short,
+        long,
+        value_name = "PATH",
+        required_unless_present_any = ["list_events_seen", "destination_template", "check_access"]
+    )]
+    pub destination: Option<PathBuf>,
+    /// Log the remaining rate-limit quota after each API request (at debug level)
+    #[arg(long)]
+    pub verbose_rate_limit: bool,
+    /// Abort the backup if less than this many bytes are free on the destination filesystem
+    #[arg(long, value_name = "BYTES")]
+    pub min_free_disk: Option<u64>,
+    /// Also backup the wiki of the repository by cloning it into `wiki/`
+    #[arg(long)]
+    pub include_wiki: bool,
+    /// Only keep these fields of the issues and pull-requests (e.g. title,state,labels,events)
+    #[arg(long, value_name = "FIELD", value_delimiter = ',')]
+    pub fields: Vec<String>,
+    /// How often to retry requests failing with a transient 5xx error, a dropped connection or timeout, or a secondary rate-limit
+    #[arg(long, value_name = "N", default_value_t = 3)]
+    pub max_retries: u8,
+    /// Record the issues and pull-requests linked to each entry (uses the GraphQL API)
+    #[arg(long)]
+    pub include_links: bool,
+    /// Read the incremental backup cursor from this file and write the new cursor to it when done
+    #[arg(long, value_name = "PATH", conflicts_with = "all_repos")]
+    pub since_file: Option<PathBuf>,
+    /// Maximum number of events and comments held in memory by fetched entries waiting to be written
+    #[arg(long, value_name = "N", default_value_t = 100_000)]
+    pub max_buffered_items: u32,
+    /// What to do when an issue or pull-request file already exists
+    #[arg(long, value_enum, value_name = "POLICY", default_value_t = OnConflict::Overwrite)]
+    pub on_conflict: OnConflict,
+    /// Order in which to fetch the issues and pull-requests [default: updated for incremental backups, otherwise created]
+    #[arg(long, value_enum, value_name = "ORDER")]
+    pub fetch_order: Option<FetchOrder>,
+    /// Record the check-runs and commit statuses of each pull-request's head commit
+    #[arg(long)]
+    pub include_checks: bool,
+    /// Replace the logins, ids and avatars of all users with stable pseudonyms
+    #[arg(long, requires = "anonymize_salt_file")]
+    pub anonymize_users: bool,
+    /// Secret salt for the pseudonyms of --anonymize-users (generated if the file doesn't exist)
+    #[arg(long, value_name = "PATH")]
+    pub anonymize_salt_file: Option<PathBuf>,
+    /// Print how often each timeline event type occurs in the repository instead of doing a backup
+    #[arg(long, conflicts_with = "all_repos")]
+    pub list_events_seen: bool,
+    /// Only store timeline events of these types (e.g. closed,merged,labeled)
+    #[arg(long, value_name = "EVENT", value_delimiter = ',')]
+    pub event_types: Vec<String>,
+    /// With --event-types, also store events of types octocrab doesn't know
+    #[arg(long, requires = "event_types")]
+    pub keep_unknown_events: bool,
+    /// Keep the timeline event as returned by the API in each item of the histories, like the label_history
+    #[arg(long)]
+    pub keep_raw_events: bool,
+    /// Keep running and do an incremental backup every --interval
+    #[arg(long)]
+    pub watch: bool,
+    /// Time between two backups with --watch (e.g. 30s, 15m, 1h or 1d)
+    #[arg(long, value_name = "DURATION", default_value = "1h", value_parser = parse_duration)]
+    pub interval: Duration,
+    /// Also store the GitHub-rendered HTML of bodies and comments (one extra API request each)
+    #[arg(long)]
+    pub render_html: bool,
+    /// Destination with {owner}, {repo} and {date} placeholders (e.g. backups/{owner}/{repo}/{date})
+    #[arg(long, value_name = "TEMPLATE", conflicts_with = "destination", value_parser = parse_destination_template)]
+    pub destination_template: Option<String>,
+    /// Also backup the repository's metadata (description, topics, size and languages) into `repository.json` and its owner's profile into `owner.json`
+    #[arg(long)]
+    pub include_repo_metadata: bool,
+    /// Backup all repositories of the --owner (an organization or user) into `<destination>/<owner>/<repo>/`
+    #[arg(long, conflicts_with = "repo")]
+    pub all_repos: bool,
+    /// How many repositories to backup at the same time with --all-repos
+    #[arg(long, value_name = "N", default_value_t = 1, value_parser = clap::value_parser!(u16).range(1..))]
+    pub parallel_repos: u16,
+    /// Start even if another backup holds the lock on the destination, and don't resume an interrupted --all-repos run
+    #[arg(long)]
+    pub force: bool,
+    /// Fail the backup if the GitHub API returns fields that would not be stored
+    #[arg(long)]
+    pub strict: bool,
+    /// Write the backup state gzip-compressed to state.json.gz
+    #[arg(long, visible_alias = "output-gzip-state")]
+    pub compress_state: bool,
+    /// POST a JSON summary of each repository's backup to this URL when it completes
+    #[arg(long, value_name = "URL")]
+    pub notify_url: Option<String>,
+    /// When to POST the summary to the --notify-url
+    #[arg(long, value_enum, value_name = "WHEN", default_value_t = NotifyOn::Always, requires = "notify_url")]
+    pub notify_on: NotifyOn,
+    /// Deprecated: the reaction totals of each issue and pull-request are always recorded
+    #[arg(long)]
+    pub include_reactions: bool,
+    /// Also write the raw API responses of each issue and pull-request to this directory
+    #[arg(long, value_name = "PATH")]
+    pub dump_raw_dir: Option<PathBuf>,
+    /// Add a "Name: Value" header to all GitHub API requests (can be repeated)
+    #[arg(long = "header", value_name = "HEADER", value_parser = parse_header)]
+    pub headers: Vec<Header>,
+    /// Name of the directory the issue files are written to
+    #[arg(long, value_name = "NAME", default_value = "issues", value_parser = parse_dir_name)]
+    pub issues_dir_name: String,
+    /// Name of the directory the pull-request files are written to
+    #[arg(long, value_name = "NAME", default_value = "pulls", value_parser = parse_dir_name)]
+    pub pulls_dir_name: String,
+    /// Compare the updated_at of the issues and pull-requests on GitHub with index.json instead of doing a backup
+    #[arg(long, conflicts_with_all = ["all_repos", "list_events_seen"])]
+    pub compare_api_vs_disk: bool,
+    /// With --compare-api-vs-disk, only check this many randomly picked entries of index.json
+    #[arg(long, value_name = "N", requires = "compare_api_vs_disk", value_parser = clap::value_parser!(u32).range(1..))]
+    pub compare_sample: Option<u32>,
+    /// Scan the repository's issue events for deleted and transferred issues and reconcile the backup
+    #[arg(long)]
+    pub detect_deletions: bool,
+    /// What to do with the files of deleted and transferred issues and pull-requests
+    #[arg(long, value_enum, value_name = "POLICY", default_value_t = OnDeletion::Annotate, requires = "detect_deletions")]
+    pub on_deletion: OnDeletion,
+    /// How to handle invalid UTF-8 and lone surrogates in API responses
+    #[arg(long, value_enum, value_name = "POLICY", default_value_t = OutputEncoding::Replace)]
+    pub output_encoding: OutputEncoding,
+    /// Pause until the rate-limit resets once fewer than N requests remain, leaving them to other tools
+    #[arg(long, value_name = "N")]
+    pub rate_limit_floor: Option<u64>,
+    /// Drop duplicated comments (with the same id) returned across page boundaries
+    #[arg(long)]
+    pub dedupe_comments: bool,
+    /// Drop duplicated timeline events (with the same node_id or id) returned across page boundaries
+    #[arg(long)]
+    pub dedupe_events: bool,
+    /// Abort the backup if an issue or pull-request can't be serialized, instead of skipping it
+    #[arg(long)]
+    pub fail_fast: bool,
+    /// Record the number of fetched timeline pages and events of each entry in a `_meta` object
+    #[arg(long)]
+    pub include_timeline_pagination_meta: bool,
+    /// Only fetch these issues and pull-requests, ignoring the incremental state (e.g. 12,345)
+    #[arg(long, value_name = "NUMBER", value_delimiter = ',', conflicts_with_all = ["all_repos", "watch"])]
+    pub prefetch: Vec<u64>,
+    /// Number of issues and pull-requests fetched at the same time with --prefetch
+    #[arg(long, value_name = "N", default_value_t = 8, value_parser = clap::value_parser!(u16).range(1..))]
+    pub prefetch_concurrency: u16,
+    /// IANA timezone (e.g. Europe/Berlin) for log timestamps and the {date} placeholder. Stored data stays in UTC
+    #[arg(long, value_name = "TZ", value_parser = parse_timezone)]
+    pub timezone: Option<chrono_tz::Tz>,
+    /// Record who reacted to each issue and pull-request. Costs at least one extra request per entry with reactions
+    #[arg(long)]
+    pub include_reaction_users: bool,
+    /// Always do a full backup, without reading or writing the state file
+    #[arg(long, conflicts_with = "since_file")]
+    pub no_state: bool,
+    /// Record the issues and pull-requests referencing each entry, also from other repositories (uses the GraphQL API)
+    #[arg(long)]
+    pub resolve_cross_refs: bool,
+    /// Maximum number of issues and pull-requests written at the same time
+    #[arg(long, value_name = "N", default_value_t = 1, value_parser = clap::value_parser!(u16).range(1..))]
+    pub max_concurrent_writes: u16,
+    /// Keep or drop nested fields of the issues and pull-requests with the rules in this file
+    #[arg(long, value_name = "PATH", value_parser = parse_fields_file)]
+    pub fields_file: Option<FieldsSpec>,
+    /// Print the time spent and requests made per phase (listing, body, timeline, comments, other, write) after each backup
+    #[arg(long)]
+    pub profile: bool,
+    /// Also append the --profile timings as a JSON line to this file
+    #[arg(long, value_name = "PATH", requires = "profile")]
+    pub profile_file: Option<PathBuf>,
+    /// Record the sub-issues of each issue. Costs an extra request per issue with sub-issues
+    #[arg(long)]
+    pub include_sub_issues: bool,
+    /// Write all issues and pull-requests to a single backup.json instead of a file each. Holds the whole backup in memory
+    #[arg(long, conflicts_with_all = ["prefetch", "compare_api_vs_disk"])]
+    pub output_single_file: bool,
+    /// Only retry the issues and pull-requests that failed in the previous backup, without listing the updated ones
+    #[arg(long, conflicts_with_all = ["no_state", "watch", "prefetch"])]
+    pub retry_failed_only: bool,
+    /// Download the images and files attached to issues, pull-requests and comments into `attachments/`
+    #[arg(long)]
+    pub download_attachments: bool,
+    /// Also write copies of the entries linking to the downloaded attachments to `rewritten/`
+    #[arg(long, requires = "download_attachments")]
+    pub rewrite_attachment_urls: bool,
+    /// Ignore incremental cursors further than this in the future (e.g. 30m, 1h, 1d)
+    #[arg(long, value_name = "DURATION", default_value = "1h", value_parser = parse_duration)]
+    pub updated_at_slack: Duration,
+    /// Where to write the issues and pull-requests to (can be combined, e.g. files,ndjson)
+    #[arg(long = "sink", value_name = "SINK", value_enum, value_delimiter = ',', default_value = "files", conflicts_with_all = ["output_single_file", "prefetch"])]
+    pub sinks: Vec<SinkKind>,
+    /// What to do if writing to a sink fails
+    #[arg(long, value_enum, value_name = "POLICY", default_value_t = OnSinkError::Abort)]
+    pub on_sink_error: OnSinkError,
+    /// Record the base and head commits of pull-requests and the heads they were force-pushed to
+    #[arg(long)]
+    pub head_sha_resolve: bool,
+    /// Check each entry against the JSON Schema of the `schema` subcommand before writing it
+    #[arg(long)]
+    pub validate_output: bool,
+    /// Do a full backup instead of failing if the state file was written by a newer version
+    #[arg(long)]
+    pub ignore_state_version_mismatch: bool,
+    /// Collect the distinct users of the backup into users.json
+    #[arg(long, conflicts_with = "prefetch")]
+    pub export_users: bool,
+    /// Replace the inline user objects in the written entries with references to users.json
+    #[arg(long, requires = "export_users")]
+    pub user_refs: bool,
+    /// Stream failures as JSON lines to this file as they happen (- for stderr)
+    #[arg(long, value_name = "PATH")]
+    pub emit_jsonl_errors: Option<PathBuf>,
+    /// Number of items per page of paginated GraphQL queries, halved if a query exceeds the GraphQL limits
+    #[arg(long, value_name = "N", default_value_t = 100, value_parser = clap::value_parser!(u8).range(1..=100))]
+    pub graphql_page_size: u8,
+    /// Spread API requests over the personal access token and the tokens in this file (one per line)
+    #[arg(long, value_name = "PATH")]
+    pub token_pool_file: Option<PathBuf>,
+    /// Write a JSON sitemap of all backed up entries with their GitHub URL, title, dates and file
+    #[arg(long, value_name = "PATH")]
+    pub emit_sitemap: Option<PathBuf>,
+    /// Record the repository id in the state file and follow the repository if it was renamed or transferred
+    #[arg(long, conflicts_with = "no_state")]
+    pub follow_renames: bool,
+    /// Only write the issues and pull-requests matching this expression, e.g. 'state == "closed" and comments > 10'
+    #[arg(long, value_name = "EXPR", value_parser = EntryFilter::parse)]
+    pub entry_filter_expr: Option<EntryFilter>,
+    /// Print the issues and pull-requests the state file lists as failed instead of doing a backup
+    #[arg(long, conflicts_with_all = ["all_repos", "list_events_seen", "compare_api_vs_disk"])]
+    pub list_open_failures: bool,
+    /// With --list-open-failures, print them as JSON
+    #[arg(long, requires = "list_open_failures")]
+    pub json: bool,
+    /// How the delay between retries of failed requests grows
+    #[arg(long, value_enum, value_name = "STRATEGY", default_value_t = BackoffStrategy::Exponential)]
+    pub backoff_strategy: BackoffStrategy,
+    /// Delay before the first retry [default: 1s for transient errors, 60s for secondary rate-limits]
+    #[arg(long, value_name = "DURATION", value_parser = parse_duration)]
+    pub backoff_base: Option<Duration>,
+    /// Longest delay between retries
+    #[arg(long, value_name = "DURATION", default_value = "15m", value_parser = parse_duration)]
+    pub backoff_cap: Duration,
+    /// Write the issues and pull-requests into open/ and closed/ subdirectories of the issues and pulls directories
+    #[arg(long)]
+    pub split_by_state: bool,
+    /// Stop the backup once N requests were sent to the GitHub API, writing the state so the next backup resumes (exit code 10)
+    #[arg(long, value_name = "N")]
+    pub request_budget: Option<u64>,
+    /// Fetch the message of each commit that referenced an issue or pull-request (one request per commit)
+    #[arg(long)]
+    pub resolve_referencing_commits: bool,
+    /// Flatten the nested objects of each entry into top-level keys, e.g. `issue_user_login`
+    #[arg(long)]
+    pub flatten_nested_json: bool,
+    /// Separator between the keys joined by --flatten-nested-json
+    #[arg(
+        long,
+        value_name = "SEP",
+        default_value = "_",
+        requires = "flatten_nested_json"
+    )]
+    pub flatten_separator: String,
+    /// How --flatten-nested-json handles arrays
+    #[arg(long, value_enum, value_name = "MODE", default_value_t = FlattenArrays::Json, requires = "flatten_nested_json")]
+    pub flatten_arrays: FlattenArrays,
+    /// Only back up the issues, leaving the cursor of the pull-requests where it is
+    #[arg(long, conflicts_with = "only_pulls")]
+    pub only_issues: bool,
+    /// Only back up the pull-requests, leaving the cursor of the issues where it is
+    #[arg(long)]
+    pub only_pulls: bool,
+    /// Record which comments were minimized as spam, off-topic and so on (uses the GraphQL API)
+    #[arg(long)]
+    pub include_comment_moderation: bool,
+    /// Print an estimate of the requests and duration of the backup instead of doing it
+    #[arg(long, conflicts_with = "all_repos")]
+    pub plan: bool,
+    /// Octal mode of the written files, like 640. Follows the umask if not set (unix only)
+    #[arg(long, value_name = "MODE", value_parser = parse_mode)]
+    pub output_file_mode: Option<u32>,
+    /// Octal mode of the created directories, like 750. Follows the umask if not set (unix only)
+    #[arg(long, value_name = "MODE", value_parser = parse_mode)]
+    pub output_dir_mode: Option<u32>,
+    /// Append a line with the outcome of each repository's backup to runs.jsonl in the destination
+    #[arg(long)]
+    pub append_run_log: bool,
+    /// Write all timestamps of the entries as UTC RFC 3339 with a Z suffix, for byte-stable diffs
+    #[arg(long)]
+    pub normalize_timestamps: bool,
+    /// Don't fetch the issues and pull-requests that already have a file in the destination
+    #[arg(long)]
+    pub skip_existing: bool,
+    /// Directory structure of the written issues and pull-requests [default: per-number]
+    #[arg(long, value_enum, value_name = "LAYOUT", conflicts_with_all = ["split_by_state", "output_single_file", "sinks"])]
+    pub layout: Option<Layout>,
+    /// Fail the backup (exit code 11) if more than this percentage of the issues and pull-requests failed
+    #[arg(long, value_name = "PERCENT", value_parser = parse_percent)]
+    pub failure_threshold: Option<f64>,
+    /// Print which repositories of the --owner the token can read the issues and pull-requests of instead of doing a backup
+    #[arg(long, conflicts_with_all = ["repo", "all_repos"])]
+    pub check_access: bool,
+    /// The endpoint the http sink POSTs the issues and pull-requests to
+    #[arg(long, value_name = "URL", alias = "entry-callback-url")]
+    pub http_sink_url: Option<String>,
+    /// How many issues and pull-requests the http sink POSTs at once
+    #[arg(long, value_name = "N", default_value_t = 100, value_parser = clap::value_parser!(u64).range(1..))]
+    pub http_sink_batch_size: u64,
+    /// Add a "Name: Value" header, e.g. for authorization, to the requests of the http sink (can be repeated)
+    #[arg(long, value_name = "HEADER", value_parser = parse_http_sink_header)]
+    pub http_sink_header: Vec<Header>,
+    /// Write the issues and pull-requests into YYYY/MM/ subdirectories by the month they were created in
+    #[arg(long, conflicts_with_all = ["layout", "split_by_state"])]
+    pub sort_files_into_buckets_by_date: bool,
+    /// Backup the releases into `releases/{id}/` and download their assets, continuing interrupted downloads
+    #[arg(long)]
+    pub download_release_assets: bool,
+    /// Stop downloading release assets once this many bytes were downloaded in this run
+    #[arg(long, value_name = "BYTES", requires = "download_release_assets")]
+    pub release_assets_max_bytes: Option<u64>,
+    /// Number of issues and pull-requests fetched at the same time
+    #[arg(long, value_name = "N", default_value_t = 4, value_parser = clap::value_parser!(u16).range(1..))]
+    pub concurrency: u16,
+    /// Re-fetch the entries updated this long before the incremental cursor (e.g. 0, 30s or 5m)
+    #[arg(long, value_name = "DURATION", default_value = "5m", value_parser = parse_overlap)]
+    pub incremental_overlap: Duration,
+    /// The format of the issue and pull-request files of the files sink
+    #[arg(long, value_enum, default_value_t = Serialization::Json)]
+    pub serialization: Serialization,
+    /// Report the fields of the API responses that octocrab's models don't capture yet
+    #[arg(long)]
+    pub detect_schema_changes: bool,
+    /// Record who reacted to each issue, pull-request, comment and review comment. Costs an extra request per entry and comment with reactions
+    #[arg(long)]
+    pub with_reactions: bool,
+    /// Write the issue and pull-request files of the files sink gzip-compressed, e.g. to issues/1234.json.gz
+    #[arg(long)]
+    pub compress: bool,
+    /// Backup the repositories starred by the --user into starred.json instead of a repository
+    #[arg(long, requires = "user", conflicts_with = "destination_template")]
+    pub starred: bool,
+    /// The user whose starred repositories are backed up with --starred
+    #[arg(long, value_name = "LOGIN", requires = "starred")]
+    pub user: Option<String>,
+    /// Only fetch the issues and pull-requests in this range, ignoring the incremental state (e.g. 100..200 excludes 200, 100..=200 includes it)
+    #[arg(long, value_name = "A..B", value_parser = parse_number_range, conflicts_with_all = ["prefetch", "all_repos", "watch", "output_single_file", "retry_failed_only", "export_users"])]
+    pub issue_number_range: Option<Range<u64>>,
+    /// Base URL of the GitHub API, e.g. https://github.example.com/api/v3 for a GitHub Enterprise Server. Defaults to https://api.github.com
+    #[arg(long, value_name = "URL", visible_alias = "github-url")]
+    pub base_url: Option<String>,
+    /// Resume from the opaque token printed by the previous backup instead of the state file, and print the next one. Without a value, a full backup is done
+    #[arg(long, value_name = "TOKEN", num_args = 0..=1, default_missing_value = "", conflicts_with_all = ["no_state", "since_file", "watch", "all_repos"])]
+    pub resume_token: Option<String>,
+    /// Write the state file every N written issues and pull-requests of an incremental backup, so an interrupted one resumes from there. 0 only writes it at the end
+    #[arg(long, value_name = "N", default_value_t = 500)]
+    pub checkpoint_interval: usize,
+    /// After a full backup, remove the files of issues and pull-requests that are no longer in the repository
+    #[arg(long)]
+    pub prune: bool,
+    /// Whether --prune moves the files into a deleted/ directory or deletes them
+    #[arg(long, value_enum, value_name = "MODE", default_value_t = PruneMode::Move, requires = "prune")]
+    pub prune_mode: PruneMode,
+    /// Fetch at most N pages of 100 review comments per pull-request and mark the ones with more as truncated
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u32).range(1..))]
+    pub max_comment_pages: Option<u32>,
+    /// Fetch at most N pages of 100 timeline events (including the comments) per issue and pull-request and mark the ones with more as truncated
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u32).range(1..))]
+    pub max_event_pages: Option<u32>,
+    /// Write a markdown changelog of the issues and pull-requests opened, closed, reopened and merged since the previous backup to changelog.md
+    #[arg(long)]
+    pub emit_changelog: bool,
+    /// How --emit-changelog groups the changes
+    #[arg(long, value_enum, value_name = "GROUPING", default_value_t = ChangelogGroupBy::Label, requires = "emit_changelog")]
+    pub changelog_group_by: ChangelogGroupBy,
+    /// Keep the ETags of the fetched responses in etags.json and skip listed issues and pull-requests that conditional requests show unchanged
+    #[arg(long, conflicts_with = "output_single_file")]
+    pub use_etags: bool,
+    /// Only update the title, state, labels and timestamps of the issues and pull-requests already backed up from the issue list, without fetching their timelines and comments
+    #[arg(long, conflicts_with_all = ["prefetch", "compare_api_vs_disk", "output_single_file", "watch"])]
+    pub only_metadata_refresh: bool,
+    /// Lower the request rate whenever GitHub's secondary rate-limit is hit, and raise it again after a quiet period
+    #[arg(long)]
+    pub throttle_on_abuse_detection: bool,
+    /// Rewrite a state file of an older version to the current version as soon as it's loaded, instead of when the backup completes
+    #[arg(long, conflicts_with_all = ["no_state", "resume_token"])]
+    pub upgrade_state: bool,
+    /// Push the metrics of each repository's backup to the Prometheus Pushgateway at this URL when it completes
+    #[arg(long, value_name = "URL")]
+    pub emit_openmetrics: Option<String>,
+    /// The job label of the metrics pushed with --emit-openmetrics
+    #[arg(
+        long,
+        value_name = "JOB",
+        default_value = "github-metadata-backup",
+        requires = "emit_openmetrics"
+    )]
+    pub openmetrics_job: String,
+    /// The instance label of the metrics pushed with --emit-openmetrics
+    #[arg(long, value_name = "INSTANCE", requires = "emit_openmetrics")]
+    pub openmetrics_instance: Option<String>,
+    /// Back up the discussions of the repository with their comments, replies and reactions into discussions/
+    #[arg(long)]
+    pub include_discussions: bool,
+    /// Keep the API URLs in the entries verbatim. This is the default
+    #[arg(long, conflicts_with = "rewrite_api_urls")]
+    pub preserve_api_urls: bool,
+    /// Rewrite the API URLs in the entries to start with this base instead, or make them relative with `relative`
+    #[arg(long, value_name = "BASE")]
+    pub rewrite_api_urls: Option<String>,
+    /// Record a hash of each written entry in the index and don't rewrite entries fetched again whose content didn't change apart from their updated_at
+    #[arg(long, conflicts_with = "output_single_file")]
+    pub diff_only_state: bool,
+}
+
+const DESTINATION_PLACEHOLDERS: [&str; 3] = ["{owner}", "{repo}", "{date}"];
+
+/// Checks that a destination template only uses known placeholders.
+fn parse_destination_template(s: &str) -> Result<String, String> {
+    let mut rest = s.to_string();
+    for placeholder in DESTINATION_PLACEHOLDERS {
+        rest = rest.replace(placeholder, "");
+    }
+    if rest.contains('{') || rest.contains('}') {
+        return Err(format!(
+            "unknown placeholder in '{}': only {} are supported",
+            s,
+            DESTINATION_PLACEHOLDERS.join(", ")
+        ));
+    }
+    if s.trim().is_empty() {
+        return Err("the destination template is empty".to_string());
+    }
+    Ok(s.to_string())
+}
+
+/// Checks that a directory name is a single, plain path component.
+fn parse_dir_name(s: &str) -> Result<String, String> {
+    if s.is_empty() || s == "." || s == ".." || s.contains(['/', '\\']) {
+        return Err(format!("'{}' is not a plain directory name", s));
+    }
+    Ok(s.to_string())
+}
+
+/// Parses a repository like `0xB10C/github-metadata-backup`.
+fn parse_repository_ref(s: &str) -> Result<RepositoryRef, String> {
+    match s.split_once('/') {
+        Some((owner, name)) if !owner.is_empty() && !name.is_empty() && !name.contains('/') => {
+            Ok(RepositoryRef {
+                owner: owner.to_string(),
+                name: name.to_string(),
+            })
+        }
+        _ => Err(format!("'{}' is not a repository like owner/repo", s)),
+    }
 }
 
+/// Parses an IANA timezone name like `Europe/Berlin`.
+fn parse_timezone(s: &str) -> Result<chrono_tz::Tz, String> {
+    s.parse::<chrono_tz::Tz>().map_err(|_| {
+        format!(
+            "unknown timezone '{}': use an IANA name like UTC, Europe/Berlin or America/New_York",
+            s
+        )
+    })
+}
+
+/// Parses an octal file mode like `640`, `0750` or `0o750`.
+fn parse_mode(s: &str) -> Result<u32, String> {
+    let digits = s.strip_prefix("0o").unwrap_or(s);
+    match u32::from_str_radix(digits, 8) {
+        Ok(mode) if !digits.is_empty() && mode <= 0o7777 => Ok(mode),
+        _ => Err(format!("'{}' is not an octal mode like 640 or 0750", s)),
+    }
+}
+
+/// Parses a percentage between 0 and 100, like `5` or `0.5`.
+fn parse_percent(s: &str) -> Result<f64, String> {
+    match s.trim_end_matches('%').parse::<f64>() {
+        Ok(percent) if (0.0..=100.0).contains(&percent) => Ok(percent),
+        _ => Err(format!("'{}' is not a percentage between 0 and 100", s)),
+    }
+}
+
+/// Reads and validates the rules of a `--fields-file`.
+fn parse_fields_file(s: &str) -> Result<FieldsSpec, String> {
+    let spec = std::fs::read_to_string(s).map_err(|e| format!("could not read '{}': {}", s, e))?;
+    FieldsSpec::parse(&spec).map_err(|e| format!("{}: {}", s, e))
+}
+
+/// Headers set by the client itself, which can't be overridden with `--header`.
+const RESERVED_HEADERS: [http::HeaderName; 4] = [
+    http::header::AUTHORIZATION,
+    http::header::HOST,
+    http::header::CONTENT_LENGTH,
+    http::header::TRANSFER_ENCODING,
+];
+
+/// An extra HTTP header for the GitHub API requests or the http sink.
 #[derive(Debug, Clone)]
+pub struct Header {
+    pub name: http::HeaderName,
+    pub value: String,
+}
+
+/// Parses a `Name: Value` header, rejecting the [RESERVED_HEADERS].
+fn parse_header(s: &str) -> Result<Header, String> {
+    let header = parse_http_sink_header(s)?;
+    if RESERVED_HEADERS.contains(&header.name) {
+        return Err(format!(
+            "the {} header is set by github-metadata-backup and can't be overridden",
+            header.name
+        ));
+    }
+    Ok(header)
+}
+
+/// Parses a `Name: Value` header. Unlike the GitHub API headers, it can set
+/// the `Authorization` of the endpoint.
+fn parse_http_sink_header(s: &str) -> Result<Header, String> {
+    let (name, value) = s
+        .split_once(':')
+        .ok_or_else(|| format!("'{}' is not a header like 'Name: Value'", s))?;
+    let name = http::HeaderName::from_bytes(name.trim().as_bytes())
+        .map_err(|_| format!("'{}' is not a valid header name", name.trim()))?;
+    let value = value.trim();
+    http::HeaderValue::from_str(value)
+        .map_err(|_| format!("'{}' is not a valid value for the {} header", value, name))?;
+    Ok(Header {
+        name,
+        value: value.to_string(),
+    })
+}
+
+/// Parses a duration like `90`, `30s`, `15m`, `1h` or `1d`. Plain numbers are
+/// seconds.
+pub fn parse_duration(s: &str) -> Result<Duration, String> {
+    let s = s.trim();
+    let (number, unit) = match s.find(|c: char| !c.is_ascii_digit()) {
+        Some(i) => s.split_at(i),
+        None => (s, "s"),
+    };
+    let number: u64 = number
+        .parse()
+        .map_err(|_| format!("'{}' is not a duration like 30s, 15m, 1h or 1d", s))?;
+    let seconds = match unit {
+        "s" => number,
+        "m" => number * 60,
+        "h" => number * 60 * 60,
+        "d" => number * 60 * 60 * 24,
+        _ => return Err(format!("unknown unit '{}': use s, m, h or d", unit)),
+    };
+    if seconds == 0 {
+        return Err("the duration must be longer than zero".to_string());
+    }
+    Ok(Duration::from_secs(seconds))
+}
+
+/// Parses a duration like `parse_duration()`, but also accepts `0`.
+pub fn parse_overlap(s: &str) -> Result<Duration, String> {
+    match s.trim() {
+        "0" => Ok(Duration::ZERO),
+        s => parse_duration(s),
+    }
+}
+
+/// Parses a range of issue and pull-request numbers like `100..200`, which
+/// excludes the end, or `100..=200`, which includes it.
+pub fn parse_number_range(s: &str) -> Result<Range<u64>, String> {
+    let (start, end) = s
+        .split_once("..")
+        .ok_or_else(|| "expected a range like 100..200 or 100..=200".to_string())?;
+    let (end, inclusive) = match end.strip_prefix('=') {
+        Some(end) => (end, true),
+        None => (end, false),
+    };
+    let parse = |n: &str| {
+        n.trim()
+            .parse::<u64>()
+            .map_err(|e| format!("invalid number '{}': {}", n, e))
+    };
+    let (start, end) = (parse(start)?, parse(end)?);
+    let end = if inclusive {
+        end.saturating_add(1)
+    } else {
+        end
+    };
+    if start >= end {
+        return Err(format!("the range {} is empty", s));
+    }
+    Ok(start..end)
+}
+
+impl Args {
+    /// Owner of the repository to backup: the `--owner`, or the owner of the
+    /// first `--repo` if it's given as `owner/repo`. Always set when no
+    /// subcommand is used.
+    pub fn owner(&self) -> &str {
+        self.owner
+            .as_deref()
+            .or_else(|| {
+                self.repo
+                    .first()
+                    .and_then(|repo| repo.split_once('/'))
+                    .map(|(owner, _)| owner)
+            })
+            .expect("--owner is required")
+    }
+
+    /// Name of the repository to backup, without the owner. Always set when
+    /// no subcommand or `--all-repos` is used. The first one if several are
+    /// given.
+    pub fn repo(&self) -> &str {
+        let repo = self.repo.first().expect("--repo is required");
+        repo.split_once('/').map_or(repo, |(_, name)| name)
+    }
+
+    /// The owner and name of each `--repo`. Names without an owner belong to
+    /// the `--owner`.
+    pub fn repositories(&self) -> Result<Vec<(String, String)>, String> {
+        self.repo
+            .iter()
+            .map(|repo| match repo.split_once('/') {
+                Some((owner, name))
+                    if !owner.is_empty() && !name.is_empty() && !name.contains('/') =>
+                {
+                    Ok((owner.to_string(), name.to_string()))
+                }
+                Some(_) => Err(format!("'{}' is not a repository name or owner/repo", repo)),
+                None => match &self.owner {
+                    Some(owner) => Ok((owner.clone(), repo.clone())),
+                    None => Err(format!(
+                        "'{}' has no owner: use --owner or owner/repo",
+                        repo
+                    )),
+                },
+            })
+            .collect()
+    }
+
+    /// Whether several repositories are backed up, each into
+    /// `<destination>/<owner>/<repo>/`: with `--all-repos` or several
+    /// `--repo`s.
+    pub fn is_multi_repo(&self) -> bool {
+        self.all_repos || self.repo.len() > 1
+    }
+
+    /// The flags that only work with a single repository, like `--all-repos`
+    /// doesn't, which are set.
+    pub fn single_repo_flags(&self) -> Vec<&'static str> {
+        [
+            ("--since-file", self.since_file.is_some()),
+            ("--list-events-seen", self.list_events_seen),
+            ("--compare-api-vs-disk", self.compare_api_vs_disk),
+            ("--prefetch", !self.prefetch.is_empty()),
+            ("--issue-number-range", self.issue_number_range.is_some()),
+            ("--resume-token", self.resume_token.is_some()),
+            ("--list-open-failures", self.list_open_failures),
+            ("--plan", self.plan),
+            ("--only-metadata-refresh", self.only_metadata_refresh),
+        ]
+        .into_iter()
+        .filter_map(|(flag, set)| set.then_some(flag))
+        .collect()
+    }
+
+    /// The current date in the `--timezone`, or UTC.
+    fn today(&self) -> String {
+        let now = Utc::now();
+        match self.timezone {
+            Some(tz) => now.with_timezone(&tz).format("%Y-%m-%d").to_string(),
+            None => now.format("%Y-%m-%d").to_string(),
+        }
+    }
+
+    /// Destination of the backup, with the placeholders of a
+    /// `--destination-template` expanded. The `{date}` is the current date.
+    /// Always set when no subcommand or `--list-events-seen` is used.
+    pub fn destination(&self) -> PathBuf {
+        match &self.destination_template {
+            Some(template) => PathBuf::from(
+                template
+                    .replace("{owner}", self.owner())
+                    .replace("{repo}", self.repo())
+                    .replace("{date}", &self.today()),
+            ),
+            None => self
+                .destination
+                .clone()
+                .expect("--destination or --destination-template is required"),
+        }
+    }
+
+    /// The sinks to write to. `--output-single-file` is the same as
+    /// `--sink single-file`, and `--layout flat` as `--sink ndjson`.
+    pub fn sinks(&self) -> Vec<SinkKind> {
+        match (self.output_single_file, self.layout) {
+            (true, _) => vec![SinkKind::SingleFile],
+            (false, Some(Layout::Flat)) => vec![SinkKind::Ndjson],
+            (false, _) => self.sinks.clone(),
+        }
+    }
+
+    /// The `--layout`. `--split-by-state` is the same as
+    /// `--layout split-by-state`, and `--sort-files-into-buckets-by-date` as
+    /// `--layout by-date`.
+    pub fn layout(&self) -> Layout {
+        match self.layout {
+            Some(layout) => layout,
+            None if self.split_by_state => Layout::SplitByState,
+            None if self.sort_files_into_buckets_by_date => Layout::ByDate,
+            None => Layout::PerNumber,
+        }
+    }
+}
+
+/// How the delay between retries of a failed request grows.
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BackoffStrategy {
+    /// Wait the base delay before each retry
+    Fixed,
+    /// Double the delay with each retry
+    Exponential,
+    /// Wait a random delay between the base and three times the previous delay
+    DecorrelatedJitter,
+}
+
+/// Policy for output files that already exist.
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OnConflict {
+    /// Replace the existing file
+    Overwrite,
+    /// Keep the existing file and don't write the new one
+    Skip,
+    /// Rename the existing file to `<number>.json.bak` before writing
+    Backup,
+}
+
+/// What to do with the file of an issue or pull-request that was deleted or
+/// transferred to another repository.
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OnDeletion {
+    /// Keep the file and add a `removed` field to it
+    Annotate,
+    /// Delete the file and remove it from index.json
+    Prune,
+}
+
+/// How `--emit-changelog` groups the changes.
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChangelogGroupBy {
+    /// A section per label, listing a change under each of its labels
+    Label,
+    /// A section per kind of change, e.g. merged pull-requests
+    Kind,
+}
+
+/// What `--prune` does with the files of issues and pull-requests that are no
+/// longer in the repository.
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PruneMode {
+    /// Move the file into a `deleted/` directory next to it
+    Move,
+    /// Delete the file
+    Delete,
+}
+
+/// How invalid UTF-8 and `\u` escapes of lone surrogates in API responses
+/// are handled.
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputEncoding {
+    /// Replace them with the U+FFFD replacement character
+    Replace,
+    /// Keep them as text, e.g. `\xff` or `\ud800`
+    Escape,
+    /// Fail the request, like any other invalid response
+    Strict,
+}
+
+/// How `--flatten-nested-json` flattens arrays.
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FlattenArrays {
+    /// Keep each array as a single value holding its JSON text
+    Json,
+    /// Flatten each item into keys with its index, e.g. `labels_0_name`
+    Index,
+    /// Keep arrays as they are, with their items unflattened
+    Keep,
+}
+
+/// Order in which the issues and pull-requests are fetched (oldest first).
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FetchOrder {
+    /// By creation time
+    Created,
+    /// By the time of the last update
+    Updated,
+    /// By number, which is the same as by creation time
+    Number,
+}
+
+/// Where the issues and pull-requests are written to.
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SinkKind {
+    /// A file per issue and pull-request
+    Files,
+    /// All issues and pull-requests in backup.json
+    SingleFile,
+    /// A line per written issue and pull-request, appended to entries.ndjson
+    Ndjson,
+    /// Batches of issues and pull-requests, POSTed to the --http-sink-url
+    Http,
+}
+
+/// The format of the files of the `files` sink.
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Serialization {
+    /// Pretty-printed JSON in <number>.json
+    Json,
+    /// MessagePack in <number>.msgpack
+    Messagepack,
+    /// CBOR in <number>.cbor
+    Cbor,
+}
+
+impl Serialization {
+    pub const ALL: [Serialization; 3] = [
+        Serialization::Json,
+        Serialization::Messagepack,
+        Serialization::Cbor,
+    ];
+
+    /// The file extension of an entry file in this format.
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Serialization::Json => "json",
+            Serialization::Messagepack => "msgpack",
+            Serialization::Cbor => "cbor",
+        }
+    }
+}
+
+/// Predefined directory structures of the written issues and pull-requests.
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Layout {
+    /// A file per issue and pull-request in issues/ and pulls/
+    PerNumber,
+    /// All issues and pull-requests as lines of a single entries.ndjson
+    Flat,
+    /// A file per issue and pull-request in open/ and closed/ subdirectories of issues/ and pulls/
+    SplitByState,
+    /// A file per issue and pull-request in subdirectories of 1000 numbers each of issues/ and pulls/
+    Sharded,
+    /// A file per issue and pull-request in YYYY/MM/ subdirectories of issues/ and pulls/ by the month it was created in
+    ByDate,
+}
+
+/// What to do when writing to one of multiple sinks fails.
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OnSinkError {
+    /// Abort the backup
+    Abort,
+    /// Keep writing to the other sinks and retry the entry with the next backup
+    Continue,
+}
+
+/// When to notify the `--notify-url` about a completed backup.
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NotifyOn {
+    /// Only when the backup failed
+    Failure,
+    /// After every backup
+    Always,
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum Command {
+    /// Print the JSON Schema of the written issue and pull-request files
+    Schema,
+    /// Check that all issue and pull-request files of a backup still load into the current models
+    SelfTest {
+        /// The backup directory to check
+        directory: PathBuf,
+    },
+    /// Convert a backup into the JSON files of a GitHub migration archive
+    ExportMigration {
+        /// The backup directory to convert
+        directory: PathBuf,
+        /// The directory to write the migration archive files to
+        output: PathBuf,
+    },
+    /// Merge the issue and pull-request files of a backup into compressed NDJSON shards
+    Compact {
+        /// The backup directory to compact
+        directory: PathBuf,
+        /// How many issue or pull-request numbers each shard covers
+        #[arg(long, value_name = "N", default_value_t = 1000, value_parser = clap::value_parser!(u64).range(1..))]
+        shard_size: u64,
+    },
+    /// Upgrade the issue and pull-request files of a backup to the current schema_version in place
+    Migrate {
+        /// The backup directory to upgrade
+        directory: PathBuf,
+        /// The repository (as owner/repo) added to files from before schema_version 2. Derived from their html_url if not set
+        #[arg(long, value_name = "OWNER/REPO", value_parser = parse_repository_ref)]
+        repository: Option<RepositoryRef>,
+    },
+    /// Report the images, attachments and other links in the bodies of a backup that are already dead (404 or 410)
+    ValidateLinks {
+        /// The backup directory to check
+        directory: PathBuf,
+        /// Also check links to other hosts than GitHub
+        #[arg(long)]
+        external_hosts: bool,
+        /// How long to wait between two requests
+        #[arg(long, value_name = "DURATION", default_value = "1s", value_parser = parse_duration)]
+        delay: Duration,
+    },
+}
+
+/// The users who reacted to comments, keyed by the id of the comment and the
+/// reaction (e.g. `+1` or `heart`).
+pub type CommentReactions = BTreeMap<u64, BTreeMap<String, Vec<serde_json::Value>>>;
+
+/// A written issue or pull-request file.
+#[derive(Debug, Clone, JsonSchema)]
+#[schemars(untagged)]
 pub enum EntryWithMetadata {
-    Issue(IssueWithMetadata),
-    Pull(PullWithMetadata),
+    Issue(Box<IssueWithMetadata>),
+    Pull(Box<PullWithMetadata>),
+}
+
+impl EntryWithMetadata {
+    /// Resolves the `milestone_history` to the milestone numbers by title.
+    pub fn link_milestones(&mut self, numbers: &BTreeMap<String, u64>) {
+        let history = match self {
+            EntryWithMetadata::Issue(i) => &mut i.milestone_history,
+            EntryWithMetadata::Pull(p) => &mut p.milestone_history,
+        };
+        for event in history.iter_mut() {
+            event.number = event
+                .title
+                .as_ref()
+                .and_then(|title| numbers.get(title))
+                .copied();
+        }
+    }
+
+    /// Whether `--max-comment-pages` or `--max-event-pages` cut off the
+    /// entry.
+    pub fn is_truncated(&self) -> bool {
+        match self {
+            EntryWithMetadata::Issue(i) => i.truncated,
+            EntryWithMetadata::Pull(p) => p.truncated,
+        }
+    }
+
+    /// The ETags of the responses the entry was built from.
+    pub fn etags(&self) -> Option<&EntryEtags> {
+        match self {
+            EntryWithMetadata::Issue(i) => i.etags.as_ref(),
+            EntryWithMetadata::Pull(p) => p.etags.as_ref(),
+        }
+    }
+
+    pub fn number(&self) -> u64 {
+        match self {
+            EntryWithMetadata::Issue(i) => i.issue.number,
+            EntryWithMetadata::Pull(p) => p.pull.number,
+        }
+    }
+
+    /// Either "issue" or "pull".
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            EntryWithMetadata::Issue(_) => "issue",
+            EntryWithMetadata::Pull(_) => "pull",
+        }
+    }
+
+    /// The `YYYY/MM` subdirectory of `--layout by-date` of the month the
+    /// entry was created in.
+    pub fn date_bucket(&self) -> String {
+        let created_at = match self {
+            EntryWithMetadata::Issue(i) => Some(i.issue.created_at),
+            EntryWithMetadata::Pull(p) => p.pull.created_at,
+        };
+        created_at
+            .unwrap_or(DateTime::UNIX_EPOCH)
+            .format("%Y/%m")
+            .to_string()
+    }
+
+    /// Either "open" or "closed".
+    pub fn state_name(&self) -> &'static str {
+        let state = match self {
+            EntryWithMetadata::Issue(i) => Some(&i.issue.state),
+            EntryWithMetadata::Pull(p) => p.pull.state.as_ref(),
+        };
+        match state {
+            Some(octocrab::models::IssueState::Open) => "open",
+            _ => "closed",
+        }
+    }
+
+    /// Number of items (the issue or pull-request itself, its events and
+    /// comments) held by the entry.
+    pub fn item_count(&self) -> usize {
+        match self {
+            EntryWithMetadata::Issue(i) => 1 + i.events.len(),
+            EntryWithMetadata::Pull(p) => 1 + p.events.len() + p.comments.len(),
+        }
+    }
 }
 
 impl fmt::Display for EntryWithMetadata {
@@ -71,44 +1186,918 @@ impl fmt::Display for EntryWithMetadata {
     }
 }
 
+/// Describes an octocrab model, which doesn't implement `JsonSchema`, as an
+/// object following the GitHub REST API schema.
+fn github_object(_: &mut SchemaGenerator) -> Schema {
+    SchemaObject {
+        instance_type: Some(InstanceType::Object.into()),
+        ..Default::default()
+    }
+    .into()
+}
+
+/// Like [github_object], but for a list of octocrab models.
+fn github_object_array(gen: &mut SchemaGenerator) -> Schema {
+    SchemaObject {
+        instance_type: Some(InstanceType::Array.into()),
+        array: Some(Box::new(schemars::schema::ArrayValidation {
+            items: Some(github_object(gen).into()),
+            ..Default::default()
+        })),
+        ..Default::default()
+    }
+    .into()
+}
+
+/// Records which version of the tool produced a file.
+#[derive(Serialize, Deserialize, Debug, Clone, JsonSchema)]
+pub struct GeneratedBy {
+    /// Version of github-metadata-backup.
+    pub tool_version: String,
+    /// Version of the octocrab library used to (de)serialize the GitHub models.
+    pub octocrab_version: String,
+    /// UTC timestamp when the file was generated.
+    pub generated_at: DateTime<Utc>,
+}
+
+impl GeneratedBy {
+    pub fn now() -> Self {
+        Self {
+            tool_version: env!("CARGO_PKG_VERSION").to_string(),
+            octocrab_version: env!("OCTOCRAB_VERSION").to_string(),
+            generated_at: Utc::now(),
+        }
+    }
+}
+
+/// The repository an issue or pull-request was backed up from.
+#[derive(Serialize, Deserialize, Debug, Clone, JsonSchema)]
+pub struct RepositoryRef {
+    pub owner: String,
+    pub name: String,
+}
+
+/// Records that an issue or pull-request was removed from the repository.
+#[derive(Serialize, Deserialize, Debug, Clone, JsonSchema)]
+pub struct Removal {
+    /// Either "deleted" or "transferred".
+    pub reason: String,
+    /// UTC timestamp when the removal was detected.
+    pub detected_at: DateTime<Utc>,
+    /// The URL of a transferred issue in its new repository.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub new_url: Option<String>,
+}
+
+/// Reference to an issue or pull-request, possibly in another repository.
+#[derive(
+    Serialize, Deserialize, Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, JsonSchema,
+)]
+pub struct LinkedEntry {
+    /// The repository as `owner/name`.
+    pub repository: String,
+    pub number: u64,
+}
+
+/// A minimized (hidden) comment, from the `isMinimized` and `minimizedReason`
+/// of the GraphQL API. The REST API doesn't report them.
+#[derive(Serialize, Debug, Clone, JsonSchema)]
+pub struct MinimizedComment {
+    /// The id of the comment, as in the REST API.
+    pub id: Option<u64>,
+    /// Either "comment" for a comment on the conversation or "review_comment".
+    pub kind: String,
+    /// Why the comment was minimized, e.g. "spam", "off-topic", "outdated",
+    /// "resolved", "duplicate" or "abuse", in lower case.
+    pub minimized_reason: Option<String>,
+}
+
+/// A reference to an issue or pull-request from another one, possibly in
+/// another repository, from a `CrossReferencedEvent` of the GraphQL API.
+#[derive(Serialize, Debug, Clone, JsonSchema)]
+pub struct CrossReference {
+    pub referenced_at: Option<DateTime<Utc>>,
+    /// Login of the user that made the reference.
+    pub actor: Option<String>,
+    /// Whether the referencing entry is in another repository.
+    pub cross_repository: bool,
+    /// Whether merging the referencing pull-request will close the entry.
+    pub will_close_target: bool,
+    /// The referencing issue or pull-request.
+    pub source: ReferencingEntry,
+}
+
+/// The issue or pull-request a [CrossReference] is made from.
+#[derive(Serialize, Debug, Clone, JsonSchema)]
+pub struct ReferencingEntry {
+    /// Either "issue" or "pull".
+    pub r#type: String,
+    /// The repository as `owner/name`.
+    pub repository: String,
+    pub number: u64,
+    pub title: Option<String>,
+    pub state: Option<String>,
+    pub url: Option<String>,
+    /// Login of the author.
+    pub author: Option<String>,
+    pub created_at: Option<DateTime<Utc>>,
+}
+
+/// A change of the classic project board columns an issue or pull-request is
+/// in, derived from the `added_to_project`, `moved_columns_in_project`,
+/// `removed_from_project` and `converted_note_to_issue` timeline events.
+#[derive(Serialize, Debug, Clone, JsonSchema)]
+pub struct ProjectEvent {
+    /// The timeline event type.
+    pub event: String,
+    pub created_at: Option<DateTime<Utc>>,
+    /// Login of the user that made the change.
+    pub actor: Option<String>,
+    pub project_id: Option<u64>,
+    pub project_url: Option<String>,
+    /// The column the card is in after the change.
+    pub column_name: Option<String>,
+    /// The column the card was moved from. Only set for moved cards.
+    pub previous_column_name: Option<String>,
+    /// The project card as returned by the GitHub REST API.
+    pub project_card: Option<serde_json::Value>,
+}
+
+/// An assignment or unassignment of a user, derived from the `assigned` and
+/// `unassigned` timeline events.
+#[derive(Serialize, Debug, Clone, JsonSchema)]
+pub struct AssignmentEvent {
+    /// Either "assigned" or "unassigned".
+    pub event: String,
+    pub created_at: Option<DateTime<Utc>>,
+    /// Login of the user that made the change.
+    pub actor: Option<String>,
+    /// Login of the user that was assigned or unassigned.
+    pub assignee: Option<String>,
+    /// Login of the assigner, if the API reports one separately from the
+    /// `actor`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub assigner: Option<String>,
+    /// Whether the `actor` assigned or unassigned themselves.
+    pub self_assigned: bool,
+}
+
+/// A review of a pull-request, as listed by the reviews API.
+#[derive(Serialize, Debug, Clone, JsonSchema)]
+pub struct Review {
+    pub id: Option<u64>,
+    pub node_id: Option<String>,
+    /// E.g. "approved", "changes_requested" or "commented".
+    pub state: Option<String>,
+    pub body: Option<String>,
+    /// Login of the reviewer.
+    pub user: Option<String>,
+    pub submitted_at: Option<DateTime<Utc>>,
+    /// The head commit that was reviewed.
+    pub commit_id: Option<String>,
+    pub html_url: Option<String>,
+    /// The ids of the review comments in `comments` made in this review.
+    pub comment_ids: Vec<u64>,
+}
+
+/// A commit the base or head of a pull-request pointed to, derived from the
+/// pull-request and its `head_ref_force_pushed` timeline events.
+#[derive(Serialize, Debug, Clone, JsonSchema)]
+pub struct ShaChange {
+    /// Either "base" or "head" for the current base and head of the
+    /// pull-request, or "head_ref_force_pushed" for a force-push.
+    pub event: String,
+    /// The commit. For a force-push, the head after it. Not reported by the
+    /// API for some force-pushes.
+    pub sha: Option<String>,
+    /// When the head was force-pushed. Not set for the current base and head.
+    pub created_at: Option<DateTime<Utc>>,
+    /// Login of the user that force-pushed.
+    pub actor: Option<String>,
+}
+
+/// A label added to or removed from an issue or pull-request, derived from
+/// the `labeled` and `unlabeled` timeline events.
+#[derive(Serialize, Debug, Clone, JsonSchema)]
+pub struct LabelEvent {
+    /// Either "labeled" or "unlabeled".
+    pub event: String,
+    pub created_at: Option<DateTime<Utc>>,
+    /// Login of the user that made the change.
+    pub actor: Option<String>,
+    /// Name of the label at the time of the event.
+    pub name: Option<String>,
+    /// Color of the label at the time of the event, e.g. "d73a4a".
+    pub color: Option<String>,
+    /// The timeline event as returned by the GitHub REST API. Only present
+    /// with `--keep-raw-events`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub raw: Option<serde_json::Value>,
+}
+
+/// A change of the milestone of an issue or pull-request, derived from the
+/// `milestoned` and `demilestoned` timeline events.
+#[derive(Serialize, Debug, Clone, JsonSchema)]
+pub struct MilestoneEvent {
+    /// Either "milestoned" or "demilestoned".
+    pub event: String,
+    pub created_at: Option<DateTime<Utc>>,
+    /// Login of the user that made the change.
+    pub actor: Option<String>,
+    /// Title of the milestone at the time of the event.
+    pub title: Option<String>,
+    /// Number of the milestone in `milestones.json`, resolved by its title.
+    /// Only present with `--include-milestones` and if a milestone still has
+    /// the title.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub number: Option<u64>,
+}
+
+/// A deployment of a pull-request, derived from the `deployed` and
+/// `deployment_environment_changed` timeline events.
+#[derive(Serialize, Debug, Clone, JsonSchema)]
+pub struct DeploymentEvent {
+    /// Either "deployed" or "deployment_environment_changed".
+    pub event: String,
+    pub created_at: Option<DateTime<Utc>>,
+    /// Login of the user or app that deployed.
+    pub actor: Option<String>,
+    /// The deployed commit.
+    pub commit_id: Option<String>,
+    pub deployment_id: Option<u64>,
+    /// The environment deployed to, e.g. "production".
+    pub environment: Option<String>,
+    /// The timeline event as returned by the GitHub REST API. Only present
+    /// with `--keep-raw-events`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub raw: Option<serde_json::Value>,
+}
+
+/// A deletion or restore of the head branch, or a change of the base branch,
+/// of a pull-request, derived from the `head_ref_deleted`,
+/// `head_ref_restored` and `base_ref_changed` timeline events.
+#[derive(Serialize, Debug, Clone, JsonSchema)]
+pub struct RefEvent {
+    /// Either "head_ref_deleted", "head_ref_restored" or "base_ref_changed".
+    pub event: String,
+    pub created_at: Option<DateTime<Utc>>,
+    /// Login of the user that made the change.
+    pub actor: Option<String>,
+    /// The deleted or restored head branch, or the base branch after the
+    /// change. Not known for base changes followed by another one.
+    pub ref_name: Option<String>,
+    /// The base branch before the change. Only known if the event reports it.
+    pub previous_ref_name: Option<String>,
+    /// The timeline event as returned by the GitHub REST API. Only present
+    /// with `--keep-raw-events`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub raw: Option<serde_json::Value>,
+}
+
+/// A conversion of a pull-request to a draft or back, derived from the
+/// `convert_to_draft` and `ready_for_review` timeline events.
+#[derive(Serialize, Debug, Clone, JsonSchema)]
+pub struct DraftEvent {
+    /// Either "convert_to_draft" or "ready_for_review".
+    pub event: String,
+    pub created_at: Option<DateTime<Utc>>,
+    /// Login of the user that made the change.
+    pub actor: Option<String>,
+    /// Whether the pull-request is a draft after the event.
+    pub draft: bool,
+    /// The timeline event as returned by the GitHub REST API. Only present
+    /// with `--keep-raw-events`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub raw: Option<serde_json::Value>,
+}
+
+/// A lock or unlock of the conversation on an issue or pull-request, derived
+/// from the `locked` and `unlocked` timeline events.
+#[derive(Serialize, Debug, Clone, JsonSchema)]
+pub struct LockEvent {
+    /// Either "locked" or "unlocked".
+    pub event: String,
+    pub created_at: Option<DateTime<Utc>>,
+    /// Login of the user that made the change.
+    pub actor: Option<String>,
+    /// Whether the conversation is locked after the event.
+    pub locked: bool,
+    /// Why the conversation was locked, e.g. "off-topic" or "spam". Not
+    /// present for unlocks or locks without a reason.
+    pub lock_reason: Option<String>,
+    /// The timeline event as returned by the GitHub REST API. Only present
+    /// with `--keep-raw-events`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub raw: Option<serde_json::Value>,
+}
+
+/// A pin or unpin of an issue to the repository's issue list, derived from
+/// the `pinned` and `unpinned` timeline events.
+#[derive(Serialize, Debug, Clone, JsonSchema)]
+pub struct PinEvent {
+    /// Either "pinned" or "unpinned".
+    pub event: String,
+    pub created_at: Option<DateTime<Utc>>,
+    /// Login of the user that made the change.
+    pub actor: Option<String>,
+    /// Whether the issue is pinned after the event.
+    pub pinned: bool,
+    /// The timeline event as returned by the GitHub REST API. Only present
+    /// with `--keep-raw-events`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub raw: Option<serde_json::Value>,
+}
+
+/// A transfer of an issue from another repository, derived from a
+/// `transferred` timeline event.
+#[derive(Serialize, Debug, Clone, JsonSchema)]
+pub struct TransferEvent {
+    pub created_at: Option<DateTime<Utc>>,
+    /// Login of the user who transferred the issue.
+    pub actor: Option<String>,
+    /// The repository the issue was transferred from as `owner/repo`. Not
+    /// present if the repository is private or deleted, or GitHub didn't
+    /// report it.
+    pub from_repository: Option<String>,
+    /// The repository the issue was transferred to as `owner/repo`: the one
+    /// it was transferred from next, or the backed up repository.
+    pub to_repository: Option<String>,
+    /// The timeline event as returned by the GitHub REST API. Only present
+    /// with `--keep-raw-events`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub raw: Option<serde_json::Value>,
+}
+
+/// A moderation of an issue or pull-request, derived from the `user_blocked`,
+/// `comment_deleted`, `marked_as_duplicate` and `unmarked_as_duplicate`
+/// timeline events.
+#[derive(Serialize, Debug, Clone, JsonSchema)]
+pub struct ModerationEvent {
+    /// The timeline event type.
+    pub event: String,
+    pub created_at: Option<DateTime<Utc>>,
+    /// Login of the moderator.
+    pub actor: Option<String>,
+    /// The timeline event as returned by the GitHub REST API. Only present
+    /// with `--keep-raw-events`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub raw: Option<serde_json::Value>,
+}
+
+/// A commit that mentioned an issue or pull-request, derived from a
+/// `referenced` timeline event.
+#[derive(Serialize, Debug, Clone, JsonSchema)]
+pub struct CommitReference {
+    pub created_at: Option<DateTime<Utc>>,
+    /// Login of the commit's author on GitHub.
+    pub actor: Option<String>,
+    pub commit_id: Option<String>,
+    /// The repository the commit is in, taken from its `commit_url`. It can be
+    /// a fork or another repository mentioning the entry.
+    pub repository: Option<RepositoryRef>,
+    /// The commit message. Only present with `--resolve-referencing-commits`
+    /// and if the commit is still available.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+    /// The timeline event as returned by the GitHub REST API. Only present
+    /// with `--keep-raw-events`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub raw: Option<serde_json::Value>,
+}
+
+/// How the timeline (and review comments) of an entry were paginated. Only a
+/// debugging aid.
+#[derive(Serialize, Debug, Clone, JsonSchema)]
+pub struct PaginationMeta {
+    /// Number of timeline pages fetched.
+    pub timeline_pages: u32,
+    /// Number of timeline events fetched, including those filtered out by
+    /// `--event-types`.
+    pub timeline_events: usize,
+    /// Number of review comment pages fetched. Only set for pull-requests.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub comments_pages: Option<u32>,
+    /// Number of review comments fetched. Only set for pull-requests.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub comments: Option<usize>,
+}
+
+/// CI results reported for the head commit of a pull-request.
+#[derive(Serialize, Debug, Clone, JsonSchema)]
+pub struct PullChecks {
+    /// The head commit the checks were reported for.
+    pub sha: String,
+    /// The check-runs reported by GitHub Apps such as GitHub Actions.
+    #[schemars(schema_with = "github_object_array")]
+    pub check_runs: Vec<serde_json::Value>,
+    /// The combined status of the commit statuses.
+    #[schemars(schema_with = "github_object")]
+    pub combined_status: serde_json::Value,
+}
+
+/// A page of the check-runs of a commit.
+#[derive(Deserialize, Debug)]
+pub struct CheckRunsPage {
+    pub check_runs: Vec<serde_json::Value>,
+}
+
 /// A GitHub Issue with metadata. Can be serialized.
-#[derive(Serialize, Debug, Clone)]
+#[derive(Serialize, Debug, Clone, JsonSchema)]
 pub struct IssueWithMetadata {
+    /// Version of the file format.
+    pub schema_version: u32,
+    /// Always "issue".
     pub r#type: String,
+    /// The repository the issue was backed up from.
+    pub repository: RepositoryRef,
+    /// The number of the issue, the same as `issue.number`.
+    pub number: u64,
+    /// The tool that generated the file.
+    pub generated_by: GeneratedBy,
+    /// The issue as returned by the GitHub REST API.
+    #[schemars(schema_with = "github_object")]
     pub issue: issues::Issue,
+    /// The number of reactions to the issue, in total and per reaction, as
+    /// returned inline by the issues API. Not present if the API didn't
+    /// return them.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reactions: Option<serde_json::Value>,
+    /// The users who reacted to the issue, keyed by the reaction. Only
+    /// present with `--include-reaction-users` or `--with-reactions`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reaction_users: Option<BTreeMap<String, Vec<serde_json::Value>>>,
+    /// The users who reacted to the comments in `events`, keyed by the id of
+    /// the comment and the reaction. Only present with `--with-reactions` and
+    /// if a comment has reactions.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub comment_reactions: Option<CommentReactions>,
+    /// The sub-issue progress (`total`, `completed` and `percent_completed`)
+    /// as returned by the GitHub REST API. Not present if the repository
+    /// doesn't have sub-issues.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sub_issues_summary: Option<serde_json::Value>,
+    /// The sub-issues as returned by the GitHub REST API. Only present with
+    /// `--include-sub-issues` and if the issue has sub-issues.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sub_issues: Option<Vec<serde_json::Value>>,
+    /// Who closed the issue last: the `actor` of the latest `closed`
+    /// timeline event, or the `closed_by` of the issue if the timeline has
+    /// none. Not present if the issue is open.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub closed_by: Option<serde_json::Value>,
+    /// The timeline events of the issue.
+    #[schemars(schema_with = "github_object_array")]
     pub events: Vec<timelines::TimelineEvent>,
+    /// Timeline events of types octocrab doesn't know yet, as returned by the
+    /// GitHub REST API. Not present if there are none.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub unknown_events: Vec<serde_json::Value>,
+    /// The HTML-rendered bodies of the comments and reviews in `events`,
+    /// keyed by the `node_id` of the event. Only present with `--render-html`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub events_body_html: Option<BTreeMap<String, String>>,
+    /// The GitHub Apps the events were performed via, keyed by the `node_id`
+    /// of the event. Not present if no event was performed via an app.
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub performed_via_github_app: BTreeMap<String, serde_json::Value>,
+    /// The classic project board history of the issue, oldest first. Not
+    /// present if the issue was never on a classic project board.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub project_history: Vec<ProjectEvent>,
+    /// Who assigned or unassigned whom on the issue, oldest first. Not
+    /// present if nobody was ever assigned.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub assignment_history: Vec<AssignmentEvent>,
+    /// The milestones the entry was added to and removed from, oldest first.
+    /// Not present if it was never on a milestone.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub milestone_history: Vec<MilestoneEvent>,
+    /// The labels added to and removed from the entry, oldest first. Not
+    /// present if it was never labeled.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub label_history: Vec<LabelEvent>,
+    /// Blocked users, deleted comments and duplicate markings, oldest first.
+    /// Not present if the entry was never moderated.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub moderation_history: Vec<ModerationEvent>,
+    /// The locks and unlocks of the conversation, oldest first. Not present
+    /// if it was never locked.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub lock_history: Vec<LockEvent>,
+    /// The pins and unpins of the issue, oldest first. Not present if it was
+    /// never pinned.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub pin_history: Vec<PinEvent>,
+    /// Whether the issue is pinned, according to its last `pinned` or
+    /// `unpinned` event. Not present if it was never pinned.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pinned: Option<bool>,
+    /// The commits that mentioned the entry, oldest first. Not present if no
+    /// commit did.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub commit_references: Vec<CommitReference>,
+    /// The repositories the issue was transferred from, oldest first. Not
+    /// present if it was never transferred.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub transfer_history: Vec<TransferEvent>,
+    /// Issues linked to the issue. Only present with `--include-links`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub linked_issues: Option<Vec<LinkedEntry>>,
+    /// Pull-requests linked to the issue, including those closing it. Only
+    /// present with `--include-links`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub linked_prs: Option<Vec<LinkedEntry>>,
+    /// The issues and pull-requests referencing the issue, oldest first.
+    /// Only present with `--resolve-cross-refs`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cross_references: Option<Vec<CrossReference>>,
+    /// The comments that were minimized (hidden), e.g. as spam or off-topic.
+    /// Only present with `--include-comment-moderation`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub minimized_comments: Option<Vec<MinimizedComment>>,
+    /// Only present with `--include-timeline-pagination-meta`.
+    #[serde(rename = "_meta", skip_serializing_if = "Option::is_none")]
+    pub meta: Option<PaginationMeta>,
+    /// Set if `--max-comment-pages` or `--max-event-pages` cut off the
+    /// events or review comments. Not present if they are complete.
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub truncated: bool,
+    /// Set if the issue was deleted or transferred. Only added to existing
+    /// files by `--detect-deletions`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub removed: Option<Removal>,
+    /// The raw API responses. Only kept with `--dump-raw-dir`.
+    #[serde(skip)]
+    pub raw: Option<RawEntry>,
+    /// The ETags of the responses the entry was built from. Only kept with
+    /// `--use-etags`.
+    #[serde(skip)]
+    pub etags: Option<EntryEtags>,
 }
 
 impl IssueWithMetadata {
-    pub fn new(issue: issues::Issue, events: Vec<timelines::TimelineEvent>) -> Self {
+    pub fn new(
+        repository: RepositoryRef,
+        issue: issues::Issue,
+        events: Vec<timelines::TimelineEvent>,
+    ) -> Self {
         Self {
+            schema_version: SCHEMA_VERSION,
             r#type: "issue".to_string(),
+            repository,
+            number: issue.number,
+            generated_by: GeneratedBy::now(),
             issue,
+            reactions: None,
+            reaction_users: None,
+            comment_reactions: None,
+            sub_issues_summary: None,
+            sub_issues: None,
+            closed_by: None,
             events,
+            unknown_events: vec![],
+            events_body_html: None,
+            performed_via_github_app: BTreeMap::new(),
+            project_history: vec![],
+            assignment_history: vec![],
+            milestone_history: vec![],
+            label_history: vec![],
+            moderation_history: vec![],
+            lock_history: vec![],
+            pin_history: vec![],
+            pinned: None,
+            commit_references: vec![],
+            transfer_history: vec![],
+            linked_issues: None,
+            linked_prs: None,
+            cross_references: None,
+            minimized_comments: None,
+            meta: None,
+            truncated: false,
+            removed: None,
+            raw: None,
+            etags: None,
         }
     }
 }
 
 /// A GitHub Pull-Request with metadata. Can be serialized.
-#[derive(Serialize, Debug, Clone)]
+#[derive(Serialize, Debug, Clone, JsonSchema)]
 pub struct PullWithMetadata {
+    /// Version of the file format.
+    pub schema_version: u32,
+    /// Always "pull".
     pub r#type: String,
+    /// The repository the pull-request was backed up from.
+    pub repository: RepositoryRef,
+    /// The number of the pull-request, the same as `pull.number`.
+    pub number: u64,
+    /// The tool that generated the file.
+    pub generated_by: GeneratedBy,
+    /// The pull-request as returned by the GitHub REST API.
+    #[schemars(schema_with = "github_object")]
     pub pull: pulls::PullRequest,
+    /// The number of reactions to the pull-request, in total and per
+    /// reaction, as returned inline by the issues API. Not present if the API
+    /// didn't return them.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reactions: Option<serde_json::Value>,
+    /// The users who reacted to the pull-request, keyed by the reaction. Only
+    /// present with `--include-reaction-users` or `--with-reactions`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reaction_users: Option<BTreeMap<String, Vec<serde_json::Value>>>,
+    /// The users who reacted to the comments in `events`, keyed by the id of
+    /// the comment and the reaction. Only present with `--with-reactions` and
+    /// if a comment has reactions.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub comment_reactions: Option<CommentReactions>,
+    /// The users who reacted to the review comments in `comments`, keyed by
+    /// the id of the review comment and the reaction. Only present with
+    /// `--with-reactions` and if a review comment has reactions.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub review_comment_reactions: Option<CommentReactions>,
+    /// Who closed the pull-request last: the `actor` of the latest `closed`
+    /// timeline event. Not present if the pull-request is open.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub closed_by: Option<serde_json::Value>,
+    /// The timeline events of the pull-request.
+    #[schemars(schema_with = "github_object_array")]
     pub events: Vec<timelines::TimelineEvent>,
+    /// Timeline events of types octocrab doesn't know yet, as returned by the
+    /// GitHub REST API. Not present if there are none.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub unknown_events: Vec<serde_json::Value>,
+    /// The HTML-rendered bodies of the comments and reviews in `events`,
+    /// keyed by the `node_id` of the event. Only present with `--render-html`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub events_body_html: Option<BTreeMap<String, String>>,
+    /// The review comments of the pull-request.
+    #[schemars(schema_with = "github_object_array")]
     pub comments: Vec<pulls::Comment>,
+    /// The HTML-rendered bodies of the review comments, keyed by the
+    /// `node_id` of the comment. Only present with `--render-html`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub comments_body_html: Option<BTreeMap<String, String>>,
+    /// The GitHub Apps the events and review comments were performed via,
+    /// keyed by their `node_id`. Not present if nothing was performed via an
+    /// app.
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub performed_via_github_app: BTreeMap<String, serde_json::Value>,
+    /// The classic project board history of the pull-request, oldest first.
+    /// Not present if the pull-request was never on a classic project board.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub project_history: Vec<ProjectEvent>,
+    /// Who assigned or unassigned whom on the pull-request, oldest first. Not
+    /// present if nobody was ever assigned.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub assignment_history: Vec<AssignmentEvent>,
+    /// The milestones the entry was added to and removed from, oldest first.
+    /// Not present if it was never on a milestone.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub milestone_history: Vec<MilestoneEvent>,
+    /// The labels added to and removed from the entry, oldest first. Not
+    /// present if it was never labeled.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub label_history: Vec<LabelEvent>,
+    /// Blocked users, deleted comments and duplicate markings, oldest first.
+    /// Not present if the entry was never moderated.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub moderation_history: Vec<ModerationEvent>,
+    /// The locks and unlocks of the conversation, oldest first. Not present
+    /// if it was never locked.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub lock_history: Vec<LockEvent>,
+    /// The commits that mentioned the entry, oldest first. Not present if no
+    /// commit did.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub commit_references: Vec<CommitReference>,
+    /// The deployments of the pull-request, oldest first. Not present if it
+    /// was never deployed or the token can't read deployments.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub deployment_history: Vec<DeploymentEvent>,
+    /// The deletions and restores of the head branch and the changes of the
+    /// base branch, oldest first.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub ref_history: Vec<RefEvent>,
+    /// The conversions of the pull-request to a draft and back, oldest first.
+    /// Not present if it never changed between draft and ready for review.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub draft_history: Vec<DraftEvent>,
+    /// Issues linked to the pull-request, including those it closes. Only
+    /// present with `--include-links`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub linked_issues: Option<Vec<LinkedEntry>>,
+    /// Pull-requests linked to the pull-request. Only present with
+    /// `--include-links`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub linked_prs: Option<Vec<LinkedEntry>>,
+    /// The issues and pull-requests referencing the pull-request, oldest first.
+    /// Only present with `--resolve-cross-refs`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cross_references: Option<Vec<CrossReference>>,
+    /// The comments that were minimized (hidden), e.g. as spam or off-topic.
+    /// Only present with `--include-comment-moderation`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub minimized_comments: Option<Vec<MinimizedComment>>,
+    /// The reviews of the pull-request with the ids of their review comments,
+    /// oldest first. Empty if the pull-request was never reviewed.
+    #[serde(default)]
+    pub reviews: Vec<Review>,
+    /// The base and head commits of the pull-request, and the heads it was
+    /// force-pushed to, oldest first. Only present with `--head-sha-resolve`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sha_history: Option<Vec<ShaChange>>,
+    /// CI results for the head commit. Only present with `--include-checks`
+    /// and if the head commit is still available.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub checks: Option<PullChecks>,
+    /// Only present with `--include-timeline-pagination-meta`.
+    #[serde(rename = "_meta", skip_serializing_if = "Option::is_none")]
+    pub meta: Option<PaginationMeta>,
+    /// Set if `--max-comment-pages` or `--max-event-pages` cut off the
+    /// events or review comments. Not present if they are complete.
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub truncated: bool,
+    /// Set if the pull-request was deleted. Only added to existing files by
+    /// `--detect-deletions`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub removed: Option<Removal>,
+    /// The raw API responses. Only kept with `--dump-raw-dir`.
+    #[serde(skip)]
+    pub raw: Option<RawEntry>,
+    /// The ETags of the responses the entry was built from. Only kept with
+    /// `--use-etags`.
+    #[serde(skip)]
+    pub etags: Option<EntryEtags>,
 }
 
 impl PullWithMetadata {
     pub fn new(
+        repository: RepositoryRef,
         pull: pulls::PullRequest,
         events: Vec<timelines::TimelineEvent>,
         comments: Vec<pulls::Comment>,
     ) -> Self {
         Self {
+            schema_version: SCHEMA_VERSION,
             r#type: "pull".to_string(),
+            repository,
+            number: pull.number,
+            generated_by: GeneratedBy::now(),
             pull,
+            reactions: None,
+            reaction_users: None,
+            comment_reactions: None,
+            review_comment_reactions: None,
+            closed_by: None,
             events,
+            unknown_events: vec![],
+            events_body_html: None,
+            performed_via_github_app: BTreeMap::new(),
             comments,
+            comments_body_html: None,
+            project_history: vec![],
+            assignment_history: vec![],
+            milestone_history: vec![],
+            label_history: vec![],
+            moderation_history: vec![],
+            lock_history: vec![],
+            commit_references: vec![],
+            deployment_history: vec![],
+            ref_history: vec![],
+            draft_history: vec![],
+            linked_issues: None,
+            linked_prs: None,
+            cross_references: None,
+            minimized_comments: None,
+            reviews: vec![],
+            sha_history: None,
+            checks: None,
+            meta: None,
+            truncated: false,
+            removed: None,
+            raw: None,
+            etags: None,
+        }
+    }
+}
+
+/// The written `repository.json` file.
+#[derive(Serialize, Debug, Clone, JsonSchema)]
+pub struct RepositoryWithMetadata {
+    /// Always "repository".
+    pub r#type: String,
+    /// Version of the file format.
+    pub schema_version: u32,
+    /// The tool that generated the file.
+    pub generated_by: GeneratedBy,
+    /// The repository as returned by the GitHub REST API, including its
+    /// description, topics and size (in KB).
+    #[schemars(schema_with = "github_object")]
+    pub repository: serde_json::Value,
+    /// Bytes of code per language. Empty for repositories without code.
+    pub languages: BTreeMap<String, u64>,
+}
+
+impl RepositoryWithMetadata {
+    pub fn new(repository: serde_json::Value, languages: BTreeMap<String, u64>) -> Self {
+        Self {
+            r#type: "repository".to_string(),
+            schema_version: SCHEMA_VERSION,
+            generated_by: GeneratedBy::now(),
+            repository,
+            languages,
+        }
+    }
+}
+
+/// The raw API responses an issue or pull-request file was created from,
+/// written to the `--dump-raw-dir`.
+#[derive(Serialize, Debug, Clone, Default)]
+pub struct RawEntry {
+    /// The issue or pull-request as listed by the issues API.
+    pub issue: serde_json::Value,
+    /// The pull-request as returned by the pulls API.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pull: Option<serde_json::Value>,
+    /// All timeline events, including those filtered out by `--event-types`.
+    pub timeline: Vec<serde_json::Value>,
+    /// The review comments of a pull-request.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub comments: Option<Vec<serde_json::Value>>,
+}
+
+/// Raw items of a paginated list fetched so far. A failed fetch is resumed
+/// from the page after the last fetched one.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct PageProgress {
+    /// Number of pages fetched.
+    pub pages: u32,
+    pub items: Vec<serde_json::Value>,
+    /// Set if `--max-comment-pages` or `--max-event-pages` stopped the
+    /// fetching before the last page.
+    #[serde(default)]
+    pub truncated: bool,
+    /// The ETags of the fetched pages, one per page, with `--use-etags`.
+    /// Fewer than `pages` if they can't tell whether the list changed.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub etags: Vec<String>,
+}
+
+impl PageProgress {
+    /// The ETags of all pages, if a conditional request for each tells
+    /// whether the list changed.
+    pub fn complete_etags(&self) -> Option<Vec<String>> {
+        (!self.truncated && self.pages > 0 && self.etags.len() == self.pages as usize)
+            .then(|| self.etags.clone())
+    }
+}
+
+/// The ETags of the responses an issue or pull-request was built from, as
+/// kept by `--use-etags` in `etags.json`. If the listed issue is the same and
+/// all of them are still current, so is the entry's file.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct EntryEtags {
+    /// The SHA-256 of the issue as listed by the issues API.
+    pub listed: String,
+    /// The ETags of the timeline pages.
+    pub timeline: Vec<String>,
+    /// The ETags of the review comment pages. Empty for issues.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub comments: Vec<String>,
+    /// The ETag of the pull-request. Not present for issues.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pull: Option<String>,
+}
+
+/// The timeline and review comments fetched so far for an issue or
+/// pull-request. Written to `partial/<number>.json` if it failed to load.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct Checkpoint {
+    pub timeline: PageProgress,
+    pub comments: PageProgress,
+}
+
+/// An issue or pull-request that failed to load. It's retried by the next
+/// backup, resuming from its checkpoint.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct FailedEntry {
+    pub number: u64,
+    /// Either "issue" or "pull".
+    pub r#type: String,
+    /// Number of timeline pages fetched before the failure.
+    pub timeline_pages: u32,
+    /// Number of review comment pages fetched before the failure.
+    pub comments_pages: u32,
+}
+
+impl FailedEntry {
+    pub fn new(number: u64, r#type: &str, checkpoint: &Checkpoint) -> Self {
+        Self {
+            number,
+            r#type: r#type.to_string(),
+            timeline_pages: checkpoint.timeline.pages,
+            comments_pages: checkpoint.comments.pages,
         }
     }
 }
@@ -117,6 +2106,143 @@ impl PullWithMetadata {
 pub struct BackupState {
     /// Version of the BackupState
     pub version: u32,
-    /// UTC Unix timestamp when the last backup was completed.
+    /// UTC Unix timestamp when the last backup was completed. The older of
+    /// `last_backup_issues` and `last_backup_pulls`, so the issue pages are
+    /// listed from here.
     pub last_backup: DateTime<Utc>,
+    /// The cursor of the issues. None if they were never backed up.
+    #[serde(default)]
+    pub last_backup_issues: Option<DateTime<Utc>>,
+    /// The cursor of the pull-requests. None if they were never backed up.
+    #[serde(default)]
+    pub last_backup_pulls: Option<DateTime<Utc>>,
+    /// Issues and pull-requests that failed to load in the last backup.
+    #[serde(default)]
+    pub failed: Vec<FailedEntry>,
+    /// The numeric id of the repository, which stays the same when it's
+    /// renamed or transferred. Recorded with `--follow-renames`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub repository_id: Option<u64>,
+    /// The owner and name of the repository when it was last backed up.
+    /// Recorded with `--follow-renames`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub repository: Option<RepositoryRef>,
+}
+
+/// A repository starred by the `--user`, as written to `starred.json`.
+#[derive(Serialize, Debug, Clone)]
+pub struct StarredRepo {
+    pub full_name: String,
+    pub starred_at: Option<DateTime<Utc>>,
+}
+
+/// Summary of a repository's backup, POSTed to the `--notify-url` and
+/// appended to the `--append-run-log`.
+#[derive(Serialize, Debug, Clone)]
+pub struct BackupSummary {
+    pub owner: String,
+    pub repo: String,
+    pub success: bool,
+    pub started_at: DateTime<Utc>,
+    pub duration_secs: f64,
+    /// Number of issues written.
+    pub issues: u64,
+    /// Number of pull-requests written.
+    pub pulls: u64,
+    /// Number of issues and pull-requests skipped by the
+    /// `--entry-filter-expr`.
+    pub filtered: u64,
+    /// Number of issue and pull-request files removed by `--prune`.
+    pub pruned: u64,
+    /// Numbers of the issues and pull-requests cut off by
+    /// `--max-comment-pages` or `--max-event-pages`.
+    pub truncated: Vec<u64>,
+    /// Number of issues and pull-requests fetched again, but not written by
+    /// `--diff-only-state` as only their `updated_at` changed.
+    pub unchanged: u64,
+    /// Numbers of the issues that failed to load.
+    pub failed_issues: Vec<u64>,
+    /// Numbers of the pull-requests that failed to load.
+    pub failed_pulls: Vec<u64>,
+}
+
+impl BackupSummary {
+    pub fn new(owner: &str, repo: &str, started_at: DateTime<Utc>) -> Self {
+        Self {
+            owner: owner.to_string(),
+            repo: repo.to_string(),
+            success: false,
+            started_at,
+            duration_secs: 0.0,
+            issues: 0,
+            pulls: 0,
+            filtered: 0,
+            pruned: 0,
+            truncated: vec![],
+            unchanged: 0,
+            failed_issues: vec![],
+            failed_pulls: vec![],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Args {
+        Args::try_parse_from(
+            ["github-metadata-backup", "--destination", "backup"]
+                .iter()
+                .chain(args),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn repositories_with_and_without_owner() {
+        let args = parse(&["--owner", "octo-org", "--repo", "repoA,octocat/repoB"]);
+        assert_eq!(
+            args.repositories().unwrap(),
+            [
+                ("octo-org".to_string(), "repoA".to_string()),
+                ("octocat".to_string(), "repoB".to_string()),
+            ]
+        );
+        assert!(args.is_multi_repo());
+
+        let args = parse(&["--repo", "octocat/repoB"]);
+        assert_eq!(args.owner(), "octocat");
+        assert_eq!(args.repo(), "repoB");
+        assert!(!args.is_multi_repo());
+    }
+
+    #[test]
+    fn repositories_rejects_invalid_names() {
+        assert!(parse(&["--repo", "repoA"]).repositories().is_err());
+        for repo in ["octocat/", "/repoA", "octocat/repoA/issues"] {
+            assert!(
+                parse(&["--owner", "octo-org", "--repo", repo])
+                    .repositories()
+                    .is_err(),
+                "{}",
+                repo
+            );
+        }
+    }
+
+    #[test]
+    fn headers() {
+        let header = parse_header("X-Api-Key:  secret ").unwrap();
+        assert_eq!(header.name, "x-api-key");
+        assert_eq!(header.value, "secret");
+        assert!(parse_header("X-Api-Key").is_err());
+        assert!(parse_header("X Api Key: secret").is_err());
+        assert!(parse_header("X-Api-Key: line\nbreak").is_err());
+        for reserved in ["Authorization: token secret", "host: example.com"] {
+            assert!(parse_header(reserved).is_err(), "{}", reserved);
+        }
+        // The endpoint of the http sink has its own authorization.
+        assert!(parse_http_sink_header("Authorization: Bearer secret").is_ok());
+    }
 }
@@ -0,0 +1,217 @@
+use log::{debug, info};
+use serde_json::{json, Map, Value};
+use std::fs;
+use std::io;
+use std::io::prelude::*;
+use std::path::{Path, PathBuf};
+
+use crate::compact;
+use crate::permissions;
+use crate::types::{GeneratedBy, RepositoryRef, WriteError, SCHEMA_VERSION};
+
+/// A step of the `migrate` subcommand, upgrading an entry from its version to
+/// the next. Fails with the reason if the entry can't be upgraded.
+type EntryMigration = fn(&mut Map<String, Value>, &Option<RepositoryRef>) -> Result<(), String>;
+
+/// The upgrades of the issue and pull-request files, keyed by the
+/// `schema_version` they upgrade from. Files written before the
+/// `schema_version` was introduced count as version 0.
+const ENTRY_MIGRATIONS: &[(u32, EntryMigration)] =
+    &[(0, add_type), (1, add_repository), (2, add_number)];
+
+/// The fields at the start of each entry, in the order they are written by a
+/// backup.
+const HEADER: [&str; 5] = [
+    "schema_version",
+    "type",
+    "repository",
+    "number",
+    "generated_by",
+];
+
+/// What the `migrate` subcommand did.
+#[derive(Debug, Default)]
+pub struct MigrateReport {
+    /// Number of entries upgraded to the current `schema_version`.
+    pub migrated: usize,
+    /// Number of entries that already were at the current `schema_version`.
+    pub current: usize,
+    /// The entries that couldn't be upgraded, with the reason.
+    pub failed: Vec<(String, String)>,
+}
+
+/// Version 0 files only have the `issue` or `pull` and their events (and
+/// comments). The `generated_by` records the version doing the upgrade, as
+/// the version that wrote the file is unknown.
+fn add_type(entry: &mut Map<String, Value>, _: &Option<RepositoryRef>) -> Result<(), String> {
+    let kind = match (entry.contains_key("issue"), entry.contains_key("pull")) {
+        (true, false) => "issue",
+        (false, true) => "pull",
+        _ => return Err("neither an issue nor a pull-request".to_string()),
+    };
+    entry.insert("type".to_string(), json!(kind));
+    entry.insert("generated_by".to_string(), json!(GeneratedBy::now()));
+    Ok(())
+}
+
+/// Version 2 added the `repository`. Without a `--repository`, it's taken from
+/// the `html_url` of the issue or pull-request.
+fn add_repository(
+    entry: &mut Map<String, Value>,
+    repository: &Option<RepositoryRef>,
+) -> Result<(), String> {
+    let repository = match repository {
+        Some(repository) => repository.clone(),
+        None => {
+            let kind = entry["type"].as_str().unwrap_or_default();
+            let url = entry[kind]["html_url"].as_str().unwrap_or_default();
+            let mut path = url
+                .strip_prefix("https://github.com/")
+                .unwrap_or_default()
+                .split('/');
+            match (path.next(), path.next()) {
+                (Some(owner), Some(name)) if !owner.is_empty() && !name.is_empty() => {
+                    RepositoryRef {
+                        owner: owner.to_string(),
+                        name: name.to_string(),
+                    }
+                }
+                _ => {
+                    return Err(format!(
+                        "the repository can't be derived from the html_url '{}', pass --repository",
+                        url
+                    ))
+                }
+            }
+        }
+    };
+    entry.insert("repository".to_string(), json!(repository));
+    Ok(())
+}
+
+/// Version 3 added the `number` of the issue or pull-request to the start of
+/// the entry.
+fn add_number(entry: &mut Map<String, Value>, _: &Option<RepositoryRef>) -> Result<(), String> {
+    let kind = entry
+        .get("type")
+        .and_then(Value::as_str)
+        .unwrap_or_default();
+    let number = entry
+        .get(kind)
+        .and_then(|model| model["number"].as_u64())
+        .ok_or(format!("the {} has no number", kind))?;
+    entry.insert("number".to_string(), json!(number));
+    Ok(())
+}
+
+/// Upgrades an entry to the current `schema_version`. Returns None if it's
+/// already current.
+fn migrate_entry(
+    mut value: Value,
+    repository: &Option<RepositoryRef>,
+) -> Result<Option<Value>, String> {
+    let Value::Object(entry) = &mut value else {
+        return Err("not a JSON object".to_string());
+    };
+    let mut version = match entry.get("schema_version") {
+        None => 0,
+        Some(version) => version
+            .as_u64()
+            .ok_or("the schema_version isn't a number")? as u32,
+    };
+    if version == SCHEMA_VERSION {
+        return Ok(None);
+    }
+    if version > SCHEMA_VERSION {
+        return Err(format!(
+            "schema_version {} is newer than this version ({})",
+            version, SCHEMA_VERSION
+        ));
+    }
+    while version < SCHEMA_VERSION {
+        let migration = ENTRY_MIGRATIONS
+            .iter()
+            .find(|(from, _)| *from == version)
+            .map(|(_, migration)| migration)
+            .ok_or(format!("no migration from schema_version {}", version))?;
+        migration(entry, repository)?;
+        version += 1;
+        entry.insert("schema_version".to_string(), json!(version));
+    }
+    let mut ordered = Map::new();
+    for key in HEADER {
+        if let Some(field) = entry.get(key) {
+            ordered.insert(key.to_string(), field.clone());
+        }
+    }
+    for (key, field) in std::mem::take(entry) {
+        if !HEADER.contains(&key.as_str()) {
+            ordered.insert(key, field);
+        }
+    }
+    Ok(Some(Value::Object(ordered)))
+}
+
+/// Upgrades the issue and pull-request files and shards in `directory` to the
+/// current `schema_version` in place. Each file is replaced atomically. The
+/// `repository` is added to entries from before version 2.
+pub fn migrate(
+    directory: &Path,
+    issues_dir: &str,
+    pulls_dir: &str,
+    repository: &Option<RepositoryRef>,
+) -> Result<MigrateReport, WriteError> {
+    let mut report = MigrateReport::default();
+    for (dir, key) in [(issues_dir, "issue"), (pulls_dir, "pull")] {
+        let path = directory.join(dir);
+        let mut files: Vec<PathBuf> = match fs::read_dir(&path) {
+            Ok(files) => files
+                .map(|file| file.map(|f| f.path()))
+                .collect::<io::Result<_>>()?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(e.into()),
+        };
+        files.sort();
+        for file in files {
+            let name = file.display().to_string();
+            if name.ends_with(".ndjson.gz") {
+                let mut lines = compact::read_shard(&file, key)?;
+                let mut changed = false;
+                for (number, line) in lines.iter_mut() {
+                    let entry = format!("{} #{}", name, number);
+                    match migrate_entry(serde_json::from_str(line)?, repository) {
+                        Ok(Some(value)) => {
+                            *line = serde_json::to_string(&value)?;
+                            changed = true;
+                            report.migrated += 1;
+                        }
+                        Ok(None) => report.current += 1,
+                        Err(e) => report.failed.push((entry, e)),
+                    }
+                }
+                if changed {
+                    compact::write_shard(&file, &lines)?;
+                }
+            } else if file.extension().is_some_and(|ext| ext == "json") {
+                let value: Value = serde_json::from_str(&fs::read_to_string(&file)?)?;
+                match migrate_entry(value, repository) {
+                    Ok(Some(value)) => {
+                        let partial = file.with_extension("json.partial");
+                        permissions::create_file(&partial)?
+                            .write_all(serde_json::to_string(&value)?.as_bytes())?;
+                        fs::rename(&partial, &file)?;
+                        debug!("Migrated {}", name);
+                        report.migrated += 1;
+                    }
+                    Ok(None) => report.current += 1,
+                    Err(e) => report.failed.push((name, e)),
+                }
+            }
+        }
+    }
+    info!(
+        "Migrated {} entries, {} already were at schema_version {}",
+        report.migrated, report.current, SCHEMA_VERSION
+    );
+    Ok(report)
+}
@@ -0,0 +1,96 @@
+use log::info;
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use crate::permissions;
+use crate::types::WriteError;
+
+pub const USERS_FILE: &str = "users.json";
+/// Key of the objects inline users are replaced with by `--user-refs`.
+pub const USER_REF: &str = "user_ref";
+
+/// The distinct users of a backup, keyed by their id, as written to
+/// `users.json`.
+pub struct UserDirectory {
+    path: PathBuf,
+    users: Mutex<BTreeMap<u64, Value>>,
+}
+
+impl UserDirectory {
+    /// Loads the `users.json` of a previous backup from `destination`, so that
+    /// users of entries that weren't updated since are kept. Starts empty if
+    /// there is none.
+    pub fn load(destination: &Path) -> Result<Self, WriteError> {
+        let path = destination.join(USERS_FILE);
+        let users = match fs::read_to_string(&path) {
+            Ok(contents) => serde_json::from_str::<Vec<Value>>(&contents)?
+                .into_iter()
+                .filter_map(|user| Some((user["id"].as_u64()?, user)))
+                .collect(),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => BTreeMap::new(),
+            Err(e) => return Err(e.into()),
+        };
+        Ok(Self {
+            path,
+            users: Mutex::new(users),
+        })
+    }
+
+    /// Records the users in a serialized entry. With `replace`, each inline
+    /// user object is replaced with `{"user_ref": <id>}`.
+    pub fn collect(&self, value: &mut Value, replace: bool) {
+        let mut users = self.users.lock().expect("the users are never poisoned");
+        collect(value, replace, &mut users);
+    }
+
+    /// Writes the users sorted by id.
+    pub fn write(&self) -> Result<(), WriteError> {
+        let users = self.users.lock().expect("the users are never poisoned");
+        let json = serde_json::to_string_pretty(&users.values().collect::<Vec<_>>())?;
+        permissions::write_atomically(&self.path, json.as_bytes())?;
+        info!("Written {} users to {}", users.len(), self.path.display());
+        Ok(())
+    }
+}
+
+/// Users are recognized like by `--anonymize-users`: objects with a `login`
+/// and an `avatar_url`.
+fn collect(value: &mut Value, replace: bool, users: &mut BTreeMap<u64, Value>) {
+    match value {
+        Value::Object(map) => {
+            let user = match (map.get("login"), map.get("avatar_url"), map.get("id")) {
+                (Some(Value::String(login)), Some(_), Some(id)) => {
+                    id.as_u64().map(|id| (id, login.clone()))
+                }
+                _ => None,
+            };
+            match user {
+                Some((id, login)) => {
+                    users.insert(
+                        id,
+                        json!({
+                            "login": login,
+                            "id": id,
+                            "node_id": map.get("node_id").cloned().unwrap_or_default(),
+                            "type": map.get("type").cloned().unwrap_or_default(),
+                        }),
+                    );
+                    if replace {
+                        *value = json!({ USER_REF: id });
+                    }
+                }
+                None => map
+                    .values_mut()
+                    .for_each(|field| collect(field, replace, users)),
+            }
+        }
+        Value::Array(items) => items
+            .iter_mut()
+            .for_each(|item| collect(item, replace, users)),
+        _ => (),
+    }
+}
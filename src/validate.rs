@@ -0,0 +1,189 @@
+use serde_json::Value;
+
+/// Validates `value` against a JSON `schema` and returns the violations, each
+/// prefixed with the JSON pointer of the offending value. Only the subset of
+/// JSON Schema that `schemars` generates for the written files is checked:
+/// `$ref` to the `definitions`, `type`, `properties`, `required`,
+/// `additionalProperties`, `items`, `enum`, `const`, `minimum`, `maximum`,
+/// `anyOf`, `oneOf` and `allOf`. Keywords like `format` are ignored.
+pub fn validate(schema: &Value, value: &Value) -> Vec<String> {
+    let mut errors = vec![];
+    check(schema, schema, value, "", &mut errors);
+    errors
+}
+
+fn check(root: &Value, schema: &Value, value: &Value, path: &str, errors: &mut Vec<String>) {
+    let schema = match schema {
+        Value::Bool(true) => return,
+        Value::Bool(false) => {
+            errors.push(format!("{}: not allowed", pointer(path)));
+            return;
+        }
+        Value::Object(schema) => schema,
+        _ => return,
+    };
+
+    if let Some(reference) = schema.get("$ref").and_then(Value::as_str) {
+        match resolve(root, reference) {
+            Some(target) => check(root, target, value, path, errors),
+            None => errors.push(format!("{}: unknown $ref {}", pointer(path), reference)),
+        }
+    }
+
+    if let Some(types) = schema.get("type") {
+        let matches = match types {
+            Value::String(name) => has_type(value, name),
+            Value::Array(names) => names
+                .iter()
+                .filter_map(Value::as_str)
+                .any(|name| has_type(value, name)),
+            _ => true,
+        };
+        if !matches {
+            errors.push(format!(
+                "{}: expected type {}, got {}",
+                pointer(path),
+                types,
+                type_name(value)
+            ));
+            return;
+        }
+    }
+
+    if let Some(allowed) = schema.get("enum").and_then(Value::as_array) {
+        if !allowed.contains(value) {
+            errors.push(format!(
+                "{}: {} is not one of {:?}",
+                pointer(path),
+                value,
+                allowed
+            ));
+        }
+    }
+    if let Some(constant) = schema.get("const") {
+        if constant != value {
+            errors.push(format!("{}: expected {}", pointer(path), constant));
+        }
+    }
+    if let (Some(minimum), Some(number)) = (
+        schema.get("minimum").and_then(Value::as_f64),
+        value.as_f64(),
+    ) {
+        if number < minimum {
+            errors.push(format!(
+                "{}: {} is below {}",
+                pointer(path),
+                number,
+                minimum
+            ));
+        }
+    }
+    if let (Some(maximum), Some(number)) = (
+        schema.get("maximum").and_then(Value::as_f64),
+        value.as_f64(),
+    ) {
+        if number > maximum {
+            errors.push(format!(
+                "{}: {} is above {}",
+                pointer(path),
+                number,
+                maximum
+            ));
+        }
+    }
+
+    if let Value::Object(object) = value {
+        for name in schema
+            .get("required")
+            .and_then(Value::as_array)
+            .into_iter()
+            .flatten()
+            .filter_map(Value::as_str)
+        {
+            if !object.contains_key(name) {
+                errors.push(format!("{}: missing required {}", pointer(path), name));
+            }
+        }
+        let properties = schema.get("properties").and_then(Value::as_object);
+        for (key, field) in object {
+            let field_path = format!("{}/{}", path, key);
+            match properties.and_then(|properties| properties.get(key)) {
+                Some(property) => check(root, property, field, &field_path, errors),
+                None => {
+                    if let Some(additional) = schema.get("additionalProperties") {
+                        check(root, additional, field, &field_path, errors);
+                    }
+                }
+            }
+        }
+    }
+
+    if let (Value::Array(items), Some(item_schema)) = (value, schema.get("items")) {
+        for (i, item) in items.iter().enumerate() {
+            check(root, item_schema, item, &format!("{}/{}", path, i), errors);
+        }
+    }
+
+    if let Some(all) = schema.get("allOf").and_then(Value::as_array) {
+        for sub in all {
+            check(root, sub, value, path, errors);
+        }
+    }
+    for (keyword, exactly_one) in [("anyOf", false), ("oneOf", true)] {
+        if let Some(alternatives) = schema.get(keyword).and_then(Value::as_array) {
+            let matching = alternatives
+                .iter()
+                .filter(|sub| {
+                    let mut sub_errors = vec![];
+                    check(root, sub, value, path, &mut sub_errors);
+                    sub_errors.is_empty()
+                })
+                .count();
+            if matching == 0 || (exactly_one && matching > 1) {
+                errors.push(format!(
+                    "{}: matches {} of the {} schemas of {}",
+                    pointer(path),
+                    matching,
+                    alternatives.len(),
+                    keyword
+                ));
+            }
+        }
+    }
+}
+
+/// Resolves a local `#/definitions/...` reference.
+fn resolve<'a>(root: &'a Value, reference: &str) -> Option<&'a Value> {
+    root.pointer(reference.strip_prefix('#')?)
+}
+
+fn has_type(value: &Value, name: &str) -> bool {
+    match name {
+        "null" => value.is_null(),
+        "boolean" => value.is_boolean(),
+        "object" => value.is_object(),
+        "array" => value.is_array(),
+        "string" => value.is_string(),
+        "number" => value.is_number(),
+        "integer" => value.is_i64() || value.is_u64(),
+        _ => true,
+    }
+}
+
+fn type_name(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "boolean",
+        Value::Number(_) => "number",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object",
+    }
+}
+
+fn pointer(path: &str) -> &str {
+    match path {
+        "" => "/",
+        path => path,
+    }
+}
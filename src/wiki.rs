@@ -0,0 +1,57 @@
+use git2::build::RepoBuilder;
+use git2::{Cred, ErrorCode, FetchOptions, RemoteCallbacks, Repository, ResetType};
+use log::info;
+use std::path::Path;
+
+pub const WIKI_DIR: &str = "wiki";
+
+fn fetch_options(pat: &str) -> FetchOptions<'_> {
+    let mut callbacks = RemoteCallbacks::new();
+    callbacks.credentials(move |_, _, _| Cred::userpass_plaintext("x-access-token", pat));
+    let mut options = FetchOptions::new();
+    options.remote_callbacks(callbacks);
+    options
+}
+
+/// Clones the wiki of a repository into `destination`, or updates it to the
+/// latest revision if it has been cloned before. Returns `Ok(false)` if the
+/// repository has no wiki to clone.
+///
+/// GitHub only creates the `<repo>.wiki.git` repository once the first wiki
+/// page is written, so a repository with the wiki feature enabled might
+/// still not have one.
+pub fn backup_wiki(
+    owner: &str,
+    repo: &str,
+    destination: &Path,
+    pat: &str,
+) -> Result<bool, git2::Error> {
+    let url = format!("https://github.com/{}/{}.wiki.git", owner, repo);
+
+    if destination.join(".git").exists() {
+        info!(
+            "Updating the wiki in {} from {}",
+            destination.display(),
+            url
+        );
+        let repository = Repository::open(destination)?;
+        let mut remote = repository.remote_anonymous(&url)?;
+        remote.fetch(&["HEAD"], Some(&mut fetch_options(pat)), None)?;
+        let fetch_head = repository.find_reference("FETCH_HEAD")?.peel_to_commit()?;
+        repository.reset(fetch_head.as_object(), ResetType::Hard, None)?;
+        return Ok(true);
+    }
+
+    info!("Cloning the wiki from {} to {}", url, destination.display());
+    match RepoBuilder::new()
+        .fetch_options(fetch_options(pat))
+        .clone(&url, destination)
+    {
+        Ok(_) => Ok(true),
+        Err(e) if e.code() == ErrorCode::NotFound || e.message().contains("404") => {
+            info!("No wiki found at {}: {}", url, e);
+            Ok(false)
+        }
+        Err(e) => Err(e),
+    }
+}